@@ -0,0 +1,187 @@
+use nannou::prelude::*;
+use std::fmt;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::locale::NumberFormat;
+
+type Rect = nannou::geom::rect::Rect;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmKind
+{
+    Trigger,
+    // A destructive action refused while locked
+    Blocked,
+    // The input came back after a disconnect
+    Reconnect,
+    // Announced by an `EVENT line, e.g. from the journal
+    Event,
+    // The condition of a rule held
+    Rule,
+}
+
+impl fmt::Display for AlarmKind
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+	match self {
+	    AlarmKind::Trigger => write!(f, "TRIGGER"),
+	    AlarmKind::Blocked => write!(f, "BLOCKED"),
+	    AlarmKind::Reconnect => write!(f, "RECONNECT"),
+	    AlarmKind::Event => write!(f, "EVENT"),
+	    AlarmKind::Rule => write!(f, "RULE"),
+	}
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AlarmEvent
+{
+    // Seconds since the epoch
+    pub timestamp: f64,
+    pub kind: AlarmKind,
+    pub scope: String,
+    pub signal: String,
+    pub value: f32,
+}
+
+impl AlarmEvent
+{
+    pub fn new(kind: AlarmKind, scope: &str, signal: &str, value: f32) -> AlarmEvent
+    {
+	let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+	    .map(|duration| { duration.as_secs_f64() })
+	    .unwrap_or(0.0);
+	AlarmEvent{ timestamp, kind, scope: scope.to_string(), signal: signal.to_string(), value }
+    }
+
+    // Case insensitive match against kind, scope and signal
+    pub fn matches(&self, needle: &str) -> bool
+    {
+	let needle = needle.to_lowercase();
+	self.kind.to_string().to_lowercase().contains(&needle)
+	    || self.scope.to_lowercase().contains(&needle)
+	    || self.signal.to_lowercase().contains(&needle)
+    }
+
+    pub fn time_of_day(&self) -> String
+    {
+	let seconds = self.timestamp % 86400.0;
+	format!("{:02}:{:02}:{:06.3}",
+		(seconds / 3600.0) as u32,
+		((seconds % 3600.0) / 60.0) as u32,
+		seconds % 60.0)
+    }
+}
+
+pub struct AlarmLog
+{
+    events: Vec<AlarmEvent>,
+}
+
+impl AlarmLog
+{
+    pub fn new() -> AlarmLog
+    {
+	AlarmLog{ events: vec![] }
+    }
+
+    pub fn push(&mut self, event: AlarmEvent)
+    {
+	self.events.push(event);
+    }
+
+    pub fn events(&self) -> &[AlarmEvent]
+    {
+	&self.events
+    }
+
+    pub fn search<'a>(&'a self, needle: &'a str) -> impl Iterator<Item=&'a AlarmEvent> + 'a
+    {
+	self.events.iter().filter(move |event| { event.matches(needle) })
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: &mut W, needle: &str, format: &NumberFormat) -> std::io::Result<()>
+    {
+	let s = format.csv_separator();
+	writeln!(writer, "timestamp{}kind{}scope{}signal{}value", s, s, s, s)?;
+	for event in self.search(needle) {
+	    writeln!(writer, "{}{}{}{}{}{}{}{}{}",
+		     format.format_csv((event.timestamp * 1000.0).round() / 1000.0), s,
+		     event.kind, s, format.csv_field(&event.scope), s, format.csv_field(&event.signal), s,
+		     format.format_csv(event.value))?;
+	}
+	Ok(())
+    }
+
+    pub fn export_csv(&self, path: &str, needle: &str, format: &NumberFormat) -> std::io::Result<()>
+    {
+	let mut file = std::fs::File::create(path)?;
+	self.write_csv(&mut file, needle, format)
+    }
+}
+
+// Shows the most recent alarms matching the filter
+pub struct AlarmPanel
+{
+    pub visible: bool,
+    pub filter: String,
+}
+
+impl AlarmPanel
+{
+    pub fn new() -> AlarmPanel
+    {
+	AlarmPanel{ visible: false, filter: String::new() }
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect, log: &AlarmLog, format: &NumberFormat)
+    {
+	if !self.visible {
+	    return;
+	}
+	let line_height = 16.0;
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.85));
+	let mut lines = vec![format!("Alarms - filter: {}_", self.filter)];
+	let matching: Vec<&AlarmEvent> = log.search(&self.filter).collect();
+	let capacity = ((rect.h() / line_height) as usize).saturating_sub(1);
+	for event in matching.iter().rev().take(capacity) {
+	    lines.push(format!("{} {} {}/{} {}", event.time_of_day(), event.kind, event.scope, event.signal,
+			       format.format(event.value, None)));
+	}
+	for (index, line) in lines.iter().enumerate() {
+	    let y = rect.top() - line_height * (index as f32 + 0.5);
+	    draw.text(line)
+		.x_y(rect.x(), y)
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(12)
+		.left_justify()
+		.color(if index == 0 { YELLOW } else { WHITE });
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn search_and_export_alarms() {
+	let mut log = AlarmLog::new();
+	log.push(AlarmEvent::new(AlarmKind::Trigger, "MyScope", "Sawtooth", 10.0));
+	log.push(AlarmEvent::new(AlarmKind::Trigger, "Other", "Current", 2.5));
+	assert_eq!(log.search("saw").count(), 1);
+	assert_eq!(log.search("trigger").count(), 2);
+	let mut output = vec![];
+	log.write_csv(&mut output, "current", &NumberFormat::default()).unwrap();
+	let csv = String::from_utf8(output).unwrap();
+	let lines: Vec<&str> = csv.lines().collect();
+	assert_eq!(lines.len(), 2);
+	assert!(lines[1].ends_with(",TRIGGER,Other,Current,2.5"));
+	let mut output = vec![];
+	log.write_csv(&mut output, "current", &NumberFormat::from_locale("de_DE")).unwrap();
+	assert!(String::from_utf8(output).unwrap().ends_with(";TRIGGER;Other;Current;2,5\n"));
+    }
+}
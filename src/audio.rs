@@ -0,0 +1,168 @@
+// Audio arrives at tens of kHz, far more lines than the scopes can
+// take. By default each block of frames is reduced to the sample of
+// largest magnitude per channel, which keeps transients like a fan
+// blade knocking visible. Analog levels on a line-in, like PWM
+// through an RC filter, read better as the block's mean.
+pub struct Decimator
+{
+    channels: usize,
+    // Device channels shown, from 0
+    selected: Vec<usize>,
+    factor: usize,
+    mean: bool,
+    reduced: Vec<f32>,
+    frames: usize,
+}
+
+impl Decimator
+{
+    // An empty selection shows all channels
+    pub fn new(channels: usize, selected: &[usize], sample_rate: u32, line_rate: u32, mean: bool) -> Decimator
+    {
+	let factor = (sample_rate / line_rate.max(1)).max(1) as usize;
+	let selected: Vec<usize> = if selected.is_empty() { (0..channels).collect() } else { selected.to_vec() };
+	Decimator{ channels, factor, mean, reduced: vec![0.0; selected.len()], selected, frames: 0 }
+    }
+
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec!["`SCOPE Audio SIZE 600 200 SAMPLES 1000".to_string()];
+	for channel in &self.selected {
+	    lines.push(format!("`Audio 'channel {}' -1 1 256 0", channel + 1));
+	}
+	lines
+    }
+
+    // Takes interleaved samples in -1..1, frames may span calls
+    pub fn feed(&mut self, samples: &[f32]) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for frame in samples.chunks(self.channels) {
+	    for (reduced, channel) in self.reduced.iter_mut().zip(&self.selected) {
+		let sample = frame.get(*channel).copied().unwrap_or(0.0);
+		if self.mean {
+		    *reduced += sample;
+		} else if sample.abs() > reduced.abs() {
+		    *reduced = sample;
+		}
+	    }
+	    self.frames += 1;
+	    if self.frames == self.factor {
+		let divisor = if self.mean { self.factor as f32 } else { 1.0 };
+		let values: Vec<String> = self.reduced.iter().map(|reduced| { (reduced / divisor).to_string() }).collect();
+		lines.push(format!("`Audio {}", values.join(" ")));
+		self.reduced.iter_mut().for_each(|reduced| { *reduced = 0.0; });
+		self.frames = 0;
+	    }
+	}
+	lines
+    }
+}
+
+#[cfg(feature = "audio")]
+pub use connector::AudioConnector;
+
+#[cfg(feature = "audio")]
+mod connector {
+    use std::thread;
+    use std::time::Duration;
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{Sample, SampleFormat};
+    use crossbeam::channel::{Receiver, Sender, bounded, unbounded};
+    use log::warn;
+    use crate::serial::Received;
+    use crate::shutdown;
+    use super::Decimator;
+
+    // Captures an audio input, "default" picks the system's default
+    pub struct AudioConnector
+    {
+	pub receiver: Receiver<Received>,
+    }
+
+    fn build(device: &cpal::Device, format: SampleFormat, config: &cpal::StreamConfig, mut decimator: Decimator, s: Sender<Received>) -> Result<cpal::Stream, String>
+    {
+	let error = |error| { warn!("audio input error: {}", error); };
+	let mut send = move |samples: &[f32]| {
+	    for line in decimator.feed(samples) {
+		s.send(Received::now(line)).ok();
+	    }
+	};
+	let stream = match format {
+	    SampleFormat::F32 => device.build_input_stream(config, move |data: &[f32], _: &cpal::InputCallbackInfo| { send(data); }, error),
+	    SampleFormat::I16 => device.build_input_stream(config, move |data: &[i16], _: &cpal::InputCallbackInfo| {
+		send(&data.iter().map(|sample| { sample.to_f32() }).collect::<Vec<f32>>());
+	    }, error),
+	    SampleFormat::U16 => device.build_input_stream(config, move |data: &[u16], _: &cpal::InputCallbackInfo| {
+		send(&data.iter().map(|sample| { sample.to_f32() }).collect::<Vec<f32>>());
+	    }, error),
+	};
+	stream.map_err(|error| { error.to_string() })
+    }
+
+    fn open(name: &str, selected: &[usize], line_rate: u32, mean: bool, s: Sender<Received>) -> Result<cpal::Stream, String>
+    {
+	let host = cpal::default_host();
+	let device = if name == "default" {
+	    host.default_input_device()
+	} else {
+	    host.input_devices().map_err(|error| { error.to_string() })?
+		.find(|device| { device.name().map(|found| { found == name }).unwrap_or(false) })
+	}.ok_or(format!("no audio input {}", name))?;
+	let supported = device.default_input_config().map_err(|error| { error.to_string() })?;
+	let config = supported.config();
+	if let Some(channel) = selected.iter().find(|channel| { **channel >= config.channels as usize }) {
+	    return Err(format!("{} has no channel {}", name, channel + 1));
+	}
+	let decimator = Decimator::new(config.channels as usize, selected, config.sample_rate.0, line_rate, mean);
+	for line in decimator.declarations() {
+	    s.send(Received::now(line)).ok();
+	}
+	let stream = build(&device, supported.sample_format(), &config, decimator, s)?;
+	stream.play().map_err(|error| { error.to_string() })?;
+	Ok(stream)
+    }
+
+    impl AudioConnector
+    {
+	pub fn new(name: &str, selected: &[usize], line_rate: u32, mean: bool) -> Result<AudioConnector, String>
+	{
+	    let (s, r) = unbounded();
+	    let (opened_s, opened_r) = bounded(1);
+	    let name = name.to_string();
+	    let selected = selected.to_vec();
+	    // Streams aren't Send on every platform, so the thread
+	    // owns it for its lifetime
+	    thread::spawn(move || {
+		match open(&name, &selected, line_rate, mean, s) {
+		    Ok(_stream) => {
+			opened_s.send(Ok(())).ok();
+			while !shutdown::requested() {
+			    thread::sleep(Duration::from_millis(100));
+			}
+		    }
+		    Err(error) => { opened_s.send(Err(error)).ok(); }
+		}
+	    });
+	    opened_r.recv().map_err(|error| { error.to_string() })??;
+	    Ok(AudioConnector{ receiver: r })
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn peaks_per_block() {
+	let mut decimator = Decimator::new(2, &[], 8000, 2000, false);
+	assert_eq!(decimator.declarations()[2], "`Audio 'channel 2' -1 1 256 0");
+	assert!(decimator.feed(&[0.1, -0.2, -0.5, 0.1, 0.3, 0.0]).is_empty());
+	assert_eq!(decimator.feed(&[0.0, 0.25, 0.2, 0.2, 0.1, 0.1]), vec!["`Audio -0.5 0.25"]);
+	let mut decimator = Decimator::new(2, &[1], 8000, 4000, true);
+	assert_eq!(decimator.declarations(), vec!["`SCOPE Audio SIZE 600 200 SAMPLES 1000", "`Audio 'channel 2' -1 1 256 0"]);
+	assert_eq!(decimator.feed(&[0.9, 0.25, -0.9, 0.75]), vec!["`Audio 0.5"]);
+    }
+}
@@ -0,0 +1,63 @@
+// Binary payloads embedded in the text protocol, see Scope's PACKED,
+// and images embedded in reports
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard alphabet, padding optional. None for anything malformed.
+pub fn decode(text: &str) -> Option<Vec<u8>>
+{
+    let text = text.trim_end_matches('=');
+    if text.len() % 4 == 1 {
+	return None;
+    }
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut count = 0;
+    for c in text.bytes() {
+	let value = ALPHABET.iter().position(|digit| { *digit == c })? as u32;
+	bits = bits << 6 | value;
+	count += 6;
+	if count >= 8 {
+	    count -= 8;
+	    result.push((bits >> count) as u8);
+	    bits &= (1 << count) - 1;
+	}
+    }
+    Some(result)
+}
+
+// Standard alphabet with padding
+pub fn encode(bytes: &[u8]) -> String
+{
+    let mut result = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+	let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| { bits | (*byte as u32) << (16 - 8 * index) });
+	for index in 0..4 {
+	    if index <= chunk.len() {
+		result.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+	    } else {
+		result.push('=');
+	    }
+	}
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn decodes_with_and_without_padding() {
+	assert_eq!(decode("/38AgA=="), Some(vec![0xff, 0x7f, 0x00, 0x80]));
+	assert_eq!(decode("/38AgA"), Some(vec![0xff, 0x7f, 0x00, 0x80]));
+	assert_eq!(decode("TWFu"), Some(b"Man".to_vec()));
+	assert_eq!(decode(""), Some(vec![]));
+	assert_eq!(decode("TWF*"), None);
+	assert_eq!(decode("TWFuT"), None);
+	assert_eq!(encode(&[0xff, 0x7f, 0x00, 0x80]), "/38AgA==");
+	assert_eq!(encode(b"Man"), "TWFu");
+	assert_eq!(decode(&encode(b"Ma")), Some(b"Ma".to_vec()));
+    }
+}
@@ -0,0 +1,143 @@
+// The device to connect to, by address or advertised name
+#[derive(Debug, Clone, PartialEq)]
+pub enum BleTarget
+{
+    Address(String),
+    Name(String),
+}
+
+impl BleTarget
+{
+    pub fn parse(target: &str) -> BleTarget
+    {
+	let octets: Vec<&str> = target.split(':').collect();
+	let is_address = octets.len() == 6 && octets.iter().all(|octet| {
+	    octet.len() == 2 && octet.chars().all(|c| { c.is_ascii_hexdigit() })
+	});
+	if is_address {
+	    BleTarget::Address(target.to_uppercase())
+	} else {
+	    BleTarget::Name(target.to_string())
+	}
+    }
+
+    pub fn matches(&self, address: &str, name: Option<&str>) -> bool
+    {
+	match self {
+	    BleTarget::Address(expected) => expected.eq_ignore_ascii_case(address),
+	    BleTarget::Name(expected) => name == Some(expected.as_str()),
+	}
+    }
+}
+
+#[cfg(all(feature = "ble", target_os = "linux"))]
+pub use connector::BleConnector;
+
+#[cfg(all(feature = "ble", target_os = "linux"))]
+mod connector {
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use btleplug::api::{Central, CentralEvent, Peripheral, UUID};
+    use btleplug::bluez::manager::Manager;
+    use crossbeam::channel::{Receiver, Sender, unbounded};
+    use log::{info, warn};
+    use crate::serial::{ConnectionState, LineEnding, LineProtocol, Received, backoff};
+    use super::BleTarget;
+
+    // Nordic UART Service, the peripheral notifies on TX
+    const NUS_TX: &str = "6E:40:00:03:B5:A3:F3:93:E0:A9:E5:0E:24:DC:CA:9E";
+    const SCAN_TIME: Duration = Duration::from_secs(3);
+
+    // Receives the Nordic UART Service of a BLE peripheral. Like the
+    // serial port it reconnects once the peripheral went away.
+    pub struct BleConnector
+    {
+	pub receiver: Receiver<Received>,
+	pub status: Receiver<ConnectionState>,
+    }
+
+    // Connects and returns once the peripheral disconnected
+    fn session(target: &BleTarget, lines: &Sender<Received>, status: &Sender<ConnectionState>) -> Result<(), String>
+    {
+	let manager = Manager::new().map_err(|error| { error.to_string() })?;
+	let adapter = manager.adapters().map_err(|error| { error.to_string() })?
+	    .into_iter().next().ok_or("no bluetooth adapter")?;
+	let central = adapter.connect().map_err(|error| { error.to_string() })?;
+	central.start_scan().map_err(|error| { error.to_string() })?;
+	thread::sleep(SCAN_TIME);
+	let peripheral = central.peripherals().into_iter()
+	    .find(|peripheral| {
+		target.matches(&peripheral.address().to_string(), peripheral.properties().local_name.as_deref())
+	    })
+	    .ok_or(format!("{:?} not found", target))?;
+	central.stop_scan().ok();
+	let (disconnect_s, disconnect_r) = unbounded();
+	let address = peripheral.address();
+	central.on_event(Box::new(move |event| {
+	    if let CentralEvent::DeviceDisconnected(gone) = event {
+		if gone == address {
+		    disconnect_s.send(()).ok();
+		}
+	    }
+	}));
+	peripheral.connect().map_err(|error| { error.to_string() })?;
+	let tx_uuid = UUID::from_str(NUS_TX).unwrap();
+	let tx = peripheral.discover_characteristics().map_err(|error| { error.to_string() })?
+	    .into_iter().find(|characteristic| { characteristic.uuid == tx_uuid })
+	    .ok_or("no Nordic UART Service")?;
+	// Notifications carry at most a few dozen bytes, lines span several
+	let lp = Arc::new(Mutex::new(LineProtocol::new(LineEnding::Auto)));
+	let lines = lines.clone();
+	peripheral.on_notification(Box::new(move |notification| {
+	    lp.lock().unwrap().feed(&notification.value, |line: &str| { lines.send(Received::now(line.to_string())).ok(); });
+	}));
+	peripheral.subscribe(&tx).map_err(|error| { error.to_string() })?;
+	info!("connected to {:?}", target);
+	status.send(ConnectionState::Connected).ok();
+	disconnect_r.recv().ok();
+	Ok(())
+    }
+
+    impl BleConnector
+    {
+	pub fn new(target: &str) -> BleConnector
+	{
+	    let target = BleTarget::parse(target);
+	    let (s, r) = unbounded();
+	    let (status_s, status_r) = unbounded();
+	    thread::spawn(move || {
+		let mut attempt = 0;
+		loop {
+		    match session(&target, &s, &status_s) {
+			Ok(()) => { attempt = 0; }
+			Err(error) => {
+			    warn!("BLE error: {}", error);
+			    attempt += 1;
+			}
+		    }
+		    status_s.send(ConnectionState::Reconnecting).ok();
+		    thread::sleep(backoff(attempt));
+		}
+	    });
+	    BleConnector{ receiver: r, status: status_r }
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn targets_by_address_or_name() {
+	let target = BleTarget::parse("c0:ff:ee:00:11:22");
+	assert_eq!(target, BleTarget::Address("C0:FF:EE:00:11:22".to_string()));
+	assert!(target.matches("C0:FF:EE:00:11:22", None));
+	let target = BleTarget::parse("Peanut-Kit");
+	assert!(target.matches("C0:FF:EE:00:11:22", Some("Peanut-Kit")));
+	assert!(!target.matches("C0:FF:EE:00:11:22", Some("Other")));
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+fn one() -> f32
+{
+    1.0
+}
+
+fn yes() -> bool
+{
+    true
+}
+
+// A sensor register read through an I2C or SPI bus, e.g. of an
+// MCP2221 or FT232H bridge, which the kernel exposes as /dev/i2c-N
+// or /dev/spidevB.C:
+//
+// [[bridge_registers]]
+// bus = "/dev/i2c-1"
+// address = 0x48
+// register = 0x00
+// length = 2
+// scope = "Sensors"
+// name = "temperature"
+// signed = true
+// scale = 0.0078125
+// min = -40.0
+// max = 125.0
+//
+// SPI devices get the register with read_flag set, followed by
+// length dummy bytes.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct BridgeRegister
+{
+    pub bus: String,
+    // I2C slave address, unused on SPI
+    #[serde(default)]
+    pub address: u16,
+    pub register: u8,
+    pub length: usize,
+    #[serde(default)]
+    pub read_flag: u8,
+    #[serde(default = "yes")]
+    pub big_endian: bool,
+    #[serde(default)]
+    pub signed: bool,
+    pub scope: String,
+    pub name: String,
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl BridgeRegister
+{
+    pub fn is_spi(&self) -> bool
+    {
+	self.bus.contains("spidev")
+    }
+
+    pub fn value(&self, bytes: &[u8]) -> Option<f32>
+    {
+	if self.length == 0 || self.length > 8 || bytes.len() < self.length {
+	    return None;
+	}
+	let bytes = &bytes[..self.length];
+	let fold = |raw: u64, byte: &u8| { raw << 8 | *byte as u64 };
+	let raw = if self.big_endian {
+	    bytes.iter().fold(0, fold)
+	} else {
+	    bytes.iter().rev().fold(0, fold)
+	};
+	let bits = self.length * 8;
+	let value = if self.signed && bits < 64 && raw >> (bits - 1) & 1 == 1 {
+	    (raw as i64 - (1i64 << bits)) as f64
+	} else if self.signed {
+	    raw as i64 as f64
+	} else {
+	    raw as f64
+	};
+	Some(value as f32 * self.scale + self.offset)
+    }
+}
+
+// Like CanMapping, with one line per scope and polling round
+pub struct BridgeMapping
+{
+    pub registers: Vec<BridgeRegister>,
+    // Scopes in order of their first register
+    scopes: Vec<String>,
+    latest: HashMap<String, Vec<f32>>,
+}
+
+impl BridgeMapping
+{
+    pub fn new(registers: &[BridgeRegister]) -> BridgeMapping
+    {
+	let mut scopes: Vec<String> = vec![];
+	for register in registers {
+	    if !scopes.contains(&register.scope) {
+		scopes.push(register.scope.clone());
+	    }
+	}
+	let latest = scopes.iter().map(|scope| {
+	    (scope.clone(), vec![0.0; registers.iter().filter(|register| { &register.scope == scope }).count()])
+	}).collect();
+	BridgeMapping{ registers: registers.to_vec(), scopes, latest }
+    }
+
+    // Declares the scopes, all signals share the full height
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for scope in &self.scopes {
+	    lines.push(format!("`SCOPE {}", scope));
+	    for register in self.registers.iter().filter(|register| { &register.scope == scope }) {
+		lines.push(format!("`{} '{}' {} {} 256 0", scope, register.name, register.min, register.max));
+	    }
+	}
+	lines
+    }
+
+    // Registers that failed to read keep their last value
+    pub fn update(&mut self, index: usize, bytes: &[u8])
+    {
+	let register = &self.registers[index];
+	let position = self.registers[..index].iter().filter(|other| { other.scope == register.scope }).count();
+	if let Some(value) = register.value(bytes) {
+	    self.latest.get_mut(&register.scope).unwrap()[position] = value;
+	}
+    }
+
+    pub fn lines(&self) -> Vec<String>
+    {
+	self.scopes.iter().map(|scope| {
+	    let values: Vec<String> = self.latest[scope].iter().map(|value| { value.to_string() }).collect();
+	    format!("`{} {}", scope, values.join(" "))
+	}).collect()
+    }
+}
+
+#[cfg(all(feature = "bridge", target_os = "linux"))]
+pub use connector::BridgeConnector;
+
+#[cfg(all(feature = "bridge", target_os = "linux"))]
+mod connector {
+    use std::collections::HashMap;
+    use std::fs::{File, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crossbeam::channel::{Receiver, unbounded};
+    use log::warn;
+    use crate::serial::Received;
+    use crate::shutdown;
+    use super::{BridgeMapping, BridgeRegister};
+
+    // From linux/i2c-dev.h and linux/spi/spidev.h
+    const I2C_RDWR: u64 = 0x0707;
+    const I2C_M_RD: u16 = 0x0001;
+    const SPI_IOC_MESSAGE_1: u64 = 0x4020_6b00;
+
+    #[repr(C)]
+    struct I2cMessage
+    {
+	addr: u16,
+	flags: u16,
+	len: u16,
+	buf: *mut u8,
+    }
+
+    #[repr(C)]
+    struct I2cTransfer
+    {
+	msgs: *mut I2cMessage,
+	nmsgs: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SpiTransfer
+    {
+	tx_buf: u64,
+	rx_buf: u64,
+	len: u32,
+	speed_hz: u32,
+	delay_usecs: u16,
+	bits_per_word: u8,
+	cs_change: u8,
+	tx_nbits: u8,
+	rx_nbits: u8,
+	word_delay_usecs: u8,
+	pad: u8,
+    }
+
+    fn ioctl<T>(file: &File, request: u64, data: &mut T) -> std::io::Result<()>
+    {
+	// The buffers data points to outlive the call
+	let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, data as *mut T) };
+	if result < 0 { Err(std::io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    // Writes the register address, then reads with a repeated start
+    fn read_i2c(file: &File, register: &BridgeRegister) -> std::io::Result<Vec<u8>>
+    {
+	let mut address = [register.register];
+	let mut bytes = vec![0; register.length];
+	let mut messages = [
+	    I2cMessage{ addr: register.address, flags: 0, len: 1, buf: address.as_mut_ptr() },
+	    I2cMessage{ addr: register.address, flags: I2C_M_RD, len: bytes.len() as u16, buf: bytes.as_mut_ptr() },
+	];
+	let mut transfer = I2cTransfer{ msgs: messages.as_mut_ptr(), nmsgs: messages.len() as u32 };
+	ioctl(file, I2C_RDWR, &mut transfer)?;
+	Ok(bytes)
+    }
+
+    fn read_spi(file: &File, register: &BridgeRegister) -> std::io::Result<Vec<u8>>
+    {
+	let mut tx = vec![0; register.length + 1];
+	tx[0] = register.register | register.read_flag;
+	let mut rx = vec![0; tx.len()];
+	let mut transfer = SpiTransfer{ tx_buf: tx.as_mut_ptr() as u64, rx_buf: rx.as_mut_ptr() as u64, len: tx.len() as u32, ..SpiTransfer::default() };
+	ioctl(file, SPI_IOC_MESSAGE_1, &mut transfer)?;
+	Ok(rx.split_off(1))
+    }
+
+    // Polls sensors on I2C and SPI buses
+    pub struct BridgeConnector
+    {
+	pub receiver: Receiver<Received>,
+    }
+
+    impl BridgeConnector
+    {
+	pub fn new(registers: &[BridgeRegister], interval: Duration) -> std::io::Result<BridgeConnector>
+	{
+	    let mut buses: HashMap<String, File> = HashMap::new();
+	    for register in registers {
+		if !buses.contains_key(&register.bus) {
+		    buses.insert(register.bus.clone(), OpenOptions::new().read(true).write(true).open(&register.bus)?);
+		}
+	    }
+	    let mut mapping = BridgeMapping::new(registers);
+	    let (s, r) = unbounded();
+	    for line in mapping.declarations() {
+		s.send(Received::now(line)).ok();
+	    }
+	    thread::spawn(move || {
+		while !shutdown::requested() {
+		    let start = Instant::now();
+		    for index in 0..mapping.registers.len() {
+			let register = &mapping.registers[index];
+			let bus = &buses[&register.bus];
+			let result = if register.is_spi() { read_spi(bus, register) } else { read_i2c(bus, register) };
+			match result {
+			    Ok(bytes) => { mapping.update(index, &bytes); }
+			    Err(error) => { warn!("reading {} from {} failed: {:?}", register.name, register.bus, error); }
+			}
+		    }
+		    for line in mapping.lines() {
+			if s.send(Received::now(line)).is_err() {
+			    return;
+			}
+		    }
+		    thread::sleep(interval.checked_sub(start.elapsed()).unwrap_or_default());
+		}
+	    });
+	    Ok(BridgeConnector{ receiver: r })
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    fn register(bus: &str, name: &str, length: usize, signed: bool) -> BridgeRegister
+    {
+	BridgeRegister{ bus: bus.to_string(), address: 0x48, register: 0, length, read_flag: 0, big_endian: true, signed, scope: "Sensors".to_string(), name: name.to_string(), scale: 1.0, offset: 0.0, min: 0.0, max: 100.0 }
+    }
+
+    #[test]
+    fn registers_to_lines() {
+	let temperature = register("/dev/i2c-1", "temperature", 2, true);
+	assert!(!temperature.is_spi());
+	assert!(register("/dev/spidev0.0", "pressure", 3, false).is_spi());
+	assert_eq!(temperature.value(&[0xff, 0x80]), Some(-128.0));
+	assert_eq!(temperature.value(&[0xff]), None);
+	let mut mapping = BridgeMapping::new(&[temperature, register("/dev/spidev0.0", "pressure", 3, false)]);
+	assert_eq!(mapping.declarations().len(), 3);
+	mapping.update(1, &[0x01, 0x00, 0x00]);
+	assert_eq!(mapping.lines(), vec!["`Sensors 0 65536"]);
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+fn one() -> f32
+{
+    1.0
+}
+
+// Bytes of a CAN frame mapped to a scope signal, e.g.
+//
+// [[can_signals]]
+// id = 0x181
+// scope = "Motor"
+// name = "rpm"
+// start = 0
+// length = 2
+// min = 0.0
+// max = 6000.0
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CanSignal
+{
+    pub id: u32,
+    pub scope: String,
+    pub name: String,
+    // First byte and number of bytes, at most 8
+    pub start: usize,
+    pub length: usize,
+    #[serde(default)]
+    pub big_endian: bool,
+    #[serde(default)]
+    pub signed: bool,
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl CanSignal
+{
+    pub fn extract(&self, data: &[u8]) -> Option<f32>
+    {
+	if self.length == 0 || self.length > 8 || self.start + self.length > data.len() {
+	    return None;
+	}
+	let bytes = &data[self.start..self.start + self.length];
+	let fold = |raw: u64, byte: &u8| { raw << 8 | *byte as u64 };
+	let raw = if self.big_endian {
+	    bytes.iter().fold(0, fold)
+	} else {
+	    bytes.iter().rev().fold(0, fold)
+	};
+	let bits = self.length * 8;
+	let value = if self.signed && bits < 64 && raw >> (bits - 1) & 1 == 1 {
+	    (raw as i64 - (1i64 << bits)) as f64
+	} else if self.signed {
+	    raw as i64 as f64
+	} else {
+	    raw as f64
+	};
+	Some(value as f32 * self.scale + self.offset)
+    }
+}
+
+// Turns frames into protocol lines. A scope may collect signals
+// of several frames, the others keep their last value.
+pub struct CanMapping
+{
+    signals: Vec<CanSignal>,
+    // Scopes in order of their first signal
+    scopes: Vec<String>,
+    latest: HashMap<String, Vec<f32>>,
+}
+
+impl CanMapping
+{
+    pub fn new(signals: &[CanSignal]) -> CanMapping
+    {
+	let mut scopes: Vec<String> = vec![];
+	for signal in signals {
+	    if !scopes.contains(&signal.scope) {
+		scopes.push(signal.scope.clone());
+	    }
+	}
+	let latest = scopes.iter().map(|scope| {
+	    (scope.clone(), vec![0.0; signals.iter().filter(|signal| { &signal.scope == scope }).count()])
+	}).collect();
+	CanMapping{ signals: signals.to_vec(), scopes, latest }
+    }
+
+    // Declares the scopes, all signals share the full height
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for scope in &self.scopes {
+	    lines.push(format!("`SCOPE {}", scope));
+	    for signal in self.signals.iter().filter(|signal| { &signal.scope == scope }) {
+		lines.push(format!("`{} '{}' {} {} 256 0", scope, signal.name, signal.min, signal.max));
+	    }
+	}
+	lines
+    }
+
+    pub fn frame(&mut self, id: u32, data: &[u8]) -> Vec<String>
+    {
+	let mut touched: Vec<String> = vec![];
+	for scope in &self.scopes {
+	    let values = self.latest.get_mut(scope).unwrap();
+	    let signals = self.signals.iter().filter(|signal| { &signal.scope == scope });
+	    for (index, signal) in signals.enumerate() {
+		if signal.id != id {
+		    continue;
+		}
+		if let Some(value) = signal.extract(data) {
+		    values[index] = value;
+		    if !touched.contains(scope) {
+			touched.push(scope.clone());
+		    }
+		}
+	    }
+	}
+	touched.iter().map(|scope| {
+	    let values: Vec<String> = self.latest[scope].iter().map(|value| { value.to_string() }).collect();
+	    format!("`{} {}", scope, values.join(" "))
+	}).collect()
+    }
+}
+
+#[cfg(feature = "can")]
+pub use connector::CanConnector;
+
+#[cfg(feature = "can")]
+mod connector {
+    use std::thread;
+    use crossbeam::channel::{Receiver, unbounded};
+    use log::warn;
+    use socketcan::CANSocket;
+    use crate::serial::{ConnectionState, Received, backoff};
+    use super::{CanMapping, CanSignal};
+
+    // Receives frames from a SocketCAN interface like can0
+    pub struct CanConnector
+    {
+	pub receiver: Receiver<Received>,
+	pub status: Receiver<ConnectionState>,
+    }
+
+    impl CanConnector
+    {
+	pub fn new(interface: &str, signals: &[CanSignal]) -> Result<CanConnector, std::io::Error>
+	{
+	    let mut socket = CANSocket::open(interface).map_err(|error| { std::io::Error::new(std::io::ErrorKind::NotFound, error) })?;
+	    let interface = interface.to_string();
+	    let mut mapping = CanMapping::new(signals);
+	    let (s, r) = unbounded();
+	    let (status_s, status_r) = unbounded();
+	    for line in mapping.declarations() {
+		s.send(Received::now(line)).ok();
+	    }
+	    thread::spawn(move || {
+		loop {
+		    match socket.read_frame() {
+			Ok(frame) => {
+			    for line in mapping.frame(frame.id(), frame.data()) {
+				if s.send(Received::now(line)).is_err() {
+				    return;
+				}
+			    }
+			}
+			// The interface went down, e.g. the adapter was unplugged
+			Err(error) => {
+			    warn!("CAN error: {:?}, reconnecting", error);
+			    status_s.send(ConnectionState::Reconnecting).ok();
+			    let mut attempt = 0;
+			    socket = loop {
+				thread::sleep(backoff(attempt));
+				match CANSocket::open(&interface) {
+				    Ok(socket) => { break socket; }
+				    Err(_) => { attempt += 1; }
+				}
+			    };
+			    status_s.send(ConnectionState::Connected).ok();
+			}
+		    }
+		}
+	    });
+	    Ok(CanConnector{ receiver: r, status: status_r })
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    fn signal(id: u32, name: &str, start: usize, length: usize) -> CanSignal
+    {
+	CanSignal{ id, scope: "Motor".to_string(), name: name.to_string(), start, length, big_endian: false, signed: false, scale: 1.0, offset: 0.0, min: 0.0, max: 100.0 }
+    }
+
+    #[test]
+    fn frames_become_lines() {
+	let mut current = signal(0x182, "current", 0, 2);
+	current.signed = true;
+	current.scale = 0.5;
+	let mut rpm = signal(0x181, "rpm", 1, 2);
+	rpm.big_endian = true;
+	let mut mapping = CanMapping::new(&[rpm, current]);
+	assert_eq!(mapping.declarations(), vec!["`SCOPE Motor", "`Motor 'rpm' 0 100 256 0", "`Motor 'current' 0 100 256 0"]);
+	assert_eq!(mapping.frame(0x181, &[0xff, 0x01, 0x02]), vec!["`Motor 258 0"]);
+	assert_eq!(mapping.frame(0x182, &[0xfe, 0xff]), vec!["`Motor 258 -1"]);
+	// unmapped ids and short frames are ignored
+	assert!(mapping.frame(0x200, &[1]).is_empty());
+	assert!(mapping.frame(0x181, &[1]).is_empty());
+    }
+}
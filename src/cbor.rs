@@ -0,0 +1,109 @@
+use serde_json::{json, Map, Value};
+
+// Half precision floats, as CBOR encoders pick the smallest size
+fn half(bits: u16) -> f64
+{
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f64;
+    let magnitude = match exponent {
+	0 => mantissa * 2f64.powi(-24),
+	0x1f if mantissa == 0.0 => f64::INFINITY,
+	0x1f => f64::NAN,
+	_ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    };
+    if bits & 0x8000 != 0 { -magnitude } else { magnitude }
+}
+
+fn take(rest: &[u8], length: u64) -> Option<(&[u8], &[u8])>
+{
+    let length = length as usize;
+    if rest.len() < length { None } else { Some(rest.split_at(length)) }
+}
+
+// A CBOR item and the bytes after it. Indefinite lengths aren't
+// supported, tags are skipped.
+fn item(bytes: &[u8]) -> Option<(Value, &[u8])>
+{
+    let (initial, rest) = bytes.split_first()?;
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+    let (argument, rest) = match info {
+	0..=23 => (info as u64, rest),
+	24..=27 => {
+	    let (bytes, rest) = take(rest, 1 << (info - 24))?;
+	    (bytes.iter().fold(0u64, |value, byte| { value << 8 | *byte as u64 }), rest)
+	}
+	_ => { return None; }
+    };
+    match major {
+	0 => Some((json!(argument), rest)),
+	1 => Some((json!(-1 - argument as i64), rest)),
+	2 => {
+	    let (bytes, rest) = take(rest, argument)?;
+	    Some((json!(bytes), rest))
+	}
+	3 => {
+	    let (bytes, rest) = take(rest, argument)?;
+	    Some((Value::String(std::str::from_utf8(bytes).ok()?.to_string()), rest))
+	}
+	4 => {
+	    let mut rest = rest;
+	    let mut values = vec![];
+	    for _ in 0..argument {
+		let (element, remaining) = item(rest)?;
+		values.push(element);
+		rest = remaining;
+	    }
+	    Some((Value::Array(values), rest))
+	}
+	// Keys have to be strings, as in JSON
+	5 => {
+	    let mut rest = rest;
+	    let mut entries = Map::new();
+	    for _ in 0..argument {
+		let (key, remaining) = item(rest)?;
+		let (element, remaining) = item(remaining)?;
+		entries.insert(key.as_str()?.to_string(), element);
+		rest = remaining;
+	    }
+	    Some((Value::Object(entries), rest))
+	}
+	6 => item(rest),
+	7 => match info {
+	    20 => Some((json!(false), rest)),
+	    21 => Some((json!(true), rest)),
+	    22 | 23 => Some((Value::Null, rest)),
+	    25 => Some((json!(half(argument as u16)), rest)),
+	    26 => Some((json!(f32::from_bits(argument as u32)), rest)),
+	    27 => Some((json!(f64::from_bits(argument)), rest)),
+	    _ => None,
+	},
+	_ => None,
+    }
+}
+
+// None unless the bytes hold exactly one item
+pub fn decode(bytes: &[u8]) -> Option<Value>
+{
+    match item(bytes)? {
+	(value, rest) if rest.is_empty() => Some(value),
+	_ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn decodes_items() {
+	// {"scope": "S", "values": [1, -2, 1.5]}
+	let bytes = [0xa2, 0x65, b's', b'c', b'o', b'p', b'e', 0x61, b'S', 0x66, b'v', b'a', b'l', b'u', b'e', b's', 0x83, 0x01, 0x21, 0xf9, 0x3e, 0x00];
+	assert_eq!(decode(&bytes), Some(json!({"scope": "S", "values": [1, -2, 1.5]})));
+	assert_eq!(decode(&[0x19, 0x01, 0x00]), Some(json!(256)));
+	assert_eq!(decode(&[0xfa, 0x40, 0x20, 0x00, 0x00]), Some(json!(2.5)));
+	assert_eq!(decode(&[0x9f, 0x01, 0xff]), None);
+	assert_eq!(decode(&[0x82, 0x01]), None);
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Deserialize;
+
+// Whether lines carry a trailing NMEA style checksum, e.g.
+//
+// `MyScope 1 2 3*2A
+//
+// the XOR of all bytes before the * as two hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChecksumMode
+{
+    Off,
+    // Verified where present
+    Optional,
+    // Lines without one are dropped too
+    Required,
+}
+
+impl std::str::FromStr for ChecksumMode
+{
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<ChecksumMode, String>
+    {
+	match mode {
+	    "off" => Ok(ChecksumMode::Off),
+	    "optional" => Ok(ChecksumMode::Optional),
+	    "required" => Ok(ChecksumMode::Required),
+	    _ => Err(format!("unknown checksum mode {}", mode)),
+	}
+    }
+}
+
+pub fn xor(content: &str) -> u8
+{
+    content.bytes().fold(0, |checksum, byte| { checksum ^ byte })
+}
+
+// The line without its checksum, None if it has to be dropped
+pub fn verify(line: &str, mode: ChecksumMode) -> Option<&str>
+{
+    if mode == ChecksumMode::Off {
+	return Some(line);
+    }
+    let split = line.len().checked_sub(3)
+	.filter(|position| { line.is_char_boundary(*position) && line.as_bytes()[*position] == b'*' })
+	.and_then(|position| { u8::from_str_radix(&line[position + 1..], 16).ok().map(|expected| { (position, expected) }) });
+    match split {
+	Some((position, expected)) => {
+	    let content = &line[..position];
+	    if xor(content) == expected { Some(content) } else { None }
+	}
+	None if mode == ChecksumMode::Optional => Some(line),
+	None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn trailing_checksums() {
+	let line = "`MyScope 1 2 3";
+	let checked = format!("{}*{:02X}", line, xor(line));
+	assert_eq!(verify(&checked, ChecksumMode::Required), Some(line));
+	assert_eq!(verify(&checked.replacen('2', "6", 1), ChecksumMode::Optional), None);
+	assert_eq!(verify(line, ChecksumMode::Optional), Some(line));
+	assert_eq!(verify(line, ChecksumMode::Required), None);
+	assert_eq!(verify(&checked, ChecksumMode::Off), Some(checked.as_str()));
+    }
+}
@@ -0,0 +1,203 @@
+use serde_json::Value;
+
+use crate::{cbor, msgpack};
+
+// COBS framed transport: frames are separated by zero bytes, the
+// first byte of a decoded frame tells what it carries.
+
+// A protocol line, UTF-8 without line ending
+pub const FRAME_TEXT: u8 = 0x01;
+// Samples of one object: name length, name, little endian f32s
+pub const FRAME_SAMPLES: u8 = 0x02;
+// Like FRAME_SAMPLES, but words as the scope's PACKED declares
+pub const FRAME_PACKED: u8 = 0x03;
+// A MessagePack or CBOR value, see value_line
+pub const FRAME_MSGPACK: u8 = 0x04;
+pub const FRAME_CBOR: u8 = 0x05;
+
+// Frames longer than this are garbage from a lost delimiter
+const MAX_FRAME: usize = 4096;
+
+// None for a malformed frame
+pub fn decode(frame: &[u8]) -> Option<Vec<u8>>
+{
+    let mut result = Vec::with_capacity(frame.len());
+    let mut index = 0;
+    while index < frame.len() {
+	let code = frame[index] as usize;
+	if code == 0 || index + code > frame.len() {
+	    return None;
+	}
+	result.extend_from_slice(&frame[index + 1..index + code]);
+	index += code;
+	if code < 0xff && index < frame.len() {
+	    result.push(0);
+	}
+    }
+    Some(result)
+}
+
+// A length prefixed object name and what follows it
+fn split_name(payload: &[u8]) -> Option<(&str, &[u8])>
+{
+    let (length, rest) = payload.split_first()?;
+    let length = *length as usize;
+    if rest.len() < length {
+	return None;
+    }
+    Some((std::str::from_utf8(&rest[..length]).ok()?, &rest[length..]))
+}
+
+// The line a structured value stands for: a string is a protocol
+// line, an array of an object name and numbers its samples, and a
+// map is passed on as JSON for the jsonl dialect.
+fn value_line(value: Value) -> Option<String>
+{
+    match value {
+	Value::String(line) => Some(line),
+	Value::Array(values) => {
+	    let (name, samples) = values.split_first()?;
+	    let mut line = format!("`{}", name.as_str()?);
+	    for sample in samples {
+		line.push_str(&format!(" {}", sample.as_f64()?));
+	    }
+	    Some(line)
+	}
+	Value::Object(_) => Some(value.to_string()),
+	_ => None,
+    }
+}
+
+// Turns a decoded frame into the line it stands for
+pub fn frame_line(frame: &[u8]) -> Option<String>
+{
+    match frame.split_first()? {
+	(&FRAME_TEXT, text) => std::str::from_utf8(text).ok().map(|text| { text.to_string() }),
+	(&FRAME_SAMPLES, payload) => {
+	    let (name, samples) = split_name(payload)?;
+	    if samples.len() % 4 != 0 {
+		return None;
+	    }
+	    let mut line = format!("`{}", name);
+	    for bytes in samples.chunks(4) {
+		let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		line.push_str(&format!(" {}", value));
+	    }
+	    Some(line)
+	}
+	// The scope decodes the words, it knows their size
+	(&FRAME_PACKED, payload) => {
+	    let (name, samples) = split_name(payload)?;
+	    let hex: String = samples.iter().map(|byte| { format!("{:02x}", byte) }).collect();
+	    Some(format!("`{} #{}", name, hex))
+	}
+	(&FRAME_MSGPACK, payload) => value_line(msgpack::decode(payload)?),
+	(&FRAME_CBOR, payload) => value_line(cbor::decode(payload)?),
+	_ => None,
+    }
+}
+
+pub(crate) struct CobsProtocol
+{
+    bytes: Vec<u8>,
+    // Frames that didn't decode, usually damaged on the wire
+    pub(crate) invalid: u64,
+}
+
+impl CobsProtocol
+{
+    pub(crate) fn new() -> CobsProtocol
+    {
+	CobsProtocol{ bytes: vec![], invalid: 0 }
+    }
+
+    pub(crate) fn reset(&mut self)
+    {
+	self.bytes.clear();
+    }
+
+    pub(crate) fn feed<F>(&mut self, buffer: &[u8], mut func: F) where F: FnMut(&str)
+    {
+	for c in buffer {
+	    if *c != 0 {
+		if self.bytes.len() < MAX_FRAME {
+		    self.bytes.push(*c);
+		}
+		continue;
+	    }
+	    // Back to back delimiters are allowed to resynchronize
+	    if self.bytes.is_empty() {
+		continue;
+	    }
+	    let line = if self.bytes.len() < MAX_FRAME { decode(&self.bytes).and_then(|frame| { frame_line(&frame) }) } else { None };
+	    match line {
+		Some(line) => { func(&line); }
+		None => { self.invalid += 1; }
+	    }
+	    self.bytes.clear();
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    // What the firmware sends
+    fn encode(data: &[u8]) -> Vec<u8>
+    {
+	let mut result = vec![0];
+	let mut code_index = 0;
+	for byte in data {
+	    if *byte != 0 {
+		result.push(*byte);
+	    }
+	    if *byte == 0 || result.len() - code_index == 0xff {
+		result[code_index] = (result.len() - code_index) as u8;
+		code_index = result.len();
+		result.push(0);
+	    }
+	}
+	result[code_index] = (result.len() - code_index) as u8;
+	result
+    }
+
+    #[test]
+    fn text_and_sample_frames() {
+	assert_eq!(encode(&[0x11, 0x00, 0x22]), vec![0x02, 0x11, 0x02, 0x22]);
+	assert_eq!(decode(&[0x02, 0x11, 0x02, 0x22]), Some(vec![0x11, 0x00, 0x22]));
+	assert_eq!(decode(&[0x05, 0x11]), None);
+	let long: Vec<u8> = (1..=300).map(|i| { (i % 255 + 1) as u8 }).collect();
+	assert_eq!(decode(&encode(&long)), Some(long));
+	let mut wire = vec![0];
+	wire.extend(encode(b"\x01`SCOPE MyScope"));
+	wire.push(0);
+	let mut samples = vec![FRAME_SAMPLES, 7];
+	samples.extend_from_slice(b"MyScope");
+	samples.extend_from_slice(&1.5f32.to_le_bytes());
+	samples.extend_from_slice(&0.0f32.to_le_bytes());
+	wire.extend(encode(&samples));
+	wire.push(0);
+	// Noise between frames
+	wire.extend(&[0x03, 0x99, 0]);
+	let mut lp = CobsProtocol::new();
+	let mut lines = vec![];
+	lp.feed(&wire, |line: &str| { lines.push(line.to_string()) });
+	let mut packed = vec![FRAME_PACKED, 7];
+	packed.extend_from_slice(b"MyScope");
+	packed.extend_from_slice(&[0xff, 0x7f, 0x00, 0x80]);
+	lp.feed(&encode(&packed), |_line: &str| {});
+	lp.feed(&[0], |line: &str| { lines.push(line.to_string()) });
+	assert_eq!(lines, vec!["`SCOPE MyScope", "`MyScope 1.5 0", "`MyScope #ff7f0080"]);
+	assert_eq!(lp.invalid, 1);
+	// ["MyScope", 1, 2.5] as MessagePack
+	let mut frame = vec![FRAME_MSGPACK, 0x93, 0xa7];
+	frame.extend_from_slice(b"MyScope");
+	frame.extend_from_slice(&[0x01, 0xca, 0x40, 0x20, 0x00, 0x00]);
+	assert_eq!(frame_line(&frame), Some("`MyScope 1 2.5".to_string()));
+	// {"scope": "S"} as CBOR
+	assert_eq!(frame_line(&[FRAME_CBOR, 0xa1, 0x65, b's', b'c', b'o', b'p', b'e', 0x61, b'S']), Some(r#"{"scope":"S"}"#.to_string()));
+	assert_eq!(frame_line(&[FRAME_CBOR, 0x82, 0x01, 0x02]), None);
+    }
+}
@@ -0,0 +1,639 @@
+use serde::Deserialize;
+use thiserror::Error;
+use crate::can::CanSignal;
+use crate::mavlink::MavlinkSignal;
+use crate::modbus::ModbusRegister;
+use crate::osc::OscSignal;
+use crate::bridge::BridgeRegister;
+use crate::rules::RuleConfig;
+use crate::serial::{DEFAULT_QUEUE, CharacterFormat, DropPolicy, FlowControl, Framing, LineEnding, LineSettings};
+use crate::checksum::ChecksumMode;
+use crate::debugobjects::{DEFAULT_CAPTURE_DIR, DEFAULT_MEMORY_BUDGET};
+use crate::debugobjects::Colors;
+use crate::parser;
+use crate::proxy::Aggregate;
+
+#[derive(Error, Debug)]
+pub enum ConfigError
+{
+    #[error("IO error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid config {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid value for {0}")]
+    Invalid(String),
+}
+
+// Settings from --config, e.g.
+//
+// port = "/dev/ttyUSB0"
+// baud = 921600
+// locale = "de_DE"
+//
+// [[devices]]
+// name = "left"
+// port = "/dev/ttyUSB1"
+//
+// Options given on the command line take precedence.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config
+{
+    // Without port, replay or stdin the port picker is shown
+    pub port: Option<String>,
+    pub baud: u32,
+    pub replay: Option<String>,
+    pub speed: f64,
+    pub stdin: bool,
+    pub record: Option<String>,
+    // Minutes per recording file
+    pub rotate: u64,
+    // Republishes the input at a reduced rate to tcp:ADDRESS,
+    // file:PATH or serial:PORT
+    pub proxy: Option<String>,
+    // Data lines per second and object passed on
+    pub proxy_rate: f64,
+    // How the rows in between are combined
+    pub proxy_aggregate: Aggregate,
+    // Of a serial proxy port, the input's baud rate if unset
+    pub proxy_baud: Option<u32>,
+    pub locale: Option<String>,
+    // Font file for labels the built-in font can't show, e.g.
+    // font = "/usr/share/fonts/truetype/noto/NotoSansSC-Regular.ttf"
+    pub font: Option<String>,
+    // JSON-RPC control socket, off unless asked for, e.g.
+    // control = "unix:/run/user/1000/rusty-peanut.sock"
+    pub control: Option<String>,
+    pub reconnect_markers: bool,
+    pub locked: bool,
+    // Raise the priority of the serial reader
+    pub realtime: bool,
+    // Pin the serial reader to this core
+    pub ingest_core: Option<usize>,
+    // Lines buffered for the UI and what is lost once they are full
+    pub queue: usize,
+    pub drop_policy: DropPolicy,
+    // Serial line format like 8N1 or 7E1
+    pub format: CharacterFormat,
+    // none, hardware or software
+    pub flow_control: FlowControl,
+    // auto, lf, cr or crlf
+    pub line_ending: LineEnding,
+    // text or cobs
+    pub framing: Framing,
+    // off, optional or required trailing *XX checksums
+    pub checksum: ChecksumMode,
+    // Megabytes of sample buffers
+    pub memory_budget: Option<usize>,
+    // mqtt://host:port, needs the mqtt feature
+    pub mqtt: Option<String>,
+    pub topics: Vec<String>,
+    // ZeroMQ PUB endpoint like tcp://host:5556, needs the zmq feature
+    pub zmq: Option<String>,
+    // Topic prefixes, all messages if empty
+    pub zmq_topics: Vec<String>,
+    // Name of a Lab Streaming Layer stream, needs the lsl feature
+    pub lsl: Option<String>,
+    // Range of all its channels
+    pub lsl_min: f32,
+    pub lsl_max: f32,
+    // Address or name of a BLE peripheral with the Nordic UART
+    // Service, needs the ble feature. Classic RFCOMM devices are
+    // bound to /dev/rfcommN and opened as serial port.
+    pub ble: Option<String>,
+    // Restored on start and saved on exit
+    pub session: Option<String>,
+    // Line format of the input, native, teleplot, arduino, jsonl, nmea or mavlink
+    pub dialect: String,
+    // JSON statistics written on exit
+    pub summary: Option<String>,
+    // One capture to compare live data against, or two to compare
+    pub diff: Vec<String>,
+    // Object.Signal to compare
+    pub diff_signal: Option<String>,
+    // start, time or trigger:LEVEL
+    pub diff_align: String,
+    pub diff_tolerance: f32,
+    // Synthetic signals instead of a device
+    pub demo: bool,
+    // Lines per second of the demo
+    pub demo_rate: f64,
+    // Unix domain socket to listen on
+    pub unix: Option<String>,
+    // TCP address accepting length-prefixed JSON messages
+    pub push: Option<String>,
+    // UDP address receiving OSC packets, mapped by osc_signals
+    pub osc: Option<String>,
+    pub osc_signals: Vec<OscSignal>,
+    // Named pipe, reopened whenever its writer closes it
+    pub fifo: Option<String>,
+    // Raw SWO capture file or FIFO
+    pub swo: Option<String>,
+    // ITM stimulus port carrying the protocol
+    pub itm_port: u8,
+    pub devices: Vec<DeviceConfig>,
+    // Prefix for the object names of the main input, e.g. left
+    // turns `MyScope into `left.MyScope
+    pub namespace: Option<String>,
+    // Devices without a namespace use their name
+    pub namespace_devices: bool,
+    // SocketCAN interface, needs the can feature
+    pub can: Option<String>,
+    pub can_signals: Vec<CanSignal>,
+    // For the mavlink dialect, empty for a default selection
+    pub mavlink_signals: Vec<MavlinkSignal>,
+    // Serial port of a Modbus RTU bus, polled at the baud rate
+    pub modbus: Option<String>,
+    // Milliseconds between polling rounds
+    pub modbus_interval: u64,
+    pub modbus_registers: Vec<ModbusRegister>,
+    // Poll bridge_registers, needs the bridge feature
+    pub bridge: bool,
+    // Milliseconds between polling rounds
+    pub bridge_interval: u64,
+    pub bridge_registers: Vec<BridgeRegister>,
+    // Audio input device, needs the audio feature
+    pub audio: Option<String>,
+    // One or two channels to show, from 1, all if empty
+    pub audio_channels: Vec<usize>,
+    // Blocks reduce to their mean rather than their peak
+    pub audio_mean: bool,
+    // Lines per second after decimation
+    pub audio_rate: u32,
+    // Plot host metrics, needs the metrics feature
+    pub metrics: bool,
+    pub metrics_interval: u64,
+    // Raspberry Pi pins to sample, needs the gpio feature
+    pub gpio: Vec<u8>,
+    pub gpio_rate: f64,
+    // Follow the systemd journal, optionally of some units only
+    pub journal: bool,
+    pub journal_units: Vec<String>,
+    pub journal_match: Option<String>,
+    // Alarm rules over several signals
+    pub rules: Vec<RuleConfig>,
+    // Directory fired rules write their captures to
+    pub capture_dir: String,
+    // Global defaults of the color cascade
+    pub colors: ColorConfig,
+    // Run in order before any input arrives
+    pub startup: Vec<StartupStep>,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+	Config{
+	    port: None,
+	    baud: 230_400,
+	    replay: None,
+	    speed: 1.0,
+	    stdin: false,
+	    record: None,
+	    rotate: 10,
+	    proxy: None,
+	    proxy_rate: 10.0,
+	    proxy_aggregate: Aggregate::Mean,
+	    proxy_baud: None,
+	    locale: None,
+	    font: None,
+	    control: None,
+	    reconnect_markers: false,
+	    locked: false,
+	    realtime: false,
+	    ingest_core: None,
+	    queue: DEFAULT_QUEUE,
+	    drop_policy: DropPolicy::DropOldest,
+	    format: CharacterFormat::default(),
+	    flow_control: FlowControl::None,
+	    line_ending: LineEnding::Auto,
+	    framing: Framing::Text,
+	    checksum: ChecksumMode::Off,
+	    memory_budget: Some(DEFAULT_MEMORY_BUDGET / (1024 * 1024)),
+	    mqtt: None,
+	    topics: vec![],
+	    zmq: None,
+	    zmq_topics: vec![],
+	    lsl: None,
+	    lsl_min: -100.0,
+	    lsl_max: 100.0,
+	    ble: None,
+	    session: None,
+	    dialect: "native".to_string(),
+	    summary: None,
+	    diff: vec![],
+	    diff_signal: None,
+	    diff_align: "start".to_string(),
+	    diff_tolerance: 0.0,
+	    demo: false,
+	    demo_rate: 50.0,
+	    unix: None,
+	    push: None,
+	    osc: None,
+	    osc_signals: vec![],
+	    fifo: None,
+	    swo: None,
+	    itm_port: 0,
+	    devices: vec![],
+	    namespace: None,
+	    namespace_devices: false,
+	    can: None,
+	    can_signals: vec![],
+	    mavlink_signals: vec![],
+	    modbus: None,
+	    modbus_interval: 100,
+	    modbus_registers: vec![],
+	    bridge: false,
+	    bridge_interval: 100,
+	    bridge_registers: vec![],
+	    audio: None,
+	    audio_channels: vec![],
+	    audio_mean: false,
+	    audio_rate: 1000,
+	    metrics: false,
+	    metrics_interval: 1000,
+	    gpio: vec![],
+	    gpio_rate: 1000.0,
+	    journal: false,
+	    journal_units: vec![],
+	    journal_match: None,
+	    rules: vec![],
+	    capture_dir: DEFAULT_CAPTURE_DIR.to_string(),
+	    colors: ColorConfig::default(),
+	    startup: vec![],
+	}
+    }
+}
+
+// One of several serial inputs shown side by side
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct DeviceConfig
+{
+    pub name: String,
+    pub port: String,
+    // Defaults to the global baud rate
+    pub baud: Option<u32>,
+    // Defaults to the global dialect
+    pub dialect: Option<String>,
+    // Prefix for the object names of this device
+    pub namespace: Option<String>,
+}
+
+impl DeviceConfig
+{
+    // NAME=PATH as given to --device
+    fn from_arg(arg: &str) -> Result<DeviceConfig, ConfigError>
+    {
+	match arg.find('=') {
+	    Some(position) if position > 0 && position + 1 < arg.len() => {
+		Ok(DeviceConfig{ name: arg[..position].to_string(), port: arg[position + 1..].to_string(), baud: None, dialect: None, namespace: None })
+	    }
+	    _ => Err(ConfigError::Invalid(format!("device {}", arg))),
+	}
+    }
+}
+
+// A protocol line or a control request, for dashboards that look
+// the same every time, e.g.
+//
+// startup = [
+//     "`SCOPE Motor SIZE 400 200 SAMPLES 500",
+//     "`Motor 'Current' 0 5 100 0",
+//     { method = "load-reference", params = { spec = "golden.toml" } },
+// ]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum StartupStep
+{
+    Line(String),
+    Request{ method: String, #[serde(default)] params: serde_json::Value },
+}
+
+// Colors as in the protocol, e.g.
+//
+// [colors]
+// background = "WHITE"
+// grid = "GRAY 3"
+// signal = "#FF8800"
+//
+// Signals take a colorblind safe cycle unless signal is set, or
+// if it is "CYCLE".
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ColorConfig
+{
+    pub background: Option<String>,
+    pub grid: Option<String>,
+    pub signal: Option<String>,
+}
+
+impl ColorConfig
+{
+    // Unset colors keep the built-in defaults
+    pub fn resolve(&self) -> Result<Colors, ConfigError>
+    {
+	let color = |name: &Option<String>, default| {
+	    match name {
+		Some(name) => parser::color(name).map_err(|_| { ConfigError::Invalid(format!("color {}", name)) }),
+		None => Ok(default),
+	    }
+	};
+	let defaults = Colors::default();
+	let cycle = self.signal.as_deref().map_or(defaults.cycle, |name| { name == "CYCLE" });
+	Ok(Colors{
+	    background: color(&self.background, defaults.background)?,
+	    grid: color(&self.grid, defaults.grid)?,
+	    signal: if cycle { defaults.signal } else { color(&self.signal, defaults.signal)? },
+	    cycle,
+	})
+    }
+}
+
+fn parse<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str) -> Result<Option<T>, ConfigError>
+{
+    match matches.value_of(name) {
+	Some(value) => value.parse::<T>().map(Some).map_err(|_| { ConfigError::Invalid(name.to_string()) }),
+	None => Ok(None),
+    }
+}
+
+impl Config
+{
+    pub fn from_str(content: &str) -> Result<Config, ConfigError>
+    {
+	Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Config, ConfigError>
+    {
+	let mut config = match matches.value_of("config") {
+	    Some(path) => Config::from_str(&std::fs::read_to_string(path)?)?,
+	    None => Config::default(),
+	};
+	config.apply(matches)?;
+	config.colors.resolve()?;
+	Ok(config)
+    }
+
+    pub fn line_settings(&self) -> LineSettings
+    {
+	LineSettings{ format: self.format, flow: self.flow_control }
+    }
+
+    fn apply(&mut self, matches: &clap::ArgMatches) -> Result<(), ConfigError>
+    {
+	if let Some(port) = matches.value_of("port") {
+	    self.port = Some(port.to_string());
+	}
+	if let Some(baud) = parse(matches, "baud")? {
+	    self.baud = baud;
+	}
+	if let Some(replay) = matches.value_of("replay") {
+	    self.replay = Some(replay.to_string());
+	}
+	if let Some(speed) = parse(matches, "speed")? {
+	    self.speed = speed;
+	}
+	if matches.is_present("stdin") {
+	    self.stdin = true;
+	}
+	if let Some(record) = matches.value_of("record") {
+	    self.record = Some(record.to_string());
+	}
+	if let Some(rotate) = parse(matches, "rotate")? {
+	    self.rotate = rotate;
+	}
+	if let Some(target) = matches.value_of("proxy") {
+	    self.proxy = Some(target.to_string());
+	}
+	if let Some(rate) = parse(matches, "proxy-rate")? {
+	    self.proxy_rate = rate;
+	}
+	if let Some(aggregate) = parse(matches, "proxy-aggregate")? {
+	    self.proxy_aggregate = aggregate;
+	}
+	if let Some(baud) = parse(matches, "proxy-baud")? {
+	    self.proxy_baud = Some(baud);
+	}
+	if matches.is_present("realtime") {
+	    self.realtime = true;
+	}
+	if let Some(core) = parse(matches, "ingest-core")? {
+	    self.ingest_core = Some(core);
+	}
+	if let Some(queue) = parse(matches, "queue")? {
+	    self.queue = queue;
+	}
+	if let Some(policy) = parse(matches, "drop-policy")? {
+	    self.drop_policy = policy;
+	}
+	if let Some(format) = parse(matches, "format")? {
+	    self.format = format;
+	}
+	if let Some(flow) = parse(matches, "flow-control")? {
+	    self.flow_control = flow;
+	}
+	if let Some(ending) = parse(matches, "line-ending")? {
+	    self.line_ending = ending;
+	}
+	if let Some(framing) = parse(matches, "framing")? {
+	    self.framing = framing;
+	}
+	if let Some(mode) = parse(matches, "checksum")? {
+	    self.checksum = mode;
+	}
+	if let Some(budget) = parse(matches, "memory-budget")? {
+	    self.memory_budget = Some(budget);
+	}
+	if let Some(broker) = matches.value_of("mqtt") {
+	    self.mqtt = Some(broker.to_string());
+	}
+	if let Some(topics) = matches.values_of("topic") {
+	    self.topics = topics.map(|topic| { topic.to_string() }).collect();
+	}
+	if let Some(endpoint) = matches.value_of("zmq") {
+	    self.zmq = Some(endpoint.to_string());
+	}
+	if let Some(topics) = matches.values_of("zmq-topic") {
+	    self.zmq_topics = topics.map(|topic| { topic.to_string() }).collect();
+	}
+	if let Some(name) = matches.value_of("lsl") {
+	    self.lsl = Some(name.to_string());
+	}
+	if let Some(min) = parse(matches, "lsl-min")? {
+	    self.lsl_min = min;
+	}
+	if let Some(max) = parse(matches, "lsl-max")? {
+	    self.lsl_max = max;
+	}
+	if let Some(target) = matches.value_of("ble") {
+	    self.ble = Some(target.to_string());
+	}
+	if matches.is_present("control") {
+	    self.control = Some(matches.value_of("control").map(|address| { address.to_string() }).unwrap_or_else(crate::control::default_address));
+	}
+	if let Some(path) = matches.value_of("session") {
+	    self.session = Some(path.to_string());
+	}
+	if let Some(dialect) = matches.value_of("dialect") {
+	    self.dialect = dialect.to_string();
+	}
+	if let Some(path) = matches.value_of("summary") {
+	    self.summary = Some(path.to_string());
+	}
+	if let Some(path) = matches.value_of("capture-dir") {
+	    self.capture_dir = path.to_string();
+	}
+	if let Some(paths) = matches.values_of("diff") {
+	    self.diff = paths.map(|path| { path.to_string() }).collect();
+	}
+	if let Some(signal) = matches.value_of("diff-signal") {
+	    self.diff_signal = Some(signal.to_string());
+	}
+	if let Some(align) = matches.value_of("diff-align") {
+	    self.diff_align = align.to_string();
+	}
+	if let Some(tolerance) = parse(matches, "diff-tolerance")? {
+	    self.diff_tolerance = tolerance;
+	}
+	if matches.is_present("demo") {
+	    self.demo = true;
+	}
+	if let Some(rate) = parse(matches, "demo-rate")? {
+	    self.demo_rate = rate;
+	}
+	if let Some(path) = matches.value_of("unix") {
+	    self.unix = Some(path.to_string());
+	}
+	if let Some(address) = matches.value_of("push") {
+	    self.push = Some(address.to_string());
+	}
+	if let Some(address) = matches.value_of("osc") {
+	    self.osc = Some(address.to_string());
+	}
+	if let Some(path) = matches.value_of("fifo") {
+	    self.fifo = Some(path.to_string());
+	}
+	if let Some(swo) = matches.value_of("swo") {
+	    self.swo = Some(swo.to_string());
+	}
+	if let Some(port) = parse(matches, "itm-port")? {
+	    self.itm_port = port;
+	}
+	if let Some(interface) = matches.value_of("can") {
+	    self.can = Some(interface.to_string());
+	}
+	if let Some(device) = matches.value_of("audio") {
+	    self.audio = Some(device.to_string());
+	}
+	if let Some(rate) = parse(matches, "audio-rate")? {
+	    self.audio_rate = rate;
+	}
+	if let Some(channels) = matches.values_of("audio-channel") {
+	    self.audio_channels = channels
+		.map(|channel| { channel.parse::<usize>().ok().filter(|channel| { *channel >= 1 }).ok_or(ConfigError::Invalid(format!("audio channel {}", channel))) })
+		.collect::<Result<Vec<usize>, ConfigError>>()?;
+	}
+	if self.audio_channels.len() > 2 {
+	    return Err(ConfigError::Invalid("more than two audio channels".to_string()));
+	}
+	if matches.is_present("audio-mean") {
+	    self.audio_mean = true;
+	}
+	if let Some(pins) = matches.value_of("gpio") {
+	    self.gpio = crate::gpio::parse_pins(pins).ok_or(ConfigError::Invalid("gpio".to_string()))?;
+	}
+	if let Some(rate) = parse(matches, "gpio-rate")? {
+	    self.gpio_rate = rate;
+	}
+	if matches.is_present("journal") {
+	    self.journal = true;
+	}
+	if let Some(units) = matches.values_of("journal-unit") {
+	    self.journal_units = units.map(|unit| { unit.to_string() }).collect();
+	}
+	if let Some(pattern) = matches.value_of("journal-match") {
+	    self.journal_match = Some(pattern.to_string());
+	}
+	if matches.is_present("metrics") {
+	    self.metrics = true;
+	}
+	if let Some(interval) = parse(matches, "metrics-interval")? {
+	    self.metrics_interval = interval;
+	}
+	if matches.is_present("bridge") {
+	    self.bridge = true;
+	}
+	if let Some(interval) = parse(matches, "bridge-interval")? {
+	    self.bridge_interval = interval;
+	}
+	if let Some(port) = matches.value_of("modbus") {
+	    self.modbus = Some(port.to_string());
+	}
+	if let Some(interval) = parse(matches, "modbus-interval")? {
+	    self.modbus_interval = interval;
+	}
+	if let Some(devices) = matches.values_of("device") {
+	    self.devices = devices.map(DeviceConfig::from_arg).collect::<Result<Vec<_>, _>>()?;
+	}
+	if let Some(namespace) = matches.value_of("namespace") {
+	    self.namespace = Some(namespace.to_string());
+	}
+	if matches.is_present("namespace-devices") {
+	    self.namespace_devices = true;
+	}
+	Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn command_line_overrides_file() {
+	let mut config = Config::from_str("port = \"/dev/ttyUSB0\"\nbaud = 921600\nlocale = \"de_DE\"\n").unwrap();
+	assert_eq!(config.baud, 921_600);
+	assert_eq!(config.rotate, 10);
+	assert_eq!(Config::from_str("proxy_aggregate = \"max\"\n").unwrap().proxy_aggregate, Aggregate::Max);
+	let app = clap::App::new("test")
+	    .arg(clap::Arg::with_name("baud").long("baud").takes_value(true))
+	    .arg(clap::Arg::with_name("port").long("port").takes_value(true));
+	config.apply(&app.clone().get_matches_from(vec!["test", "--baud", "115200"])).unwrap();
+	assert_eq!(config.baud, 115_200);
+	assert_eq!(config.port, Some("/dev/ttyUSB0".to_string()));
+	assert!(config.apply(&app.get_matches_from(vec!["test", "--baud", "fast"])).is_err());
+    }
+
+    #[test]
+    fn devices_from_file_and_arguments() {
+	let config = Config::from_str("[[devices]]\nname = \"left\"\nport = \"/dev/ttyUSB1\"\nbaud = 115200\n").unwrap();
+	assert_eq!(config.devices, vec![DeviceConfig{ name: "left".to_string(), port: "/dev/ttyUSB1".to_string(), baud: Some(115_200), dialect: None, namespace: None }]);
+	assert_eq!(DeviceConfig::from_arg("right=/dev/ttyACM0").unwrap().port, "/dev/ttyACM0");
+	assert!(DeviceConfig::from_arg("/dev/ttyACM0").is_err());
+    }
+
+    #[test]
+    fn resolve_colors() {
+	let colors = ColorConfig{ background: Some("#102030".to_string()), grid: Some("RGB 1 2 3".to_string()), signal: None }.resolve().unwrap();
+	assert_eq!(colors.background, nannou::color::Rgb::new(16, 32, 48));
+	assert_eq!(colors.grid, nannou::color::Rgb::new(1, 2, 3));
+	assert!(colors.cycle);
+	let colors = ColorConfig{ signal: Some("CYAN".to_string()), ..Default::default() }.resolve().unwrap();
+	assert!(!colors.cycle);
+	assert!(ColorConfig{ signal: Some("CYCLE".to_string()), ..Default::default() }.resolve().unwrap().cycle);
+	assert!(ColorConfig{ background: Some("BLURPLE".to_string()), ..Default::default() }.resolve().is_err());
+    }
+
+    #[test]
+    fn startup_lines_and_requests() {
+	let config = Config::from_str("startup = [\"`SCOPE Motor\", { method = \"set-colors\", params = { background = \"WHITE\" } }, { method = \"summary\" }]\n").unwrap();
+	assert_eq!(config.startup, vec![
+	    StartupStep::Line("`SCOPE Motor".to_string()),
+	    StartupStep::Request{ method: "set-colors".to_string(), params: serde_json::json!({ "background": "WHITE" }) },
+	    StartupStep::Request{ method: "summary".to_string(), params: serde_json::Value::Null },
+	]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::thread;
+use crossbeam::channel::{Receiver, Sender, bounded, unbounded};
+use log::{debug, warn};
+use serde_json::{json, Value};
+
+// A JSON-RPC request forwarded to the UI thread, which
+// answers through reply.
+pub struct Request
+{
+    pub method: String,
+    pub params: Value,
+    pub reply: Sender<Result<Value, String>>,
+}
+
+pub struct ControlServer
+{
+    pub receiver: Receiver<Request>
+}
+
+fn respond(id: Value, result: Result<Value, String>) -> Value
+{
+    match result {
+	Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+	Err(message) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32000, "message": message } }),
+    }
+}
+
+fn handle_client<R: Read, W: Write>(reader: R, mut writer: W, sender: Sender<Request>)
+{
+    for line in BufReader::new(reader).lines() {
+	let line = match line {
+	    Ok(line) => line,
+	    Err(_) => { break; }
+	};
+	if line.trim().is_empty() {
+	    continue;
+	}
+	// Anything but a JSON object, like the request line of a
+	// browser's cross-origin POST, ends the connection
+	if !line.trim_start().starts_with('{') {
+	    warn!("control connection closed, not a request: {:?}", line);
+	    break;
+	}
+	debug!("control request: {}", line);
+	let response = match serde_json::from_str::<Value>(&line) {
+	    Ok(request) => {
+		let id = request.get("id").cloned().unwrap_or(Value::Null);
+		match request.get("method").and_then(|method| { method.as_str() }) {
+		    Some(method) => {
+			let (reply, answer) = bounded(1);
+			let params = request.get("params").cloned().unwrap_or(Value::Null);
+			if sender.send(Request{ method: method.to_string(), params, reply }).is_err() {
+			    break;
+			}
+			respond(id, answer.recv().unwrap_or(Err("no answer".to_string())))
+		    }
+		    None => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32600, "message": "Invalid Request" } }),
+		}
+	    }
+	    Err(_) => json!({ "jsonrpc": "2.0", "id": Value::Null, "error": { "code": -32700, "message": "Parse error" } }),
+	};
+	if writeln!(writer, "{}", response).is_err() {
+	    break;
+	}
+    }
+}
+
+// A unix socket only the user can reach, in XDG_RUNTIME_DIR
+// or else the temporary directory
+pub fn default_address() -> String
+{
+    let directory = std::env::var_os("XDG_RUNTIME_DIR").map(std::path::PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    format!("unix:{}", directory.join("rusty-peanut.sock").display())
+}
+
+impl ControlServer
+{
+    // Listens on a TCP address like 127.0.0.1:7878, or on
+    // a unix socket when given unix:/path/to/socket
+    pub fn new(address: &str) -> std::io::Result<ControlServer>
+    {
+	let (s, r) = unbounded();
+	if address.starts_with("unix:") {
+	    serve_unix(&address["unix:".len()..], s)?;
+	} else {
+	    let listener = std::net::TcpListener::bind(address)?;
+	    thread::spawn(move || {
+		for stream in listener.incoming() {
+		    match stream.and_then(|stream| { Ok((stream.try_clone()?, stream)) }) {
+			Ok((reader, writer)) => {
+			    let sender = s.clone();
+			    thread::spawn(move || { handle_client(reader, writer, sender); });
+			}
+			Err(error) => { warn!("control socket error: {:?}", error); }
+		    }
+		}
+	    });
+	}
+	Ok(ControlServer{ receiver: r })
+    }
+}
+
+#[cfg(unix)]
+fn serve_unix(path: &str, s: Sender<Request>) -> std::io::Result<()>
+{
+    let listener = bind_unix(std::path::Path::new(path))?;
+    thread::spawn(move || {
+	for stream in listener.incoming() {
+	    match stream.and_then(|stream| { Ok((stream.try_clone()?, stream)) }) {
+		Ok((reader, writer)) => {
+		    let sender = s.clone();
+		    thread::spawn(move || { handle_client(reader, writer, sender); });
+		}
+		Err(error) => { warn!("control socket error: {:?}", error); }
+	    }
+	}
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_path: &str, _s: Sender<Request>) -> std::io::Result<()>
+{
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "unix domain sockets are not available on this platform"))
+}
+
+// The socket is bound inside a directory only the user may enter
+// and moved into place once it is 0600, so it is never reachable
+// with looser permissions
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> std::io::Result<std::os::unix::net::UnixListener>
+{
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    // A stale socket from a previous run would make bind fail,
+    // anything else at path is left alone
+    match std::fs::symlink_metadata(path) {
+	Ok(metadata) if metadata.file_type().is_socket() => { std::fs::remove_file(path)?; }
+	Ok(_) => {
+	    return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+	}
+	Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+	Err(error) => { return Err(error); }
+    }
+    let parent = match path.parent() {
+	Some(parent) if !parent.as_os_str().is_empty() => parent,
+	_ => std::path::Path::new("."),
+    };
+    let private = parent.join(format!(".rusty-peanut-{}", std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("control.sock");
+    let bound = std::os::unix::net::UnixListener::bind(&staged).and_then(|listener| {
+	std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+	std::fs::rename(&staged, path)?;
+	Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    std::fs::remove_dir(&private)?;
+    bound
+}
+
+pub fn str_param<'a>(params: &'a Value, key: &str) -> Result<&'a str, String>
+{
+    params.get(key).and_then(|value| { value.as_str() }).ok_or(format!("missing string parameter {}", key))
+}
+
+pub fn f64_param(params: &Value, key: &str) -> Result<f64, String>
+{
+    params.get(key).and_then(|value| { value.as_f64() }).ok_or(format!("missing numeric parameter {}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn requests_are_forwarded_and_answered() {
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    for request in r.iter() {
+		let request: Request = request;
+		let result = match request.method.as_str() {
+		    "pause" => Ok(json!(true)),
+		    _ => Err("unknown method".to_string()),
+		};
+		request.reply.send(result).unwrap();
+	    }
+	});
+	let input = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"pause\"}\n\
+		     {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"explode\"}\n\
+		     {garbage\n";
+	let mut output = vec![];
+	handle_client(input.as_bytes(), &mut output, s);
+	let responses: Vec<Value> = String::from_utf8(output).unwrap().lines()
+	    .map(|line| { serde_json::from_str(line).unwrap() })
+	    .collect();
+	assert_eq!(responses[0]["result"], json!(true));
+	assert_eq!(responses[1]["error"]["message"], json!("unknown method"));
+	assert_eq!(responses[2]["error"]["code"], json!(-32700));
+    }
+
+    #[test]
+    fn browser_requests_are_rejected() {
+	let (s, r) = unbounded();
+	let input = "POST / HTTP/1.1\r\n\
+		     Content-Type: text/plain\r\n\
+		     \r\n\
+		     {\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"pause\"}\n";
+	let mut output = vec![];
+	handle_client(input.as_bytes(), &mut output, s);
+	assert!(output.is_empty());
+	assert!(r.try_recv().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_stale_sockets_are_replaced() {
+	use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+	let directory = std::env::temp_dir().join(format!("rusty-peanut-control-{}", std::process::id()));
+	std::fs::create_dir_all(&directory).unwrap();
+	let file = directory.join("notes.txt");
+	std::fs::write(&file, "keep me").unwrap();
+	assert!(bind_unix(&file).is_err());
+	assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep me");
+	let socket = directory.join("control.sock");
+	drop(bind_unix(&socket).unwrap());
+	// The socket left behind counts as stale
+	let _listener = bind_unix(&socket).unwrap();
+	let metadata = std::fs::symlink_metadata(&socket).unwrap();
+	assert!(metadata.file_type().is_socket());
+	assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+	std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,10 +1,25 @@
 use nannou::prelude::*;
-use std::collections::hash_map::HashMap;
 use std::vec::Vec;
-use std::collections::VecDeque;
-use log::{debug, warn};
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
 use thiserror::Error;
 use phf::phf_map;
+use serde::Serialize;
+
+use crate::alarms::{AlarmEvent, AlarmKind, AlarmLog};
+use crate::diagnostics::{DrawCost, WidgetStats};
+use crate::export::file_name;
+use crate::locale::NumberFormat;
+use crate::parser::{self, ast};
+use crate::parser::ast::Legend;
+use crate::raster::Canvas;
+use crate::svg::{Anchor, Svg};
+use crate::rules::Rule;
+use crate::sink::CsvSink;
+use crate::proxy::Proxy;
 
 type Rect = nannou::geom::rect::Rect;
 type Color = Rgb<u8>;
@@ -20,9 +35,160 @@ static COLOR_MAP: phf::Map<&'static str, Color> = phf_map! {
     "RED" => RED,
     "MAGENTA" => MAGENTA,
     "YELLOW" => YELLOW,
+    "GREY" => GREY,
+    "GRAY" => GRAY,
+    "SILVER" => SILVER,
+    "LIGHTGRAY" => LIGHTGRAY,
+    "DARKGRAY" => DARKGRAY,
+    "SLATEGRAY" => SLATEGRAY,
+    "NAVY" => NAVY,
+    "DARKBLUE" => DARKBLUE,
+    "ROYALBLUE" => ROYALBLUE,
+    "STEELBLUE" => STEELBLUE,
+    "DODGERBLUE" => DODGERBLUE,
+    "DEEPSKYBLUE" => DEEPSKYBLUE,
+    "SKYBLUE" => SKYBLUE,
+    "LIGHTBLUE" => LIGHTBLUE,
+    "TEAL" => TEAL,
+    "TURQUOISE" => TURQUOISE,
+    "AQUA" => AQUA,
+    "DARKGREEN" => DARKGREEN,
+    "FORESTGREEN" => FORESTGREEN,
+    "SEAGREEN" => SEAGREEN,
+    "LIMEGREEN" => LIMEGREEN,
+    "LIME" => LIME,
+    "SPRINGGREEN" => SPRINGGREEN,
+    "CHARTREUSE" => CHARTREUSE,
+    "OLIVE" => OLIVE,
+    "OLIVEDRAB" => OLIVEDRAB,
+    "KHAKI" => KHAKI,
+    "GOLD" => GOLD,
+    "DARKORANGE" => DARKORANGE,
+    "ORANGERED" => ORANGERED,
+    "CORAL" => CORAL,
+    "TOMATO" => TOMATO,
+    "SALMON" => SALMON,
+    "CRIMSON" => CRIMSON,
+    "FIREBRICK" => FIREBRICK,
+    "DARKRED" => DARKRED,
+    "MAROON" => MAROON,
+    "BROWN" => BROWN,
+    "CHOCOLATE" => CHOCOLATE,
+    "TAN" => TAN,
+    "BEIGE" => BEIGE,
+    "IVORY" => IVORY,
+    "PINK" => PINK,
+    "HOTPINK" => HOTPINK,
+    "DEEPPINK" => DEEPPINK,
+    "FUCHSIA" => FUCHSIA,
+    "ORCHID" => ORCHID,
+    "PLUM" => PLUM,
+    "VIOLET" => VIOLET,
+    "DARKVIOLET" => DARKVIOLET,
+    "PURPLE" => PURPLE,
+    "INDIGO" => INDIGO,
+    "LAVENDER" => LAVENDER,
 };
 
-struct Style
+// Okabe and Ito's set, told apart with any kind of color blindness.
+// Their black is left out, it vanishes on the default background.
+const COLORBLIND_CYCLE: [Color; 7] = [
+    Color{ red: 230, green: 159, blue: 0, standard: std::marker::PhantomData },
+    Color{ red: 86, green: 180, blue: 233, standard: std::marker::PhantomData },
+    Color{ red: 0, green: 158, blue: 115, standard: std::marker::PhantomData },
+    Color{ red: 240, green: 228, blue: 66, standard: std::marker::PhantomData },
+    Color{ red: 0, green: 114, blue: 178, standard: std::marker::PhantomData },
+    Color{ red: 213, green: 94, blue: 0, standard: std::marker::PhantomData },
+    Color{ red: 204, green: 121, blue: 167, standard: std::marker::PhantomData },
+];
+
+pub fn color_by_name(name: &str) -> Option<Color>
+{
+    COLOR_MAP.get::<str>(name).cloned()
+}
+
+// Colors cascade like a style sheet: the global defaults are
+// overridden per object by BACKGROUND, GRID and COLOR, and per
+// signal by its color token.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors
+{
+    pub background: Color,
+    pub grid: Color,
+    // Of signals without a color of their own
+    pub signal: Color,
+    // Such signals take the colorblind safe cycle by their position
+    // instead, unless the object sets COLOR
+    pub cycle: bool,
+}
+
+impl Default for Colors
+{
+    fn default() -> Self
+    {
+	Colors{ background: BLACK, grid: GREY, signal: YELLOW, cycle: true }
+    }
+}
+
+// What an object sets itself
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ColorOverrides
+{
+    background: Option<Color>,
+    grid: Option<Color>,
+    signal: Option<Color>,
+}
+
+impl ColorOverrides
+{
+    fn resolve(&self, defaults: &Colors) -> Colors
+    {
+	Colors{
+	    background: self.background.unwrap_or(defaults.background),
+	    grid: self.grid.unwrap_or(defaults.grid),
+	    signal: self.signal.unwrap_or(defaults.signal),
+	    cycle: defaults.cycle && self.signal.is_none(),
+	}
+    }
+
+    // Takes BACKGROUND, GRID or COLOR, false for other options
+    fn apply(&mut self, atom: &ast::DebugInstructionAtom) -> bool
+    {
+	match atom {
+	    ast::DebugInstructionAtom::Background(color) => { self.background = Some(*color); }
+	    ast::DebugInstructionAtom::Grid(color) => { self.grid = Some(*color); }
+	    ast::DebugInstructionAtom::Color(color) => { self.signal = Some(*color); }
+	    _ => { return false; }
+	}
+	true
+    }
+}
+
+// Colors handed out to the members of a signal group, in order
+const GROUP_PALETTE: [Color; 8] = [YELLOW, CYAN, MAGENTA, ORANGE, GREEN, RED, BLUE, WHITE];
+
+// Protocol features announced in reply to `CAPS, firmware falls
+// back to plain ASCII lines for those missing. Viewers predating
+// CAPS don't answer at all.
+pub const CAPABILITIES: &[&str] = &["PACKED", "SEQUENCE", "TIMESTAMP", "MATH", "EYE", "GROUP", "EVENT", "RULE", "SKIP"];
+
+// Keywords that aren't objects
+const COMMANDS: &[&str] = &["CLEAR", "GROUP", "EVENT", "RULE", "CAPS"];
+
+// The reply to `CAPS, e.g. `CAPS rusty-peanut 0.1.0 PACKED MATH
+pub fn capabilities_line() -> String
+{
+    format!("`CAPS rusty-peanut {} {}", env!("CARGO_PKG_VERSION"), CAPABILITIES.join(" "))
+}
+
+// How long a focused object stays outlined
+const HIGHLIGHT_TIME: Duration = Duration::from_secs(2);
+// Sample buffers allowed unless configured otherwise
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+// Where fired rules write their captures
+pub const DEFAULT_CAPTURE_DIR: &str = "captures";
+
+pub struct Style
 {
     font_size: u32,
     // the offset from the topleft corner we
@@ -30,6 +196,16 @@ struct Style
     signal_name_offset: Point2,
     // Padding between two subsequent signal names
     signal_name_padding: f32,
+    label_font_size: u32,
+    number_format: NumberFormat,
+    colors: Colors,
+    default_font: text::Font,
+    // For labels in scripts the default font lacks, like CJK
+    fallback_font: Option<text::Font>,
+    // Seconds since start at the frame being drawn, for staleness
+    now: Cell<f64>,
+    // Mouse position, for the full names of shortened labels
+    pointer: Cell<Option<Point2>>,
 }
 
 impl Style
@@ -40,8 +216,39 @@ impl Style
 	    font_size: 15,
 	    signal_name_offset: pt2(0.0, 6.0),
 	    signal_name_padding: 4.0,
+	    label_font_size: 10,
+	    number_format: NumberFormat::default(),
+	    colors: Colors::default(),
+	    default_font: text::font::default_notosans(),
+	    fallback_font: None,
+	    now: Cell::new(0.0),
+	    pointer: Cell::new(None),
 	}
     }
+
+    fn font_for(&self, label: &str) -> Option<&text::Font>
+    {
+	self.fallback_font.as_ref().filter(|_| { !covers(&self.default_font, label) })
+    }
+
+    // Lays out a label unwrapped, its bounding rect is the width
+    // the legend advances by
+    fn layout<'a>(&self, label: &'a str) -> text::Text<'a>
+    {
+	let builder = text(label).font_size(self.font_size);
+	let builder = match self.font_for(label) {
+	    Some(font) => builder.font(font.clone()),
+	    None => builder,
+	};
+	// the rectangle is for wrapping, so we make it really big to avoid that wrapping
+	builder.build(Rect::from_w_h(1000.0, 1000.0))
+    }
+}
+
+// Whether the font has a glyph for every character of text
+fn covers(font: &text::Font, text: &str) -> bool
+{
+    text.chars().filter(|c| { !c.is_whitespace() }).all(|c| { font.glyph(c).id().0 != 0 })
 }
 
 #[derive(Error, Debug)]
@@ -55,6 +262,20 @@ pub enum DebugObjectError
     IndexError,
     #[error("ParseNumberError")]
     ParseNumberError,
+    #[error("Unknown DebugObject {0}")]
+    UnknownObject(String),
+    #[error("Operation not supported by {0}")]
+    Unsupported(String),
+    #[error("Locked, refusing to {0}")]
+    Locked(String),
+    #[error("Unknown signal group {0}")]
+    UnknownGroup(String),
+    #[error("{name} needs {needed} bytes, only {available} of the memory budget left")]
+    OverBudget{ name: String, needed: usize, available: usize },
+    #[error("IO error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Syntax error, {0}")]
+    Syntax(#[from] parser::ParseError),
 }
 
 impl From<std::num::ParseFloatError> for DebugObjectError {
@@ -70,372 +291,3366 @@ impl From<std::num::ParseIntError> for DebugObjectError {
 }
 
 
-fn strip_single_quotes(input: &str) -> &str
-{
-    let v: Vec<&str> = input.split("'").collect();
-    unsafe {
-	v.get_unchecked(v.len() / 2)
-    }
-}
-
 pub struct DebugLine
 {
     pub keyword: String,
-    pub tokens: Vec<String>
+    pub tokens: Vec<String>,
+    // Everything after the keyword, for the parser
+    pub body: String,
+    // Seconds after start the line was received
+    pub received: f64,
 }
 
 impl DebugLine
 {
-    pub fn from_str(line: &str) -> std::result::Result<DebugLine, DebugObjectError>
+    pub fn from_str(line: &str, received: f64) -> std::result::Result<DebugLine, DebugObjectError>
     {
-	let tokens:Vec<String> = line.split_whitespace().map(|s| { s.to_string() }).filter(|part| { part.len() > 0 }).collect();
-	if tokens.len() > 0{
-	    let mut keyword = tokens[0].clone();
-	    if keyword.starts_with("`") {
-		keyword = keyword[1..].to_string();
-		return Ok(DebugLine{keyword: keyword, tokens: tokens[1..].to_vec()});
+	match parser::symbol(line) {
+	    Ok((keyword, body)) => {
+		let tokens = body.split_whitespace().map(|s| { s.to_string() }).collect();
+		Ok(DebugLine{ keyword, tokens, body: body.to_string(), received })
 	    }
+	    Err(_) => Err(DebugObjectError::InvalidFormat(line.to_string())),
 	}
-	Err(DebugObjectError::InvalidFormat(line.to_string()))
+    }
+}
+
+// Prefixes the object name of a protocol line, so that identically
+// named objects of several inputs stay apart: `SCOPE MyScope ...
+// becomes `SCOPE left.MyScope ... and `MyScope 1 2 `left.MyScope 1 2.
+// Commands not addressing an object pass unchanged.
+pub fn namespace_line(namespace: &str, line: &str) -> String
+{
+    // The body is kept as it is, quoted names may hold several spaces
+    let (keyword, body) = match parser::symbol(line) {
+	Ok(parsed) => parsed,
+	Err(_) => { return line.to_string(); }
+    };
+    match keyword.as_str() {
+	"SCOPE" | "EYE" => match body.strip_prefix('\'') {
+	    Some(quoted) => format!("`{} '{}.{}", keyword, namespace, quoted),
+	    None if !body.is_empty() => format!("`{} {}.{}", keyword, namespace, body),
+	    None => line.to_string(),
+	},
+	keyword if COMMANDS.contains(&keyword) => line.to_string(),
+	keyword if body.is_empty() => format!("`{}.{}", namespace, keyword),
+	keyword => format!("`{}.{} {}", namespace, keyword, body),
     }
 }
 
 pub trait DebugProcessor
 {
     fn name(&self) -> String;
-    fn draw(&self, draw: &nannou::draw::Draw);
-    fn feed(&mut self, tokens: Vec<String>);
+    fn draw(&self, draw: &nannou::draw::Draw, style: &Style);
+    // body follows the object name, timestamp is the host
+    // arrival time in seconds
+    fn feed(&mut self, body: &str, timestamp: f64);
+    // The area covered on screen
+    fn rect(&self) -> Rect;
+    // Alarms raised since the last call
+    fn drain_events(&mut self) -> Vec<AlarmEvent>
+    {
+	vec![]
+    }
+    fn write_csv(&self, _writer: &mut dyn std::io::Write, _format: &NumberFormat) -> Result<(), DebugObjectError>
+    {
+	Err(DebugObjectError::Unsupported(self.name()))
+    }
+    // Draws without the GPU, e.g. for headless exports
+    fn render(&self, _canvas: &mut Canvas) -> Result<(), DebugObjectError>
+    {
+	Err(DebugObjectError::Unsupported(self.name()))
+    }
+    // Vector graphics straight from the buffers, for publication
+    fn render_svg(&self, _svg: &mut Svg, _style: &Style) -> Result<(), DebugObjectError>
+    {
+	Err(DebugObjectError::Unsupported(self.name()))
+    }
+    fn signal_stats(&self) -> Vec<SignalStats>
+    {
+	vec![]
+    }
+    // now is the host time in seconds
+    fn signal_quality(&self, _now: f64) -> Vec<SignalQuality>
+    {
+	vec![]
+    }
+    // Drops all recorded samples, keeping the configuration
+    fn clear(&mut self);
+    // Marks a discontinuity in the input, e.g. after a reconnect
+    fn mark_gap(&mut self)
+    {
+    }
+    // Lines lost according to their sequence numbers
+    fn dropped(&self) -> u64
+    {
+	0
+    }
+    fn signal_names(&self) -> Vec<String>
+    {
+	vec![]
+    }
+    // Retained values of a signal, oldest first
+    fn signal_values(&self, _signal: &str) -> Option<Vec<f32>>
+    {
+	None
+    }
+    // The newest value of each signal, unclamped, for rules
+    fn latest_values(&self) -> Vec<(String, f32)>
+    {
+	vec![]
+    }
+    // The styling setters return false for unknown signals
+    fn set_signal_visible(&mut self, _signal: &str, _visible: bool) -> bool
+    {
+	false
+    }
+    fn set_signal_color(&mut self, _signal: &str, _color: Color) -> bool
+    {
+	false
+    }
+    // Resolves the colors against new global defaults
+    fn restyle(&mut self, _defaults: &Colors)
+    {
+    }
+    // Redraws of the samples per second, None for every frame
+    fn set_refresh(&mut self, _rate: Option<f64>)
+    {
+    }
+    // An external capture drawn behind the signal for comparison
+    fn set_reference(&mut self, _signal: &str, _values: Vec<f32>) -> bool
+    {
+	false
+    }
+    // What draw submits, for the diagnostics view
+    fn draw_cost(&self, _style: &Style) -> DrawCost
+    {
+	DrawCost::default()
+    }
+    // Bytes of sample buffers once they are full
+    fn memory_footprint(&self) -> usize
+    {
+	0
+    }
+    // Bytes feeding body would add, e.g. for declaring a signal
+    fn memory_for(&self, _body: &str) -> usize
+    {
+	0
+    }
+    // Reserves the sample buffers, once the budget allows them
+    fn preallocate(&mut self)
+    {
+    }
 }
 
-#[derive(Debug)]
-struct ScopeConfig
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SignalStats
 {
-    name: String,
-    pos: Point2,
-    size: Point2,
-    samples: usize,
-    rate: usize,
-    color: Color,
+    pub name: String,
+    pub count: usize,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub mean: Option<f32>,
 }
 
-impl ScopeConfig
+impl SignalStats
 {
-    fn from_tokens(tokens: &Vec<String>) -> Result<ScopeConfig, DebugObjectError>
+    fn from_values<'a, I>(name: &str, values: I) -> SignalStats
+    where I: Iterator<Item=&'a f32>
     {
-	let name = tokens.get(0).ok_or(DebugObjectError::NoNameGiven)?;
-	let pos = pt2(0.0, 0.0);
-	let mut size = pt2(255.0, 256.0);
-	let mut samples: usize = 256;
-	let rate: usize = 1;
-	let color = BLACK;
-	let mut index: usize = 1;
-	while index < tokens.len() {
-	    let command = tokens.get(index).ok_or(DebugObjectError::IndexError)?;
-	    debug!("ScopeConfig: attempting to decode {} at index {}", &command, index);
-	    if command == "SIZE" {
-		let width = tokens.get(index + 1).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-		let height = tokens.get(index + 2).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-		size = pt2(width, height);
-		debug!("decoded SIZE: {:?}", size);
-		index += 3
-	    } else if command == "SAMPLES" {
-		samples = tokens.get(index + 1).ok_or(DebugObjectError::IndexError)?.parse::<usize>()?;
-                index += 2;
-	    } else {
-		warn!("Not implemented");
-		break;
-	    }
+	let mut count = 0;
+	let mut min: Option<f32> = None;
+	let mut max: Option<f32> = None;
+	let mut sum = 0.0;
+	for value in values {
+	    count += 1;
+	    sum += *value as f64;
+	    min = Some(min.map_or(*value, |min| { min.min(*value) }));
+	    max = Some(max.map_or(*value, |max| { max.max(*value) }));
 	}
-	Ok(ScopeConfig{ name: strip_single_quotes(name).to_string(), pos, size, samples, rate, color })
+	let mean = if count > 0 { Some((sum / count as f64) as f32) } else { None };
+	SignalStats{ name: name.to_string(), count, min, max, mean }
     }
 }
 
-#[derive(Debug)]
-struct ScopeSignalConfig
+// A signal not updated for STALE_AFTER seconds is doubtful, for
+// DEAD_AFTER seconds bad
+const STALE_AFTER: f64 = 1.0;
+const DEAD_AFTER: f64 = 5.0;
+// Samples the dropout rate is averaged over
+const DROPOUT_WINDOW: f32 = 100.0;
+const HEALTH_DOT_RADIUS: f32 = 3.0;
+const UPDATE_MARKER_RADIUS: f32 = 2.0;
+// What a health dot adds to a legend entry
+const LEGEND_DOT_WIDTH: f32 = 2.0 + 2.0 * HEALTH_DOT_RADIUS;
+const ELLIPSIS: &str = "\u{2026}";
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health
 {
-    name: String,
-    min: f32,
-    max: f32,
-    y_size: f32,
-    y_base: f32,
-    color: Color,
+    Good,
+    Doubtful,
+    Bad,
 }
 
-impl ScopeSignalConfig
+impl Health
 {
-    fn from_tokens(tokens: &Vec<String>) -> Result<ScopeSignalConfig, DebugObjectError>
+    fn color(self) -> Color
     {
-	let name = tokens.get(0).ok_or(DebugObjectError::NoNameGiven)?;
-	let min = tokens.get(1).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-	let max = tokens.get(2).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-	let y_size = tokens.get(3).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-	let y_base = tokens.get(4).ok_or(DebugObjectError::IndexError)?.parse::<f32>()?;
-	let mut color = YELLOW;
-	if let Some(legend_or_color) = tokens.get(5)
-	{
-	    if legend_or_color.starts_with("%") {
-		if let Some(color_name) = tokens.get(6) {
-		    color = *COLOR_MAP.get::<str>(&color_name).unwrap_or(&YELLOW);
-		}
-	    }
+	match self {
+	    Health::Good => GREEN,
+	    Health::Doubtful => ORANGE,
+	    Health::Bad => RED,
 	}
-
-	Ok(ScopeSignalConfig{
-	    name: strip_single_quotes(name).to_string(),
-	    min,
-	    max,
-	    y_size,
-	    y_base,
-	    color,
-	})
     }
 }
 
-struct ScopeSignal
+// How far a signal can be trusted, shown as a dot after its name
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SignalQuality
 {
-    name: String,
-    min: f32,
-    max: f32,
-    y_size: f32,
-    y_base: f32,
-    //{legend
-    color: Color,
-    pub values: VecDeque<f32>,
+    pub name: String,
+    // Seconds since the signal received a value, None before the first
+    pub staleness: Option<f64>,
+    // Fraction of recent samples held or lost instead of received
+    pub dropout: f32,
+    pub variance: Option<f32>,
+    pub health: Health,
 }
 
-pub struct Scope
+impl SignalQuality
 {
-    name: String,
-    samples: usize, // Number of retained samples
-    rect: Rect,
-    background: Color,
-    grid: Color,
-    signals: Vec<ScopeSignal>
+    fn new(name: &str, staleness: Option<f64>, dropout: f32, variance: Option<f32>) -> SignalQuality
+    {
+	let health = match staleness {
+	    None => Health::Bad,
+	    Some(staleness) if staleness >= DEAD_AFTER || dropout > 0.5 || !variance.map_or(true, f32::is_finite) => Health::Bad,
+	    Some(staleness) if staleness >= STALE_AFTER || dropout > 0.1 => Health::Doubtful,
+	    Some(_) => Health::Good,
+	};
+	SignalQuality{ name: name.to_string(), staleness, dropout, variance, health }
+    }
 }
 
-impl Scope {
+// The area between two curves as one outline per segment between
+// bounds, as sample indices and y: along upper, back along lower
+fn area_outlines<U, L>(bounds: &[usize], upper: U, lower: L) -> Vec<Vec<(usize, f32)>>
+where U: Fn(usize) -> f32, L: Fn(usize) -> f32
+{
+    bounds.windows(2)
+	.filter(|segment| { segment[1] > segment[0] + 1 })
+	.map(|segment| {
+	    let range = segment[0]..segment[1];
+	    range.clone().map(|i| { (i, upper(i)) }).chain(range.rev().map(|i| { (i, lower(i)) })).collect()
+	})
+	.collect()
+}
 
-    pub fn new(tokens: &Vec<String>) -> Result<Scope, DebugObjectError>
-    {
-	let config = ScopeConfig::from_tokens(tokens)?;
+fn legend_width(labels: &[String], style: &Style) -> f32
+{
+    labels.iter().map(|label| { style.layout(label).bounding_rect().w() + LEGEND_DOT_WIDTH + style.signal_name_padding }).sum()
+}
 
-	let res = Scope{
-	    name: config.name,
-	    samples: config.samples,
-	    rect: Rect::from_x_y_w_h(config.pos.x, config.pos.y, config.size.x, config.size.y),
-	    background: BLACK,
-	    grid: GREY,
-	    signals: vec![],
-	};
-	Ok(res)
+// What all names start with, up to the last separator within, so
+// Motor.Left and Motor.Right share Motor. but Mode and Motor nothing
+fn common_prefix(names: &[&str]) -> String
+{
+    let (first, rest) = match names.split_first() {
+	Some((first, rest)) if !rest.is_empty() => (first, rest),
+	_ => { return String::new(); }
+    };
+    let mut len = rest.iter().fold(first.len(), |len, name| {
+	first.char_indices().zip(name.chars()).take_while(|((index, a), b)| { index < &len && a == b }).last().map_or(0, |((index, a), _)| { index + a.len_utf8() })
+    });
+    loop {
+	match first[..len].rfind(|c: char| { ".:/_- ".contains(c) }) {
+	    // A name is never elided completely
+	    Some(index) if names.iter().any(|name| { name.len() == index + 1 }) => { len = index; }
+	    Some(index) => { return first[..=index].to_string(); }
+	    None => { return String::new(); }
+	}
     }
+}
 
-    pub fn feed_floats(&mut self, values: Vec<f32>)
-    {
-	if values.len() != self.signals.len() {
-	    warn!("Scope<{}>::feed values and signals length differ", self.name);
+// Cuts label down to width, marking the cut with an ellipsis
+fn truncate_label(label: &str, width: f32, style: &Style) -> String
+{
+    if style.layout(label).bounding_rect().w() <= width {
+	return label.to_string();
+    }
+    let mut ends: Vec<usize> = label.char_indices().map(|(index, _)| { index }).skip(1).collect();
+    while let Some(end) = ends.pop() {
+	let truncated = format!("{}{}", &label[..end], ELLIPSIS);
+	if ends.is_empty() || style.layout(&truncated).bounding_rect().w() <= width {
+	    return truncated;
 	}
-	let samples = self.samples;
-	self.signals.iter_mut().zip(values)
-	    .for_each(|(signal, value)| {
-		signal.values.push_back(value.clamp(signal.min, signal.max));
-		while signal.values.len() >= samples {
-		    signal.values.pop_front();
-		}
-	    });
     }
+    label.to_string()
+}
 
-    pub fn setup_signal(&mut self, tokens: &Vec<String>) -> Result<(), DebugObjectError>
-    {
-	println!("setup_signal: {:?}", tokens);
-	let sc = ScopeSignalConfig::from_tokens(tokens)?;
-	println!("setup_signal: {:?}", sc);
-	self.signals.push(
-	    ScopeSignal
-	    {
-	       name: sc.name,
-	       min: sc.min,
-	       max: sc.max,
-	       y_size: sc.y_size,
-	       y_base: sc.y_base,
-	       color: sc.color,
-	       values: VecDeque::from(vec![0.0, 0.0])
-	    });
-	Ok(())
+// Sample-and-hold: each value is kept up to the x of the next one
+fn hold_steps(points: &[(f32, f32)]) -> Vec<(f32, f32)>
+{
+    let mut steps = Vec::with_capacity(points.len() * 2);
+    for (x, y) in points {
+	if let Some(&(_, previous)) = steps.last() {
+	    steps.push((*x, previous));
+	}
+	steps.push((*x, *y));
     }
+    steps
 }
 
-impl DebugProcessor for Scope {
-
-    fn name(&self) -> String {
-	self.name.clone()
+// REFRESH takes updates per second
+fn refresh_rate(rate: f64) -> Result<f64, DebugObjectError>
+{
+    if rate <= 0.0 {
+	return Err(DebugObjectError::InvalidFormat(format!("REFRESH {}", rate)));
     }
+    Ok(rate)
+}
 
-    fn draw(&self, draw: &nannou::draw::Draw)
-    {
-	let style = Style::new();
-
-	let xy = self.rect.xy();
-	let wh = self.rect.wh();
-
-	let draw = draw.y(-wh.y);
+fn gap_label(dropped: u64) -> String
+{
+    if dropped > 0 { format!("DROPPED {}", dropped) } else { "RECONNECT".to_string() }
+}
 
-	let mut cursor = pt2(0.0, wh.y) + style.signal_name_offset;
+fn fade(color: Color, background: Color, factor: f32) -> Color
+{
+    let mix = |c: u8, b: u8| { (b as f32 + (c as f32 - b as f32) * factor) as u8 };
+    Color::new(
+	mix(color.red, background.red),
+	mix(color.green, background.green),
+	mix(color.blue, background.blue))
+}
 
-	fn draw_signal_name(draw: &nannou::draw::Draw, signal: &ScopeSignal, cursor: Point2, style: &Style) -> Point2
-	{
-	    // the rectangle is for wrapping, so we make it really big to avoid that wrapping
-	    let text = text(&signal.name).font_size(style.font_size).build(Rect::from_w_h(1000.0, 1000.0));
-	    let bounding_rect = text.bounding_rect();
-	    draw.xy(cursor + bounding_rect.wh() / 2.0).path().fill().color(signal.color).events(text.path_events());
-	    cursor + pt2(bounding_rect.w() + style.signal_name_padding, 0.0)
-	}
+// Objects are drawn downwards from their position,
+// which is the top left corner.
+fn screen_rect(rect: &Rect) -> Rect
+{
+    let xy = rect.xy();
+    Rect::from_corners(xy - pt2(0.0, rect.h()), xy + pt2(rect.w(), 0.0))
+}
 
-	let step = wh.x / (self.samples as f32 - 1.0);
+// What an object draws from its samples, kept to be drawn again in
+// later frames. Areas go behind lines, dots and labels.
+#[derive(Default)]
+struct Shapes
+{
+    areas: Vec<(Vec<Point2>, Color)>,
+    lines: Vec<Vec<(Point2, Color)>>,
+    dots: Vec<(Point2, Color)>,
+    // Left justified, starting at the point
+    labels: Vec<(Point2, String, Color)>,
+}
 
-	draw.rect().xy(xy + wh / 2.0).wh(wh).color(self.background);
-	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(wh.x, 0.0));
-	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(0.0, wh.y));
-	draw.line().weight(1.0).color(self.grid).start(xy + pt2(wh.x, 0.0)).end(xy + wh);
-	draw.line().weight(1.0).color(self.grid).start(xy + pt2(0.0, wh.y)).end(xy + wh);
-	self.signals.iter().for_each(|signal| {
-	    // Lower/Upper Boundary
-	    for v in &[signal.min, signal.max] {
-		let v = map_range(*v, signal.min, signal.max, 0.0, -signal.y_size) + wh.y - signal.y_base;
-		draw.line().weight(1.0).color(self.grid).start(pt2(0.0, v)).end(pt2(wh.x, 0.0) + pt2(0.0, v));
-	    }
-	    cursor = draw_signal_name(&draw, signal, cursor, &style);
+impl Shapes
+{
+    fn line(&mut self, from: Point2, to: Point2, color: Color)
+    {
+	self.lines.push(vec![(from, color), (to, color)]);
+    }
 
-	    // Draw the actual waveform
-	    let vertices = signal.values.iter().enumerate()
-		.map(|(i, value)| {
-		    let v = map_range(*value, signal.min, signal.max, 0.0, signal.y_size) - signal.y_size - signal.y_base + wh.y;
-		    (pt2(i as f32 * step, v), signal.color)
-		});
-	    draw.polyline()
-		.weight(1.0)
-		.points_colored(vertices);
-	});
+    fn draw_areas(&self, draw: &nannou::draw::Draw)
+    {
+	for (points, color) in &self.areas {
+	    draw.polygon().color(*color).points(points.iter().cloned());
+	}
     }
 
-    fn feed(&mut self, tokens: Vec<String>)
+    fn draw(&self, draw: &nannou::draw::Draw, width: f32, style: &Style)
     {
-	let mut err = Ok(());
-	let mut floats = vec![];
-	for token in &tokens {
-	    let mut token = token.clone();
-	    if token.ends_with(",") {
-		token.remove(token.len() - 1);
-	    }
-	    match token.parse::<f32>() {
-		Ok(number) => {
-		    floats.push(number);
-		}
-		Err(e) => {
-		    err = Err(e);
-		    break;
-		}
-	    }
+	for points in &self.lines {
+	    draw.polyline().weight(1.0).points_colored(points.iter().cloned());
 	}
-	match err {
-	    Ok(_) => {
-		self.feed_floats(floats);
-	    }
-	    _ => {
-		if self.setup_signal(&tokens).is_err() {
-		    warn!("couldn't setup signal with {:?}", &tokens);
-		}
-	    }
+	for (xy, color) in &self.dots {
+	    draw.ellipse().xy(*xy).radius(UPDATE_MARKER_RADIUS).color(*color);
+	}
+	for (xy, text, color) in &self.labels {
+	    draw.text(text)
+		.x_y(xy.x + width / 2.0 + 2.0, xy.y)
+		.w_h(width, style.label_font_size as f32 + 2.0)
+		.font_size(style.label_font_size)
+		.left_justify()
+		.color(*color);
 	}
     }
 }
 
-pub enum DebugObject
+// Recomputes the shapes of an object only once its data changed,
+// and then at most rate times a second, as set with REFRESH. Objects
+// that are idle or slow cost nothing but submitting their cached
+// shapes, so a few busy ones don't slow down the whole dashboard.
+struct Throttle
 {
-    Scope(Scope)
+    // Per second, None for every frame with new data
+    rate: Option<f64>,
+    // Bumped by every sample and declaration fed
+    revision: u64,
+    // Host time, rect and revision they were computed for
+    cache: RefCell<Option<(f64, Rect, u64, Shapes)>>,
 }
 
-impl DebugProcessor for DebugObject
+impl Throttle
 {
-    fn name(&self) -> std::string::String {
-	match self {
-	    DebugObject::Scope(scope) => scope.name()
-	}
+    fn new(rate: Option<f64>) -> Throttle
+    {
+	Throttle{ rate, revision: 0, cache: RefCell::new(None) }
     }
 
-    fn draw(&self, draw: &nannou::draw::Draw)
+    fn shapes<F: FnOnce() -> Shapes>(&self, now: f64, rect: Rect, compute: F) -> Ref<Shapes>
     {
-	match self {
-	    DebugObject::Scope(scope) => { scope.draw(draw); }
+	let due = match &*self.cache.borrow() {
+	    // Moving and resizing don't wait for the next refresh
+	    Some((at, cached, revision, _)) => *cached != rect || (*revision != self.revision && match self.rate {
+		Some(rate) => now < *at || now - at >= 1.0 / rate,
+		None => true,
+	    }),
+	    None => true,
+	};
+	if due {
+	    self.cache.replace(Some((now, rect, self.revision, compute())));
 	}
+	Ref::map(self.cache.borrow(), |cache| { &cache.as_ref().unwrap().3 })
     }
 
-    fn feed(&mut self, tokens: Vec<String>)
+    // The data changed, the shapes are recomputed when due
+    fn touch(&mut self)
     {
-	match self {
-	    DebugObject::Scope(scope) => { scope.feed(tokens); }
-	}
+	self.revision += 1;
+    }
+
+    fn set_rate(&mut self, rate: Option<f64>)
+    {
+	self.rate = rate;
+	self.invalidate();
+    }
+
+    // For changes that shouldn't wait, like new colors
+    fn invalidate(&self)
+    {
+	self.cache.replace(None);
     }
 }
 
-pub struct DebugObjects
+#[derive(Debug, PartialEq)]
+struct TriggerConfig
 {
-    objects: HashMap<String, DebugObject>
+    channel: usize,
+    arm_level: f32,
+    trigger_level: f32,
+    offset: Option<usize>,
 }
 
-impl DebugObjects
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeBase
 {
-    pub fn new() -> DebugObjects
-    {
-	DebugObjects{objects: HashMap::new()}
-    }
+    // Samples are stamped with their arrival time
+    Host,
+    // Data lines carry a leading @ticks token
+    Device{ ticks_per_second: f64 },
 }
 
-impl DebugObjects
+// How signals missing from a line are filled in, so all signals
+// of a scope stay aligned on the same time axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resample
 {
-    pub fn feed(&mut self, line: &str)
-    {
-	if let Ok(line) = DebugLine::from_str(line) {
-	    match self.objects.get_mut(&line.keyword) {
-		Some(debug_object) => {
-		    debug!("found DebugObject `{}, feeding to it", debug_object.name());
-		    debug_object.feed(line.tokens);
-		}
+    // Repeat the last value
+    Hold,
+    // Hold at first, interpolated once the next value arrived
+    Linear,
+}
+
+// Binary samples of a scope, sent as `MyScope #0a00ff7f... with one
+// little endian word per signal and sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Packed
+{
+    bytes: usize,
+    signed: bool,
+}
+
+impl Packed
+{
+    // The rows of samples in the hex token
+    fn decode(&self, bytes: &[u8], signals: usize) -> Result<Vec<Vec<f32>>, DebugObjectError>
+    {
+	let row = self.bytes * signals.max(1);
+	if bytes.len() % row != 0 {
+	    return Err(DebugObjectError::InvalidFormat(format!("{} packed bytes for rows of {}", bytes.len(), row)));
+	}
+	let bits = self.bytes as u32 * 8;
+	Ok(bytes.chunks(row)
+	   .map(|row| {
+	       row.chunks(self.bytes).map(|word| {
+		   let raw = word.iter().rev().fold(0u64, |raw, byte| { raw << 8 | *byte as u64 });
+		   if self.signed {
+		       // Sign extend from the word size
+		       ((raw << (64 - bits)) as i64 >> (64 - bits)) as f32
+		   } else {
+		       raw as f32
+		   }
+	       }).collect()
+	   })
+	   .collect())
+    }
+}
+
+// Samples a compressed run takes on the time axis
+const BREAK_WIDTH: f32 = 2.0;
+
+#[derive(Debug)]
+struct ScopeConfig
+{
+    name: String,
+    pos: Point2,
+    size: Point2,
+    samples: usize,
+    rate: usize,
+    colors: ColorOverrides,
+    trigger: Option<TriggerConfig>,
+    // Number of triggered captures drawn on top of each other
+    overlay: usize,
+    timebase: TimeBase,
+    // Updates per second of the value readouts
+    text_rate: f64,
+    resample: Option<Resample>,
+    packed: Option<Packed>,
+    // Unchanged runs longer than this many samples are compressed
+    skip: Option<usize>,
+    // Redraws of the samples per second, every frame if None
+    refresh: Option<f64>,
+}
+
+impl ScopeConfig
+{
+    fn from_instruction(instruction: &ast::DebugInstruction) -> Result<ScopeConfig, DebugObjectError>
+    {
+	let (name, configurations) = match instruction {
+	    ast::DebugInstruction::SCOPE{ name, configurations } => (name, configurations),
+	    _ => { return Err(DebugObjectError::InvalidFormat(format!("{:?}", instruction))); }
+	};
+	let mut pos = pt2(0.0, 0.0);
+	let mut size = pt2(255.0, 256.0);
+	let mut samples: usize = 256;
+	let mut rate: usize = 1;
+	let mut colors = ColorOverrides::default();
+	let mut trigger = None;
+	let mut overlay: usize = 1;
+	let mut timebase = TimeBase::Host;
+	let mut text_rate = 10.0;
+	let mut resample = None;
+	let mut packed = None;
+	let mut skip = None;
+	let mut refresh = None;
+	for atom in configurations {
+	    debug!("ScopeConfig: applying {:?}", atom);
+	    match atom {
+		ast::DebugInstructionAtom::Size(width, height) => { size = pt2(*width as f32, *height as f32); }
+		ast::DebugInstructionAtom::Pos(x, y) => { pos = pt2(*x as f32, *y as f32); }
+		ast::DebugInstructionAtom::Samples(count) => {
+		    if *count < 1 {
+			return Err(DebugObjectError::InvalidFormat(format!("SAMPLES {}", count)));
+		    }
+		    samples = *count as usize;
+		}
+		ast::DebugInstructionAtom::Rate(count) => { rate = (*count as usize).max(1); }
+		ast::DebugInstructionAtom::Trigger{ channel, arm_level, trigger_level, offset } => {
+		    trigger = Some(TriggerConfig{
+			channel: *channel as usize,
+			arm_level: *arm_level as f32,
+			trigger_level: *trigger_level as f32,
+			offset: offset.map(|offset| { offset as usize }),
+		    });
+		}
+		ast::DebugInstructionAtom::Overlay(count) => { overlay = (*count as usize).max(1); }
+		ast::DebugInstructionAtom::TextRate(rate) => {
+		    if *rate <= 0.0 {
+			return Err(DebugObjectError::InvalidFormat(format!("TEXTRATE {}", rate)));
+		    }
+		    text_rate = *rate;
+		}
+		ast::DebugInstructionAtom::Refresh(rate) => { refresh = Some(refresh_rate(*rate)?); }
+		ast::DebugInstructionAtom::Resample{ linear } => {
+		    resample = Some(if *linear { Resample::Linear } else { Resample::Hold });
+		}
+		ast::DebugInstructionAtom::Packed{ bits, signed } => {
+		    if ![8, 16, 32].contains(bits) {
+			return Err(DebugObjectError::InvalidFormat(format!("PACKED {}", bits)));
+		    }
+		    packed = Some(Packed{ bytes: *bits as usize / 8, signed: *signed });
+		}
+		ast::DebugInstructionAtom::Skip(count) => { skip = Some((*count as usize).max(2)); }
+		ast::DebugInstructionAtom::Timebase{ ticks_per_second } => {
+		    timebase = match ticks_per_second {
+			Some(ticks_per_second) => TimeBase::Device{ ticks_per_second: *ticks_per_second },
+			None => TimeBase::Host,
+		    };
+		}
+		atom if colors.apply(atom) => {}
+		// TITLE and the sizes of dots, lines and text aren't drawn
+		_ => { debug!("ScopeConfig: ignoring {:?}", atom); }
+	    }
+	}
+	Ok(ScopeConfig{ name: name.clone(), pos, size, samples, rate, colors, trigger, overlay, timebase, text_rate, resample, packed, skip, refresh })
+    }
+}
+
+// A derived channel computed from the fed values, given after
+// legend and color: `ABS`, `RMS n` or `DB` (20*log10)
+#[derive(Debug, Clone, PartialEq)]
+enum SignalMath
+{
+    Abs,
+    Rms{ window: usize, squares: VecDeque<f64>, sum: f64 },
+    Db,
+}
+
+impl SignalMath
+{
+    fn from_math(math: &ast::Math) -> Result<SignalMath, DebugObjectError>
+    {
+	match math {
+	    ast::Math::Abs => Ok(SignalMath::Abs),
+	    ast::Math::Db => Ok(SignalMath::Db),
+	    ast::Math::Rms(window) => {
+		if *window == 0 {
+		    return Err(DebugObjectError::InvalidFormat("RMS 0".to_string()));
+		}
+		let window = *window as usize;
+		Ok(SignalMath::Rms{ window, squares: VecDeque::with_capacity(window), sum: 0.0 })
+	    }
+	}
+    }
+
+    fn apply(&mut self, value: f32) -> f32
+    {
+	match self {
+	    SignalMath::Abs => value.abs(),
+	    SignalMath::Rms{ window, squares, sum } => {
+		let square = value as f64 * value as f64;
+		squares.push_back(square);
+		*sum += square;
+		while squares.len() > *window {
+		    *sum -= squares.pop_front().unwrap();
+		}
+		(sum.max(0.0) / squares.len() as f64).sqrt() as f32
+	    }
+	    // Silence ends up at the bottom instead of -inf
+	    SignalMath::Db => 20.0 * value.abs().max(1e-12).log10(),
+	}
+    }
+
+    fn reset(&mut self)
+    {
+	if let SignalMath::Rms{ squares, sum, .. } = self {
+	    squares.clear();
+	    *sum = 0.0;
+	}
+    }
+}
+
+#[derive(Debug)]
+struct ScopeSignalConfig
+{
+    name: String,
+    min: f32,
+    max: f32,
+    y_size: f32,
+    y_base: f32,
+    legend: Legend,
+    // Inherited from the scope if not given
+    color: Option<Color>,
+    math: Option<SignalMath>,
+    hold: bool,
+    sigma: bool,
+}
+
+impl ScopeSignalConfig
+{
+    fn from_instruction(instruction: &ast::DebugInstruction) -> Result<ScopeSignalConfig, DebugObjectError>
+    {
+	match instruction {
+	    ast::DebugInstruction::SignalDefinition{ name, min, max, y_size, y_base, legend, color, math, hold, sigma } => {
+		let required = |value: &Option<f64>| { value.map(|value| { value as f32 }).ok_or(DebugObjectError::IndexError) };
+		Ok(ScopeSignalConfig{
+		    name: name.clone(),
+		    min: required(min)?,
+		    max: required(max)?,
+		    y_size: required(y_size)?,
+		    y_base: required(y_base)?,
+		    legend: legend.unwrap_or(Legend{ max: true, min: true, max_line: true, min_line: true }),
+		    color: *color,
+		    math: math.as_ref().map(SignalMath::from_math).transpose()?,
+		    hold: *hold,
+		    sigma: *sigma,
+		})
+	    }
+	    _ => Err(DebugObjectError::InvalidFormat(format!("{:?}", instruction))),
+	}
+    }
+}
+
+// A shaded area between two signals, e.g. commanded and measured
+#[derive(Debug, Clone, PartialEq)]
+struct Band
+{
+    first: String,
+    second: String,
+    // A faded color of the first signal if not given
+    color: Option<Color>,
+}
+
+// A discontinuity in the history of a scope
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Gap
+{
+    // Samples received since
+    since: usize,
+    // Lines lost according to the sequence numbers, 0 after a reconnect
+    dropped: u64,
+}
+
+struct ScopeSignal
+{
+    name: String,
+    min: f32,
+    max: f32,
+    y_size: f32,
+    y_base: f32,
+    legend: Legend,
+    color: Color,
+    // Set by the declaration or a group palette, overrides the scope
+    own_color: Option<Color>,
+    visible: bool,
+    // Resampled to the width of the scope
+    reference: Option<Vec<f32>>,
+    readout: Option<Readout>,
+    math: Option<SignalMath>,
+    pub values: VecDeque<f32>,
+    // Last value received, before math, repeated while resampling
+    last_input: f32,
+    // Samples filled in since the last value received
+    held: usize,
+    // Newest value after math, before clamping to the range
+    latest: Option<f32>,
+    // Host time the signal last received a value of its own
+    updated: Option<f64>,
+    // Moving average of samples held or lost instead of received
+    dropout: f32,
+    // For slow signals, drawn as steps with a marker per update
+    hold: bool,
+    // Row numbers of the retained samples that carried an update
+    update_rows: VecDeque<u64>,
+    // Standard deviations aligned with values, for signals declared
+    // with SIGMA. They take the column after the value.
+    sigmas: Option<VecDeque<f32>>,
+    last_sigma: f32,
+}
+
+// The numeric value shown next to a signal name. It only follows
+// changes beyond the deadband and at most text_rate times a second,
+// so flickering last digits stay readable.
+#[derive(Debug, Clone, PartialEq)]
+struct Readout
+{
+    precision: Option<usize>,
+    deadband: f32,
+    shown: Option<f32>,
+    // Time of the last update
+    updated: f64,
+}
+
+impl Readout
+{
+    // `MyScope READOUT 'A' precision [deadband]
+    fn new(precision: i64, deadband: Option<f64>) -> Readout
+    {
+	Readout{ precision: Some(precision as usize), deadband: deadband.unwrap_or(0.0).abs() as f32, shown: None, updated: 0.0 }
+    }
+
+    fn update(&mut self, value: f32, time: f64, text_rate: f64)
+    {
+	// Time going backwards means the history was restarted
+	let due = time < self.updated || time - self.updated >= 1.0 / text_rate;
+	let moved = self.shown.map(|shown| { (value - shown).abs() > self.deadband }).unwrap_or(true);
+	if due && moved {
+	    self.shown = Some(value);
+	    self.updated = time;
+	}
+    }
+}
+
+impl ScopeSignal
+{
+    // The name, followed by the readout if configured
+    fn label(&self, format: &NumberFormat) -> String
+    {
+	match &self.readout {
+	    Some(Readout{ shown: Some(value), precision, .. }) => format!("{} {}", self.name, format.format(*value, *precision)),
+	    _ => self.name.clone(),
+	}
+    }
+
+    fn quality(&self, now: f64) -> SignalQuality
+    {
+	let count = self.values.len();
+	let variance = if count > 0 {
+	    let mean = self.values.iter().sum::<f32>() / count as f32;
+	    Some(self.values.iter().map(|value| { (value - mean) * (value - mean) }).sum::<f32>() / count as f32)
+	} else {
+	    None
+	};
+	let staleness = self.updated.map(|updated| { (now - updated).max(0.0) });
+	SignalQuality::new(&self.name, staleness, self.dropout, variance)
+    }
+
+    // Vertical position of value, relative to the bottom of a scope of the given height
+    fn y(&self, value: f32, height: f32) -> f32
+    {
+	map_range(value, self.min, self.max, 0.0, self.y_size) - self.y_size - self.y_base + height
+    }
+}
+
+struct Trigger
+{
+    channel: usize,
+    arm_level: f32,
+    trigger_level: f32,
+    // Position of the trigger event within a capture
+    offset: usize,
+    armed: bool,
+    // Samples still to record after the trigger fired
+    remaining: Option<usize>,
+}
+
+impl Trigger
+{
+    fn from_config(config: &TriggerConfig, samples: usize) -> Trigger
+    {
+	Trigger{
+	    channel: config.channel,
+	    arm_level: config.arm_level,
+	    trigger_level: config.trigger_level,
+	    offset: config.offset.unwrap_or(samples / 2),
+	    armed: false,
+	    remaining: None,
+	}
+    }
+
+    // Returns true once a capture with post_trigger samples
+    // after the trigger event is complete.
+    fn process(&mut self, value: f32, post_trigger: usize) -> bool
+    {
+	if let Some(remaining) = self.remaining {
+	    if remaining > 1 {
+		self.remaining = Some(remaining - 1);
+		return false;
+	    }
+	    self.remaining = None;
+	    return true;
+	}
+	// An arm level above the trigger level means we trigger on the falling edge
+	let rising = self.arm_level <= self.trigger_level;
+	if !self.armed {
+	    self.armed = if rising { value <= self.arm_level } else { value >= self.arm_level };
+	    return false;
+	}
+	let fired = if rising { value >= self.trigger_level } else { value <= self.trigger_level };
+	if fired {
+	    self.armed = false;
+	    if post_trigger == 0 {
+		return true;
+	    }
+	    self.remaining = Some(post_trigger);
+	}
+	false
+    }
+}
+
+pub struct Scope
+{
+    name: String,
+    samples: usize, // Number of retained samples
+    rect: Rect,
+    colors: ColorOverrides,
+    background: Color,
+    grid: Color,
+    // Of signals without a color of their own
+    signal_color: Color,
+    // Whether they take the colorblind cycle instead
+    cycle: bool,
+    signals: Vec<ScopeSignal>,
+    trigger: Option<Trigger>,
+    overlay: usize,
+    // The last overlay captures, oldest first. Each
+    // capture holds the values of all signals.
+    captures: VecDeque<Vec<Vec<f32>>>,
+    events: Vec<AlarmEvent>,
+    timebase: TimeBase,
+    text_rate: f64,
+    // Partial lines and single signal updates need it
+    resample: Option<Resample>,
+    packed: Option<Packed>,
+    skip: Option<usize>,
+    // Set while a device time base lacks timestamps and host time is used instead
+    fallback: bool,
+    // Sample times in seconds, aligned with the newest signal values
+    times: VecDeque<f64>,
+    gaps: Vec<Gap>,
+    // Expected sequence number of the next line, once lines carry them
+    next_sequence: Option<u64>,
+    dropped: u64,
+    // Layout problems of the signals, shown inside the scope
+    warnings: Vec<String>,
+    // Host time of the line being fed
+    received: f64,
+    // Rows pushed so far, numbering the samples of all signals
+    rows: u64,
+    bands: Vec<Band>,
+    throttle: Throttle,
+}
+
+impl Scope {
+
+    // Takes what follows `SCOPE
+    pub fn new(body: &str) -> Result<Scope, DebugObjectError>
+    {
+	let config = ScopeConfig::from_instruction(&parser::declaration(&format!("`SCOPE {}", body))?)?;
+	let colors = config.colors.resolve(&Colors::default());
+
+	let res = Scope{
+	    name: config.name,
+	    samples: config.samples,
+	    rect: Rect::from_x_y_w_h(config.pos.x, config.pos.y, config.size.x, config.size.y),
+	    colors: config.colors,
+	    background: colors.background,
+	    grid: colors.grid,
+	    signal_color: colors.signal,
+	    cycle: colors.cycle,
+	    signals: vec![],
+	    trigger: config.trigger.as_ref().map(|trigger| { Trigger::from_config(trigger, config.samples) }),
+	    overlay: config.overlay,
+	    text_rate: config.text_rate,
+	    resample: config.resample,
+	    packed: config.packed,
+	    skip: config.skip,
+	    captures: VecDeque::with_capacity(config.overlay + 1),
+	    events: vec![],
+	    timebase: config.timebase,
+	    fallback: false,
+	    // Reserved by preallocate after the budget check
+	    times: VecDeque::new(),
+	    gaps: vec![],
+	    next_sequence: None,
+	    dropped: 0,
+	    warnings: vec![],
+	    received: 0.0,
+	    rows: 0,
+	    bands: vec![],
+	    throttle: Throttle::new(config.refresh),
+	};
+	Ok(res)
+    }
+
+    // Saturating, so an absurd SAMPLES is refused rather than overflowing
+    fn signal_bytes(&self) -> usize
+    {
+	self.samples.saturating_mul(1 + self.overlay).saturating_mul(std::mem::size_of::<f32>())
+    }
+
+    // Buffers the options of a signal add to its values
+    fn option_bytes(&self, sigma: bool, hold: bool, math: Option<&SignalMath>) -> usize
+    {
+	let mut bytes: usize = 0;
+	if sigma {
+	    bytes = bytes.saturating_add(self.samples.saturating_mul(std::mem::size_of::<f32>()));
+	}
+	// At most one update row per retained sample
+	if hold {
+	    bytes = bytes.saturating_add(self.samples.saturating_mul(std::mem::size_of::<u64>()));
+	}
+	if let Some(SignalMath::Rms{ window, .. }) = math {
+	    bytes = bytes.saturating_add(window.saturating_mul(std::mem::size_of::<f64>()));
+	}
+	bytes
+    }
+
+    fn clear_history(&mut self)
+    {
+	for signal in self.signals.iter_mut() {
+	    signal.values.clear();
+	    signal.held = 0;
+	    signal.dropout = 0.0;
+	    signal.update_rows.clear();
+	    if let Some(sigmas) = &mut signal.sigmas {
+		sigmas.clear();
+	    }
+	    if let Some(math) = &mut signal.math {
+		math.reset();
+	    }
+	}
+	self.times.clear();
+	self.captures.clear();
+	self.throttle.invalidate();
+	self.gaps.clear();
+    }
+
+    // Index of the first sample after each gap in a history of len
+    // samples, along with the lines lost there
+    fn gap_markers(&self, len: usize) -> Vec<(usize, u64)>
+    {
+	self.gaps.iter().rev().filter_map(|gap| { len.checked_sub(gap.since).map(|position| { (position, gap.dropped) }) }).collect()
+    }
+
+    fn gap_positions(&self, len: usize) -> Vec<usize>
+    {
+	self.gap_markers(len).into_iter().map(|(position, _)| { position }).collect()
+    }
+
+    // The x of each sample in sample widths, and where breaks are
+    // drawn. With SKIP the middle of long runs without any signal
+    // changing collapses into a break BREAK_WIDTH samples wide.
+    fn x_mapping(&self, len: usize) -> (Vec<f32>, Vec<f32>)
+    {
+	let mut breaks = vec![];
+	let skip = match self.skip {
+	    Some(skip) if self.trigger.is_none() => skip,
+	    _ => { return ((0..len).map(|i| { i as f32 }).collect(), breaks); }
+	};
+	let unchanged = |i: usize| { self.signals.iter().all(|signal| { signal.values.get(i) == signal.values.get(i - 1) }) };
+	let keep = skip / 2;
+	let mut xs = Vec::with_capacity(len);
+	let mut x = 0.0;
+	let mut i = 0;
+	while i < len {
+	    if i == 0 || !unchanged(i) {
+		x += if i == 0 { 0.0 } else { 1.0 };
+		xs.push(x);
+		i += 1;
+		continue;
+	    }
+	    let mut end = i;
+	    while end < len && unchanged(end) {
+		end += 1;
+	    }
+	    for j in i..end {
+		if end - i <= skip || j < i + keep || j >= end - keep {
+		    x += 1.0;
+		} else if j == i + keep {
+		    breaks.push(x + BREAK_WIDTH / 2.0);
+		    x += BREAK_WIDTH;
+		}
+		xs.push(x);
+	    }
+	    i = end;
+	}
+	(xs, breaks)
+    }
+
+    fn add_gap(&mut self, dropped: u64)
+    {
+	match self.gaps.iter_mut().find(|gap| { gap.since == 0 }) {
+	    Some(gap) => { gap.dropped += dropped; }
+	    None => { self.gaps.push(Gap{ since: 0, dropped }); }
+	}
+    }
+
+    // Lines missing in between break the trace. A counter going
+    // backwards restarted or wrapped, which isn't counted as loss.
+    fn check_sequence(&mut self, sequence: u64)
+    {
+	if let Some(expected) = self.next_sequence {
+	    if sequence > expected {
+		let dropped = sequence - expected;
+		warn!("Scope<{}> lost {} lines before sequence number {}", self.name, dropped, sequence);
+		self.dropped += dropped;
+		self.add_gap(dropped);
+		// Each lost line counts as a missed sample of every signal
+		let kept = (1.0 - 1.0 / DROPOUT_WINDOW).powi(dropped.min(i32::MAX as u64) as i32);
+		for signal in self.signals.iter_mut() {
+		    signal.dropout = 1.0 - (1.0 - signal.dropout) * kept;
+		}
+	    }
+	}
+	self.next_sequence = Some(sequence.wrapping_add(1));
+    }
+
+    // Picks the time of a sample according to the time base. The two
+    // sources are never mixed within the history, switching clears it.
+    fn sample_time(&mut self, device_ticks: Option<f64>, host_time: f64) -> f64
+    {
+	match (self.timebase, device_ticks) {
+	    (TimeBase::Device{ ticks_per_second }, Some(ticks)) => {
+		if self.fallback {
+		    warn!("Scope<{}> receives device timestamps again, clearing history", self.name);
+		    self.fallback = false;
+		    self.clear_history();
+		}
+		ticks / ticks_per_second
+	    }
+	    (TimeBase::Device{ .. }, None) => {
+		if !self.fallback {
+		    warn!("Scope<{}> lacks device timestamps, falling back to host time", self.name);
+		    self.fallback = true;
+		    self.clear_history();
+		}
+		host_time
+	    }
+	    (TimeBase::Host, _) => host_time,
+	}
+    }
+
+    fn time_source(&self) -> &'static str
+    {
+	match (self.timebase, self.fallback) {
+	    (TimeBase::Host, _) => "HOST",
+	    (TimeBase::Device{ .. }, false) => "DEVICE",
+	    (TimeBase::Device{ .. }, true) => "HOST (no device time)",
+	}
+    }
+
+    // Columns of a data line, one per signal and one more per SIGMA
+    fn columns(&self) -> usize
+    {
+	self.signals.iter().map(|signal| { if signal.sigmas.is_some() { 2 } else { 1 } }).sum()
+    }
+
+    // Keeps the standard deviations out of columns for the next
+    // row, leaving one value per signal
+    fn take_sigmas(&mut self, columns: Vec<f32>) -> Vec<f32>
+    {
+	let mut columns = columns.into_iter();
+	let mut values = vec![];
+	for signal in self.signals.iter_mut() {
+	    match columns.next() {
+		Some(value) => { values.push(value); }
+		None => { break; }
+	    }
+	    if signal.sigmas.is_some() {
+		if let Some(sigma) = columns.next() {
+		    signal.last_sigma = sigma.abs();
+		}
+	    }
+	}
+	values
+    }
+
+    pub fn feed_floats(&mut self, values: Vec<f32>, time: f64)
+    {
+	self.throttle.touch();
+	let values = self.take_sigmas(values);
+	let fresh: Vec<usize> = (0..values.len()).collect();
+	self.push_row(values, time);
+	self.note_quality(&fresh);
+    }
+
+    // Signals listed in fresh received a value with the newest
+    // sample, the others were held
+    fn note_quality(&mut self, fresh: &[usize])
+    {
+	let (received, rows) = (self.received, self.rows);
+	for (index, signal) in self.signals.iter_mut().enumerate() {
+	    let missed = !fresh.contains(&index);
+	    signal.dropout += (if missed { 1.0 } else { 0.0 } - signal.dropout) / DROPOUT_WINDOW;
+	    if !missed {
+		signal.updated = Some(received);
+		if signal.hold {
+		    signal.update_rows.push_back(rows);
+		}
+	    }
+	    while signal.update_rows.front().map_or(false, |row| { rows - row >= signal.values.len() as u64 }) {
+		signal.update_rows.pop_front();
+	    }
+	}
+    }
+
+    // What the scope draws from its samples: bands, uncertainty,
+    // traces, update markers, breaks and gaps
+    fn shapes(&self, wh: Point2) -> Shapes
+    {
+	fn trace<'a, I, X>(signal: &ScopeSignal, values: I, x: X, color: Color, height: f32) -> Vec<(Point2, Color)>
+	where I: Iterator<Item=&'a f32>, X: Fn(usize) -> f32
+	{
+	    let points: Vec<(f32, f32)> = values.enumerate()
+		.map(|(i, value)| {
+		    (x(i), signal.y(*value, height))
+		})
+		.collect();
+	    let points = if signal.hold { hold_steps(&points) } else { points };
+	    points.into_iter().map(|(x, y)| { (pt2(x, y), color) }).collect()
+	}
+
+	let mut shapes = Shapes::default();
+	let len = self.signals.iter().map(|signal| { signal.values.len() }).max().unwrap_or(0);
+	let (xs, breaks) = self.x_mapping(len);
+	// Compressed runs leave room for more history
+	let removed = len.saturating_sub(1) as f32 - xs.last().cloned().unwrap_or(0.0);
+	let step = wh.x / (self.samples as f32 - 1.0 - removed).max(1.0);
+	let x_of = |i: usize| { xs.get(i).cloned().unwrap_or(i as f32 - removed) * step };
+
+	for band in &self.bands {
+	    if let Some((color, outlines)) = self.band_outlines(band, wh.y) {
+		for outline in outlines {
+		    shapes.areas.push((outline.into_iter().map(|(i, y)| { pt2(x_of(i), y) }).collect(), color));
+		}
+	    }
+	}
+	for (index, signal) in self.signals.iter().enumerate().filter(|(_, signal)| { signal.visible }) {
+	    for outline in self.sigma_outlines(signal, wh.y) {
+		shapes.areas.push((outline.into_iter().map(|(i, y)| { pt2(x_of(i), y) }).collect(), fade(signal.color, self.background, 0.4)));
+	    }
+
+	    if let Some(reference) = &signal.reference {
+		let step = wh.x / (reference.len().max(2) as f32 - 1.0);
+		shapes.lines.push(trace(signal, reference.iter(), |i| { i as f32 * step }, fade(signal.color, self.background, 0.4), wh.y));
+	    }
+
+	    // In triggered mode we show the accumulated captures,
+	    // older ones faded out.
+	    match &self.trigger {
+		Some(_) => {
+		    let count = self.captures.len();
+		    for (age, capture) in self.captures.iter().enumerate() {
+			if let Some(values) = capture.get(index) {
+			    let color = fade(signal.color, self.background, (age + 1) as f32 / count as f32);
+			    shapes.lines.push(trace(signal, values.iter(), |i| { i as f32 * step }, color, wh.y));
+			}
+		    }
+		}
+		None => {
+		    // Samples on both sides of a gap aren't connected
+		    let values: Vec<f32> = signal.values.iter().cloned().collect();
+		    let mut bounds = vec![0];
+		    bounds.extend(self.gap_positions(values.len()));
+		    bounds.push(values.len());
+		    for segment in bounds.windows(2) {
+			shapes.lines.push(trace(signal, values[segment[0]..segment[1]].iter(), |i| { x_of(i + segment[0]) }, signal.color, wh.y));
+		    }
+		    if signal.hold {
+			for position in self.update_positions(signal) {
+			    shapes.dots.push((pt2(x_of(position), signal.y(values[position], wh.y)), signal.color));
+			}
+		    }
+		}
+	    }
+	}
+
+	// Break markers at the bottom and top edge
+	for x in breaks {
+	    let x = x * step;
+	    for y in &[0.0, wh.y - 6.0] {
+		shapes.line(pt2(x - 4.0, *y), pt2(x - 1.0, y + 6.0), self.grid);
+		shapes.line(pt2(x + 1.0, *y), pt2(x + 4.0, y + 6.0), self.grid);
+	    }
+	}
+
+	if self.trigger.is_none() {
+	    for (position, dropped) in self.gap_markers(len) {
+		let x = x_of(position);
+		shapes.line(pt2(x, 0.0), pt2(x, wh.y), ORANGE);
+		shapes.labels.push((pt2(x, wh.y / 2.0), gap_label(dropped), ORANGE));
+	    }
+	}
+	shapes
+    }
+
+    // The color of a band, and the outline of each of its segments
+    // as sample indices and y: along the first signal, back along the
+    // second. Like the traces, bands break at gaps.
+    fn band_outlines(&self, band: &Band, height: f32) -> Option<(Color, Vec<Vec<(usize, f32)>>)>
+    {
+	let position = |name: &str| { self.signals.iter().position(|signal| { signal.name == name && signal.visible }) };
+	let (first, second) = (position(&band.first)?, position(&band.second)?);
+	let values = |index: usize| -> Vec<f32> {
+	    match &self.trigger {
+		Some(_) => self.captures.back().and_then(|capture| { capture.get(index) }).cloned().unwrap_or_default(),
+		None => self.signals[index].values.iter().cloned().collect(),
+	    }
+	};
+	let (upper, lower) = (values(first), values(second));
+	let bounds = self.segment_bounds(upper.len().min(lower.len()));
+	let outlines = area_outlines(&bounds, |i| { self.signals[first].y(upper[i], height) }, |i| { self.signals[second].y(lower[i], height) });
+	let color = band.color.unwrap_or_else(|| { fade(self.signals[first].color, self.background, 0.4) });
+	Some((color, outlines))
+    }
+
+    // The value plus and minus its standard deviation, within the
+    // range of the signal. Only drawn for the rolling history.
+    fn sigma_outlines(&self, signal: &ScopeSignal, height: f32) -> Vec<Vec<(usize, f32)>>
+    {
+	let sigmas = match &signal.sigmas {
+	    Some(sigmas) if self.trigger.is_none() => sigmas,
+	    _ => { return vec![]; }
+	};
+	let bounds = self.segment_bounds(signal.values.len().min(sigmas.len()));
+	let y = |i: usize, sign: f32| { signal.y((signal.values[i] + sign * sigmas[i]).clamp(signal.min, signal.max), height) };
+	area_outlines(&bounds, |i| { y(i, 1.0) }, |i| { y(i, -1.0) })
+    }
+
+    // Where traces of len samples start and end, they break at gaps
+    fn segment_bounds(&self, len: usize) -> Vec<usize>
+    {
+	let mut bounds = vec![0];
+	if self.trigger.is_none() {
+	    bounds.extend(self.gap_positions(len));
+	}
+	bounds.push(len);
+	bounds
+    }
+
+    // The legend entries of all signals, shortened until they fit the
+    // width: first the prefix all names share is elided, then each
+    // entry is cut down to an equal share
+    fn legend_labels(&self, style: &Style) -> Vec<String>
+    {
+	let labels: Vec<String> = self.signals.iter().map(|signal| { signal.label(&style.number_format) }).collect();
+	let available = self.rect.w() - style.signal_name_offset.x;
+	if legend_width(&labels, style) <= available {
+	    return labels;
+	}
+	let names: Vec<&str> = self.signals.iter().map(|signal| { signal.name.as_str() }).collect();
+	let prefix = common_prefix(&names);
+	let labels: Vec<String> = labels.iter().map(|label| { format!("{}{}", if prefix.is_empty() { "" } else { ELLIPSIS }, &label[prefix.len()..]) }).collect();
+	if legend_width(&labels, style) <= available {
+	    return labels;
+	}
+	let share = available / labels.len() as f32 - LEGEND_DOT_WIDTH - style.signal_name_padding;
+	labels.iter().map(|label| { truncate_label(label, share, style) }).collect()
+    }
+
+    // Index of each retained sample of signal that carried an update
+    fn update_positions(&self, signal: &ScopeSignal) -> Vec<usize>
+    {
+	let len = signal.values.len() as u64;
+	signal.update_rows.iter().filter_map(|row| { (len + row).checked_sub(self.rows + 1).map(|position| { position as usize }) }).collect()
+    }
+
+    fn push_row(&mut self, values: Vec<f32>, time: f64)
+    {
+	if values.len() != self.signals.len() {
+	    warn!("Scope<{}>::feed values and signals length differ", self.name);
+	}
+	let samples = self.samples;
+	self.rows += 1;
+	self.times.push_back(time);
+	while self.times.len() >= samples {
+	    self.times.pop_front();
+	}
+	for gap in self.gaps.iter_mut() {
+	    gap.since += 1;
+	}
+	// Forget gaps that scrolled out of the history
+	let retained = self.times.len();
+	self.gaps.retain(|gap| { gap.since < retained });
+	let values: Vec<f32> = self.signals.iter_mut().zip(values)
+	    .map(|(signal, value)| {
+		match &mut signal.math {
+		    Some(math) => math.apply(value),
+		    None => value,
+		}
+	    })
+	    .collect();
+	let trigger_value = self.trigger.as_ref().and_then(|trigger| { values.get(trigger.channel).cloned() });
+	let text_rate = self.text_rate;
+	self.signals.iter_mut().zip(values)
+	    .for_each(|(signal, value)| {
+		if let Some(readout) = &mut signal.readout {
+		    readout.update(value, time, text_rate);
+		}
+		signal.latest = Some(value);
+		signal.values.push_back(value.clamp(signal.min, signal.max));
+		while signal.values.len() >= samples {
+		    signal.values.pop_front();
+		}
+		if let Some(sigmas) = &mut signal.sigmas {
+		    sigmas.push_back(signal.last_sigma);
+		    while sigmas.len() >= samples {
+			sigmas.pop_front();
+		    }
+		}
+	    });
+	if let (Some(trigger), Some(value)) = (&mut self.trigger, trigger_value) {
+	    let post_trigger = (samples - 1).saturating_sub(trigger.offset + 1);
+	    if trigger.process(value, post_trigger) {
+		let capture: Vec<Vec<f32>> = self.signals.iter().map(|signal| { signal.values.iter().cloned().collect() }).collect();
+		if let Some(signal) = self.signals.get(trigger.channel) {
+		    let trigger_value = capture[trigger.channel].get(trigger.offset).cloned().unwrap_or(value);
+		    self.events.push(AlarmEvent::new(AlarmKind::Trigger, &self.name, &signal.name, trigger_value));
+		}
+		self.captures.push_back(capture);
+		while self.captures.len() > self.overlay {
+		    self.captures.pop_front();
+		}
+	    }
+	}
+    }
+
+    // Feeds the values of some signals, by index, filling in the others
+    fn feed_updates(&mut self, updates: Vec<(usize, f32)>, time: f64)
+    {
+	let resample = self.resample.unwrap_or(Resample::Hold);
+	let row: Vec<f32> = self.signals.iter().enumerate()
+	    .map(|(index, signal)| {
+		updates.iter().find(|(updated, _)| { *updated == index }).map(|(_, value)| { *value }).unwrap_or(signal.last_input)
+	    })
+	    .collect();
+	self.push_row(row.clone(), time);
+	self.note_quality(&updates.iter().map(|(index, _)| { *index }).collect::<Vec<usize>>());
+	let samples = self.samples;
+	for (index, signal) in self.signals.iter_mut().enumerate() {
+	    if !updates.iter().any(|(updated, _)| { *updated == index }) {
+		signal.held = (signal.held + 1).min(samples);
+		continue;
+	    }
+	    let held = std::mem::replace(&mut signal.held, 0);
+	    signal.last_input = row[index];
+	    let len = signal.values.len();
+	    // The samples between the previous and this value are
+	    // rewritten on a straight line between both
+	    if resample == Resample::Linear && !signal.hold && held > 0 && len >= held + 2 {
+		let previous = len - held - 2;
+		let (start, end) = (signal.values[previous], signal.values[len - 1]);
+		for step in 1..=held {
+		    signal.values[previous + step] = start + (end - start) * step as f32 / (held + 1) as f32;
+		}
+	    }
+	}
+    }
+
+    fn set_trigger(&mut self, config: Option<TriggerConfig>)
+    {
+	self.trigger = config.as_ref().map(|trigger| { Trigger::from_config(trigger, self.samples) });
+	self.captures.clear();
+	self.throttle.invalidate();
+    }
+
+    pub fn setup_signal(&mut self, body: &str) -> Result<(), DebugObjectError>
+    {
+	self.add_signal(ScopeSignalConfig::from_instruction(&parser::scope_line(body)?)?);
+	Ok(())
+    }
+
+    // Of the signal at index without a color of its own
+    fn default_color(&self, index: usize) -> Color
+    {
+	if self.cycle { COLORBLIND_CYCLE[index % COLORBLIND_CYCLE.len()] } else { self.signal_color }
+    }
+
+    fn add_signal(&mut self, sc: ScopeSignalConfig)
+    {
+	debug!("setup_signal: {:?}", sc);
+	self.throttle.invalidate();
+	let color = sc.color.unwrap_or_else(|| { self.default_color(self.signals.len()) });
+	self.signals.push(
+	    ScopeSignal
+	    {
+	       name: sc.name,
+	       min: sc.min,
+	       max: sc.max,
+	       y_size: sc.y_size,
+	       y_base: sc.y_base,
+	       legend: sc.legend,
+	       color,
+	       own_color: sc.color,
+	       visible: true,
+	       reference: None,
+	       readout: None,
+	       math: sc.math,
+	       values: {
+		   let mut values = VecDeque::with_capacity(self.samples);
+		   values.extend(&[0.0, 0.0]);
+		   values
+	       },
+	       last_input: 0.0,
+	       held: 0,
+	       latest: None,
+	       updated: None,
+	       dropout: 0.0,
+	       hold: sc.hold,
+	       update_rows: VecDeque::new(),
+	       sigmas: if sc.sigma {
+		   let mut sigmas = VecDeque::with_capacity(self.samples);
+		   sigmas.extend(&[0.0, 0.0]);
+		   Some(sigmas)
+	       } else {
+		   None
+	       },
+	       last_sigma: 0.0,
+	    });
+	let warnings = self.validate_layout(self.signals.last().unwrap());
+	for warning in &warnings {
+	    warn!("Scope<{}>: {}", self.name, warning);
+	}
+	self.warnings.extend(warnings);
+    }
+
+    // Checks the vertical band of a signal against the scope
+    // and the other signals. Signals sharing exactly the same band
+    // are stacked on purpose, partial overlaps are likely mistakes.
+    fn validate_layout(&self, signal: &ScopeSignal) -> Vec<String>
+    {
+	let height = self.rect.h();
+	let mut warnings = vec![];
+	if signal.y_size <= 0.0 {
+	    warnings.push(format!("{} has no height", signal.name));
+	}
+	if signal.y_base < 0.0 || signal.y_base + signal.y_size > height {
+	    warnings.push(format!("{} spans {}..{} outside of height {}", signal.name, signal.y_base, signal.y_base + signal.y_size, height));
+	}
+	for other in &self.signals {
+	    if std::ptr::eq(other, signal) || (other.y_base == signal.y_base && other.y_size == signal.y_size) {
+		continue;
+	    }
+	    if signal.y_base < other.y_base + other.y_size && other.y_base < signal.y_base + signal.y_size {
+		warnings.push(format!("{} partially overlaps {}", signal.name, other.name));
+	    }
+	}
+	warnings
+    }
+}
+
+impl DebugProcessor for Scope {
+
+    fn name(&self) -> String {
+	self.name.clone()
+    }
+
+    fn clear(&mut self)
+    {
+	self.clear_history();
+    }
+
+    fn mark_gap(&mut self)
+    {
+	self.throttle.touch();
+	self.add_gap(0);
+    }
+
+    fn dropped(&self) -> u64
+    {
+	self.dropped
+    }
+
+    fn signal_names(&self) -> Vec<String>
+    {
+	self.signals.iter().map(|signal| { signal.name.clone() }).collect()
+    }
+
+    fn signal_values(&self, signal: &str) -> Option<Vec<f32>>
+    {
+	self.signals.iter().find(|s| { s.name == signal }).map(|s| { s.values.iter().cloned().collect() })
+    }
+
+    fn latest_values(&self) -> Vec<(String, f32)>
+    {
+	self.signals.iter().filter_map(|s| { s.latest.map(|value| { (s.name.clone(), value) }) }).collect()
+    }
+
+    fn set_signal_visible(&mut self, signal: &str, visible: bool) -> bool
+    {
+	self.throttle.invalidate();
+	self.signals.iter_mut().find(|s| { s.name == signal }).map(|s| { s.visible = visible }).is_some()
+    }
+
+    fn set_signal_color(&mut self, signal: &str, color: Color) -> bool
+    {
+	self.throttle.invalidate();
+	self.signals.iter_mut().find(|s| { s.name == signal }).map(|s| {
+	    s.color = color;
+	    s.own_color = Some(color);
+	}).is_some()
+    }
+
+    fn restyle(&mut self, defaults: &Colors)
+    {
+	let colors = self.colors.resolve(defaults);
+	self.background = colors.background;
+	self.grid = colors.grid;
+	self.signal_color = colors.signal;
+	self.cycle = colors.cycle;
+	self.throttle.invalidate();
+	let defaults: Vec<Color> = (0..self.signals.len()).map(|index| { self.default_color(index) }).collect();
+	for (signal, default) in self.signals.iter_mut().zip(defaults) {
+	    signal.color = signal.own_color.unwrap_or(default);
+	}
+    }
+
+    fn set_refresh(&mut self, rate: Option<f64>)
+    {
+	self.throttle.set_rate(rate);
+    }
+
+    // Times plus values, triggered captures, the buffers of the
+    // signal options and references per signal
+    fn memory_footprint(&self) -> usize
+    {
+	let signals = self.signals.iter().map(|signal| {
+	    let reference = signal.reference.as_ref().map_or(0, |reference| { reference.len() * std::mem::size_of::<f32>() });
+	    self.signal_bytes().saturating_add(self.option_bytes(signal.sigmas.is_some(), signal.hold, signal.math.as_ref())) + reference
+	}).fold(0, usize::saturating_add);
+	self.samples.saturating_mul(std::mem::size_of::<f64>()).saturating_add(signals)
+    }
+
+    fn preallocate(&mut self)
+    {
+	self.times.reserve(self.samples);
+    }
+
+    fn memory_for(&self, body: &str) -> usize
+    {
+	// Only signal declarations start with their name
+	if !body.starts_with('\'') {
+	    return 0;
+	}
+	match parser::scope_line(body) {
+	    Ok(instruction @ ast::DebugInstruction::SignalDefinition{ .. }) => match ScopeSignalConfig::from_instruction(&instruction) {
+		Ok(config) => self.signal_bytes().saturating_add(self.option_bytes(config.sigma, config.hold, config.math.as_ref())),
+		Err(_) => 0,
+	    },
+	    _ => 0,
+	}
+    }
+
+    fn draw_cost(&self, style: &Style) -> DrawCost
+    {
+	// Background, frame and time base indicator
+	let mut cost = DrawCost::new(5, 4 + 4 * 2) + DrawCost::text(self.time_source());
+	for warning in &self.warnings {
+	    cost += DrawCost::text(warning);
+	}
+	for band in &self.bands {
+	    if let Some((_, outlines)) = self.band_outlines(band, self.rect.h()) {
+		cost += DrawCost::new(outlines.len(), outlines.iter().map(|outline| { outline.len() }).sum());
+	    }
+	}
+	let labels = self.legend_labels(style);
+	for (index, signal) in self.signals.iter().enumerate() {
+	    cost += DrawCost::text(&labels[index]);
+	    if !signal.visible {
+		continue;
+	    }
+	    // Health dot
+	    cost += DrawCost::new(1, 1);
+	    let boundaries = [(signal.max, signal.legend.max_line, signal.legend.max), (signal.min, signal.legend.min_line, signal.legend.min)];
+	    for (value, line, label) in &boundaries {
+		if *line {
+		    cost += DrawCost::polyline(2);
+		}
+		if *label {
+		    cost += DrawCost::text(&style.number_format.format(*value, None));
+		}
+	    }
+	    match &self.trigger {
+		Some(_) => {
+		    for capture in &self.captures {
+			cost += DrawCost::polyline(capture.get(index).map(|values| { values.len() }).unwrap_or(0));
+		    }
+		}
+		None => {
+		    let segments = self.gap_positions(signal.values.len()).len() + 1;
+		    if signal.hold {
+			let markers = self.update_positions(signal).len();
+			cost += DrawCost::new(segments, (2 * signal.values.len()).saturating_sub(segments)) + DrawCost::new(markers, markers);
+		    } else {
+			cost += DrawCost::new(segments, signal.values.len());
+		    }
+		}
+	    }
+	    if let Some(reference) = &signal.reference {
+		cost += DrawCost::polyline(reference.len());
+	    }
+	    let outlines = self.sigma_outlines(signal, self.rect.h());
+	    cost += DrawCost::new(outlines.len(), outlines.iter().map(|outline| { outline.len() }).sum());
+	}
+	if self.trigger.is_none() {
+	    for gap in &self.gaps {
+		cost += DrawCost::polyline(2) + DrawCost::text(&gap_label(gap.dropped));
+	    }
+	}
+	cost
+    }
+
+    fn set_reference(&mut self, signal: &str, values: Vec<f32>) -> bool
+    {
+	self.throttle.invalidate();
+	let samples = self.samples;
+	self.signals.iter_mut().find(|s| { s.name == signal }).map(|s| {
+	    let reference = (0..samples)
+		.filter_map(|i| { values.get(i * values.len() / samples) })
+		.map(|value| { value.clamp(s.min, s.max) })
+		.collect();
+	    s.reference = Some(reference);
+	}).is_some()
+    }
+
+    fn draw(&self, draw: &nannou::draw::Draw, style: &Style)
+    {
+	let wh = self.rect.wh();
+
+	// Everything is drawn relative to the top left corner
+	let draw = draw.xy(self.rect.xy()).y(-wh.y);
+	let xy = pt2(0.0, 0.0);
+
+	// Time base indicator in the lower right corner
+	draw.text(self.time_source())
+	    .x_y(wh.x / 2.0 - 2.0, style.label_font_size as f32 / 2.0 + 2.0)
+	    .w_h(wh.x, style.label_font_size as f32 + 2.0)
+	    .font_size(style.label_font_size)
+	    .right_justify()
+	    .color(if self.fallback { RED } else { self.grid });
+
+	if self.dropped > 0 {
+	    draw.text(&format!("dropped: {}", self.dropped))
+		.x_y(wh.x / 2.0 - 2.0, style.label_font_size as f32 * 1.5 + 4.0)
+		.w_h(wh.x, style.label_font_size as f32 + 2.0)
+		.font_size(style.label_font_size)
+		.right_justify()
+		.color(RED);
+	}
+
+	// Layout warnings stacked above the lower left corner
+	for (index, warning) in self.warnings.iter().enumerate() {
+	    draw.text(warning)
+		.x_y(wh.x / 2.0 + 2.0, (index as f32 + 0.5) * (style.label_font_size as f32 + 2.0) + 2.0)
+		.w_h(wh.x, style.label_font_size as f32 + 2.0)
+		.font_size(style.label_font_size)
+		.left_justify()
+		.color(RED);
+	}
+
+	let mut cursor = pt2(0.0, wh.y) + style.signal_name_offset;
+	let labels = self.legend_labels(style);
+	// In the coordinates of the scope
+	let pointer = style.pointer.get().map(|pointer| { pointer - self.rect.xy() + pt2(0.0, wh.y) });
+	let mut tooltip = None;
+
+	// The label, followed by a health dot if given
+	fn draw_signal_name(draw: &nannou::draw::Draw, label: &str, color: Color, health: Option<Health>, cursor: Point2, style: &Style) -> Point2
+	{
+	    let text = style.layout(label);
+	    let bounding_rect = text.bounding_rect();
+	    draw.xy(cursor + bounding_rect.wh() / 2.0).path().fill().color(color).events(text.path_events());
+	    let mut cursor = cursor + pt2(bounding_rect.w(), 0.0);
+	    if let Some(health) = health {
+		cursor += pt2(2.0 + HEALTH_DOT_RADIUS, 0.0);
+		draw.ellipse().xy(cursor + pt2(0.0, bounding_rect.h() / 2.0)).radius(HEALTH_DOT_RADIUS).color(health.color());
+		cursor += pt2(HEALTH_DOT_RADIUS, 0.0);
+	    }
+	    cursor + pt2(style.signal_name_padding, 0.0)
+	}
+
+	draw.rect().xy(xy + wh / 2.0).wh(wh).color(self.background);
+	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(wh.x, 0.0));
+	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(0.0, wh.y));
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(wh.x, 0.0)).end(xy + wh);
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(0.0, wh.y)).end(xy + wh);
+	let shapes = self.throttle.shapes(style.now.get(), self.rect, || { self.shapes(wh) });
+	// Bands go behind the traces
+	shapes.draw_areas(&draw);
+	self.signals.iter().enumerate().for_each(|(index, signal)| {
+	    let start = cursor;
+	    cursor = if signal.visible {
+		draw_signal_name(&draw, &labels[index], signal.color, Some(signal.quality(style.now.get()).health), cursor, style)
+	    } else {
+		draw_signal_name(&draw, &labels[index], fade(signal.color, self.background, 0.3), None, cursor, style)
+	    };
+	    let hovered = pointer.map_or(false, |pointer| {
+		pointer.x >= start.x && pointer.x < cursor.x && pointer.y >= start.y && pointer.y <= start.y + style.font_size as f32
+	    });
+	    if hovered && labels[index] != signal.label(&style.number_format) {
+		tooltip = Some((signal.label(&style.number_format), signal.color));
+	    }
+	    // Hidden signals only keep a faded name
+	    if !signal.visible {
+		return;
+	    }
+	    // Upper/Lower Boundary with their value labels
+	    let boundaries = [
+		(signal.max, signal.legend.max_line, signal.legend.max),
+		(signal.min, signal.legend.min_line, signal.legend.min),
+	    ];
+	    for (value, line, label) in &boundaries {
+		let v = signal.y(*value, wh.y);
+		if *line {
+		    draw.line().weight(1.0).color(self.grid).start(pt2(0.0, v)).end(pt2(wh.x, 0.0) + pt2(0.0, v));
+		}
+		if *label {
+		    let text = style.number_format.format(*value, None);
+		    draw.text(&text)
+			.x_y(wh.x / 2.0 + 2.0, v + style.label_font_size as f32 / 2.0 + 1.0)
+			.w_h(wh.x, style.label_font_size as f32 + 2.0)
+			.font_size(style.label_font_size)
+			.left_justify()
+			.color(signal.color);
+		}
+	    }
+	});
+	shapes.draw(&draw, wh.x, style);
+
+	// The full label of a shortened legend entry, below the pointer
+	if let (Some((label, color)), Some(pointer)) = (tooltip, pointer) {
+	    let text = style.layout(&label);
+	    let size = text.bounding_rect().wh() + pt2(6.0, 4.0);
+	    let corner = pointer + pt2(8.0, -8.0 - size.y);
+	    draw.rect().xy(corner + size / 2.0).wh(size).color(self.background).stroke(self.grid).stroke_weight(1.0);
+	    draw.xy(corner + pt2(3.0, 2.0) + text.bounding_rect().wh() / 2.0).path().fill().color(color).events(text.path_events());
+	}
+    }
+
+    fn feed(&mut self, body: &str, timestamp: f64)
+    {
+	self.received = timestamp;
+	self.throttle.touch();
+	let instruction = match parser::scope_line(body) {
+	    Ok(instruction) => instruction,
+	    Err(error) => {
+		warn!("Scope<{}>: {}", self.name, error);
+		return;
+	    }
+	};
+	match instruction {
+	    ast::DebugInstruction::Data{ sequence, ticks, payload } => {
+		if let Some(sequence) = sequence {
+		    self.check_sequence(sequence);
+		}
+		match payload {
+		    ast::Payload::Values(values) => {
+			let floats = values.into_iter().map(|value| { value as f32 }).collect::<Vec<f32>>();
+			let time = self.sample_time(ticks, timestamp);
+			if self.resample.is_some() {
+			    let floats = self.take_sigmas(floats);
+			    self.feed_updates(floats.into_iter().enumerate().collect(), time);
+			} else {
+			    self.feed_floats(floats, time);
+			}
+		    }
+		    ast::Payload::Blob(bytes) => {
+			let rows = self.packed
+			    .ok_or(DebugObjectError::InvalidFormat("packed samples without PACKED".to_string()))
+			    .and_then(|packed| { packed.decode(&bytes, self.columns()) });
+			match rows {
+			    Ok(rows) => {
+				let time = self.sample_time(ticks, timestamp);
+				for row in rows {
+				    self.feed_floats(row, time);
+				}
+			    }
+			    Err(error) => { warn!("Scope<{}>: {}", self.name, error); }
+			}
+		    }
+		    // `MyScope 'B' 1.5 updates a single signal
+		    ast::Payload::Update{ name, value } if self.resample.is_some() => {
+			match self.signals.iter().position(|signal| { signal.name == name }) {
+			    Some(index) => {
+				let time = self.sample_time(ticks, timestamp);
+				self.feed_updates(vec![(index, value as f32)], time);
+			    }
+			    None => { warn!("Scope<{}> has no signal {}", self.name, name); }
+			}
+		    }
+		    ast::Payload::Update{ name, .. } => { warn!("Scope<{}> updates {} without RESAMPLE", self.name, name); }
+		}
+	    }
+	    ast::DebugInstruction::Readout{ name, precision, deadband } => {
+		match self.signals.iter_mut().find(|signal| { signal.name == name }) {
+		    Some(signal) => { signal.readout = Some(Readout::new(precision, deadband)); }
+		    None => { warn!("Scope<{}> has no signal {} for READOUT", self.name, name); }
+		}
+	    }
+	    ast::DebugInstruction::Band{ first, second, color } => {
+		match [&first, &second].iter().cloned().find(|name| { self.signals.iter().all(|signal| { &signal.name != *name }) }) {
+		    Some(name) => { warn!("Scope<{}> has no signal {} for BAND", self.name, name); }
+		    None => {
+			self.bands.retain(|band| { !(band.first == first && band.second == second) });
+			self.bands.push(Band{ first, second, color });
+		    }
+		}
+	    }
+	    instruction => {
+		match ScopeSignalConfig::from_instruction(&instruction) {
+		    Ok(config) => { self.add_signal(config); }
+		    Err(error) => { warn!("couldn't setup signal with {}: {}", body, error); }
+		}
+	    }
+	}
+    }
+
+    fn drain_events(&mut self) -> Vec<AlarmEvent>
+    {
+	std::mem::replace(&mut self.events, vec![])
+    }
+
+    fn rect(&self) -> Rect
+    {
+	screen_rect(&self.rect)
+    }
+
+    // One row per retained sample, one column per signal. Columns
+    // are aligned on their newest sample, gap is 1 for the first
+    // sample after a discontinuity.
+    fn write_csv(&self, writer: &mut dyn std::io::Write, format: &NumberFormat) -> Result<(), DebugObjectError>
+    {
+	let separator = format.csv_separator().to_string();
+	let names: Vec<String> = self.signals.iter().map(|signal| { format.csv_field(&signal.name).into_owned() }).collect();
+	writeln!(writer, "sample{}time{}gap{}{}", separator, separator, separator, names.join(&separator))?;
+	let rows = self.signals.iter().map(|signal| { signal.values.len() }).chain(Some(self.times.len())).max().unwrap_or(0);
+	let gaps = self.gap_positions(rows);
+	for row in 0..rows {
+	    let gap = if gaps.contains(&row) { "1" } else { "0" };
+	    let time = (row + self.times.len()).checked_sub(rows).and_then(|index| { self.times.get(index) })
+		.map(|time| { format.format_csv(*time) })
+		.unwrap_or_default();
+	    let values: Vec<String> = self.signals.iter()
+		.map(|signal| {
+		    (row + signal.values.len()).checked_sub(rows).and_then(|index| { signal.values.get(index) })
+			.map(|value| { format.format_csv(*value) })
+			.unwrap_or_default()
+		})
+		.collect();
+	    writeln!(writer, "{}{}{}{}{}{}{}", row, separator, time, separator, gap, separator, values.join(&separator))?;
+	}
+	Ok(())
+    }
+
+    fn render(&self, canvas: &mut Canvas) -> Result<(), DebugObjectError>
+    {
+	let (w, h) = (canvas.width(), canvas.height());
+	canvas.frame(self.grid);
+	let step = (w - 1.0) / (self.samples as f32 - 1.0);
+	for (index, signal) in self.signals.iter().enumerate() {
+	    for (value, line) in &[(signal.max, signal.legend.max_line), (signal.min, signal.legend.min_line)] {
+		if *line {
+		    let y = h - signal.y(*value, h);
+		    canvas.line((0.0, y), (w - 1.0, y), self.grid);
+		}
+	    }
+	    // In triggered mode the latest capture is shown
+	    let values: Vec<f32> = match &self.trigger {
+		Some(_) => self.captures.back().and_then(|capture| { capture.get(index) }).cloned().unwrap_or_default(),
+		None => signal.values.iter().cloned().collect(),
+	    };
+	    let points: Vec<(f32, f32)> = values.iter().enumerate()
+		.map(|(i, value)| { (i as f32 * step, h - signal.y(*value, h)) })
+		.collect();
+	    let points = if signal.hold { hold_steps(&points) } else { points };
+	    canvas.polyline(&points, signal.color);
+	}
+	Ok(())
+    }
+
+    // Like render, but with the legend, value labels and update
+    // markers, and traces broken at gaps
+    fn render_svg(&self, svg: &mut Svg, style: &Style) -> Result<(), DebugObjectError>
+    {
+	let (w, h) = (svg.width(), svg.height());
+	let step = w / (self.samples as f32 - 1.0).max(1.0);
+	let label_size = style.label_font_size as f32;
+	svg.fill(self.background);
+	let labels = self.legend_labels(style);
+	for band in &self.bands {
+	    if let Some((color, outlines)) = self.band_outlines(band, h) {
+		for outline in outlines {
+		    svg.polygon(&outline.into_iter().map(|(i, y)| { (i as f32 * step, h - y) }).collect::<Vec<(f32, f32)>>(), color);
+		}
+	    }
+	}
+	let mut legend_x = style.signal_name_offset.x;
+	for (index, signal) in self.signals.iter().enumerate() {
+	    if !signal.visible {
+		continue;
+	    }
+	    for (value, line, label) in &[(signal.max, signal.legend.max_line, signal.legend.max), (signal.min, signal.legend.min_line, signal.legend.min)] {
+		let y = h - signal.y(*value, h);
+		if *line {
+		    svg.line((0.0, y), (w, y), self.grid);
+		}
+		if *label {
+		    svg.text((2.0, y - 2.0), &style.number_format.format(*value, None), style.label_font_size, Anchor::Start, signal.color);
+		}
+	    }
+	    for outline in self.sigma_outlines(signal, h) {
+		svg.polygon(&outline.into_iter().map(|(i, y)| { (i as f32 * step, h - y) }).collect::<Vec<(f32, f32)>>(), fade(signal.color, self.background, 0.4));
+	    }
+	    let point = |(i, value): (usize, &f32)| { (i as f32 * step, h - signal.y(*value, h)) };
+	    let values: Vec<f32> = match &self.trigger {
+		Some(_) => self.captures.back().and_then(|capture| { capture.get(index) }).cloned().unwrap_or_default(),
+		None => signal.values.iter().cloned().collect(),
+	    };
+	    let mut bounds = vec![0];
+	    if self.trigger.is_none() {
+		bounds.extend(self.gap_positions(values.len()));
+	    }
+	    bounds.push(values.len());
+	    let segments: Vec<Vec<(f32, f32)>> = bounds.windows(2)
+		.map(|segment| {
+		    let points: Vec<(f32, f32)> = values[segment[0]..segment[1]].iter().enumerate().map(|(i, value)| { point((i + segment[0], value)) }).collect();
+		    if signal.hold { hold_steps(&points) } else { points }
+		})
+		.collect();
+	    svg.path(&segments, signal.color);
+	    if signal.hold && self.trigger.is_none() {
+		for position in self.update_positions(signal) {
+		    svg.circle(point((position, &values[position])), UPDATE_MARKER_RADIUS, signal.color);
+		}
+	    }
+	    let label = &labels[index];
+	    svg.text((legend_x, style.signal_name_offset.y + style.font_size as f32), label, style.font_size, Anchor::Start, signal.color);
+	    legend_x += style.layout(label).bounding_rect().w() + style.signal_name_padding;
+	}
+	svg.text((w - 2.0, h - 2.0), self.time_source(), style.label_font_size, Anchor::End, self.grid);
+	if self.dropped > 0 {
+	    svg.text((w - 2.0, h - label_size - 4.0), &format!("dropped: {}", self.dropped), style.label_font_size, Anchor::End, RED);
+	}
+	svg.frame(self.grid);
+	Ok(())
+    }
+
+    fn signal_stats(&self) -> Vec<SignalStats>
+    {
+	self.signals.iter().map(|signal| { SignalStats::from_values(&signal.name, signal.values.iter()) }).collect()
+    }
+
+    fn signal_quality(&self, now: f64) -> Vec<SignalQuality>
+    {
+	self.signals.iter().map(|signal| { signal.quality(now) }).collect()
+    }
+}
+
+#[derive(Debug)]
+struct EyeConfig
+{
+    name: String,
+    pos: Point2,
+    size: Point2,
+    // Number of samples the signal is folded by
+    period: usize,
+    // Number of retained traces
+    persist: usize,
+    colors: ColorOverrides,
+    refresh: Option<f64>,
+}
+
+impl EyeConfig
+{
+    fn from_instruction(instruction: &ast::DebugInstruction) -> Result<EyeConfig, DebugObjectError>
+    {
+	let (name, configurations) = match instruction {
+	    ast::DebugInstruction::EYE{ name, configurations } => (name, configurations),
+	    _ => { return Err(DebugObjectError::InvalidFormat(format!("{:?}", instruction))); }
+	};
+	let mut pos = pt2(0.0, 0.0);
+	let mut size = pt2(256.0, 256.0);
+	let mut period: usize = 64;
+	let mut persist: usize = 16;
+	let mut colors = ColorOverrides::default();
+	let mut refresh = None;
+	for atom in configurations {
+	    debug!("EyeConfig: applying {:?}", atom);
+	    match atom {
+		ast::DebugInstructionAtom::Size(width, height) => { size = pt2(*width as f32, *height as f32); }
+		ast::DebugInstructionAtom::Pos(x, y) => { pos = pt2(*x as f32, *y as f32); }
+		ast::DebugInstructionAtom::Period(count) if *count < 1 => {
+		    return Err(DebugObjectError::InvalidFormat(format!("PERIOD {}", count)));
+		}
+		ast::DebugInstructionAtom::Persist(count) if *count < 1 => {
+		    return Err(DebugObjectError::InvalidFormat(format!("PERSIST {}", count)));
+		}
+		ast::DebugInstructionAtom::Period(count) => { period = (*count as usize).max(2); }
+		ast::DebugInstructionAtom::Persist(count) => { persist = *count as usize; }
+		ast::DebugInstructionAtom::Refresh(rate) => { refresh = Some(refresh_rate(*rate)?); }
+		atom if colors.apply(atom) => {}
+		_ => { debug!("EyeConfig: ignoring {:?}", atom); }
+	    }
+	}
+	Ok(EyeConfig{ name: name.clone(), pos, size, period, persist, colors, refresh })
+    }
+}
+
+struct EyeSignal
+{
+    name: String,
+    min: f32,
+    max: f32,
+    color: Color,
+    own_color: Option<Color>,
+}
+
+impl EyeSignal
+{
+    // `MyEye 'Name' min max {color}
+    fn from_instruction(instruction: &ast::DebugInstruction) -> Result<EyeSignal, DebugObjectError>
+    {
+	match instruction {
+	    ast::DebugInstruction::SignalDefinition{ name, min: Some(min), max: Some(max), color, .. } => {
+		Ok(EyeSignal{ name: name.clone(), min: *min as f32, max: *max as f32, color: color.unwrap_or(YELLOW), own_color: *color })
+	    }
+	    _ => Err(DebugObjectError::InvalidFormat(format!("{:?}", instruction))),
+	}
+    }
+}
+
+// Folds a signal modulo a period and overlays the
+// resulting traces, producing an eye diagram.
+pub struct Eye
+{
+    name: String,
+    rect: Rect,
+    period: usize,
+    persist: usize,
+    colors: ColorOverrides,
+    background: Color,
+    grid: Color,
+    // Of a signal without a color of its own
+    signal_color: Color,
+    signal: EyeSignal,
+    // The trace currently being filled
+    current: Vec<f32>,
+    // Completed traces, oldest first
+    traces: VecDeque<Vec<f32>>,
+    throttle: Throttle,
+}
+
+impl Eye
+{
+    // Takes what follows `EYE
+    pub fn new(body: &str) -> Result<Eye, DebugObjectError>
+    {
+	let config = EyeConfig::from_instruction(&parser::declaration(&format!("`EYE {}", body))?)?;
+	let colors = config.colors.resolve(&Colors::default());
+	Ok(Eye{
+	    name: config.name.clone(),
+	    rect: Rect::from_x_y_w_h(config.pos.x, config.pos.y, config.size.x, config.size.y),
+	    period: config.period,
+	    persist: config.persist,
+	    colors: config.colors,
+	    background: colors.background,
+	    grid: colors.grid,
+	    signal_color: colors.signal,
+	    signal: EyeSignal{ name: config.name, min: 0.0, max: 255.0, color: colors.signal, own_color: None },
+	    // Reserved by preallocate after the budget check
+	    current: Vec::new(),
+	    traces: VecDeque::new(),
+	    throttle: Throttle::new(config.refresh),
+	})
+    }
+
+    // The retained traces, older ones faded out
+    fn shapes(&self) -> Shapes
+    {
+	let xy = self.rect.xy();
+	let wh = self.rect.wh();
+	let signal = &self.signal;
+	let step = wh.x / (self.period as f32 - 1.0);
+	let count = self.traces.len();
+	let lines = self.traces.iter().enumerate().map(|(age, trace)| {
+	    let color = fade(signal.color, self.background, (age + 1) as f32 / count as f32);
+	    trace.iter().enumerate()
+		.map(|(i, value)| {
+		    (xy + pt2(i as f32 * step, map_range(*value, signal.min, signal.max, 0.0, wh.y)), color)
+		})
+		.collect()
+	}).collect();
+	Shapes{ lines, ..Default::default() }
+    }
+
+    pub fn feed_floats(&mut self, values: Vec<f32>)
+    {
+	self.throttle.touch();
+	for value in values {
+	    self.current.push(value.clamp(self.signal.min, self.signal.max));
+	    if self.current.len() >= self.period {
+		let trace = std::mem::replace(&mut self.current, Vec::with_capacity(self.period));
+		self.traces.push_back(trace);
+		while self.traces.len() > self.persist {
+		    self.traces.pop_front();
+		}
+	    }
+	}
+    }
+}
+
+impl DebugProcessor for Eye {
+
+    fn name(&self) -> String {
+	self.name.clone()
+    }
+
+    fn clear(&mut self)
+    {
+	self.current.clear();
+	self.traces.clear();
+	self.throttle.invalidate();
+    }
+
+    // A partial trace would be misaligned after the gap
+    fn mark_gap(&mut self)
+    {
+	self.current.clear();
+    }
+
+    fn restyle(&mut self, defaults: &Colors)
+    {
+	let colors = self.colors.resolve(defaults);
+	self.background = colors.background;
+	self.grid = colors.grid;
+	self.signal_color = colors.signal;
+	self.signal.color = self.signal.own_color.unwrap_or(colors.signal);
+	self.throttle.invalidate();
+    }
+
+    fn set_refresh(&mut self, rate: Option<f64>)
+    {
+	self.throttle.set_rate(rate);
+    }
+
+    // The retained traces and the one being filled
+    fn memory_footprint(&self) -> usize
+    {
+	let traces = self.persist.saturating_mul(self.period);
+	let current = self.period;
+	traces.saturating_add(current).saturating_mul(std::mem::size_of::<f32>())
+    }
+
+    fn preallocate(&mut self)
+    {
+	self.current.reserve(self.period);
+	self.traces.reserve(self.persist.saturating_add(1));
+    }
+
+    fn draw_cost(&self, _style: &Style) -> DrawCost
+    {
+	// Background, frame and crosshair
+	let mut cost = DrawCost::new(7, 4 + 6 * 2) + DrawCost::text(&self.signal.name);
+	for trace in &self.traces {
+	    cost += DrawCost::polyline(trace.len());
+	}
+	cost
+    }
+
+    fn rect(&self) -> Rect
+    {
+	screen_rect(&self.rect)
+    }
+
+    fn render(&self, canvas: &mut Canvas) -> Result<(), DebugObjectError>
+    {
+	let (w, h) = (canvas.width(), canvas.height());
+	let signal = &self.signal;
+	canvas.frame(self.grid);
+	canvas.line((w / 2.0, 0.0), (w / 2.0, h), self.grid);
+	canvas.line((0.0, h / 2.0), (w, h / 2.0), self.grid);
+	let step = (w - 1.0) / (self.period as f32 - 1.0);
+	let count = self.traces.len();
+	for (age, trace) in self.traces.iter().enumerate() {
+	    let color = fade(signal.color, self.background, (age + 1) as f32 / count as f32);
+	    let points: Vec<(f32, f32)> = trace.iter().enumerate()
+		.map(|(i, value)| { (i as f32 * step, h - 1.0 - map_range(*value, signal.min, signal.max, 0.0, h - 1.0)) })
+		.collect();
+	    canvas.polyline(&points, color);
+	}
+	Ok(())
+    }
+
+    fn signal_stats(&self) -> Vec<SignalStats>
+    {
+	vec![SignalStats::from_values(&self.signal.name, self.traces.iter().flatten())]
+    }
+
+    fn draw(&self, draw: &nannou::draw::Draw, style: &Style)
+    {
+	let xy = self.rect.xy();
+	let wh = self.rect.wh();
+	let draw = draw.y(-wh.y);
+	let signal = &self.signal;
+
+	draw.rect().xy(xy + wh / 2.0).wh(wh).color(self.background);
+	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(wh.x, 0.0));
+	draw.line().weight(1.0).color(self.grid).start(xy).end(xy + pt2(0.0, wh.y));
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(wh.x, 0.0)).end(xy + wh);
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(0.0, wh.y)).end(xy + wh);
+	// Center crosshair to judge the eye opening
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(wh.x / 2.0, 0.0)).end(xy + pt2(wh.x / 2.0, wh.y));
+	draw.line().weight(1.0).color(self.grid).start(xy + pt2(0.0, wh.y / 2.0)).end(xy + pt2(wh.x, wh.y / 2.0));
+
+	let text = style.layout(&signal.name);
+	let bounding_rect = text.bounding_rect();
+	let cursor = xy + pt2(0.0, wh.y) + style.signal_name_offset;
+	draw.xy(cursor + bounding_rect.wh() / 2.0).path().fill().color(signal.color).events(text.path_events());
+
+	let shapes = self.throttle.shapes(style.now.get(), self.rect, || { self.shapes() });
+	shapes.draw(&draw, wh.x, style);
+    }
+
+    fn feed(&mut self, body: &str, _timestamp: f64)
+    {
+	self.throttle.touch();
+	match parser::eye_line(body) {
+	    Ok(ast::DebugInstruction::Data{ payload: ast::Payload::Values(values), .. }) => {
+		self.feed_floats(values.into_iter().map(|value| { value as f32 }).collect());
+	    }
+	    Ok(instruction) => {
+		match EyeSignal::from_instruction(&instruction) {
+		    Ok(mut signal) => {
+			signal.color = signal.own_color.unwrap_or(self.signal_color);
+			self.signal = signal;
+			self.current.clear();
+			self.traces.clear();
+		    }
+		    Err(_) => {
+			warn!("couldn't setup signal with {}", body);
+		    }
+		}
+	    }
+	    Err(error) => { warn!("Eye<{}>: {}", self.name, error); }
+	}
+    }
+}
+
+pub enum DebugObject
+{
+    Scope(Scope),
+    Eye(Eye),
+}
+
+impl DebugObject
+{
+    // Like name, without the copy for lookups
+    fn name_ref(&self) -> &str
+    {
+	match self {
+	    DebugObject::Scope(scope) => &scope.name,
+	    DebugObject::Eye(eye) => &eye.name,
+	}
+    }
+}
+
+impl DebugProcessor for DebugObject
+{
+    fn name(&self) -> std::string::String {
+	match self {
+	    DebugObject::Scope(scope) => scope.name(),
+	    DebugObject::Eye(eye) => eye.name(),
+	}
+    }
+
+    fn draw(&self, draw: &nannou::draw::Draw, style: &Style)
+    {
+	match self {
+	    DebugObject::Scope(scope) => { scope.draw(draw, style); }
+	    DebugObject::Eye(eye) => { eye.draw(draw, style); }
+	}
+    }
+
+    fn feed(&mut self, body: &str, timestamp: f64)
+    {
+	match self {
+	    DebugObject::Scope(scope) => { scope.feed(body, timestamp); }
+	    DebugObject::Eye(eye) => { eye.feed(body, timestamp); }
+	}
+    }
+
+    fn drain_events(&mut self) -> Vec<AlarmEvent>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.drain_events(),
+	    DebugObject::Eye(eye) => eye.drain_events(),
+	}
+    }
+
+    fn write_csv(&self, writer: &mut dyn std::io::Write, format: &NumberFormat) -> Result<(), DebugObjectError>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.write_csv(writer, format),
+	    DebugObject::Eye(eye) => eye.write_csv(writer, format),
+	}
+    }
+
+    fn render(&self, canvas: &mut Canvas) -> Result<(), DebugObjectError>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.render(canvas),
+	    DebugObject::Eye(eye) => eye.render(canvas),
+	}
+    }
+
+    fn signal_stats(&self) -> Vec<SignalStats>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.signal_stats(),
+	    DebugObject::Eye(eye) => eye.signal_stats(),
+	}
+    }
+
+    fn render_svg(&self, svg: &mut Svg, style: &Style) -> Result<(), DebugObjectError>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.render_svg(svg, style),
+	    DebugObject::Eye(eye) => eye.render_svg(svg, style),
+	}
+    }
+
+    fn signal_quality(&self, now: f64) -> Vec<SignalQuality>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.signal_quality(now),
+	    DebugObject::Eye(eye) => eye.signal_quality(now),
+	}
+    }
+
+    fn rect(&self) -> Rect
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.rect(),
+	    DebugObject::Eye(eye) => eye.rect(),
+	}
+    }
+
+    fn clear(&mut self)
+    {
+	match self {
+	    DebugObject::Scope(scope) => { scope.clear(); }
+	    DebugObject::Eye(eye) => { eye.clear(); }
+	}
+    }
+
+    fn mark_gap(&mut self)
+    {
+	match self {
+	    DebugObject::Scope(scope) => { scope.mark_gap(); }
+	    DebugObject::Eye(eye) => { eye.mark_gap(); }
+	}
+    }
+
+    fn dropped(&self) -> u64
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.dropped(),
+	    DebugObject::Eye(eye) => eye.dropped(),
+	}
+    }
+
+    fn signal_names(&self) -> Vec<String>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.signal_names(),
+	    DebugObject::Eye(eye) => eye.signal_names(),
+	}
+    }
+
+    fn signal_values(&self, signal: &str) -> Option<Vec<f32>>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.signal_values(signal),
+	    DebugObject::Eye(eye) => eye.signal_values(signal),
+	}
+    }
+
+    fn latest_values(&self) -> Vec<(String, f32)>
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.latest_values(),
+	    DebugObject::Eye(eye) => eye.latest_values(),
+	}
+    }
+
+    fn set_signal_visible(&mut self, signal: &str, visible: bool) -> bool
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.set_signal_visible(signal, visible),
+	    DebugObject::Eye(eye) => eye.set_signal_visible(signal, visible),
+	}
+    }
+
+    fn set_signal_color(&mut self, signal: &str, color: Color) -> bool
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.set_signal_color(signal, color),
+	    DebugObject::Eye(eye) => eye.set_signal_color(signal, color),
+	}
+    }
+
+    fn restyle(&mut self, defaults: &Colors)
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.restyle(defaults),
+	    DebugObject::Eye(eye) => eye.restyle(defaults),
+	}
+    }
+
+    fn set_refresh(&mut self, rate: Option<f64>)
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.set_refresh(rate),
+	    DebugObject::Eye(eye) => eye.set_refresh(rate),
+	}
+    }
+
+    fn set_reference(&mut self, signal: &str, values: Vec<f32>) -> bool
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.set_reference(signal, values),
+	    DebugObject::Eye(eye) => eye.set_reference(signal, values),
+	}
+    }
+
+    fn draw_cost(&self, style: &Style) -> DrawCost
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.draw_cost(style),
+	    DebugObject::Eye(eye) => eye.draw_cost(style),
+	}
+    }
+
+    fn memory_footprint(&self) -> usize
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.memory_footprint(),
+	    DebugObject::Eye(eye) => eye.memory_footprint(),
+	}
+    }
+
+    fn preallocate(&mut self)
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.preallocate(),
+	    DebugObject::Eye(eye) => eye.preallocate(),
+	}
+    }
+
+    fn memory_for(&self, body: &str) -> usize
+    {
+	match self {
+	    DebugObject::Scope(scope) => scope.memory_for(body),
+	    DebugObject::Eye(eye) => eye.memory_for(body),
+	}
+    }
+}
+
+// Signals handled together, members are either `Object.Signal`
+// or a bare signal name matching in every object.
+pub struct SignalGroup
+{
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+pub struct DebugObjects
+{
+    // Kept in order of creation so iteration is deterministic
+    objects: Vec<DebugObject>,
+    // Names in drawing order, the last one is on top
+    z_order: Vec<String>,
+    alarms: AlarmLog,
+    style: Style,
+    // Host time is measured relative to this
+    start: Instant,
+    // Refuses clearing, redeclaring and deleting objects
+    locked: bool,
+    // Mark reconnects in all objects instead of joining the data seamlessly
+    reconnect_markers: bool,
+    groups: Vec<SignalGroup>,
+    // Receives every sample while streaming
+    sink: Option<CsvSink>,
+    // Passes every line on at a reduced rate
+    proxy: Option<Proxy>,
+    // Filled by draw, draw only gets a shared reference
+    widget_stats: RefCell<Vec<WidgetStats>>,
+    // In bytes, declarations beyond it are refused
+    memory_budget: Option<usize>,
+    // The last refused declaration, shown on screen
+    budget_error: Option<String>,
+    // Device and border color of the line being fed
+    source: Option<(String, Color)>,
+    // Device that declared each object
+    owners: HashMap<String, (String, Color)>,
+    // Object found with the search palette, outlined for a moment
+    highlight: Option<(String, Instant)>,
+    // Seconds since start of each reconnect of the input
+    gaps: Vec<f64>,
+    rules: Vec<Rule>,
+    // Fired rules write their captured objects in here
+    capture_dir: PathBuf,
+    // Newest value of every signal, as OBJECT.SIGNAL and SIGNAL
+    latest: HashMap<String, f32>,
+    // Lines fired rules send to the input
+    commands: Vec<String>,
+    // What the firmware announced with `CAPS
+    peer_capabilities: Option<Vec<String>>,
+    // Keywords neither objects nor commands, warned about once
+    unknown_keywords: HashSet<String>,
+}
+
+impl DebugObjects
+{
+    pub fn new() -> DebugObjects
+    {
+	DebugObjects{objects: vec![], z_order: vec![], alarms: AlarmLog::new(), style: Style::new(), start: Instant::now(), locked: false, reconnect_markers: false, groups: vec![], sink: None, proxy: None, widget_stats: RefCell::new(vec![]), memory_budget: Some(DEFAULT_MEMORY_BUDGET), budget_error: None, source: None, owners: HashMap::new(), highlight: None, gaps: vec![], rules: vec![], capture_dir: PathBuf::from(DEFAULT_CAPTURE_DIR), latest: HashMap::new(), commands: vec![], peer_capabilities: None, unknown_keywords: HashSet::new()}
+    }
+
+    pub fn set_number_format(&mut self, number_format: NumberFormat)
+    {
+	self.style.number_format = number_format;
+    }
+
+    pub fn set_fallback_font(&mut self, font: Option<text::Font>)
+    {
+	self.style.fallback_font = font;
+    }
+
+    // New global defaults, objects keep what they set themselves
+    pub fn set_colors(&mut self, colors: Colors)
+    {
+	self.style.colors = colors;
+	for debug_object in self.objects.iter_mut() {
+	    debug_object.restyle(&colors);
+	}
+    }
+
+    pub fn colors(&self) -> &Colors
+    {
+	&self.style.colors
+    }
+
+    pub fn number_format(&self) -> &NumberFormat
+    {
+	&self.style.number_format
+    }
+
+    fn get(&self, name: &str) -> Option<&DebugObject>
+    {
+	self.objects.iter().find(|debug_object| { debug_object.name_ref() == name })
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut DebugObject>
+    {
+	self.objects.iter_mut().find(|debug_object| { debug_object.name_ref() == name })
+    }
+
+    // Adds the object, replacing one of the same name in place
+    fn insert(&mut self, debug_object: DebugObject)
+    {
+	let name = debug_object.name();
+	match self.objects.iter().position(|other| { other.name_ref() == name }) {
+	    Some(position) => { self.objects[position] = debug_object; }
+	    None => {
+		self.objects.push(debug_object);
+		self.z_order.push(name);
+	    }
+	}
+    }
+
+    pub fn set_locked(&mut self, locked: bool)
+    {
+	self.locked = locked;
+    }
+
+    pub fn locked(&self) -> bool
+    {
+	self.locked
+    }
+
+    // Records refused actions, so a stray reset from the
+    // firmware leaves a trace in the alarm log.
+    fn check_unlocked(&mut self, action: &str, name: &str) -> Result<(), DebugObjectError>
+    {
+	if self.locked {
+	    warn!("locked, refusing to {} {}", action, name);
+	    self.alarms.push(AlarmEvent::new(AlarmKind::Blocked, name, action, 0.0));
+	    return Err(DebugObjectError::Locked(format!("{} {}", action, name)));
+	}
+	Ok(())
+    }
+
+    pub fn set_reconnect_markers(&mut self, reconnect_markers: bool)
+    {
+	self.reconnect_markers = reconnect_markers;
+    }
+
+    pub fn reconnect_markers(&self) -> bool
+    {
+	self.reconnect_markers
+    }
+
+    // To be called when the input comes back after a disconnect. The
+    // data before the gap is kept either way.
+    pub fn reconnected(&mut self)
+    {
+	self.gaps.push(self.start.elapsed().as_secs_f64());
+	if !self.reconnect_markers {
+	    return;
+	}
+	for debug_object in self.objects.iter_mut() {
+	    debug_object.mark_gap();
+	}
+	self.alarms.push(AlarmEvent::new(AlarmKind::Reconnect, "*", "", 0.0));
+    }
+
+    pub fn set_reference(&mut self, name: &str, signal: &str, values: Vec<f32>) -> Result<(), DebugObjectError>
+    {
+	let debug_object = self.get_mut(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	if !debug_object.set_reference(signal, values) {
+	    return Err(DebugObjectError::UnknownObject(format!("{}.{}", name, signal)));
+	}
+	Ok(())
+    }
+
+    pub fn set_memory_budget(&mut self, budget: Option<usize>)
+    {
+	self.memory_budget = budget;
+    }
+
+    pub fn set_capture_dir(&mut self, directory: &str)
+    {
+	self.capture_dir = PathBuf::from(directory);
+    }
+
+    pub fn memory_used(&self) -> usize
+    {
+	self.objects.iter().map(|debug_object| { debug_object.memory_footprint() }).fold(0, usize::saturating_add)
+    }
+
+    pub fn budget_error(&self) -> Option<&String>
+    {
+	self.budget_error.as_ref()
+    }
+
+    // released is the footprint of an object about to be replaced
+    fn check_budget(&mut self, name: &str, needed: usize, released: usize) -> Result<(), DebugObjectError>
+    {
+	if let Some(budget) = self.memory_budget {
+	    let available = budget.saturating_sub(self.memory_used() - released);
+	    if needed > available {
+		let error = DebugObjectError::OverBudget{ name: name.to_string(), needed, available };
+		warn!("{}", error);
+		self.budget_error = Some(error.to_string());
+		return Err(error);
+	    }
+	}
+	Ok(())
+    }
+
+    pub fn set_sink(&mut self, sink: Option<CsvSink>)
+    {
+	self.sink = sink;
+    }
+
+    pub fn recording(&self) -> bool
+    {
+	self.sink.is_some()
+    }
+
+    pub fn set_proxy(&mut self, proxy: Option<Proxy>)
+    {
+	self.proxy = proxy;
+    }
+
+    pub fn proxying(&self) -> bool
+    {
+	self.proxy.is_some()
+    }
+
+    // Stops recording, flushing what was recorded so far
+    pub fn close_sink(&mut self) -> std::io::Result<()>
+    {
+	match self.sink.take() {
+	    Some(mut sink) => sink.close(),
+	    None => Ok(()),
+	}
+    }
+
+    // Defines or redefines a signal group
+    pub fn define_group(&mut self, name: &str, members: Vec<String>)
+    {
+	match self.groups.iter_mut().find(|group| { group.name == name }) {
+	    Some(group) => { group.members = members; }
+	    None => { self.groups.push(SignalGroup{ name: name.to_string(), members }); }
+	}
+    }
+
+    pub fn groups(&self) -> &Vec<SignalGroup>
+    {
+	&self.groups
+    }
+
+    // The (object, signal) pairs of a group, in member order
+    fn group_signals(&self, name: &str) -> Result<Vec<(String, String)>, DebugObjectError>
+    {
+	let group = self.groups.iter().find(|group| { group.name == name }).ok_or(DebugObjectError::UnknownGroup(name.to_string()))?;
+	let mut result = vec![];
+	for member in &group.members {
+	    let (object, signal) = match member.rfind('.') {
+		Some(position) => (Some(&member[..position]), &member[position + 1..]),
+		None => (None, &member[..]),
+	    };
+	    for debug_object in &self.objects {
+		let object_name = debug_object.name();
+		if object.map(|object| { object == object_name }).unwrap_or(true)
+		    && debug_object.signal_names().iter().any(|s| { s == signal }) {
+		    let pair = (object_name, signal.to_string());
+		    if !result.contains(&pair) {
+			result.push(pair);
+		    }
+		}
+	    }
+	}
+	Ok(result)
+    }
+
+    pub fn set_group_visible(&mut self, name: &str, visible: bool) -> Result<(), DebugObjectError>
+    {
+	for (object, signal) in self.group_signals(name)? {
+	    if let Some(debug_object) = self.get_mut(&object) {
+		debug_object.set_signal_visible(&signal, visible);
+	    }
+	}
+	Ok(())
+    }
+
+    // Colors the members from the group palette
+    pub fn color_group(&mut self, name: &str) -> Result<(), DebugObjectError>
+    {
+	for (index, (object, signal)) in self.group_signals(name)?.into_iter().enumerate() {
+	    if let Some(debug_object) = self.get_mut(&object) {
+		debug_object.set_signal_color(&signal, GROUP_PALETTE[index % GROUP_PALETTE.len()]);
+	    }
+	}
+	Ok(())
+    }
+
+    // One column per member signal, aligned on the newest sample
+    pub fn write_group_csv(&self, name: &str, writer: &mut dyn std::io::Write) -> Result<(), DebugObjectError>
+    {
+	let format = &self.style.number_format;
+	let separator = format.csv_separator().to_string();
+	let signals = self.group_signals(name)?;
+	let columns: Vec<Vec<f32>> = signals.iter()
+	    .map(|(object, signal)| { self.get(object).and_then(|o| { o.signal_values(signal) }).unwrap_or_default() })
+	    .collect();
+	let names: Vec<String> = signals.iter().map(|(object, signal)| { format.csv_field(&format!("{}.{}", object, signal)).into_owned() }).collect();
+	writeln!(writer, "sample{}{}", separator, names.join(&separator))?;
+	let rows = columns.iter().map(|column| { column.len() }).max().unwrap_or(0);
+	for row in 0..rows {
+	    let values: Vec<String> = columns.iter()
+		.map(|column| {
+		    (row + column.len()).checked_sub(rows).and_then(|index| { column.get(index) })
+			.map(|value| { format.format_csv(*value) })
+			.unwrap_or_default()
+		})
+		.collect();
+	    writeln!(writer, "{}{}{}", row, separator, values.join(&separator))?;
+	}
+	Ok(())
+    }
+
+    pub fn export_group_csv(&self, name: &str, path: &str) -> Result<(), DebugObjectError>
+    {
+	let mut file = std::fs::File::create(path)?;
+	self.write_group_csv(name, &mut file)
+    }
+
+    // `GROUP Phases MyScope.IA IB IC defines a group,
+    // `GROUP Phases HIDE|SHOW|PALETTE operates on it.
+    fn group_command(&mut self, body: &str) -> Result<(), DebugObjectError>
+    {
+	let (name, action) = parser::group(body)?;
+	match action {
+	    ast::GroupAction::Hide => self.set_group_visible(&name, false),
+	    ast::GroupAction::Show => self.set_group_visible(&name, true),
+	    ast::GroupAction::Palette => self.color_group(&name),
+	    ast::GroupAction::Define(members) => {
+		self.define_group(&name, members);
+		Ok(())
+	    }
+	}
+    }
+
+    // Clears the named object, or all of them
+    pub fn clear(&mut self, name: Option<&str>) -> Result<(), DebugObjectError>
+    {
+	self.check_unlocked("clear", name.unwrap_or("*"))?;
+	match name {
+	    Some(name) => {
+		self.get_mut(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?.clear();
+	    }
+	    None => {
+		for debug_object in self.objects.iter_mut() {
+		    debug_object.clear();
+		}
+	    }
+	}
+	Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), DebugObjectError>
+    {
+	self.check_unlocked("delete", name)?;
+	let position = self.objects.iter().position(|debug_object| { debug_object.name_ref() == name })
+	    .ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	self.objects.remove(position);
+	self.z_order.retain(|n| { n != name });
+	self.owners.remove(name);
+	Ok(())
+    }
+
+    // Device that declared the object, if several are connected
+    pub fn owner(&self, name: &str) -> Option<&str>
+    {
+	self.owners.get(name).map(|(device, _)| { device.as_str() })
+    }
+
+    // Object names in order of creation
+    pub fn names(&self) -> Vec<String>
+    {
+	self.objects.iter().map(|debug_object| { debug_object.name() }).collect()
+    }
+
+    // All (object, signal) pairs in order of declaration
+    pub fn signals(&self) -> Vec<(String, String)>
+    {
+	self.objects.iter()
+	    .flat_map(|debug_object| {
+		let name = debug_object.name();
+		debug_object.signal_names().into_iter().map(move |signal| { (name.clone(), signal) })
+	    })
+	    .collect()
+    }
+
+    // Raises the object and outlines it for HIGHLIGHT_TIME
+    pub fn focus(&mut self, name: &str) -> Result<(), DebugObjectError>
+    {
+	self.raise(name)?;
+	self.highlight = Some((name.to_string(), Instant::now()));
+	Ok(())
+    }
+
+    // Shows only the given signal, or all of them again for None
+    pub fn solo(&mut self, only: Option<(&str, &str)>)
+    {
+	for debug_object in self.objects.iter_mut() {
+	    let name = debug_object.name();
+	    for signal in debug_object.signal_names() {
+		let visible = only.map(|(object, only)| { object == name && only == signal }).unwrap_or(true);
+		debug_object.set_signal_visible(&signal, visible);
+	    }
+	}
+    }
+
+    // Bottom first
+    pub fn z_order(&self) -> &[String]
+    {
+	&self.z_order
+    }
+
+    // Objects missing in order stay on top, unknown names are dropped
+    pub fn set_z_order(&mut self, order: &[String])
+    {
+	let mut z_order: Vec<String> = order.iter().filter(|name| { self.z_order.contains(name) }).cloned().collect();
+	z_order.extend(self.z_order.iter().filter(|name| { !order.contains(name) }).cloned());
+	self.z_order = z_order;
+    }
+
+    // Moves the object to the top of the drawing order
+    // Limits how often the samples of an object are redrawn, None
+    // redraws them every frame
+    pub fn set_refresh(&mut self, name: &str, rate: Option<f64>) -> Result<(), DebugObjectError>
+    {
+	let rate = rate.map(refresh_rate).transpose()?;
+	self.get_mut(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?.set_refresh(rate);
+	Ok(())
+    }
+
+    pub fn raise(&mut self, name: &str) -> Result<(), DebugObjectError>
+    {
+	let position = self.z_order.iter().position(|n| { n == name }).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	let name = self.z_order.remove(position);
+	self.z_order.push(name);
+	Ok(())
+    }
+
+    // Moves the object to the bottom of the drawing order
+    pub fn lower(&mut self, name: &str) -> Result<(), DebugObjectError>
+    {
+	let position = self.z_order.iter().position(|n| { n == name }).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	let name = self.z_order.remove(position);
+	self.z_order.insert(0, name);
+	Ok(())
+    }
+
+    // The topmost object covering point
+    pub fn object_at(&self, point: Point2) -> Option<String>
+    {
+	self.z_order.iter().rev()
+	    .find(|name| { self.get(name).map(|o| { o.rect().contains(point) }).unwrap_or(false) })
+	    .cloned()
+    }
+
+    // All pairs of objects whose areas intersect
+    pub fn overlaps(&self) -> Vec<(String, String)>
+    {
+	let mut result = vec![];
+	for (index, a) in self.objects.iter().enumerate() {
+	    for b in &self.objects[index + 1..] {
+		if a.rect().overlap(b.rect()).is_some() {
+		    result.push((a.name(), b.name()));
+		}
+	    }
+	}
+	result
+    }
+
+    pub fn alarms(&self) -> &AlarmLog
+    {
+	&self.alarms
+    }
+
+    pub fn set_trigger(&mut self, name: &str, channel: usize, arm_level: f32, trigger_level: f32, offset: Option<usize>) -> Result<(), DebugObjectError>
+    {
+	match self.get_mut(name) {
+	    Some(DebugObject::Scope(scope)) => {
+		scope.set_trigger(Some(TriggerConfig{ channel, arm_level, trigger_level, offset }));
+		Ok(())
+	    }
+	    Some(_) => Err(DebugObjectError::Unsupported(name.to_string())),
+	    None => Err(DebugObjectError::UnknownObject(name.to_string())),
+	}
+    }
+
+    pub fn export_csv(&self, name: &str, path: &str) -> Result<(), DebugObjectError>
+    {
+	let debug_object = self.get(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	let mut file = std::fs::File::create(path)?;
+	debug_object.write_csv(&mut file, &self.style.number_format)
+    }
+
+    fn rasterize(&self, name: &str) -> Result<Canvas, DebugObjectError>
+    {
+	let debug_object = self.get(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	let rect = debug_object.rect();
+	let mut canvas = Canvas::new(rect.w() as u32, rect.h() as u32, BLACK);
+	debug_object.render(&mut canvas)?;
+	Ok(canvas)
+    }
+
+    pub fn export_png(&self, name: &str, path: &str) -> Result<(), DebugObjectError>
+    {
+	self.rasterize(name)?.save(path)?;
+	Ok(())
+    }
+
+    pub fn png(&self, name: &str) -> Result<Vec<u8>, DebugObjectError>
+    {
+	Ok(self.rasterize(name)?.png()?)
+    }
+
+    pub fn svg(&self, name: &str) -> Result<String, DebugObjectError>
+    {
+	let debug_object = self.get(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?;
+	let rect = debug_object.rect();
+	let mut svg = Svg::new(rect.w(), rect.h());
+	debug_object.render_svg(&mut svg, &self.style)?;
+	Ok(svg.to_string())
+    }
+
+    pub fn export_svg(&self, name: &str, path: &str) -> Result<(), DebugObjectError>
+    {
+	std::fs::write(path, self.svg(name)?)?;
+	Ok(())
+    }
+
+    pub fn signal_stats(&self, name: &str) -> Result<Vec<SignalStats>, DebugObjectError>
+    {
+	Ok(self.get(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?.signal_stats())
+    }
+
+    pub fn signal_quality(&self, name: &str) -> Result<Vec<SignalQuality>, DebugObjectError>
+    {
+	let now = self.start.elapsed().as_secs_f64();
+	Ok(self.get(name).ok_or(DebugObjectError::UnknownObject(name.to_string()))?.signal_quality(now))
+    }
+
+    pub fn gaps(&self) -> &[f64]
+    {
+	&self.gaps
+    }
+
+    // Replaces a rule of the same name
+    pub fn add_rule(&mut self, rule: Rule)
+    {
+	self.rules.retain(|other| { other.config.name != rule.config.name });
+	self.rules.push(rule);
+    }
+
+    // Firmware name, version and features, once announced
+    pub fn peer_capabilities(&self) -> Option<&[String]>
+    {
+	self.peer_capabilities.as_deref()
+    }
+
+    pub fn unknown_keywords(&self) -> Vec<String>
+    {
+	let mut keywords: Vec<String> = self.unknown_keywords.iter().cloned().collect();
+	keywords.sort();
+	keywords
+    }
+
+    // Lines to send to the input since the last call
+    pub fn drain_commands(&mut self) -> Vec<String>
+    {
+	std::mem::take(&mut self.commands)
+    }
+
+    fn check_rules(&mut self, time: f64)
+    {
+	let mut fired = vec![];
+	for rule in self.rules.iter_mut() {
+	    if rule.update(&self.latest, time) {
+		if rule.config.alarm {
+		    self.alarms.push(AlarmEvent::new(AlarmKind::Rule, &rule.config.name, &rule.config.when, 0.0));
+		}
+		fired.push(rule.fired());
+	    }
+	}
+	for fired in fired {
+	    if !fired.capture.is_empty() {
+		if let Err(error) = std::fs::create_dir_all(&self.capture_dir) {
+		    warn!("rule {} creating {} failed: {}", fired.rule, self.capture_dir.display(), error);
+		}
+	    }
+	    for object in &fired.capture {
+		// Rule and object names may come from the input
+		let path = self.capture_dir.join(file_name(&format!("{}-{}-{}", fired.rule, object, (time * 1000.0) as u64), "csv"));
+		if let Err(error) = self.export_csv(object, &path.to_string_lossy()) {
+		    warn!("rule {} capturing {} failed: {}", fired.rule, object, error);
+		}
+	    }
+	    self.commands.extend(fired.send);
+	}
+    }
+
+    // Lines lost per object, for those that lost any
+    pub fn dropped(&self) -> Vec<(String, u64)>
+    {
+	self.objects.iter()
+	    .map(|debug_object| { (debug_object.name(), debug_object.dropped()) })
+	    .filter(|(_, dropped)| { *dropped > 0 })
+	    .collect()
+    }
+}
+
+impl DebugObjects
+{
+    pub fn feed(&mut self, line: &str)
+    {
+	let timestamp = self.start.elapsed().as_secs_f64();
+	self.feed_at(line, timestamp);
+    }
+
+    // Feeds a line at the time its reader thread received it
+    pub fn feed_received(&mut self, line: &str, at: Instant)
+    {
+	let timestamp = at.saturating_duration_since(self.start).as_secs_f64();
+	self.feed_at(line, timestamp);
+    }
+
+    // Feeds a line of one of several devices, objects it declares
+    // get a border of the device color.
+    pub fn feed_from(&mut self, device: &str, color: Color, line: &str, at: Instant)
+    {
+	self.source = Some((device.to_string(), color));
+	self.feed_received(line, at);
+	self.source = None;
+    }
+
+    // Feeds a block of protocol lines, e.g. pasted from an editor.
+    // Returns the number of lines.
+    pub fn feed_text(&mut self, text: &str) -> usize
+    {
+	let mut count = 0;
+	for line in text.lines().map(|line| { line.trim_end_matches('\r') }).filter(|line| { !line.trim().is_empty() && !parser::comment(line) }) {
+	    self.feed(line);
+	    count += 1;
+	}
+	count
+    }
+
+    // Feeds a line that arrived timestamp seconds after start
+    pub fn feed_at(&mut self, line: &str, timestamp: f64)
+    {
+	if let Some(proxy) = &mut self.proxy {
+	    proxy.feed(line, timestamp);
+	}
+	if parser::comment(line) {
+	    return;
+	}
+	if let Ok(line) = DebugLine::from_str(line, timestamp) {
+	    // `CLEAR clears everything, `MyScope CLEAR a single object
+	    if line.keyword == "CLEAR" && line.tokens.is_empty() {
+		self.clear(None).ok();
+		return;
+	    }
+	    if line.tokens.len() == 1 && line.tokens[0] == "CLEAR" && self.get(&line.keyword).is_some() {
+		self.clear(Some(&line.keyword)).ok();
+		return;
+	    }
+	    // `RULE name condition, see rules::Rule
+	    if line.keyword == "RULE" {
+		match Rule::from_tokens(&line.tokens) {
+		    Ok(rule) => { self.add_rule(rule); }
+		    Err(error) => { warn!("RULE {:?} failed: {}", line.tokens, error); }
+		}
+		return;
+	    }
+	    // `EVENT origin text, shown in the alarm log
+	    if line.keyword == "EVENT" {
+		if let Some((origin, text)) = line.tokens.split_first() {
+		    self.alarms.push(AlarmEvent::new(AlarmKind::Event, origin, &text.join(" "), 0.0));
+		}
+		return;
+	    }
+	    if line.keyword == "GROUP" {
+		if let Err(error) = self.group_command(&line.body) {
+		    warn!("GROUP {} failed: {}", line.body, error);
+		}
+		return;
+	    }
+	    // `CAPS name version features..., answered with ours
+	    if line.keyword == "CAPS" {
+		info!("input announced {}", line.tokens.join(" "));
+		self.peer_capabilities = Some(line.tokens);
+		self.commands.push(capabilities_line());
+		return;
+	    }
+	    match self.objects.iter().position(|debug_object| { debug_object.name_ref() == line.keyword }) {
+		Some(position) => {
+		    let needed = self.objects[position].memory_for(&line.body);
+		    if needed > 0 && self.check_budget(&line.keyword, needed, 0).is_err() {
+			return;
+		    }
+		    let debug_object = &mut self.objects[position];
+		    debug!("found DebugObject `{}, feeding to it", debug_object.name());
+		    if let Some(sink) = &mut self.sink {
+			if let Ok(ast::DebugInstruction::Data{ payload: ast::Payload::Values(values), .. }) = parser::scope_line(&line.body) {
+			    let values: Vec<f32> = values.into_iter().map(|value| { value as f32 }).collect();
+			    if let Err(error) = sink.write(line.received, &debug_object.name(), &debug_object.signal_names(), &values) {
+				warn!("streaming samples failed: {:?}", error);
+			    }
+			}
+		    }
+		    debug_object.feed(&line.body, line.received);
+		    for event in debug_object.drain_events() {
+			self.alarms.push(event);
+		    }
+		    if !self.rules.is_empty() {
+			let name = debug_object.name();
+			for (signal, value) in debug_object.latest_values() {
+			    self.latest.insert(format!("{}.{}", name, signal), value);
+			    self.latest.insert(signal, value);
+			}
+			self.check_rules(line.received);
+		    }
+		}
 		None => {
 		    debug!("no DebugObject for keyword  {} - trying to create one", line.keyword);
-		    match self.create(&line.keyword, &line.tokens)
+		    match self.create(&line.keyword, &line.body)
 		    {
-			Some(new_object) => {
-			    self.objects.insert(new_object.name(), new_object);
+			Some(Ok(mut new_object)) => {
+			    new_object.restyle(&self.style.colors);
+			    let name = new_object.name();
+			    // Redeclaring replaces the object and its history
+			    if self.get(&name).is_some() && self.check_unlocked("redeclare", &name).is_err() {
+				return;
+			    }
+			    let released = self.get(&name).map(|old| { old.memory_footprint() }).unwrap_or(0);
+			    if self.check_budget(&name, new_object.memory_footprint(), released).is_err() {
+				return;
+			    }
+			    new_object.preallocate();
+			    for other in &self.objects {
+				if other.name_ref() != name && other.rect().overlap(new_object.rect()).is_some() {
+				    warn!("DebugObject {} overlaps {}", name, other.name());
+				}
+			    }
+			    match &self.source {
+				Some(source) => { self.owners.insert(name.clone(), source.clone()); }
+				None => { self.owners.remove(&name); }
+			    }
+			    self.insert(new_object);
 			},
-			_ => { warn!("No factory found for {}", line.keyword); }
+			Some(Err(error)) => { warn!("invalid declaration {} {}: {}", line.keyword, line.body, error); }
+			// Likely a newer protocol feature, or a typo
+			None => {
+			    if self.unknown_keywords.insert(line.keyword.clone()) {
+				warn!("unknown keyword {}, ignoring it", line.keyword);
+			    }
+			}
 		    }
 		}
 	    }
 	}
     }
 
-    pub fn draw(&self, draw: &nannou::draw::Draw)
+    // Costs of the last drawn frame
+    pub fn widget_stats(&self) -> Vec<WidgetStats>
     {
-	for (_, debug_object) in &self.objects {
-	    debug_object.draw(draw);
-	}
+	self.widget_stats.borrow().clone()
+    }
+
+    // Where the mouse is, None once it left the window
+    pub fn hover(&self, pointer: Option<Point2>)
+    {
+	self.style.pointer.set(pointer);
     }
 
-    fn create(&self, keyword: &str, tokens: &Vec<String>) -> Option<DebugObject>
+    pub fn draw(&self, draw: &nannou::draw::Draw)
     {
-	// We need at least one additional token afetr the
-	// name, which will become the identifier.
-	if tokens.len() >= 1 {
-	    if keyword == "SCOPE" {
-		debug!("created Scope object named {}", tokens[0]);
-		if let Some(scope) = Scope::new(tokens).ok()
-		{
-		    return Some(DebugObject::Scope(scope))
+	self.style.now.set(self.start.elapsed().as_secs_f64());
+	let mut widget_stats = vec![];
+	for name in &self.z_order {
+	    if let Some(debug_object) = self.get(name) {
+		let start = Instant::now();
+		debug_object.draw(draw, &self.style);
+		widget_stats.push(WidgetStats{
+		    name: name.clone(),
+		    cost: debug_object.draw_cost(&self.style),
+		    draw_time: start.elapsed(),
+		});
+	    }
+	}
+	self.widget_stats.replace(widget_stats);
+	for (name, (_, color)) in &self.owners {
+	    if let Some(debug_object) = self.get(name) {
+		let rect = debug_object.rect();
+		draw.rect().xy(rect.xy()).wh(rect.wh()).no_fill().stroke(*color).stroke_weight(2.0);
+	    }
+	}
+	if let Some((name, since)) = &self.highlight {
+	    if let Some(debug_object) = self.get(name).filter(|_| { since.elapsed() < HIGHLIGHT_TIME }) {
+		let rect = debug_object.rect().pad(-3.0);
+		draw.rect().xy(rect.xy()).wh(rect.wh()).no_fill().stroke(YELLOW).stroke_weight(3.0);
+	    }
+	}
+	// Outline overlapping objects so misconfigured positions stand out
+	for (first, second) in self.overlaps() {
+	    for name in &[first, second] {
+		if let Some(debug_object) = self.get(name) {
+		    let rect = debug_object.rect();
+		    draw.rect().xy(rect.xy()).wh(rect.wh()).no_fill().stroke(RED).stroke_weight(1.0);
 		}
 	    }
 	}
-	None
+    }
+
+    // None for keywords not declaring an object
+    fn create(&self, keyword: &str, body: &str) -> Option<Result<DebugObject, DebugObjectError>>
+    {
+	match keyword {
+	    "SCOPE" => Some(Scope::new(body).map(DebugObject::Scope)),
+	    "EYE" => Some(Eye::new(body).map(DebugObject::Eye)),
+	    _ => None,
+	}
     }
 
 }
@@ -455,9 +3670,48 @@ mod tests {
 `MyScope 36\r\n\
 ";
 
-    fn to_tokens(tokens: &[&str]) -> Vec<String>
+    fn parse_scope_config(body: &str) -> ScopeConfig
+    {
+	ScopeConfig::from_instruction(&parser::declaration(&format!("`SCOPE {}", body)).unwrap()).expect("invalid configuration")
+    }
+
+    fn parse_signal_config(body: &str) -> Result<ScopeSignalConfig, DebugObjectError>
     {
-	tokens.iter().map(|s| { s.to_string() }).collect()
+	ScopeSignalConfig::from_instruction(&parser::scope_line(body)?)
+    }
+
+    #[test]
+    fn namespaces_keep_inputs_apart() {
+	assert_eq!(namespace_line("left", "`SCOPE MyScope SIZE 254 84"), "`SCOPE left.MyScope SIZE 254 84");
+	assert_eq!(namespace_line("left", "`MyScope 31"), "`left.MyScope 31");
+	assert_eq!(namespace_line("left", "`EYE 'Data' PERIOD 16"), "`EYE 'left.Data' PERIOD 16");
+	assert_eq!(namespace_line("left", "`CLEAR"), "`CLEAR");
+	assert_eq!(namespace_line("left", "`SCOPE 'Power  Rail' SAMPLES 8"), "`SCOPE 'left.Power  Rail' SAMPLES 8");
+	assert_eq!(namespace_line("left", "`GROUP 'Power Rail' 'Power  Rail'.Volts"), "`GROUP 'Power Rail' 'Power  Rail'.Volts");
+	assert_eq!(namespace_line("left", "booting"), "booting");
+	let mut debug_objects = DebugObjects::new();
+	for namespace in &["left", "right"] {
+	    for line in SCOPE_DECLARATION.lines() {
+		debug_objects.feed(&namespace_line(namespace, line));
+	    }
+	}
+	assert!(debug_objects.get("left.MyScope").is_some());
+	assert!(debug_objects.get("right.MyScope").is_some());
+    }
+
+    #[test]
+    fn idle_runs_are_compressed() {
+	let mut scope = Scope::new("MyScope SAMPLES 32 SKIP 4").unwrap();
+	scope.feed("'A' 0 10 10 0", 0.0);
+	for value in &[1, 2, 5, 5, 5, 5, 5, 5, 5, 5, 6, 5, 5, 7] {
+	    scope.feed(&value.to_string(), 0.0);
+	}
+	let (xs, breaks) = scope.x_mapping(14);
+	// The seven repeats of 5 keep two samples on each side of the break
+	assert_eq!(xs, vec![0.0, 1.0, 2.0, 3.0, 4.0, 6.0, 6.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+	assert_eq!(breaks, vec![5.0]);
+	scope.skip = None;
+	assert_eq!(scope.x_mapping(3).0, vec![0.0, 1.0, 2.0]);
     }
 
     #[test]
@@ -479,23 +3733,795 @@ mod tests {
 
     #[test]
     fn test_configuration_commandline() {
-	let tokens = to_tokens(&["MyScope", "SIZE", "254", "84", "SAMPLES", "128"]);
-	let scope_config = ScopeConfig::from_tokens(&tokens).expect("invalid configuration");
+	let scope_config = parse_scope_config("MyScope SIZE 254 84 SAMPLES 128");
 	assert_eq!(scope_config.name, "MyScope");
 	assert_eq!(scope_config.size, pt2(254.0, 84.0));
 	assert_eq!(scope_config.samples, 128);
     }
 
+    #[test]
+    fn test_configuration_trigger() {
+	let scope_config = parse_scope_config("MyScope SAMPLES 128 TRIGGER 0 10 20 OVERLAY 4");
+	assert_eq!(scope_config.trigger, Some(TriggerConfig{ channel: 0, arm_level: 10.0, trigger_level: 20.0, offset: None }));
+	assert_eq!(scope_config.overlay, 4);
+	// The trigger offset is counted back from the last sample
+	assert!(Scope::new("MyScope SAMPLES 0 TRIGGER 0 10 20").is_err());
+    }
+
+    #[test]
+    fn triggered_captures_accumulate() {
+	let mut scope = Scope::new("MyScope SAMPLES 8 TRIGGER 0 0 10 2 OVERLAY 2").unwrap();
+	scope.setup_signal("'Sawtooth' 0 63 64 10").unwrap();
+	for _ in 0..8 {
+	    for value in &[0.0, 5.0, 10.0, 15.0, 20.0, 25.0] {
+		scope.feed_floats(vec![*value], 0.0);
+	    }
+	}
+	assert_eq!(scope.captures.len(), 2);
+	// The trigger sample sits at the configured offset
+	assert_eq!(scope.captures[1][0][2], 10.0);
+	let events = scope.drain_events();
+	assert_eq!(events.len(), 4);
+	assert_eq!(events[0].signal, "Sawtooth");
+	assert_eq!(events[0].value, 10.0);
+    }
+
+    #[test]
+    fn scope_csv_export() {
+	let mut scope = Scope::new("MyScope SAMPLES 8").unwrap();
+	scope.setup_signal("'A' 0 63 64 10").unwrap();
+	scope.setup_signal("'B' 0 63 64 10").unwrap();
+	scope.feed_floats(vec![1.0, 2.0], 0.5);
+	let mut output = vec![];
+	scope.write_csv(&mut output, &NumberFormat::default()).unwrap();
+	assert_eq!(String::from_utf8(output).unwrap(), "sample,time,gap,A,B\n0,,0,0,0\n1,,0,0,0\n2,0.5,0,1,2\n");
+	scope.feed_floats(vec![1.5, 2.25], 0.75);
+	let mut output = vec![];
+	scope.write_csv(&mut output, &NumberFormat::from_locale("de_DE")).unwrap();
+	assert!(String::from_utf8(output).unwrap().ends_with("3;0,75;0;1,5;2,25\n"));
+    }
+
+    #[test]
+    fn signal_layout_warnings() {
+	let mut scope = Scope::new("MyScope SIZE 100 100").unwrap();
+	scope.setup_signal("'A' 0 10 50 0").unwrap();
+	scope.setup_signal("'B' 0 10 50 0").unwrap();
+	scope.setup_signal("'C' 0 10 50 50").unwrap();
+	assert!(scope.warnings.is_empty());
+	scope.setup_signal("'D' 0 10 40 30").unwrap();
+	scope.setup_signal("'E' 0 10 40 80").unwrap();
+	assert_eq!(scope.warnings, vec![
+	    "D partially overlaps A".to_string(),
+	    "D partially overlaps B".to_string(),
+	    "D partially overlaps C".to_string(),
+	    "E spans 80..120 outside of height 100".to_string(),
+	    "E partially overlaps C".to_string(),
+	    "E partially overlaps D".to_string(),
+	]);
+    }
+
+    #[test]
+    fn reference_is_resampled() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SAMPLES 4\n`MyScope 'A' 0 10 64 0\n");
+	views.set_reference("MyScope", "A", (0..8).map(|v| { v as f32 * 2.0 }).collect()).unwrap();
+	match views.get("MyScope").unwrap() {
+	    DebugObject::Scope(scope) => { assert_eq!(scope.signals[0].reference, Some(vec![0.0, 4.0, 8.0, 10.0])); }
+	    _ => { assert!(false); }
+	}
+	assert!(views.set_reference("MyScope", "B", vec![]).is_err());
+    }
+
+    #[test]
+    fn scope_draw_cost() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SAMPLES 8\n`MyScope 'A' 0 10 64 0 %0000\n`MyScope 1\n`MyScope 2\n");
+	let cost = views.get("MyScope").unwrap().draw_cost(&views.style);
+	// frame, indicator, name and a trace of 4 values
+	assert_eq!(cost, DrawCost::new(5, 12) + DrawCost::text("HOST") + DrawCost::text("A") + DrawCost::polyline(4));
+    }
+
+    #[test]
+    fn objects_remember_their_device() {
+	let mut views = DebugObjects::new();
+	views.feed_from("left", CYAN, "`SCOPE Left", Instant::now());
+	views.feed("`SCOPE Local");
+	assert_eq!(views.owner("Left"), Some("left"));
+	assert_eq!(views.owner("Local"), None);
+	// Samples of another device don't take the object over
+	views.feed_from("right", MAGENTA, "`Left 1", Instant::now());
+	assert_eq!(views.owner("Left"), Some("left"));
+	views.delete("Left").unwrap();
+	assert_eq!(views.owner("Left"), None);
+    }
+
+    #[test]
+    fn memory_budget_refuses_declarations() {
+	let mut views = DebugObjects::new();
+	// 64 samples: 512 bytes of times, 512 per signal with the default overlay of 1
+	views.set_memory_budget(Some(1600));
+	views.feed_text("`SCOPE MyScope SAMPLES 64\n`MyScope 'A' 0 10 64 0\n`MyScope 'B' 0 10 64 0\n");
+	assert_eq!(views.memory_used(), 1536);
+	assert!(views.budget_error().is_none());
+	views.feed("`MyScope 'C' 0 10 64 0");
+	assert_eq!(views.memory_used(), 1536);
+	assert!(views.budget_error().unwrap().starts_with("MyScope needs 512 bytes"));
+	views.feed("`SCOPE Other SAMPLES 64");
+	assert_eq!(views.names(), vec!["MyScope"]);
+	// Samples don't count against the budget, they are preallocated
+	views.feed("`MyScope 1 2");
+	assert_eq!(views.memory_used(), 1536);
+    }
+
+    #[test]
+    fn memory_footprint_counts_signal_options() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SAMPLES 64\n`MyScope 'Plain' 0 10 64 0\n");
+	// 512 bytes of times, 512 per signal with the default overlay of 1
+	assert_eq!(views.memory_used(), 1024);
+	// RMS keeps 8 squares as f64, HOLD one u64 row per sample
+	views.set_memory_budget(Some(1024 + 512 + 64 + 512 + 512 - 1));
+	views.feed("`MyScope 'Rms' 0 10 64 0 RMS 8");
+	assert_eq!(views.memory_used(), 1024 + 512 + 64);
+	views.feed("`MyScope 'Slow' 0 10 64 0 HOLD");
+	assert!(views.budget_error().unwrap().starts_with("MyScope needs 1024 bytes"));
+	views.set_memory_budget(None);
+	views.feed("`MyScope 'Slow' 0 10 64 0 HOLD");
+	assert_eq!(views.memory_used(), 1024 + 512 + 64 + 512 + 512);
+	views.feed("`EYE MyEye PERIOD 10 PERSIST 4");
+	assert_eq!(views.memory_used(), 1024 + 512 + 64 + 512 + 512 + 5 * 10 * 4);
+    }
+
+    #[test]
+    fn absurd_buffer_sizes_are_refused() {
+	assert!(Scope::new("MyScope SAMPLES -1").is_err());
+	assert!(Eye::new("MyEye PERIOD 0").is_err());
+	assert!(Eye::new("MyEye PERSIST 0").is_err());
+	let mut views = DebugObjects::new();
+	// Refused by the default budget before anything is allocated
+	views.feed("`SCOPE Huge SAMPLES 1000000000000");
+	assert!(views.budget_error().unwrap().starts_with("Huge needs"));
+	views.feed("`EYE Wide PERIOD 1000000000000 PERSIST 1000000000000");
+	assert!(views.budget_error().unwrap().starts_with("Wide needs"));
+	assert!(views.names().is_empty());
+	assert_eq!(views.memory_used(), 0);
+    }
+
+    #[test]
+    fn signal_math() {
+	let mut scope = Scope::new("MyScope SAMPLES 8").unwrap();
+	scope.feed("'A' -10 10 64 0 ABS", 0.0);
+	scope.feed("'B' 0 10 64 0 RMS 2", 0.0);
+	scope.feed("'C' -100 100 64 0 BLUE DB", 0.0);
+	assert_eq!(scope.signals[2].color, BLUE);
+	scope.feed("-3 3 10", 0.0);
+	scope.feed("2 4 0", 0.0);
+	let last: Vec<f32> = scope.signals.iter().map(|signal| { *signal.values.back().unwrap() }).collect();
+	assert_eq!(last[0], 2.0);
+	assert!((last[1] - 12.5f32.sqrt()).abs() < 1e-6);
+	// silence is clamped to the minimum
+	assert_eq!(last[2], -100.0);
+	assert_eq!(scope.signals[2].values[scope.signals[2].values.len() - 2], 20.0);
+	assert!(parse_signal_config("'D' 0 1 64 0 RMS 0").is_err());
+    }
+
+    #[test]
+    fn readout_deadband_and_rate() {
+	let mut scope = Scope::new("MyScope SAMPLES 8 TEXTRATE 2").unwrap();
+	scope.feed("'A' 0 10 64 0", 0.0);
+	scope.feed("READOUT 'A' 1 0.5", 0.0);
+	let format = NumberFormat::default();
+	scope.feed("1.04", 1.0);
+	assert_eq!(scope.signals[0].label(&format), "A 1.0");
+	// too soon
+	scope.feed("5", 1.2);
+	assert_eq!(scope.signals[0].label(&format), "A 1.0");
+	// within the deadband
+	scope.feed("1.3", 2.0);
+	assert_eq!(scope.signals[0].label(&format), "A 1.0");
+	scope.feed("1.6", 2.1);
+	assert_eq!(scope.signals[0].label(&format), "A 1.6");
+    }
+
+    #[test]
+    fn resampled_partial_updates() {
+	let mut scope = Scope::new("MyScope SAMPLES 16 RESAMPLE LINEAR").unwrap();
+	scope.feed("'A' 0 10 64 0", 0.0);
+	scope.feed("'B' 0 10 64 0", 0.0);
+	scope.feed("1 2", 0.0);
+	scope.feed("'B' 4", 0.1);
+	scope.feed("'B' 6", 0.2);
+	scope.feed("4", 0.3);
+	let values = |index: usize| -> Vec<f32> { scope.signals[index].values.iter().cloned().collect() };
+	assert_eq!(values(0), vec![0.0, 0.0, 1.0, 2.0, 3.0, 4.0]);
+	assert_eq!(values(1), vec![0.0, 0.0, 2.0, 4.0, 6.0, 6.0]);
+	assert_eq!(scope.times.len(), 4);
+	assert!(Scope::new("MyScope RESAMPLE CUBIC").is_err());
+    }
+
+    #[test]
+    fn signal_quality() {
+	let mut scope = Scope::new("MyScope SAMPLES 16 RESAMPLE HOLD").unwrap();
+	scope.feed("'A' 0 10 64 0", 0.0);
+	scope.feed("'B' 0 10 64 0", 0.0);
+	scope.feed("1 2", 0.0);
+	scope.feed("'A' 3", 2.0);
+	let quality = scope.signal_quality(2.5);
+	assert_eq!(quality[0].staleness, Some(0.5));
+	assert_eq!(quality[0].dropout, 0.0);
+	assert_eq!(quality[0].variance, Some(1.5));
+	assert_eq!(quality[0].health, Health::Good);
+	assert_eq!(quality[1].staleness, Some(2.5));
+	assert!((quality[1].dropout - 0.01).abs() < 1e-6);
+	assert_eq!(quality[1].health, Health::Doubtful);
+	assert_eq!(scope.signal_quality(10.0)[0].health, Health::Bad);
+	// Lost lines count against every signal
+	scope.feed("%5 4 5", 3.0);
+	scope.feed("%105 4 5", 3.0);
+	let quality = scope.signal_quality(3.0);
+	assert!(quality.iter().all(|quality| { quality.dropout > 0.5 && quality.health == Health::Bad }));
+	scope.feed("'C' 0 10 64 0", 3.0);
+	assert_eq!(scope.signal_quality(3.0)[2].staleness, None);
+    }
+
+    #[test]
+    fn refresh_reuses_shapes() {
+	let mut scope = Scope::new("MyScope SAMPLES 8 REFRESH 5").unwrap();
+	scope.feed("'A' 0 10 64 0", 0.0);
+	scope.feed("1", 0.0);
+	let points = |scope: &Scope, now: f64| -> usize {
+	    scope.throttle.shapes(now, scope.rect, || { scope.shapes(scope.rect.wh()) }).lines.iter().map(|line| { line.len() }).sum()
+	};
+	assert_eq!(points(&scope, 0.0), 1);
+	scope.feed("2", 0.1);
+	assert_eq!(points(&scope, 0.1), 1);
+	assert_eq!(points(&scope, 0.2), 2);
+	scope.feed("3", 0.25);
+	scope.restyle(&Colors::default());
+	assert_eq!(points(&scope, 0.25), 3);
+	// So do changes made from the UI
+	scope.feed("4", 0.3);
+	scope.set_signal_visible("A", false);
+	assert_eq!(points(&scope, 0.3), 0);
+	scope.set_signal_visible("A", true);
+	assert_eq!(points(&scope, 0.3), 4);
+	scope.clear();
+	assert_eq!(points(&scope, 0.3), 0);
+	scope.set_refresh(None);
+	scope.feed("5", 0.35);
+	assert_eq!(points(&scope, 0.35), 1);
+	assert!(Scope::new("MyScope REFRESH 0").is_err());
+	let mut views = DebugObjects::new();
+	views.feed_text("`EYE MyEye PERIOD 4 REFRESH 2\n");
+	assert!(views.set_refresh("MyEye", Some(10.0)).is_ok());
+	assert!(views.set_refresh("MyEye", Some(-1.0)).is_err());
+	assert!(views.set_refresh("Missing", None).is_err());
+    }
+
+    #[test]
+    fn idle_objects_keep_their_shapes() {
+	let mut scope = Scope::new("MyScope SAMPLES 8").unwrap();
+	scope.feed("'A' 0 10 64 0", 0.0);
+	scope.feed("1", 0.0);
+	let computed = Cell::new(0);
+	let frame = |scope: &Scope, now: f64| {
+	    scope.throttle.shapes(now, scope.rect, || { computed.set(computed.get() + 1); scope.shapes(scope.rect.wh()) });
+	};
+	frame(&scope, 0.0);
+	frame(&scope, 0.1);
+	frame(&scope, 0.2);
+	assert_eq!(computed.get(), 1);
+	scope.feed("2", 0.3);
+	frame(&scope, 0.3);
+	frame(&scope, 0.4);
+	assert_eq!(computed.get(), 2);
+	scope.mark_gap();
+	frame(&scope, 0.5);
+	assert_eq!(computed.get(), 3);
+    }
+
+    #[test]
+    fn held_signals_step_between_updates() {
+	let mut scope = Scope::new("MyScope SAMPLES 16 RESAMPLE LINEAR").unwrap();
+	scope.feed("'Fast' 0 10 64 0", 0.0);
+	scope.feed("'Slow' 0 10 64 0 HOLD", 0.0);
+	scope.feed("1 2", 0.0);
+	scope.feed("'Fast' 2", 0.1);
+	scope.feed("'Fast' 3", 0.2);
+	scope.feed("4 6", 0.3);
+	let values = |index: usize| -> Vec<f32> { scope.signals[index].values.iter().cloned().collect() };
+	assert_eq!(values(1), vec![0.0, 0.0, 2.0, 2.0, 2.0, 6.0]);
+	assert_eq!(scope.update_positions(&scope.signals[1]), vec![2, 5]);
+	assert!(scope.update_positions(&scope.signals[0]).is_empty());
+	assert_eq!(hold_steps(&[(0.0, 1.0), (1.0, 2.0), (2.0, 2.0)]), vec![(0.0, 1.0), (1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 2.0)]);
+	// Markers scroll out along with their samples
+	for _ in 0..16 {
+	    scope.feed("'Fast' 1", 0.4);
+	}
+	assert!(scope.update_positions(&scope.signals[1]).is_empty());
+    }
+
+    #[test]
+    fn export_svg() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SIZE 64 32 SAMPLES 16\n`MyScope 'A<B' 0 63 32 0\n`MyScope %1 10\n`MyScope %2 20\n`MyScope %5 30\n`MyScope %6 40\n");
+	let svg = views.svg("MyScope").unwrap();
+	assert!(svg.contains(">A&lt;B</text>"));
+	assert!(svg.contains(">dropped: 2</text>"));
+	// The trace breaks at the lost lines
+	let path = svg.lines().find(|line| { line.contains("<path") }).unwrap();
+	assert_eq!(path.matches('M').count(), 2);
+	assert!(views.svg("Unknown").is_err());
+    }
+
+    #[test]
+    fn bands_between_signals() {
+	let mut scope = Scope::new("MyScope SIZE 64 100 SAMPLES 16").unwrap();
+	scope.feed("'Set' 0 100 100 0", 0.0);
+	scope.feed("'Actual' 0 100 100 0", 0.0);
+	scope.feed("BAND 'Set' 'Missing'", 0.0);
+	assert!(scope.bands.is_empty());
+	scope.feed("BAND 'Set' 'Actual'", 0.0);
+	scope.feed("BAND 'Set' 'Actual' RED", 0.0);
+	assert_eq!(scope.bands, vec![Band{ first: "Set".to_string(), second: "Actual".to_string(), color: Some(RED) }]);
+	scope.feed("%1 50 40", 0.0);
+	scope.feed("%3 60 70", 0.1);
+	scope.feed("%4 80 75", 0.2);
+	let (color, outlines) = scope.band_outlines(&scope.bands[0], 100.0).unwrap();
+	assert_eq!(color, RED);
+	assert_eq!(outlines, vec![
+	    vec![(0, 0.0), (1, 0.0), (2, 50.0), (2, 40.0), (1, 0.0), (0, 0.0)],
+	    vec![(3, 60.0), (4, 80.0), (4, 75.0), (3, 70.0)],
+	]);
+	scope.set_signal_visible("Actual", false);
+	assert!(scope.band_outlines(&scope.bands[0], 100.0).is_none());
+    }
+
+    #[test]
+    fn uncertainty_bands() {
+	let mut scope = Scope::new("MyScope SIZE 64 100 SAMPLES 16").unwrap();
+	scope.feed("'Estimate' 0 100 100 0 SIGMA", 0.0);
+	scope.feed("'Raw' 0 100 100 0", 0.0);
+	assert_eq!(scope.columns(), 3);
+	scope.feed("50 5 48", 0.0);
+	scope.feed("60 -2 61", 0.1);
+	scope.feed("98", 0.2);
+	let values = |index: usize| -> Vec<f32> { scope.signals[index].values.iter().cloned().collect() };
+	assert_eq!(values(0), vec![0.0, 0.0, 50.0, 60.0, 98.0]);
+	assert_eq!(values(1), vec![0.0, 0.0, 48.0, 61.0]);
+	// A missing deviation keeps the previous one
+	assert_eq!(scope.signals[0].sigmas.as_ref().unwrap().iter().cloned().collect::<Vec<f32>>(), vec![0.0, 0.0, 5.0, 2.0, 2.0]);
+	assert_eq!(scope.sigma_outlines(&scope.signals[0], 100.0), vec![vec![
+	    (0, 0.0), (1, 0.0), (2, 55.0), (3, 62.0), (4, 100.0),
+	    (4, 96.0), (3, 58.0), (2, 45.0), (1, 0.0), (0, 0.0),
+	]]);
+	assert!(scope.sigma_outlines(&scope.signals[1], 100.0).is_empty());
+    }
+
+    #[test]
+    fn legend_labels_fit_the_width() {
+	assert_eq!(common_prefix(&["Motor.Left.Current", "Motor.Left.Voltage", "Motor.Right.Current"]), "Motor.");
+	assert_eq!(common_prefix(&["Motor.Left.Current", "Motor.Left.Voltage"]), "Motor.Left.");
+	assert_eq!(common_prefix(&["Motor", "Mode"]), "");
+	assert_eq!(common_prefix(&["axis_x", "axis_x_raw"]), "axis_");
+	assert_eq!(common_prefix(&["a.", "a.b"]), "");
+	assert_eq!(common_prefix(&["Motor.Left"]), "");
+	assert_eq!(common_prefix(&["Motör.A", "Motör.B"]), "Motör.");
+	let style = Style::new();
+	let mut scope = Scope::new("MyScope SIZE 2000 64").unwrap();
+	for name in &["Motor.Left.Current", "Motor.Left.Voltage", "Motor.Right.Current"] {
+	    scope.feed(&format!("'{}' 0 10 64 0", name), 0.0);
+	}
+	let full = scope.legend_labels(&style);
+	assert_eq!(full[0], "Motor.Left.Current");
+	let elided = vec!["\u{2026}Left.Current".to_string(), "\u{2026}Left.Voltage".to_string(), "\u{2026}Right.Current".to_string()];
+	let width = (legend_width(&full, &style) + legend_width(&elided, &style)) / 2.0;
+	scope.rect = Rect::from_x_y_w_h(0.0, 0.0, width + style.signal_name_offset.x, 64.0);
+	assert_eq!(scope.legend_labels(&style), elided);
+	scope.rect = Rect::from_x_y_w_h(0.0, 0.0, 240.0, 64.0);
+	let truncated = scope.legend_labels(&style);
+	assert!(truncated.iter().all(|label| { label.starts_with('\u{2026}') && label.ends_with('\u{2026}') }));
+	assert!(legend_width(&truncated, &style) <= 240.0 - style.signal_name_offset.x);
+    }
+
+    #[test]
+    fn packed_samples() {
+	let mut scope = Scope::new("MyScope SAMPLES 16 PACKED 16 SIGNED").unwrap();
+	scope.feed("'A' -40000 40000 64 0", 0.0);
+	scope.feed("'B' -40000 40000 64 0", 0.0);
+	scope.feed("#ff7f0080", 0.0);
+	scope.feed("#0100020003000400", 0.1);
+	scope.feed("=BQAGAA==", 0.15);
+	// Not a whole row
+	scope.feed("#010002", 0.2);
+	let values = |index: usize| -> Vec<f32> { scope.signals[index].values.iter().cloned().collect() };
+	assert_eq!(values(0), vec![0.0, 0.0, 32767.0, 1.0, 3.0, 5.0]);
+	assert_eq!(values(1), vec![0.0, 0.0, -32768.0, 2.0, 4.0, 6.0]);
+	let unsigned = Packed{ bytes: 1, signed: false };
+	assert_eq!(unsigned.decode(&[0xff], 1).unwrap(), vec![vec![255.0]]);
+	assert!(parser::scope_line("#0g").is_err());
+    }
+
+    #[test]
+    fn signal_groups() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE Motor SAMPLES 8\n\
+			 `Motor 'IA' 0 63 64 10\n\
+			 `Motor 'IB' 0 63 64 10\n\
+			 `Motor 'Speed' 0 63 64 10\n\
+			 `SCOPE Other SAMPLES 8\n\
+			 `Other 'IA' 0 63 64 10\n\
+			 `Motor 1 2 3\n\
+			 `GROUP Phases Motor.IA IB\n\
+			 `GROUP Phases HIDE\n\
+			 `GROUP Phases PALETTE\n");
+	assert_eq!(views.group_signals("Phases").unwrap(), vec![
+	    ("Motor".to_string(), "IA".to_string()),
+	    ("Motor".to_string(), "IB".to_string()),
+	]);
+	match views.get("Motor").unwrap() {
+	    DebugObject::Scope(scope) => {
+		assert_eq!(scope.signals.iter().map(|s| { s.visible }).collect::<Vec<bool>>(), vec![false, false, true]);
+		assert_eq!(scope.signals[1].color, GROUP_PALETTE[1]);
+	    }
+	    _ => { assert!(false); }
+	}
+	let mut output = vec![];
+	views.write_group_csv("Phases", &mut output).unwrap();
+	assert!(String::from_utf8(output).unwrap().starts_with("sample,Motor.IA,Motor.IB\n"));
+	// Quoted names may hold spaces and commas
+	views.feed_text("`Motor 'I, total' 0 63 64 10\n\
+			 `GROUP 'Power Rail' Motor.'I, total' Motor.Speed\n\
+			 `GROUP 'Power Rail' HIDE\n");
+	assert_eq!(views.group_signals("Power Rail").unwrap(), vec![
+	    ("Motor".to_string(), "I, total".to_string()),
+	    ("Motor".to_string(), "Speed".to_string()),
+	]);
+	let mut output = vec![];
+	views.write_group_csv("Power Rail", &mut output).unwrap();
+	assert!(String::from_utf8(output).unwrap().starts_with("sample,\"Motor.I, total\",Motor.Speed\n"));
+	assert!(views.set_group_visible("Nope", true).is_err());
+    }
+
+    #[test]
+    fn feed_pasted_text() {
+	let mut views = DebugObjects::new();
+	let count = views.feed_text("`SCOPE MyScope SAMPLES 8\r\n\n`MyScope 'A' 0 63 64 10\r\n; calibrated\r\n#`MyScope 9\n`MyScope 7\n");
+	assert_eq!(count, 3);
+	assert_eq!(views.signal_stats("MyScope").unwrap()[0].max, Some(7.0));
+    }
+
+    #[test]
+    fn non_latin_names() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE 温度 SAMPLES 8\n`温度 '电压' 0 10 64 0\n`温度 'Напряжение' 0 10 64 0\n`温度 3 4\n");
+	assert_eq!(views.names(), vec!["温度"]);
+	let stats = views.signal_stats("温度").unwrap();
+	assert_eq!((stats[0].name.as_str(), stats[1].name.as_str()), ("电压", "Напряжение"));
+	assert_eq!(namespace_line("左", "`SCOPE '温度' SAMPLES 8"), "`SCOPE '左.温度' SAMPLES 8");
+	let style = Style::new();
+	assert!(covers(&style.default_font, "Temperatur Ωμ Напряжение"));
+	assert!(!covers(&style.default_font, "电压"));
+	assert!(style.font_for("电压").is_none());
+    }
+
+    #[test]
+    fn capabilities_are_answered() {
+	let mut views = DebugObjects::new();
+	views.feed("`CAPS motor-fw 2.1 PACKED NAMED");
+	assert_eq!(views.peer_capabilities().unwrap(), &["motor-fw", "2.1", "PACKED", "NAMED"]);
+	let reply = views.drain_commands();
+	assert_eq!(reply.len(), 1);
+	assert!(reply[0].starts_with("`CAPS rusty-peanut ") && reply[0].ends_with(" MATH EYE GROUP EVENT RULE SKIP"));
+	assert_eq!(namespace_line("left", "`CAPS motor-fw 2.1"), "`CAPS motor-fw 2.1");
+	views.feed("`PLOT Motor 1 2");
+	views.feed("`PLOT Motor 3 4");
+	assert_eq!(views.unknown_keywords(), vec!["PLOT"]);
+	assert!(views.names().is_empty());
+    }
+
+    #[test]
+    fn samples_keep_their_receive_time() {
+	let mut views = DebugObjects::new();
+	for line in &["`SCOPE MyScope SAMPLES 8", "`MyScope 'A' 0 63 64 10"] {
+	    views.feed(line);
+	}
+	views.feed_received("`MyScope 1", views.start + Duration::from_millis(1500));
+	views.feed_received("`MyScope 2", views.start + Duration::from_millis(2500));
+	let mut output = vec![];
+	views.get("MyScope").unwrap().write_csv(&mut output, &NumberFormat::default()).unwrap();
+	let times: Vec<f64> = String::from_utf8(output).unwrap().lines().skip(1)
+	    .map(|line| { line.split(',').nth(1).unwrap().parse().unwrap() })
+	    .collect();
+	assert_eq!(times, vec![1.5, 2.5]);
+    }
+
+    #[test]
+    fn rules_raise_alarms_and_send() {
+	let mut views = DebugObjects::new();
+	for line in &["`SCOPE Motor SAMPLES 8", "`Motor 'Current' 0 1 64 10", "`Motor 'Velocity' 0 100 64 10"] {
+	    views.feed(line);
+	}
+	views.add_rule(Rule::new(crate::rules::RuleConfig{ name: "stall".to_string(), when: "Motor.Current > 2 && Velocity < 10".to_string(), alarm: true, capture: vec![], send: Some("STOP".to_string()) }).unwrap());
+	views.feed("`Motor 1 50");
+	views.feed("`Motor 3 5");
+	views.feed("`Motor 3 4");
+	assert_eq!(views.alarms().search("stall").count(), 1);
+	assert_eq!(views.drain_commands(), vec!["STOP"]);
+	views.feed("`RULE fast Velocity > 90");
+	views.feed("`Motor 0 95");
+	assert_eq!(views.alarms().search("fast").count(), 1);
+	assert!(views.drain_commands().is_empty());
+    }
+
+    #[test]
+    fn rule_captures_stay_inside_capture_dir() {
+	let directory = std::env::temp_dir().join(format!("rusty-peanut-captures-{}", std::process::id()));
+	let mut views = DebugObjects::new();
+	views.set_capture_dir(&directory.to_string_lossy());
+	for line in &["`SCOPE Motor SAMPLES 8", "`Motor 'Current' 0 1 64 10"] {
+	    views.feed(line);
+	}
+	views.add_rule(Rule::new(crate::rules::RuleConfig{ name: "../../escape".to_string(), when: "Current > 2".to_string(), alarm: false, capture: vec!["Motor".to_string()], send: None }).unwrap());
+	views.feed("`Motor 1");
+	views.feed("`Motor 3");
+	let written: Vec<String> = std::fs::read_dir(&directory).unwrap()
+	    .map(|entry| { entry.unwrap().file_name().to_string_lossy().to_string() })
+	    .collect();
+	assert_eq!(written.len(), 1);
+	assert!(written[0].starts_with("_.._escape-Motor-"));
+	assert!(!directory.parent().unwrap().join("escape-Motor").exists());
+	std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn events_go_to_the_alarm_log() {
+	let mut views = DebugObjects::new();
+	views.feed("`EVENT kernel usb 1-1: new device");
+	views.feed("`EVENT");
+	let events: Vec<&AlarmEvent> = views.alarms().search("usb").collect();
+	assert_eq!(events.len(), 1);
+	assert_eq!(events[0].kind, AlarmKind::Event);
+	assert_eq!(events[0].scope, "kernel");
+	assert_eq!(events[0].signal, "usb 1-1: new device");
+    }
+
+    #[test]
+    fn reconnect_marks_gaps() {
+	let mut views = DebugObjects::new();
+	for line in &["`SCOPE MyScope SAMPLES 8", "`MyScope 'A' 0 63 64 10", "`MyScope 1"] {
+	    views.feed(line);
+	}
+	views.reconnected();
+	assert_eq!(views.alarms().search("reconnect").count(), 0);
+	views.set_reconnect_markers(true);
+	views.reconnected();
+	views.feed("`MyScope 2");
+	views.feed("`MyScope 3");
+	let mut output = vec![];
+	views.get("MyScope").unwrap().write_csv(&mut output, &NumberFormat::default()).unwrap();
+	let gaps: Vec<String> = String::from_utf8(output).unwrap().lines().skip(1)
+	    .map(|line| { line.split(',').nth(2).unwrap().to_string() })
+	    .collect();
+	assert_eq!(gaps, vec!["0", "0", "0", "1", "0"]);
+	assert_eq!(views.alarms().search("reconnect").count(), 1);
+    }
+
+    #[test]
+    fn sequence_numbers_reveal_drops() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SAMPLES 16\n`MyScope 'A' 0 63 64 10\n`MyScope %7 1\n`MyScope %8 2\n`MyScope %11 @5 3\n`MyScope %0 4\n`MyScope %1 5\n");
+	assert_eq!(views.dropped(), vec![("MyScope".to_string(), 2)]);
+	match views.get("MyScope").unwrap() {
+	    DebugObject::Scope(scope) => {
+		assert_eq!(scope.signals[0].values, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+		assert_eq!(scope.gap_markers(5), vec![(2, 2)]);
+	    }
+	    _ => { assert!(false); }
+	}
+    }
+
+    #[test]
+    fn lock_refuses_destructive_actions() {
+	let mut views = DebugObjects::new();
+	for line in &["`SCOPE MyScope SAMPLES 8", "`MyScope 'A' 0 63 64 10", "`MyScope 1"] {
+	    views.feed(line);
+	}
+	views.set_locked(true);
+	views.feed("`CLEAR");
+	views.feed("`MyScope CLEAR");
+	views.feed("`SCOPE MyScope SAMPLES 16");
+	assert!(views.delete("MyScope").is_err());
+	assert_eq!(views.signal_stats("MyScope").unwrap()[0].max, Some(1.0));
+	assert_eq!(views.alarms().search("blocked").count(), 4);
+	views.set_locked(false);
+	views.feed("`MyScope CLEAR");
+	assert_eq!(views.signal_stats("MyScope").unwrap()[0].count, 0);
+	views.delete("MyScope").unwrap();
+	assert!(views.names().is_empty());
+    }
+
+    #[test]
+    fn z_order_and_overlaps() {
+	let mut debug_objects = DebugObjects::new();
+	debug_objects.feed("`SCOPE A POS 0 0 SIZE 100 100");
+	debug_objects.feed("`SCOPE B POS 50 -50 SIZE 100 100");
+	debug_objects.feed("`EYE C POS 300 0 SIZE 100 100");
+	assert_eq!(debug_objects.z_order, vec!["A", "B", "C"]);
+	assert_eq!(debug_objects.overlaps(), vec![("A".to_string(), "B".to_string())]);
+	assert_eq!(debug_objects.object_at(pt2(75.0, -75.0)), Some("B".to_string()));
+	debug_objects.raise("A").unwrap();
+	assert_eq!(debug_objects.object_at(pt2(75.0, -75.0)), Some("A".to_string()));
+	debug_objects.lower("C").unwrap();
+	assert_eq!(debug_objects.z_order, vec!["C", "B", "A"]);
+	assert!(debug_objects.raise("D").is_err());
+    }
+
+    #[test]
+    fn objects_keep_creation_order() {
+	let mut debug_objects = DebugObjects::new();
+	for name in &["Zeta", "Alpha", "Mu", "Beta"] {
+	    debug_objects.feed(&format!("`SCOPE {}", name));
+	}
+	// Redeclaring keeps the original position
+	debug_objects.feed("`SCOPE Alpha SAMPLES 64");
+	assert_eq!(debug_objects.names(), vec!["Zeta", "Alpha", "Mu", "Beta"]);
+	assert_eq!(debug_objects.z_order, vec!["Zeta", "Alpha", "Mu", "Beta"]);
+    }
+
+    #[test]
+    fn timebase_falls_back_to_host_time() {
+	let mut scope = Scope::new("MyScope SAMPLES 8 TIMEBASE DEVICE 1000").unwrap();
+	assert_eq!(scope.timebase, TimeBase::Device{ ticks_per_second: 1000.0 });
+	scope.feed("'A' 0 63 64 10", 0.0);
+	scope.feed("@1500 1", 10.0);
+	scope.feed("@2000 2", 11.0);
+	assert_eq!(scope.times, vec![1.5, 2.0]);
+	assert_eq!(scope.time_source(), "DEVICE");
+	// A line without timestamp switches to host time and drops the history
+	scope.feed("3", 12.0);
+	assert!(scope.fallback);
+	assert_eq!(scope.times, vec![12.0]);
+	assert_eq!(scope.signals[0].values, vec![3.0]);
+	scope.feed("@3000 4", 13.0);
+	assert!(!scope.fallback);
+	assert_eq!(scope.times, vec![3.0]);
+    }
+
+    #[test]
+    fn eye_folds_signal_by_period() {
+	let mut eye = Eye::new("MyEye PERIOD 4 PERSIST 2").unwrap();
+	eye.feed("'PWM' 0 1 CYAN", 0.0);
+	assert_eq!(eye.signal.color, CYAN);
+	eye.feed("0, 1, 1, 0, 0, 1, 1, 0, 0, 0, 1, 1, 1", 0.0);
+	assert_eq!(eye.traces.len(), 2);
+	assert_eq!(eye.traces[1], vec![0.0, 0.0, 1.0, 1.0]);
+	assert_eq!(eye.current, vec![1.0]);
+    }
+
+    #[test]
+    fn colors_cascade_from_defaults_to_signals() {
+	let mut debug_objects = DebugObjects::new();
+	debug_objects.feed("`SCOPE MyScope GRID WHITE SAMPLES 8");
+	debug_objects.feed("`MyScope 'A' 0 10 64 0");
+	debug_objects.feed("`MyScope 'B' 0 10 64 0 RED");
+	let colors = |debug_objects: &DebugObjects| -> Vec<Color> {
+	    match debug_objects.get("MyScope") {
+		Some(DebugObject::Scope(scope)) => scope.signals.iter().map(|signal| { signal.color }).collect(),
+		_ => panic!("no scope"),
+	    }
+	};
+	assert_eq!(colors(&debug_objects), vec![COLORBLIND_CYCLE[0], RED]);
+	debug_objects.set_colors(Colors{ background: WHITE, grid: BLACK, signal: BLUE, cycle: false });
+	debug_objects.feed("`MyScope 'C' 0 10 64 0");
+	match debug_objects.get("MyScope") {
+	    Some(DebugObject::Scope(scope)) => {
+		assert_eq!((scope.background, scope.grid), (WHITE, WHITE));
+	    }
+	    _ => panic!("no scope"),
+	}
+	assert_eq!(colors(&debug_objects), vec![BLUE, RED, BLUE]);
+	debug_objects.set_colors(Colors::default());
+	assert_eq!(colors(&debug_objects), vec![COLORBLIND_CYCLE[0], RED, COLORBLIND_CYCLE[2]]);
+	// COLOR of the object wins over the cycle
+	debug_objects.feed("`SCOPE Other COLOR CYAN");
+	debug_objects.feed("`Other 'A' 0 10 64 0");
+	match debug_objects.get("Other") {
+	    Some(DebugObject::Scope(scope)) => { assert_eq!(scope.signals[0].color, CYAN); }
+	    _ => panic!("no scope"),
+	}
+	assert!(Scope::new("MyScope BACKGROUND BLURPLE").is_err());
+	assert_eq!(color_by_name("STEELBLUE"), Some(STEELBLUE));
+    }
+
     #[test]
     fn test_configuration_signal() {
-	let tokens = to_tokens(&["'Sawtooth'", "0", "63", "64", "10", "%1111", "CYAN"]);
-	let signal_config = ScopeSignalConfig::from_tokens(&tokens).expect("invalid configuration");
+	let signal_config = parse_signal_config("'Sawtooth' 0 63 64 10 %1111 CYAN").expect("invalid configuration");
 	assert_eq!(signal_config.name, "Sawtooth");
 	assert_eq!(signal_config.min, 0.0);
 	assert_eq!(signal_config.max, 63.0);
 	assert_eq!(signal_config.y_size, 64.0);
 	assert_eq!(signal_config.y_base, 10.0);
-	assert_eq!(signal_config.color, CYAN);
+	assert_eq!(signal_config.color, Some(CYAN));
+	assert_eq!(signal_config.legend, Legend{ max: true, min: true, max_line: true, min_line: true });
+
+	let signal_config = parse_signal_config("'Sawtooth' 0 63 64 10 %1001").expect("invalid configuration");
+	assert_eq!(signal_config.legend, Legend{ max: true, min: false, max_line: false, min_line: true });
+	assert_eq!(signal_config.color, None);
+
+	let signal_config = parse_signal_config("'Sawtooth' 0 63 64 10 RED").expect("invalid configuration");
+	assert_eq!(signal_config.color, Some(RED));
+    }
+
+    // What examples/NAME.toml expects after feeding examples/NAME.peanut
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    struct CorpusObject
+    {
+	name: String,
+	signals: usize,
+	samples: usize,
+	checksum: String,
     }
 
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    struct CorpusExpectation
+    {
+	objects: Vec<CorpusObject>,
+    }
+
+    // FNV-1a over the bits of all retained values
+    fn buffer_checksum(debug_object: &DebugObject) -> String
+    {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for signal in debug_object.signal_names() {
+	    for value in debug_object.signal_values(&signal).unwrap() {
+		for byte in value.to_bits().to_le_bytes().iter() {
+		    hash ^= *byte as u64;
+		    hash = hash.wrapping_mul(0x100000001b3);
+		}
+	    }
+	}
+	format!("{:016x}", hash)
+    }
+
+    #[test]
+    fn example_corpus() {
+	let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+	let mut captures = 0;
+	for entry in std::fs::read_dir(&directory).unwrap() {
+	    let path = entry.unwrap().path();
+	    if path.extension().map(|extension| { extension != "peanut" }).unwrap_or(true) {
+		continue;
+	    }
+	    let mut views = DebugObjects::new();
+	    views.feed_text(&std::fs::read_to_string(&path).unwrap());
+	    // UPDATE_CORPUS=1 cargo test example_corpus rewrites the
+	    // expectations after an intended change of the results
+	    if std::env::var_os("UPDATE_CORPUS").is_some() {
+		let objects = views.names().into_iter().map(|name| {
+		    let debug_object = views.get(&name).unwrap();
+		    let signals = debug_object.signal_names();
+		    CorpusObject{
+			samples: debug_object.signal_values(&signals[0]).unwrap().len(),
+			signals: signals.len(),
+			checksum: buffer_checksum(debug_object),
+			name,
+		    }
+		}).collect();
+		std::fs::write(path.with_extension("toml"), toml::to_string(&CorpusExpectation{ objects }).unwrap()).unwrap();
+	    }
+	    let expectation: CorpusExpectation = toml::from_str(&std::fs::read_to_string(path.with_extension("toml")).unwrap()).unwrap();
+	    let names: Vec<String> = expectation.objects.iter().map(|object| { object.name.clone() }).collect();
+	    assert_eq!(views.names(), names, "{:?}", path);
+	    for expected in &expectation.objects {
+		let debug_object = views.get(&expected.name).unwrap();
+		let signals = debug_object.signal_names();
+		assert_eq!(signals.len(), expected.signals, "{:?} {}", path, expected.name);
+		assert_eq!(debug_object.signal_values(&signals[0]).unwrap().len(), expected.samples, "{:?} {}", path, expected.name);
+		assert_eq!(buffer_checksum(debug_object), expected.checksum, "{:?} {}", path, expected.name);
+	    }
+	    captures += 1;
+	}
+	assert!(captures >= 3);
+    }
 }
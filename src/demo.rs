@@ -0,0 +1,108 @@
+use std::f64::consts::PI;
+use std::thread;
+use std::time::Duration;
+use crossbeam::channel::{Receiver, unbounded};
+use crate::serial::Received;
+use crate::shutdown;
+
+// Samples per period of the sine and the sawtooth
+const PERIOD: u64 = 64;
+
+// Produces the lines of a fictional device: a scope with a sine,
+// a sawtooth and noise.
+pub struct DemoSignals
+{
+    step: u64,
+    // xorshift state of the noise
+    seed: u64,
+}
+
+impl DemoSignals
+{
+    pub fn new() -> DemoSignals
+    {
+	DemoSignals{ step: 0, seed: 0x2545_f491_4f6c_dd1d }
+    }
+
+    pub fn declarations(&self) -> Vec<String>
+    {
+	vec![
+	    "`SCOPE Demo SIZE 400 256 SAMPLES 256".to_string(),
+	    "`Demo 'Sine' -1 1 80 170 CYAN".to_string(),
+	    "`Demo 'Sawtooth' 0 1 80 85 MAGENTA".to_string(),
+	    "`Demo 'Noise' -1 1 80 0 YELLOW".to_string(),
+	]
+    }
+
+    fn noise(&mut self) -> f64
+    {
+	self.seed ^= self.seed << 13;
+	self.seed ^= self.seed >> 7;
+	self.seed ^= self.seed << 17;
+	(self.seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+
+    pub fn next_line(&mut self) -> String
+    {
+	let phase = (self.step % PERIOD) as f64 / PERIOD as f64;
+	self.step += 1;
+	let noise = self.noise();
+	format!("`Demo {:.4} {:.4} {:.4}", (phase * 2.0 * PI).sin(), phase, noise)
+    }
+}
+
+// Streams DemoSignals, so the renderer can be tried without hardware
+pub struct SimulatedSource
+{
+    pub receiver: Receiver<Received>
+}
+
+impl SimulatedSource
+{
+    // Rate in lines per second
+    pub fn new(rate: f64) -> SimulatedSource
+    {
+	let (s, r) = unbounded();
+	let interval = Duration::from_secs_f64(1.0 / rate.max(1.0));
+	thread::spawn(move || {
+	    let mut signals = DemoSignals::new();
+	    for line in signals.declarations() {
+		s.send(Received::now(line)).ok();
+	    }
+	    while !shutdown::requested() {
+		if s.send(Received::now(signals.next_line())).is_err() {
+		    break;
+		}
+		thread::sleep(interval);
+	    }
+	});
+	SimulatedSource{ receiver: r }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+    use crate::debugobjects::DebugObjects;
+
+    #[test]
+    fn demo_lines_fill_a_scope() {
+	let mut signals = DemoSignals::new();
+	let mut views = DebugObjects::new();
+	for line in signals.declarations() {
+	    views.feed(&line);
+	}
+	assert_eq!(signals.next_line().split_whitespace().take(3).collect::<Vec<&str>>(), vec!["`Demo", "0.0000", "0.0000"]);
+	for _ in 0..PERIOD {
+	    let line = signals.next_line();
+	    let noise = line.split_whitespace().last().unwrap().parse::<f64>().unwrap();
+	    assert!((-1.0..=1.0).contains(&noise));
+	    views.feed(&line);
+	}
+	assert_eq!(views.names(), vec!["Demo"]);
+	let stats = views.signal_stats("Demo").unwrap();
+	assert_eq!(stats.len(), 3);
+	assert_eq!(stats[0].max, Some(1.0));
+    }
+}
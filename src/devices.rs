@@ -0,0 +1,149 @@
+use nannou::prelude::*;
+use std::time::{Duration, Instant};
+use crate::serial::{ConnectionState, IngestOptions, LineSettings, SerialConnector};
+use crate::source::DataSource;
+use crate::dialect::Dialect;
+
+type Rect = nannou::geom::rect::Rect;
+
+// Border colors of the scopes of each device, in configuration order
+const DEVICE_PALETTE: [Color; 6] = [CYAN, MAGENTA, ORANGE, GREEN, YELLOW, WHITE];
+
+// How often the line rate is recomputed
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Connection state and traffic of one device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceHealth
+{
+    pub name: String,
+    pub color: Color,
+    pub state: ConnectionState,
+    // Lines per second over the last window
+    pub rate: f64,
+    pub last_seen: Option<Instant>,
+    lines: u64,
+    window_start: Instant,
+}
+
+impl DeviceHealth
+{
+    pub fn new(name: &str, index: usize, now: Instant) -> DeviceHealth
+    {
+	DeviceHealth{
+	    name: name.to_string(),
+	    color: DEVICE_PALETTE[index % DEVICE_PALETTE.len()],
+	    state: ConnectionState::Connected,
+	    rate: 0.0,
+	    last_seen: None,
+	    lines: 0,
+	    window_start: now,
+	}
+    }
+
+    pub fn line(&mut self, now: Instant)
+    {
+	self.lines += 1;
+	self.last_seen = Some(now);
+    }
+
+    pub fn tick(&mut self, now: Instant)
+    {
+	let elapsed = now.duration_since(self.window_start);
+	if elapsed >= RATE_WINDOW {
+	    self.rate = self.lines as f64 / elapsed.as_secs_f64();
+	    self.lines = 0;
+	    self.window_start = now;
+	}
+    }
+
+    pub fn summary(&self, now: Instant) -> String
+    {
+	let state = match self.state {
+	    ConnectionState::Connected => "connected",
+	    ConnectionState::Reconnecting => "reconnecting",
+	};
+	let last_seen = match self.last_seen {
+	    Some(last_seen) => format!("{:.1}s ago", now.duration_since(last_seen).as_secs_f64()),
+	    None => "never".to_string(),
+	};
+	format!("{}: {}, {:.0} lines/s, last seen {}", self.name, state, self.rate, last_seen)
+    }
+}
+
+// One of several serial inputs, its objects get its color
+pub struct Device
+{
+    pub source: Box<dyn DataSource>,
+    pub dialect: Box<dyn Dialect>,
+    pub health: DeviceHealth,
+    // Prefixed to the object names, see debugobjects::namespace_line
+    pub namespace: Option<String>,
+}
+
+impl Device
+{
+    pub fn open(name: &str, index: usize, port: &str, baud: u32, line: LineSettings, options: IngestOptions, dialect: Box<dyn Dialect>) -> Result<Device, serialport::Error>
+    {
+	let serial = SerialConnector::new(port, baud, line, options)?;
+	Ok(Device{ source: Box::new(serial), dialect, health: DeviceHealth::new(name, index, Instant::now()), namespace: None })
+    }
+}
+
+// Lists all devices, shown while more than one is configured
+pub struct HealthPanel
+{
+    pub visible: bool,
+}
+
+impl HealthPanel
+{
+    pub fn new() -> HealthPanel
+    {
+	HealthPanel{ visible: true }
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect, devices: &[Device])
+    {
+	if !self.visible || devices.is_empty() {
+	    return;
+	}
+	let now = Instant::now();
+	let line_height = 16.0;
+	let rect = Rect::from_w_h(rect.w(), line_height * devices.len() as f32 + 4.0).align_top_of(rect).align_left_of(rect);
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.85));
+	for (index, device) in devices.iter().enumerate() {
+	    let health = &device.health;
+	    let y = rect.top() - 2.0 - line_height * (index as f32 + 0.5);
+	    draw.text(&health.summary(now))
+		.x_y(rect.x(), y)
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(12)
+		.left_justify()
+		.color(if health.state == ConnectionState::Connected { health.color } else { RED });
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn rate_and_last_seen() {
+	let start = Instant::now();
+	let mut health = DeviceHealth::new("left", 1, start);
+	assert_eq!(health.color, MAGENTA);
+	assert_eq!(health.summary(start), "left: connected, 0 lines/s, last seen never");
+	for _ in 0..20 {
+	    health.line(start + Duration::from_millis(500));
+	}
+	health.tick(start + Duration::from_millis(500));
+	assert_eq!(health.rate, 0.0);
+	health.tick(start + Duration::from_secs(2));
+	assert_eq!(health.rate, 10.0);
+	health.state = ConnectionState::Reconnecting;
+	assert_eq!(health.summary(start + Duration::from_secs(2)), "left: reconnecting, 10 lines/s, last seen 1.5s ago");
+    }
+}
@@ -0,0 +1,131 @@
+use nannou::prelude::*;
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+type Rect = nannou::geom::rect::Rect;
+
+// What a widget submits per frame. Vertices count the points
+// handed to nannou before tessellation, text counts a quad per glyph.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawCost
+{
+    pub primitives: usize,
+    pub vertices: usize,
+    pub texture_bytes: usize,
+}
+
+impl DrawCost
+{
+    pub fn new(primitives: usize, vertices: usize) -> DrawCost
+    {
+	DrawCost{ primitives, vertices, texture_bytes: 0 }
+    }
+
+    pub fn text(text: &str) -> DrawCost
+    {
+	DrawCost::new(1, text.chars().count() * 4)
+    }
+
+    pub fn polyline(points: usize) -> DrawCost
+    {
+	DrawCost::new(1, points)
+    }
+}
+
+impl Add for DrawCost
+{
+    type Output = DrawCost;
+
+    fn add(self, other: DrawCost) -> DrawCost
+    {
+	DrawCost{
+	    primitives: self.primitives + other.primitives,
+	    vertices: self.vertices + other.vertices,
+	    texture_bytes: self.texture_bytes + other.texture_bytes,
+	}
+    }
+}
+
+impl AddAssign for DrawCost
+{
+    fn add_assign(&mut self, other: DrawCost)
+    {
+	*self = *self + other;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WidgetStats
+{
+    pub name: String,
+    pub cost: DrawCost,
+    // CPU time spent issuing the draw commands
+    pub draw_time: Duration,
+}
+
+// Per frame costs of all widgets, most expensive first
+pub struct DiagnosticsPanel
+{
+    pub visible: bool,
+}
+
+impl DiagnosticsPanel
+{
+    pub fn new() -> DiagnosticsPanel
+    {
+	DiagnosticsPanel{ visible: false }
+    }
+
+    pub fn lines(widgets: &[WidgetStats], frame_time: Duration) -> Vec<String>
+    {
+	let mut widgets: Vec<&WidgetStats> = widgets.iter().collect();
+	widgets.sort_by(|a, b| { b.cost.vertices.cmp(&a.cost.vertices) });
+	let total = widgets.iter().fold(DrawCost::default(), |total, widget| { total + widget.cost });
+	let mut lines = vec![
+	    format!("Frame {:.1}ms - {} primitives, {} vertices, {} texture bytes",
+		    frame_time.as_secs_f64() * 1000.0, total.primitives, total.vertices, total.texture_bytes),
+	];
+	for widget in widgets {
+	    lines.push(format!("{}: {} primitives, {} vertices, {} texture bytes, {}us",
+			       widget.name, widget.cost.primitives, widget.cost.vertices,
+			       widget.cost.texture_bytes, widget.draw_time.as_micros()));
+	}
+	lines
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect, widgets: &[WidgetStats], frame_time: Duration)
+    {
+	if !self.visible {
+	    return;
+	}
+	let line_height = 16.0;
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.85));
+	for (index, line) in DiagnosticsPanel::lines(widgets, frame_time).iter().enumerate() {
+	    let y = rect.top() - line_height * (index as f32 + 0.5);
+	    draw.text(line)
+		.x_y(rect.x(), y)
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(12)
+		.left_justify()
+		.color(if index == 0 { YELLOW } else { WHITE });
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn widgets_sorted_by_cost() {
+	let widgets = vec![
+	    WidgetStats{ name: "Small".to_string(), cost: DrawCost::new(2, 10), draw_time: Duration::from_micros(5) },
+	    WidgetStats{ name: "Big".to_string(), cost: DrawCost::new(3, 100), draw_time: Duration::from_micros(50) },
+	];
+	let lines = DiagnosticsPanel::lines(&widgets, Duration::from_millis(16));
+	assert_eq!(lines[0], "Frame 16.0ms - 5 primitives, 110 vertices, 0 texture bytes");
+	assert!(lines[1].starts_with("Big: 3 primitives"));
+	assert_eq!(DrawCost::text("abc") + DrawCost::polyline(5), DrawCost::new(2, 17));
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use log::warn;
+use serde_json::Value;
+
+use crate::checksum::{self, ChecksumMode};
+use crate::config::Config;
+use crate::parser;
+use crate::mavlink::MavlinkMapping;
+
+// Translates the lines of an input written for another tool into
+// the instructions DebugObjects understands. Each input has its own
+// instance, dialects may keep state such as declared scopes.
+pub trait Dialect: Send
+{
+    fn translate(&mut self, line: &str) -> Vec<String>;
+}
+
+// Our own protocol, passed through
+pub struct Native;
+
+impl Dialect for Native
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	vec![line.to_string()]
+    }
+}
+
+// Range, layout and time base of a Teleplot variable's scope
+struct Variable
+{
+    position: usize,
+    min: f32,
+    max: f32,
+    timestamped: bool,
+}
+
+// Teleplot's >name:value and >name:timestamp:value lines, with an
+// optional unit and flags (>name:value§unit|flags). Each variable
+// gets a scope of its own, stacked top to bottom. A value outside
+// the range declared so far redeclares the scope with a wider one,
+// which restarts its history.
+#[derive(Default)]
+pub struct Teleplot
+{
+    variables: HashMap<String, Variable>,
+}
+
+// Object names can't contain whitespace
+fn object_name(name: &str) -> String
+{
+    name.trim().chars().map(|c| { if c.is_whitespace() { '_' } else { c } }).collect()
+}
+
+// The range declared for values between low and high
+fn range_around(low: f32, high: f32) -> (f32, f32)
+{
+    let margin = ((high - low) / 2.0).max(1.0);
+    (low - margin, high + margin)
+}
+
+impl Teleplot
+{
+    // Name, millisecond timestamp and value
+    fn parse(line: &str) -> Option<(String, Option<f64>, f32)>
+    {
+	let line = line.strip_prefix('>')?;
+	let line = line.split(|c| { c == '|' || c == '§' }).next()?;
+	let mut fields = line.splitn(3, ':');
+	let name = object_name(fields.next()?);
+	let first = fields.next()?.trim();
+	match fields.next() {
+	    Some(value) => Some((name, Some(first.parse::<f64>().ok()?), value.trim().parse::<f32>().ok()?)),
+	    None => Some((name, None, first.parse::<f32>().ok()?)),
+	}
+    }
+
+    fn declare(name: &str, variable: &Variable) -> Vec<String>
+    {
+	let timebase = if variable.timestamped { " TIMEBASE DEVICE 1000" } else { "" };
+	vec![
+	    format!("`SCOPE {} POS 0 {} SIZE 400 128 SAMPLES 256{}", name, variable.position * 140, timebase),
+	    format!("`{} '{}' {} {} 128 0", name, name, variable.min, variable.max),
+	]
+    }
+}
+
+impl Dialect for Teleplot
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	// Anything else is passed on, firmware may mix in our protocol
+	let (name, timestamp, value) = match Teleplot::parse(line) {
+	    Some(parsed) => parsed,
+	    None => { return vec![line.to_string()]; }
+	};
+	let mut lines = vec![];
+	let position = self.variables.len();
+	match self.variables.get_mut(&name) {
+	    Some(variable) => {
+		if value < variable.min || value > variable.max {
+		    let (min, max) = range_around(variable.min.min(value), variable.max.max(value));
+		    variable.min = min;
+		    variable.max = max;
+		    lines.extend(Teleplot::declare(&name, variable));
+		}
+	    }
+	    None => {
+		let (min, max) = range_around(value, value);
+		let variable = Variable{ position, min, max, timestamped: timestamp.is_some() };
+		lines.extend(Teleplot::declare(&name, &variable));
+		self.variables.insert(name.clone(), variable);
+	    }
+	}
+	lines.push(match timestamp {
+	    Some(timestamp) => format!("`{} @{} {}", name, timestamp, value),
+	    None => format!("`{} {}", name, value),
+	});
+	lines
+    }
+}
+
+// Colors of the plotter's columns, in order
+const PLOTTER_COLORS: [&str; 6] = ["YELLOW", "CYAN", "MAGENTA", "ORANGE", "GREEN", "RED"];
+
+// A scope declared from the data it receives, one signal per column.
+// All columns share one range, which grows like Teleplot's.
+pub(crate) struct AutoScope
+{
+    name: String,
+    // Everything after the name in the SCOPE declaration
+    options: String,
+    height: f32,
+    names: Vec<String>,
+    min: f32,
+    max: f32,
+    declared: bool,
+}
+
+impl AutoScope
+{
+    pub(crate) fn new(name: &str, options: String, height: f32) -> AutoScope
+    {
+	AutoScope{ name: name.to_string(), options, height, names: vec![], min: 0.0, max: 0.0, declared: false }
+    }
+
+    // Other names redeclare the scope with the next sample
+    pub(crate) fn rename(&mut self, names: Vec<String>)
+    {
+	if names != self.names {
+	    self.names = names;
+	    self.declared = false;
+	}
+    }
+
+    // The lines for a row of values, after redeclaring if needed.
+    // The prefix carries sequence numbers and timestamps.
+    pub(crate) fn feed(&mut self, prefix: &str, values: &[f32]) -> Vec<String>
+    {
+	if self.names.len() != values.len() {
+	    self.rename((1..=values.len()).map(|index| { format!("value {}", index) }).collect());
+	}
+	let low = values.iter().cloned().fold(f32::INFINITY, f32::min);
+	let high = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+	let mut lines = vec![];
+	if !self.declared || low < self.min || high > self.max {
+	    let (min, max) = if self.declared { range_around(self.min.min(low), self.max.max(high)) } else { range_around(low, high) };
+	    self.min = min;
+	    self.max = max;
+	    self.declared = true;
+	    lines.push(format!("`SCOPE {} {}", self.name, self.options));
+	    lines.extend(self.names.iter().enumerate().map(|(index, name)| {
+		format!("`{} '{}' {} {} {} 0 {}", self.name, name, self.min, self.max, self.height, PLOTTER_COLORS[index % PLOTTER_COLORS.len()])
+	    }));
+	}
+	let values: Vec<String> = values.iter().map(|value| { value.to_string() }).collect();
+	lines.push(format!("`{} {}{}", self.name, prefix, values.join(" ")));
+	lines
+    }
+}
+
+// What the Arduino IDE's Serial Plotter reads: numbers separated by
+// commas, tabs or spaces, each optionally labelled as in temp:20. A
+// line without numbers is a header naming the columns.
+pub struct ArduinoPlotter
+{
+    scope: AutoScope,
+}
+
+impl ArduinoPlotter
+{
+    pub fn new() -> ArduinoPlotter
+    {
+	ArduinoPlotter{ scope: AutoScope::new("Plotter", "SIZE 600 300 SAMPLES 500".to_string(), 300.0) }
+    }
+}
+
+impl Dialect for ArduinoPlotter
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	if line.starts_with('`') {
+	    return vec![line.to_string()];
+	}
+	// Would otherwise rename the columns
+	if parser::comment(line) {
+	    return vec![];
+	}
+	let fields: Vec<(Option<&str>, &str)> = line.split(|c| { c == ',' || c == '\t' || c == ' ' })
+	    .filter(|field| { !field.is_empty() })
+	    .map(|field| {
+		match field.rfind(':') {
+		    Some(position) => (Some(&field[..position]), &field[position + 1..]),
+		    None => (None, field),
+		}
+	    })
+	    .collect();
+	if fields.is_empty() {
+	    return vec![];
+	}
+	let values: Vec<Option<f32>> = fields.iter().map(|(_, value)| { value.parse::<f32>().ok() }).collect();
+	if values.iter().all(Option::is_none) {
+	    self.scope.rename(fields.iter().map(|(label, value)| { label.unwrap_or(value).to_string() }).collect());
+	    return vec![];
+	}
+	// A partly numeric line is noise, e.g. from a reset
+	let values: Vec<f32> = match values.into_iter().collect() {
+	    Some(values) => values,
+	    None => { return vec![]; }
+	};
+	if fields.iter().any(|(label, _)| { label.is_some() }) {
+	    self.scope.rename(fields.iter().enumerate()
+		.map(|(index, (label, _))| { label.map(str::to_string).unwrap_or_else(|| { format!("value {}", index + 1) }) })
+		.collect());
+	}
+	self.scope.feed("", &values)
+    }
+}
+
+// One JSON object per line, e.g.
+//
+// {"scope": "MyScope", "values": [1, 2, 3]}
+// {"scope": "MyScope", "values": {"a": 1, "b": 2}, "seq": 17, "timestamp": 1500}
+//
+// Each scope is declared from its first object, signals are named
+// by the keys of the values or by their position. Timestamps are
+// milliseconds. Other lines are passed on.
+#[derive(Default)]
+pub struct JsonLines
+{
+    scopes: HashMap<String, AutoScope>,
+}
+
+impl Dialect for JsonLines
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	if !line.trim_start().starts_with('{') {
+	    return vec![line.to_string()];
+	}
+	let object: Value = match serde_json::from_str(line) {
+	    Ok(object) => object,
+	    Err(error) => {
+		warn!("invalid JSON line {}: {}", line, error);
+		return vec![];
+	    }
+	};
+	let name = match object["scope"].as_str() {
+	    Some(name) => object_name(name),
+	    None => {
+		warn!("JSON line without scope: {}", line);
+		return vec![];
+	    }
+	};
+	let (names, values): (Option<Vec<String>>, Vec<Option<f64>>) = match &object["values"] {
+	    Value::Array(values) => (None, values.iter().map(Value::as_f64).collect()),
+	    Value::Object(values) => (Some(values.keys().cloned().collect()), values.values().map(Value::as_f64).collect()),
+	    _ => (None, vec![]),
+	};
+	let values: Vec<f32> = match values.into_iter().collect::<Option<Vec<f64>>>() {
+	    Some(values) if !values.is_empty() => values.into_iter().map(|value| { value as f32 }).collect(),
+	    _ => {
+		warn!("JSON line without numeric values: {}", line);
+		return vec![];
+	    }
+	};
+	let timestamp = object["timestamp"].as_f64();
+	let mut prefix = String::new();
+	if let Some(sequence) = object["seq"].as_u64() {
+	    prefix.push_str(&format!("%{} ", sequence));
+	}
+	if let Some(timestamp) = timestamp {
+	    prefix.push_str(&format!("@{} ", timestamp));
+	}
+	let position = self.scopes.len();
+	let scope = self.scopes.entry(name.clone()).or_insert_with(|| {
+	    let timebase = if timestamp.is_some() { " TIMEBASE DEVICE 1000" } else { "" };
+	    AutoScope::new(&name, format!("POS 0 {} SIZE 400 200 SAMPLES 256{}", position * 220, timebase), 200.0)
+	});
+	if let Some(names) = names {
+	    scope.rename(names);
+	}
+	scope.feed(&prefix, &values)
+    }
+}
+
+// Metres per degree of latitude
+const METRES_PER_DEGREE: f64 = 111_320.0;
+
+// NMEA 0183 sentences of GPS modules. GGA, RMC and VTG are turned
+// into Teleplot variables, each in a scope of its own: the position
+// as metres north and east of the first fix, altitude, speed in km/h,
+// course and the number of satellites. Checksums are verified where
+// present, other sentences are ignored.
+#[derive(Default)]
+pub struct Nmea
+{
+    plots: Teleplot,
+    origin: Option<(f64, f64)>,
+}
+
+// Degrees from ddmm.mmmm and the hemisphere
+fn coordinate(value: &str, hemisphere: &str) -> Option<f64>
+{
+    let value = value.parse::<f64>().ok()?;
+    let degrees = (value / 100.0).trunc() + (value % 100.0) / 60.0;
+    match hemisphere {
+	"N" | "E" => Some(degrees),
+	"S" | "W" => Some(-degrees),
+	_ => None,
+    }
+}
+
+impl Nmea
+{
+    fn position(&mut self, fields: &[&str], variables: &mut Vec<(&'static str, f64)>)
+    {
+	let position = coordinate(fields.get(0).unwrap_or(&""), fields.get(1).unwrap_or(&""))
+	    .zip(coordinate(fields.get(2).unwrap_or(&""), fields.get(3).unwrap_or(&"")));
+	if let Some((latitude, longitude)) = position {
+	    let (origin_latitude, origin_longitude) = *self.origin.get_or_insert((latitude, longitude));
+	    variables.push(("north", (latitude - origin_latitude) * METRES_PER_DEGREE));
+	    variables.push(("east", (longitude - origin_longitude) * METRES_PER_DEGREE * origin_latitude.to_radians().cos()));
+	}
+    }
+}
+
+impl Dialect for Nmea
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	let sentence = match line.strip_prefix('$') {
+	    Some(sentence) => sentence,
+	    None => { return vec![line.to_string()]; }
+	};
+	let sentence = match checksum::verify(sentence, ChecksumMode::Optional) {
+	    Some(sentence) => sentence,
+	    None => {
+		warn!("NMEA sentence with a bad checksum: {}", line);
+		return vec![];
+	    }
+	};
+	let fields: Vec<&str> = sentence.split(',').collect();
+	let number = |index: usize| -> Option<f64> { fields.get(index).and_then(|field| { field.parse::<f64>().ok() }) };
+	let mut variables = vec![];
+	// The talker, e.g. GP or GN, doesn't matter
+	match fields[0].get(2..) {
+	    Some("GGA") => {
+		self.position(fields.get(2..).unwrap_or(&[]), &mut variables);
+		variables.extend(number(7).map(|satellites| { ("satellites", satellites) }));
+		variables.extend(number(9).map(|altitude| { ("altitude", altitude) }));
+	    }
+	    // Only valid fixes
+	    Some("RMC") if fields.get(2) == Some(&"A") => {
+		self.position(fields.get(3..).unwrap_or(&[]), &mut variables);
+		variables.extend(number(7).map(|knots| { ("speed", knots * 1.852) }));
+		variables.extend(number(8).map(|course| { ("course", course) }));
+	    }
+	    Some("VTG") => {
+		variables.extend(number(1).map(|course| { ("course", course) }));
+		variables.extend(number(7).map(|speed| { ("speed", speed) }));
+	    }
+	    _ => {}
+	}
+	variables.into_iter()
+	    .flat_map(|(name, value)| { self.plots.translate(&format!(">{}:{}", name, value)) })
+	    .collect()
+    }
+}
+
+type Factory = fn(&Config) -> Box<dyn Dialect>;
+
+fn native(_config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(Native)
+}
+
+fn teleplot(_config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(Teleplot::default())
+}
+
+fn arduino(_config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(ArduinoPlotter::new())
+}
+
+fn jsonl(_config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(JsonLines::default())
+}
+
+fn nmea(_config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(Nmea::default())
+}
+
+fn mavlink(config: &Config) -> Box<dyn Dialect>
+{
+    Box::new(MavlinkMapping::new(&config.mavlink_signals))
+}
+
+// All dialects by the name used in the configuration
+const DIALECTS: &[(&str, Factory)] = &[
+    ("native", native),
+    ("teleplot", teleplot),
+    ("arduino", arduino),
+    ("jsonl", jsonl),
+    ("nmea", nmea),
+    ("mavlink", mavlink),
+];
+
+pub fn create(name: &str, config: &Config) -> Result<Box<dyn Dialect>, String>
+{
+    DIALECTS.iter()
+	.find(|(dialect, _)| { *dialect == name })
+	.map(|(_, factory)| { factory(config) })
+	.ok_or_else(|| {
+	    let names: Vec<&str> = DIALECTS.iter().map(|(dialect, _)| { *dialect }).collect();
+	    format!("unknown dialect {}, expected one of {}", name, names.join(", "))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn dialects_by_name() {
+	assert_eq!(create("native", &Config::default()).unwrap().translate("`MyScope 1"), vec!["`MyScope 1"]);
+	assert!(create("klingon", &Config::default()).err().unwrap().contains("native"));
+    }
+
+    #[test]
+    fn teleplot_variables_get_scopes() {
+	let mut teleplot = create("teleplot", &Config::default()).unwrap();
+	assert_eq!(teleplot.translate(">temp:20§°C|g"), vec![
+	    "`SCOPE temp POS 0 0 SIZE 400 128 SAMPLES 256",
+	    "`temp 'temp' 19 21 128 0",
+	    "`temp 20",
+	]);
+	assert_eq!(teleplot.translate(">temp:20.5"), vec!["`temp 20.5"]);
+	assert_eq!(teleplot.translate(">temp:24")[1], "`temp 'temp' 16.5 26.5 128 0");
+	assert_eq!(teleplot.translate(">speed:1500:3")[0], "`SCOPE speed POS 0 140 SIZE 400 128 SAMPLES 256 TIMEBASE DEVICE 1000");
+	assert_eq!(teleplot.translate(">speed:1510:3"), vec!["`speed @1510 3"]);
+	assert_eq!(teleplot.translate("`MyScope 1"), vec!["`MyScope 1"]);
+    }
+
+    #[test]
+    fn arduino_plotter_columns() {
+	let mut plotter = create("arduino", &Config::default()).unwrap();
+	assert!(plotter.translate("temp,humidity").is_empty());
+	assert_eq!(plotter.translate("20,\t40"), vec![
+	    "`SCOPE Plotter SIZE 600 300 SAMPLES 500",
+	    "`Plotter 'temp' 10 50 300 0 YELLOW",
+	    "`Plotter 'humidity' 10 50 300 0 CYAN",
+	    "`Plotter 20 40",
+	]);
+	assert_eq!(plotter.translate("21 39.5"), vec!["`Plotter 21 39.5"]);
+	assert!(plotter.translate("21 garbage").is_empty());
+	assert!(plotter.translate("# temp humidity").is_empty());
+	assert_eq!(plotter.translate("22 38")[0], "`Plotter 22 38");
+	assert_eq!(plotter.translate("a:1 b:2")[1], "`Plotter 'a' 0 3 300 0 YELLOW");
+	assert_eq!(plotter.translate("1 2 3")[3], "`Plotter 'value 3' 0 4 300 0 MAGENTA");
+    }
+
+    #[test]
+    fn json_lines_feed_scopes() {
+	let mut jsonl = create("jsonl", &Config::default()).unwrap();
+	assert_eq!(jsonl.translate(r#"{"scope": "MyScope", "values": [1, 2]}"#), vec![
+	    "`SCOPE MyScope POS 0 0 SIZE 400 200 SAMPLES 256",
+	    "`MyScope 'value 1' 0 3 200 0 YELLOW",
+	    "`MyScope 'value 2' 0 3 200 0 CYAN",
+	    "`MyScope 1 2",
+	]);
+	assert_eq!(jsonl.translate(r#"{"scope": "MyScope", "values": [2, 1], "seq": 4}"#), vec!["`MyScope %4 2 1"]);
+	let lines = jsonl.translate(r#"{"scope": "Motor", "values": {"rpm": 1500}, "timestamp": 20}"#);
+	assert_eq!(lines[0], "`SCOPE Motor POS 0 220 SIZE 400 200 SAMPLES 256 TIMEBASE DEVICE 1000");
+	assert_eq!(lines[1], "`Motor 'rpm' 1499 1501 200 0 YELLOW");
+	assert_eq!(lines[2], "`Motor @20 1500");
+	assert!(jsonl.translate(r#"{"scope": "Motor", "values": ["fast"]}"#).is_empty());
+	assert!(jsonl.translate("{broken").is_empty());
+	assert_eq!(jsonl.translate("`MyScope 1 2"), vec!["`MyScope 1 2"]);
+    }
+
+    #[test]
+    fn nmea_sentences() {
+	let mut nmea = create("nmea", &Config::default()).unwrap();
+	let sentence = |body: &str| { format!("${}*{:02X}", body, checksum::xor(body)) };
+	let lines = nmea.translate(&sentence("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"));
+	assert!(lines.contains(&"`north 0".to_string()));
+	assert!(lines.contains(&"`satellites 8".to_string()));
+	assert!(lines.contains(&"`altitude 545.4".to_string()));
+	let lines = nmea.translate(&sentence("GNRMC,123520,A,4807.138,N,01131.000,E,10.0,84.4,230394,003.1,W"));
+	assert!(lines.iter().any(|line| { line.starts_with("`north 185.5") }));
+	assert!(lines.contains(&"`speed 18.52".to_string()));
+	assert!(nmea.translate(&sentence("GPRMC,123521,V,,,,,,,230394,,")).is_empty());
+	assert!(nmea.translate("$GPGGA,1*00").is_empty());
+	assert_eq!(nmea.translate("`MyScope 1"), vec!["`MyScope 1"]);
+    }
+}
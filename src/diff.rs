@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use crate::replay::parse_timestamped;
+
+// Name of the scope the comparison is rendered in
+const DIFF_SCOPE: &str = "Diff";
+
+// Samples of every signal in a capture file, in the format the
+// replay reads: protocol lines, optionally prefixed by a timestamp.
+#[derive(Debug, Default)]
+pub struct Capture
+{
+    // Signal names of each object in order of declaration
+    signals: HashMap<String, Vec<String>>,
+    // Per object and signal, capture time and value
+    samples: HashMap<(String, String), Vec<(Option<f64>, f32)>>,
+}
+
+impl Capture
+{
+    pub fn parse(text: &str) -> Capture
+    {
+	let mut capture = Capture::default();
+	for line in text.lines() {
+	    let (timestamp, line) = parse_timestamped(line);
+	    capture.line(timestamp, line);
+	}
+	capture
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Capture>
+    {
+	Ok(Capture::parse(&std::fs::read_to_string(path)?))
+    }
+
+    // Remembers declarations, returns the values of a sample line
+    fn line(&mut self, timestamp: Option<f64>, line: &str) -> Option<(String, Vec<f32>)>
+    {
+	let mut tokens = line.strip_prefix('`')?.split_whitespace();
+	let object = tokens.next()?;
+	let rest: Vec<&str> = tokens.collect();
+	if object == "SCOPE" {
+	    self.signals.insert(rest.first()?.to_string(), vec![]);
+	    return None;
+	}
+	let signals = self.signals.get_mut(object)?;
+	if let Some(signal) = rest.first().filter(|token| { token.starts_with('\'') }) {
+	    signals.push(signal.trim_matches('\'').to_string());
+	    return None;
+	}
+	let values = rest.iter().map(|token| { token.parse::<f32>() }).collect::<Result<Vec<f32>, _>>().ok()?;
+	for (signal, value) in signals.iter().zip(values.iter()) {
+	    self.samples.entry((object.to_string(), signal.clone())).or_default().push((timestamp, *value));
+	}
+	Some((object.to_string(), values))
+    }
+
+    pub fn series(&self, object: &str, signal: &str) -> Option<&Vec<(Option<f64>, f32)>>
+    {
+	self.samples.get(&(object.to_string(), signal.to_string()))
+    }
+}
+
+// How the two captures are lined up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align
+{
+    // Both from their first sample
+    Start,
+    // From the first rising crossing of the level
+    Trigger(f32),
+    // By capture time relative to the first sample
+    Time,
+}
+
+impl std::str::FromStr for Align
+{
+    type Err = String;
+
+    fn from_str(align: &str) -> Result<Align, String>
+    {
+	match align {
+	    "start" => Ok(Align::Start),
+	    "time" => Ok(Align::Time),
+	    _ => align.strip_prefix("trigger:")
+		.and_then(|level| { level.parse::<f32>().ok() })
+		.map(Align::Trigger)
+		.ok_or(format!("unknown alignment {}, expected start, time or trigger:LEVEL", align)),
+	}
+    }
+}
+
+fn trigger_index(values: &[f32], level: f32) -> Option<usize>
+{
+    values.windows(2).position(|pair| { pair[0] < level && pair[1] >= level }).map(|index| { index + 1 })
+}
+
+// The sample pairs to compare
+pub fn align(a: &[(Option<f64>, f32)], b: &[(Option<f64>, f32)], align: Align) -> Vec<(f32, f32)>
+{
+    let values = |series: &[(Option<f64>, f32)]| -> Vec<f32> { series.iter().map(|(_, value)| { *value }).collect() };
+    let (a_values, b_values) = (values(a), values(b));
+    match align {
+	Align::Start => a_values.into_iter().zip(b_values).collect(),
+	Align::Trigger(level) => match (trigger_index(&a_values, level), trigger_index(&b_values, level)) {
+	    (Some(a_start), Some(b_start)) => a_values[a_start..].iter().cloned().zip(b_values[b_start..].iter().cloned()).collect(),
+	    _ => vec![],
+	},
+	Align::Time => {
+	    let times = |series: &[(Option<f64>, f32)]| -> Vec<f64> {
+		let first = series.iter().find_map(|(time, _)| { *time }).unwrap_or(0.0);
+		series.iter().map(|(time, _)| { time.unwrap_or(first) - first }).collect()
+	    };
+	    let b_times = times(b);
+	    // For each sample of a the latest sample of b not after it
+	    let mut b_index = 0;
+	    times(a).iter().zip(a_values).filter_map(|(time, a_value)| {
+		while b_index + 1 < b_times.len() && b_times[b_index + 1] <= *time {
+		    b_index += 1;
+		}
+		b_values.get(b_index).map(|b_value| { (a_value, *b_value) })
+	    }).collect()
+	}
+    }
+}
+
+// Lines declaring and filling the Diff scope: both traces on top,
+// their difference with the tolerance band below
+pub struct DiffRenderer
+{
+    pub tolerance: f32,
+    // Samples whose difference exceeded the tolerance
+    pub violations: usize,
+}
+
+impl DiffRenderer
+{
+    pub fn new(tolerance: f32) -> DiffRenderer
+    {
+	DiffRenderer{ tolerance, violations: 0 }
+    }
+
+    // min and max of the traces, range of the difference
+    pub fn declarations(&self, samples: usize, min: f32, max: f32, range: f32) -> Vec<String>
+    {
+	let range = range.max(self.tolerance * 3.0).max(f32::EPSILON);
+	vec![
+	    format!("`SCOPE {} SIZE 600 400 SAMPLES {}", DIFF_SCOPE, samples.max(2)),
+	    format!("`{} 'A' {} {} 180 210 CYAN", DIFF_SCOPE, min, max),
+	    format!("`{} 'B' {} {} 180 210 MAGENTA", DIFF_SCOPE, min, max),
+	    format!("`{} 'A-B' {} {} 180 10 YELLOW", DIFF_SCOPE, -range, range),
+	    format!("`{} '+tol' {} {} 180 10 RED", DIFF_SCOPE, -range, range),
+	    format!("`{} '-tol' {} {} 180 10 RED", DIFF_SCOPE, -range, range),
+	]
+    }
+
+    pub fn sample(&mut self, a: f32, b: f32) -> String
+    {
+	let difference = a - b;
+	if difference.abs() > self.tolerance {
+	    self.violations += 1;
+	}
+	format!("`{} {} {} {} {} {}", DIFF_SCOPE, a, b, difference, self.tolerance, -self.tolerance)
+    }
+
+    // Everything for two aligned captures
+    pub fn render(&mut self, pairs: &[(f32, f32)]) -> Vec<String>
+    {
+	let min = pairs.iter().map(|(a, b)| { a.min(*b) }).fold(f32::INFINITY, f32::min);
+	let max = pairs.iter().map(|(a, b)| { a.max(*b) }).fold(f32::NEG_INFINITY, f32::max);
+	let range = pairs.iter().map(|(a, b)| { (a - b).abs() }).fold(0.0, f32::max);
+	let (min, max) = if min < max { (min, max) } else { (min.min(0.0) - 1.0, max.max(0.0) + 1.0) };
+	let mut lines = self.declarations(pairs.len(), min, max, range);
+	lines.extend(pairs.iter().map(|(a, b)| { self.sample(*a, *b) }));
+	lines
+    }
+}
+
+// Compares live data against a capture, sample by sample
+pub struct LiveDiff
+{
+    object: String,
+    signal: String,
+    reference: Vec<f32>,
+    // Declarations of the live input, to find the signal's column
+    live: Capture,
+    // Next reference sample, None until the trigger fired
+    position: Option<usize>,
+    trigger: Option<f32>,
+    last: Option<f32>,
+    renderer: DiffRenderer,
+}
+
+impl LiveDiff
+{
+    // The declarations of the Diff scope are returned along
+    pub fn new(capture: &Capture, object: &str, signal: &str, align: Align, tolerance: f32) -> Result<(LiveDiff, Vec<String>), String>
+    {
+	let series = capture.series(object, signal).ok_or(format!("no samples of {}.{} in the capture", object, signal))?;
+	let mut reference: Vec<f32> = series.iter().map(|(_, value)| { *value }).collect();
+	let trigger = match align {
+	    Align::Trigger(level) => {
+		let start = trigger_index(&reference, level).ok_or(format!("the capture never crosses {}", level))?;
+		reference.drain(..start);
+		Some(level)
+	    }
+	    _ => None,
+	};
+	let min = reference.iter().cloned().fold(f32::INFINITY, f32::min);
+	let max = reference.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+	let renderer = DiffRenderer::new(tolerance);
+	let declarations = renderer.declarations(reference.len(), min, max, (max - min) / 4.0);
+	let position = if trigger.is_some() { None } else { Some(0) };
+	Ok((LiveDiff{ object: object.to_string(), signal: signal.to_string(), reference, live: Capture::default(), position, trigger, last: None, renderer }, declarations))
+    }
+
+    pub fn violations(&self) -> usize
+    {
+	self.renderer.violations
+    }
+
+    // The Diff sample for a live line, if it carried the signal
+    pub fn feed(&mut self, line: &str) -> Option<String>
+    {
+	let (object, values) = self.live.line(None, line)?;
+	if object != self.object {
+	    return None;
+	}
+	let column = self.live.signals.get(&object)?.iter().position(|signal| { signal == &self.signal })?;
+	let value = *values.get(column)?;
+	let last = self.last.replace(value);
+	if self.position.is_none() {
+	    let level = self.trigger?;
+	    if last.map(|last| { last < level && value >= level }).unwrap_or(false) {
+		self.position = Some(0);
+	    } else {
+		return None;
+	    }
+	}
+	let position = self.position?;
+	let reference = *self.reference.get(position)?;
+	self.position = Some(position + 1);
+	Some(self.renderer.sample(value, reference))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    const DECLARATIONS: &str = "`SCOPE S\n`S 'A' 0 10 100 0\n`S 'B' 0 10 100 0\n";
+
+    #[test]
+    fn aligned_differences() {
+	let a = Capture::parse(&format!("{}0.0 `S 0 1\n0.1 `S 5 1\n0.2 `S 6 1\n", DECLARATIONS));
+	let b = Capture::parse(&format!("{}1.0 `S 0 0\n1.1 `S 0 0\n1.15 `S 5 0\n1.2 `S 7 0\n", DECLARATIONS));
+	let (a, b) = (a.series("S", "A").unwrap(), b.series("S", "A").unwrap());
+	assert_eq!(align(a, b, Align::Start), vec![(0.0, 0.0), (5.0, 0.0), (6.0, 5.0)]);
+	assert_eq!(align(a, b, Align::Trigger(2.5)), vec![(5.0, 5.0), (6.0, 7.0)]);
+	assert_eq!(align(a, b, Align::Time), vec![(0.0, 0.0), (5.0, 0.0), (6.0, 7.0)]);
+	assert_eq!("trigger:2.5".parse::<Align>(), Ok(Align::Trigger(2.5)));
+	let mut renderer = DiffRenderer::new(0.5);
+	let lines = renderer.render(&align(a, b, Align::Trigger(2.5)));
+	assert_eq!(lines.len(), 8);
+	assert_eq!(lines[7], "`Diff 6 7 -1 0.5 -0.5");
+	assert_eq!(renderer.violations, 1);
+    }
+
+    #[test]
+    fn live_data_against_a_capture() {
+	let capture = Capture::parse(&format!("{}`S 0 0\n`S 4 0\n`S 5 0\n", DECLARATIONS));
+	let (mut live, declarations) = LiveDiff::new(&capture, "S", "A", Align::Trigger(2.0), 0.1).unwrap();
+	assert_eq!(declarations[0], "`SCOPE Diff SIZE 600 400 SAMPLES 2");
+	let lines: Vec<Option<String>> = ["`SCOPE S", "`S 'A' 0 10 100 0", "`S 1", "`S 3", "`S 5.5", "`S 6"].iter()
+	    .map(|line| { live.feed(line) })
+	    .collect();
+	assert!(lines[..3].iter().all(Option::is_none));
+	assert_eq!(lines[3], Some("`Diff 3 4 -1 0.1 -0.1".to_string()));
+	assert_eq!(lines[4], Some("`Diff 5.5 5 0.5 0.1 -0.1".to_string()));
+	assert_eq!(lines[5], None);
+	assert_eq!(live.violations(), 2);
+    }
+}
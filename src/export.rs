@@ -0,0 +1,195 @@
+use std::path::Path;
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::debugobjects::{DebugObjects, DebugObjectError};
+use crate::locale::NumberFormat;
+use crate::replay::parse_timestamped;
+
+#[derive(Error, Debug)]
+pub enum ExportError
+{
+    #[error("IO error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid export spec {0}")]
+    Spec(#[from] toml::de::Error),
+    #[error("{0}")]
+    DebugObject(#[from] DebugObjectError),
+    #[error("JSON error {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// Describes the artifacts of a headless run, e.g.
+//
+// output = "report"
+// locale = "de_DE"
+// csv = ["*"]
+// png = ["MyScope"]
+// svg = ["MyScope"]
+// stats = "stats.json"
+//
+// Object lists name DebugObjects, "*" selects all of them.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ExportSpec
+{
+    pub output: String,
+    pub locale: Option<String>,
+    pub csv: Vec<String>,
+    pub png: Vec<String>,
+    pub svg: Vec<String>,
+    pub stats: Option<String>,
+}
+
+impl Default for ExportSpec
+{
+    fn default() -> Self
+    {
+	ExportSpec{ output: ".".to_string(), locale: None, csv: vec![], png: vec![], svg: vec![], stats: None }
+    }
+}
+
+impl ExportSpec
+{
+    pub fn from_file(path: &str) -> Result<ExportSpec, ExportError>
+    {
+	Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+fn select(names: &Vec<String>, selection: &Vec<String>) -> Vec<String>
+{
+    if selection.iter().any(|name| { name == "*" }) {
+	return names.clone();
+    }
+    selection.clone()
+}
+
+// Object names come from the input, so only a plain file name
+// inside the output directory is made of them
+pub fn file_name(name: &str, extension: &str) -> String
+{
+    let stem: String = name.chars()
+	.map(|c| { if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' { c } else { '_' } })
+	.collect();
+    match stem.trim_start_matches('.') {
+	"" => format!("_.{}", extension),
+	stem => format!("{}.{}", stem, extension),
+    }
+}
+
+// Captured timestamps are kept, without waiting for them
+pub fn replay(views: &mut DebugObjects, input: &str) -> Result<usize, ExportError>
+{
+    let content = std::fs::read_to_string(input)?;
+    let mut count = 0;
+    for line in content.lines() {
+	match parse_timestamped(line) {
+	    (Some(timestamp), line) => { views.feed_at(line, timestamp); }
+	    (None, line) => { views.feed(line); }
+	}
+	count += 1;
+    }
+    Ok(count)
+}
+
+pub fn write_artifacts(views: &DebugObjects, spec: &ExportSpec) -> Result<(), ExportError>
+{
+    let output = Path::new(&spec.output);
+    std::fs::create_dir_all(output)?;
+    let names = views.names();
+    for name in select(&names, &spec.csv) {
+	let path = output.join(file_name(&name, "csv"));
+	info!("writing {:?}", path);
+	views.export_csv(&name, &path.to_string_lossy())?;
+    }
+    for name in select(&names, &spec.png) {
+	let path = output.join(file_name(&name, "png"));
+	info!("writing {:?}", path);
+	views.export_png(&name, &path.to_string_lossy())?;
+    }
+    for name in select(&names, &spec.svg) {
+	let path = output.join(file_name(&name, "svg"));
+	info!("writing {:?}", path);
+	views.export_svg(&name, &path.to_string_lossy())?;
+    }
+    if let Some(stats) = &spec.stats {
+	let mut objects = serde_json::Map::new();
+	for name in &names {
+	    objects.insert(name.clone(), json!(views.signal_stats(name)?));
+	}
+	let path = output.join(stats);
+	info!("writing {:?}", path);
+	std::fs::write(path, serde_json::to_string_pretty(&json!({
+	    "objects": objects,
+	    "alarms": views.alarms().search("").count(),
+	}))?)?;
+    }
+    Ok(())
+}
+
+// Entry point of `rusty-peanut export --input capture.log --spec export.toml`
+pub fn run(input: &str, spec: &str) -> Result<(), ExportError>
+{
+    let spec = ExportSpec::from_file(spec)?;
+    let mut views = DebugObjects::new();
+    if let Some(locale) = &spec.locale {
+	views.set_number_format(NumberFormat::from_locale(locale));
+    }
+    let count = replay(&mut views, input)?;
+    info!("replayed {} lines from {}", count, input);
+    write_artifacts(&views, &spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn parse_spec() {
+	let spec: ExportSpec = toml::from_str("output = \"out\"\ncsv = [\"*\"]\nstats = \"stats.json\"\n").unwrap();
+	assert_eq!(spec, ExportSpec{
+	    output: "out".to_string(),
+	    locale: None,
+	    csv: vec!["*".to_string()],
+	    png: vec![],
+	    svg: vec![],
+	    stats: Some("stats.json".to_string()),
+	});
+    }
+
+    #[test]
+    fn file_names_stay_inside_output() {
+	assert_eq!(file_name("MyScope", "csv"), "MyScope.csv");
+	assert_eq!(file_name("../../etc/passwd", "csv"), "_.._etc_passwd.csv");
+	assert_eq!(file_name("..", "png"), "_.png");
+	assert_eq!(file_name("dev1/Power Rail", "svg"), "dev1_Power_Rail.svg");
+    }
+
+    #[test]
+    fn export_all_artifacts() {
+	let directory = std::env::temp_dir().join(format!("rusty-peanut-export-{}", std::process::id()));
+	let mut views = DebugObjects::new();
+	for line in &["`SCOPE MyScope SIZE 64 32 SAMPLES 16", "`MyScope 'Sawtooth' 0 63 32 0", "`MyScope 10", "`MyScope 20"] {
+	    views.feed(line);
+	}
+	let spec = ExportSpec{
+	    output: directory.to_string_lossy().to_string(),
+	    locale: None,
+	    csv: vec!["*".to_string()],
+	    png: vec!["MyScope".to_string()],
+	    svg: vec!["MyScope".to_string()],
+	    stats: Some("stats.json".to_string()),
+	};
+	write_artifacts(&views, &spec).unwrap();
+	assert!(directory.join("MyScope.csv").exists());
+	assert!(directory.join("MyScope.png").exists());
+	assert!(std::fs::read_to_string(directory.join("MyScope.svg")).unwrap().contains(">Sawtooth</text>"));
+	let stats: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(directory.join("stats.json")).unwrap()).unwrap();
+	assert_eq!(stats["objects"]["MyScope"][0]["max"], json!(20.0));
+	std::fs::remove_dir_all(directory).unwrap();
+    }
+}
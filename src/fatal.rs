@@ -0,0 +1,71 @@
+use nannou::prelude::*;
+
+type Rect = nannou::geom::rect::Rect;
+
+// Shown instead of the scopes when an input couldn't be opened,
+// e.g. a missing port, so the user can fix it without a terminal.
+pub struct FatalError
+{
+    pub message: String,
+}
+
+impl FatalError
+{
+    pub fn new(message: &str) -> FatalError
+    {
+	FatalError{ message: message.to_string() }
+    }
+
+    // The message wrapped at width characters, then the choices
+    pub fn lines(&self, width: usize) -> Vec<String>
+    {
+	let mut lines = vec![];
+	let mut line = String::new();
+	for word in self.message.split_whitespace() {
+	    if !line.is_empty() && line.len() + 1 + word.len() > width {
+		lines.push(std::mem::take(&mut line));
+	    }
+	    if !line.is_empty() {
+		line.push(' ');
+	    }
+	    line.push_str(word);
+	}
+	lines.push(line);
+	lines.push(String::new());
+	lines.push("R: retry   P: pick another serial port   Esc: quit".to_string());
+	lines
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect)
+    {
+	let line_height = 18.0;
+	// Roughly the characters fitting at font size 14
+	let width = (rect.w() / 8.0) as usize;
+	let lines = self.lines(width.max(20));
+	let last = lines.len() - 1;
+	for (index, line) in lines.iter().enumerate() {
+	    draw.text(line)
+		.x_y(rect.x(), rect.top() - line_height * (index as f32 + 0.5))
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(14)
+		.left_justify()
+		.color(if index == last { YELLOW } else { RED });
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn message_is_wrapped() {
+	let error = FatalError::new("opening /dev/ttyUSB0 failed: No such file or directory");
+	let lines = error.lines(24);
+	assert_eq!(lines[0], "opening /dev/ttyUSB0");
+	assert_eq!(lines[1], "failed: No such file or");
+	assert_eq!(lines[2], "directory");
+	assert!(lines.last().unwrap().starts_with("R: retry"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::os::unix::fs::FileTypeExt;
+use std::thread;
+use std::time::Duration;
+use crossbeam::channel::{Receiver, unbounded};
+use log::{info, warn};
+use crate::serial::{LineEnding, Received, forward_lines};
+
+// Reads a named pipe created with mkfifo. A writer closing it is
+// not the end, the pipe is reopened for the next writer.
+pub struct FifoConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+impl FifoConnector
+{
+    pub fn new(path: &str, ending: LineEnding) -> std::io::Result<FifoConnector>
+    {
+	// Reopening a regular file would replay it over and over
+	if !std::fs::metadata(path)?.file_type().is_fifo() {
+	    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is no FIFO", path)));
+	}
+	let path = path.to_string();
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    loop {
+		// Blocks until a writer shows up
+		let file = match File::open(&path) {
+		    Ok(file) => file,
+		    Err(error) => {
+			warn!("opening {} failed: {:?}", path, error);
+			thread::sleep(Duration::from_secs(1));
+			continue;
+		    }
+		};
+		match forward_lines(file, ending, &path, |line| { s.send(Received::now(line.to_string())).is_ok() }) {
+		    Ok(true) => {}
+		    Ok(false) => { return; }
+		    Err(error) => { warn!("reading {} failed: {:?}", path, error); }
+		}
+		info!("writer of {} closed, waiting for the next", path);
+	    }
+	});
+	Ok(FifoConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use test_env_log::test;
+
+    #[test]
+    fn writer_restarts_are_survived() {
+	let path = std::env::temp_dir().join(format!("rusty-peanut-{}.fifo", std::process::id()));
+	let path = path.to_str().unwrap();
+	assert!(std::process::Command::new("mkfifo").arg(path).status().unwrap().success());
+	let connector = FifoConnector::new(path, LineEnding::Auto).unwrap();
+	File::create(path).unwrap().write_all(b"`SCOPE MyScope\r\n\xc3\x28\n").unwrap();
+	File::create(path).unwrap().write_all(b"`MyScope 1\n").unwrap();
+	let lines: Vec<String> = connector.receiver.iter().take(2).map(|received| { received.line }).collect();
+	assert_eq!(lines, vec!["`SCOPE MyScope", "`MyScope 1"]);
+	std::fs::remove_file(path).unwrap();
+	assert!(FifoConnector::new("Cargo.toml", LineEnding::Auto).is_err());
+    }
+}
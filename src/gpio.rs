@@ -0,0 +1,108 @@
+// Stacks each pin into its own lane of one scope, like the
+// channels of a logic analyzer
+pub struct LogicProbe
+{
+    pins: Vec<u8>,
+}
+
+// Vertical space of a lane and the trace inside it
+const LANE: f32 = 30.0;
+const TRACE: f32 = 20.0;
+
+impl LogicProbe
+{
+    pub fn new(pins: &[u8]) -> LogicProbe
+    {
+	LogicProbe{ pins: pins.to_vec() }
+    }
+
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let height = self.pins.len() as f32 * LANE + LANE / 2.0;
+	let mut lines = vec![format!("`SCOPE Logic SIZE 600 {} SAMPLES 1000", height)];
+	for (lane, pin) in self.pins.iter().enumerate() {
+	    lines.push(format!("`Logic 'GPIO{}' 0 1 {} {}", pin, TRACE, lane as f32 * LANE + LANE / 2.0));
+	}
+	lines
+    }
+
+    pub fn line(&self, levels: &[bool]) -> String
+    {
+	let levels: Vec<&str> = levels.iter().map(|high| { if *high { "1" } else { "0" } }).collect();
+	format!("`Logic {}", levels.join(" "))
+    }
+}
+
+// Pins as given to --gpio, e.g. 17,27,22
+pub fn parse_pins(pins: &str) -> Option<Vec<u8>>
+{
+    pins.split(',').map(|pin| { pin.trim().parse().ok() }).collect()
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+pub use connector::GpioConnector;
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+mod connector {
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crossbeam::channel::{Receiver, unbounded};
+    use rppal::gpio::Gpio;
+    use crate::serial::Received;
+    use crate::shutdown;
+    use super::LogicProbe;
+
+    // Samples Raspberry Pi pins at a fixed rate
+    pub struct GpioConnector
+    {
+	pub receiver: Receiver<Received>,
+    }
+
+    impl GpioConnector
+    {
+	pub fn new(pins: &[u8], rate: f64) -> Result<GpioConnector, rppal::gpio::Error>
+	{
+	    let gpio = Gpio::new()?;
+	    let inputs = pins.iter().map(|pin| { Ok(gpio.get(*pin)?.into_input()) }).collect::<Result<Vec<_>, rppal::gpio::Error>>()?;
+	    let probe = LogicProbe::new(pins);
+	    let period = Duration::from_secs_f64(1.0 / rate.max(1.0));
+	    let (s, r) = unbounded();
+	    for line in probe.declarations() {
+		s.send(Received::now(line)).ok();
+	    }
+	    thread::spawn(move || {
+		// Sleeping to a deadline keeps the rate from drifting
+		let mut next = Instant::now();
+		while !shutdown::requested() {
+		    let levels: Vec<bool> = inputs.iter().map(|input| { input.is_high() }).collect();
+		    if s.send(Received::now(probe.line(&levels))).is_err() {
+			return;
+		    }
+		    next += period;
+		    let now = Instant::now();
+		    if next > now {
+			thread::sleep(next - now);
+		    } else {
+			next = now;
+		    }
+		}
+	    });
+	    Ok(GpioConnector{ receiver: r })
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn pins_become_lanes() {
+	assert_eq!(parse_pins("17, 27,22"), Some(vec![17, 27, 22]));
+	assert_eq!(parse_pins("17,x"), None);
+	let probe = LogicProbe::new(&[17, 27]);
+	assert_eq!(probe.declarations(), vec!["`SCOPE Logic SIZE 600 75 SAMPLES 1000", "`Logic 'GPIO17' 0 1 20 15", "`Logic 'GPIO27' 0 1 20 45"]);
+	assert_eq!(probe.line(&[true, false]), "`Logic 1 0");
+    }
+}
@@ -0,0 +1,92 @@
+// Undo and redo of changes made with keyboard and mouse.
+// Control requests and protocol lines aren't recorded.
+
+// Older changes are forgotten
+const LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change
+{
+    GroupVisible{ group: String, visible: bool },
+    // Drawing order before and after
+    ZOrder{ before: Vec<String>, after: Vec<String> },
+    Locked(bool),
+    ReconnectMarkers(bool),
+}
+
+impl Change
+{
+    pub fn inverse(&self) -> Change
+    {
+	match self {
+	    Change::GroupVisible{ group, visible } => Change::GroupVisible{ group: group.clone(), visible: !visible },
+	    Change::ZOrder{ before, after } => Change::ZOrder{ before: after.clone(), after: before.clone() },
+	    Change::Locked(locked) => Change::Locked(!locked),
+	    Change::ReconnectMarkers(enabled) => Change::ReconnectMarkers(!enabled),
+	}
+    }
+}
+
+pub struct History
+{
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History
+{
+    pub fn new() -> History
+    {
+	History{ undo: vec![], redo: vec![] }
+    }
+
+    // A new change makes the undone ones unreachable
+    pub fn record(&mut self, change: Change)
+    {
+	self.redo.clear();
+	self.undo.push(change);
+	if self.undo.len() > LIMIT {
+	    self.undo.remove(0);
+	}
+    }
+
+    // The change to apply for undoing the last one
+    pub fn undo(&mut self) -> Option<Change>
+    {
+	let change = self.undo.pop()?;
+	let inverse = change.inverse();
+	self.redo.push(change);
+	Some(inverse)
+    }
+
+    pub fn redo(&mut self) -> Option<Change>
+    {
+	let change = self.redo.pop()?;
+	self.undo.push(change.clone());
+	Some(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn undo_and_redo() {
+	let mut history = History::new();
+	assert_eq!(history.undo(), None);
+	history.record(Change::Locked(true));
+	history.record(Change::GroupVisible{ group: "Power".to_string(), visible: false });
+	assert_eq!(history.undo(), Some(Change::GroupVisible{ group: "Power".to_string(), visible: true }));
+	assert_eq!(history.undo(), Some(Change::Locked(false)));
+	assert_eq!(history.redo(), Some(Change::Locked(true)));
+	history.record(Change::ZOrder{ before: vec!["A".to_string(), "B".to_string()], after: vec!["B".to_string(), "A".to_string()] });
+	assert_eq!(history.redo(), None);
+	assert_eq!(history.undo(), Some(Change::ZOrder{ before: vec!["B".to_string(), "A".to_string()], after: vec!["A".to_string(), "B".to_string()] }));
+	for _ in 0..LIMIT + 10 {
+	    history.record(Change::ReconnectMarkers(true));
+	}
+	assert_eq!(history.undo.len(), LIMIT);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use log::info;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::debugobjects::{DebugObjects, DebugObjectError};
+
+#[derive(Error, Debug)]
+pub enum ImportError
+{
+    #[error("IO error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid reference spec {0}")]
+    Spec(#[from] toml::de::Error),
+    #[error("Unrecognized instrument CSV: {0}")]
+    Format(String),
+    #[error("No column {0} in the capture")]
+    UnknownColumn(String),
+    #[error("{0}")]
+    DebugObject(#[from] DebugObjectError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstrumentFormat
+{
+    // Time [s],Channel 0,Channel 1
+    Saleae,
+    // X,CH1,CH2,Start,Increment followed by a units row carrying both
+    Rigol,
+    // x-axis,1,2 followed by a units row
+    Keysight,
+}
+
+impl InstrumentFormat
+{
+    pub fn from_name(name: &str) -> Option<InstrumentFormat>
+    {
+	match name.to_lowercase().as_str() {
+	    "saleae" => Some(InstrumentFormat::Saleae),
+	    "rigol" => Some(InstrumentFormat::Rigol),
+	    "keysight" => Some(InstrumentFormat::Keysight),
+	    _ => None,
+	}
+    }
+
+    fn detect(header: &[String]) -> Option<InstrumentFormat>
+    {
+	match header.first().map(|cell| { cell.as_str() }) {
+	    Some(cell) if cell.starts_with("Time [") => Some(InstrumentFormat::Saleae),
+	    Some("X") if header.iter().any(|cell| { cell == "Increment" }) => Some(InstrumentFormat::Rigol),
+	    Some("x-axis") => Some(InstrumentFormat::Keysight),
+	    _ => None,
+	}
+    }
+}
+
+// A capture as columns of samples over time in seconds
+#[derive(Debug)]
+pub struct Capture
+{
+    pub time: Vec<f64>,
+    pub columns: Vec<(String, Vec<f32>)>,
+}
+
+impl Capture
+{
+    pub fn column(&self, name: &str) -> Option<&Vec<f32>>
+    {
+	self.columns.iter().find(|(column, _)| { column == name }).map(|(_, values)| { values })
+    }
+}
+
+fn cells(line: &str) -> Vec<String>
+{
+    line.split(',').map(|cell| { cell.trim().trim_matches('"').to_string() }).collect()
+}
+
+pub fn parse(content: &str, format: Option<InstrumentFormat>) -> Result<Capture, ImportError>
+{
+    let mut lines = content.lines().filter(|line| { !line.trim().is_empty() });
+    let header = cells(lines.next().ok_or(ImportError::Format("empty file".to_string()))?);
+    let format = format.or_else(|| { InstrumentFormat::detect(&header) })
+	.ok_or(ImportError::Format(header.join(",")))?;
+    // Rigol appends its time base as extra columns
+    let names: Vec<String> = header[1..].iter()
+	.filter(|name| { !name.is_empty() && !(format == InstrumentFormat::Rigol && (*name == "Start" || *name == "Increment")) })
+	.cloned()
+	.collect();
+    let mut time_base = (0.0, 1.0);
+    let mut capture = Capture{ time: vec![], columns: names.iter().map(|name| { (name.clone(), vec![]) }).collect() };
+    for line in lines {
+	let row = cells(line);
+	let first = match row[0].parse::<f64>() {
+	    Ok(first) => first,
+	    Err(_) => {
+		// The units row, Rigol keeps start and increment there
+		if format == InstrumentFormat::Rigol {
+		    let start = row.get(names.len() + 1).and_then(|cell| { cell.parse::<f64>().ok() });
+		    let increment = row.get(names.len() + 2).and_then(|cell| { cell.parse::<f64>().ok() });
+		    if let (Some(start), Some(increment)) = (start, increment) {
+			time_base = (start, increment);
+		    }
+		}
+		continue;
+	    }
+	};
+	let values: Option<Vec<f32>> = row[1..=names.len().min(row.len() - 1)].iter()
+	    .map(|cell| { cell.parse::<f32>().ok() })
+	    .collect();
+	match values {
+	    Some(values) if values.len() == names.len() => {
+		capture.time.push(match format {
+		    InstrumentFormat::Rigol => time_base.0 + first * time_base.1,
+		    _ => first,
+		});
+		for ((_, column), value) in capture.columns.iter_mut().zip(values) {
+		    column.push(value);
+		}
+	    }
+	    _ => { continue; }
+	}
+    }
+    Ok(capture)
+}
+
+// Maps instrument columns onto the signals of a scope, e.g.
+//
+// path = "rigol.csv"
+// format = "rigol"
+// scope = "MyScope"
+// [columns]
+// CH1 = "Sawtooth"
+//
+// format is detected from the header when left out.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ReferenceSpec
+{
+    pub path: String,
+    pub format: Option<String>,
+    pub scope: String,
+    pub columns: BTreeMap<String, String>,
+}
+
+impl ReferenceSpec
+{
+    pub fn from_file(path: &str) -> Result<ReferenceSpec, ImportError>
+    {
+	Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+// Overlays the mapped columns as reference traces
+pub fn load(views: &mut DebugObjects, spec: &ReferenceSpec) -> Result<(), ImportError>
+{
+    let format = match &spec.format {
+	Some(name) => Some(InstrumentFormat::from_name(name).ok_or(ImportError::Format(name.clone()))?),
+	None => None,
+    };
+    let capture = parse(&std::fs::read_to_string(&spec.path)?, format)?;
+    info!("imported {} samples from {}", capture.time.len(), spec.path);
+    for (column, signal) in &spec.columns {
+	let values = capture.column(column).ok_or(ImportError::UnknownColumn(column.clone()))?;
+	views.set_reference(&spec.scope, signal, values.clone())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn parse_saleae() {
+	let capture = parse("Time [s],Channel 0,Channel 1\n0.000000,0.1,1.5\n0.000001,0.2,1.4\n", None).unwrap();
+	assert_eq!(capture.time, vec![0.0, 0.000001]);
+	assert_eq!(capture.column("Channel 1"), Some(&vec![1.5, 1.4]));
+    }
+
+    #[test]
+    fn parse_rigol() {
+	let capture = parse("X,CH1,CH2,Start,Increment,\nSequence,Volt,Volt,-1.0e-03,1.0e-06,\n0,1.2,3.3,\n1,1.3,3.2,\n", None).unwrap();
+	assert_eq!(capture.columns.iter().map(|(name, _)| { name.as_str() }).collect::<Vec<&str>>(), vec!["CH1", "CH2"]);
+	assert_eq!(capture.column("CH2"), Some(&vec![3.3, 3.2]));
+	assert!((capture.time[1] - (-1.0e-03 + 1.0e-06)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parse_keysight() {
+	let capture = parse("x-axis,1,2\nsecond,Volt,Volt\n-5.0E-04,0.5,1\n-4.9E-04,0.6,\n", None).unwrap();
+	// incomplete rows are skipped
+	assert_eq!(capture.time, vec![-5.0E-04]);
+	assert_eq!(capture.column("1"), Some(&vec![0.5]));
+	assert!(parse("a,b\n1,2\n", None).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use serde_json::Value;
+
+// The protocol line of a journalctl -o json entry, if its message
+// contains pattern. The unit or syslog identifier names the origin.
+pub fn event_line(entry: &str, pattern: Option<&str>) -> Option<String>
+{
+    let entry: Value = serde_json::from_str(entry).ok()?;
+    // Binary messages come as byte arrays and are skipped
+    let message = entry.get("MESSAGE")?.as_str()?;
+    if let Some(pattern) = pattern {
+	if !message.to_lowercase().contains(&pattern.to_lowercase()) {
+	    return None;
+	}
+    }
+    let origin = ["_SYSTEMD_UNIT", "SYSLOG_IDENTIFIER"].iter()
+	.find_map(|field| { entry.get(*field).and_then(|origin| { origin.as_str() }) })
+	.unwrap_or("journal");
+    let message: Vec<&str> = message.split_whitespace().collect();
+    Some(format!("`EVENT {} {}", origin.replace(char::is_whitespace, "_"), message.join(" ")))
+}
+
+#[cfg(target_os = "linux")]
+pub use connector::JournalConnector;
+
+#[cfg(target_os = "linux")]
+mod connector {
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+    use crossbeam::channel::{Receiver, unbounded};
+    use log::warn;
+    use crate::serial::{LineEnding, Received, forward_lines};
+    use super::event_line;
+
+    // Follows the systemd journal through journalctl, turning new
+    // entries into events
+    pub struct JournalConnector
+    {
+	pub receiver: Receiver<Received>,
+	child: Child,
+    }
+
+    impl JournalConnector
+    {
+	pub fn new(units: &[String], pattern: Option<&str>) -> std::io::Result<JournalConnector>
+	{
+	    let mut command = Command::new("journalctl");
+	    command.args(&["--follow", "--lines=0", "--output=json"]);
+	    for unit in units {
+		command.arg(format!("--unit={}", unit));
+	    }
+	    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+	    let stdout = child.stdout.take().ok_or(std::io::ErrorKind::BrokenPipe)?;
+	    let pattern = pattern.map(|pattern| { pattern.to_string() });
+	    let (s, r) = unbounded();
+	    thread::spawn(move || {
+		// journalctl ends entries with LF whatever the input uses
+		let result = forward_lines(stdout, LineEnding::Lf, "journal", |entry| {
+		    match event_line(entry, pattern.as_deref()) {
+			Some(line) => s.send(Received::now(line)).is_ok(),
+			None => true,
+		    }
+		});
+		if let Err(error) = result {
+		    warn!("reading the journal failed: {:?}", error);
+		}
+	    });
+	    Ok(JournalConnector{ receiver: r, child })
+	}
+    }
+
+    impl Drop for JournalConnector
+    {
+	fn drop(&mut self)
+	{
+	    self.child.kill().ok();
+	    self.child.wait().ok();
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn entries_to_events() {
+	let entry = r#"{"MESSAGE":"Started  NetworkManager\nagain","_SYSTEMD_UNIT":"NetworkManager.service","PRIORITY":"6"}"#;
+	assert_eq!(event_line(entry, None), Some("`EVENT NetworkManager.service Started NetworkManager again".to_string()));
+	assert_eq!(event_line(entry, Some("network")).is_some(), true);
+	assert_eq!(event_line(entry, Some("usb")), None);
+	assert_eq!(event_line(r#"{"MESSAGE":"plugged","SYSLOG_IDENTIFIER":"kernel"}"#, None), Some("`EVENT kernel plugged".to_string()));
+	assert_eq!(event_line(r#"{"MESSAGE":[1,2]}"#, None), None);
+    }
+}
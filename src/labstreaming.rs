@@ -0,0 +1,134 @@
+// Turns the channels of an LSL stream into one scope. The stream
+// name becomes the scope name, the channel labels from the stream
+// description the signal names.
+pub struct LslLayout
+{
+    scope: String,
+    labels: Vec<String>,
+    rate: f64,
+    min: f32,
+    max: f32,
+}
+
+impl LslLayout
+{
+    // Missing or empty labels are numbered, irregular streams
+    // have a rate of 0
+    pub fn new(name: &str, labels: &[String], channels: usize, rate: f64, min: f32, max: f32) -> LslLayout
+    {
+	let scope: String = name.chars().map(|c| { if c.is_alphanumeric() || c == '_' { c } else { '_' } }).collect();
+	let labels = (0..channels).map(|channel| {
+	    match labels.get(channel) {
+		Some(label) if !label.is_empty() => label.replace('\'', ""),
+		_ => format!("channel {}", channel + 1),
+	    }
+	}).collect();
+	LslLayout{ scope, labels, rate, min, max }
+    }
+
+    pub fn declarations(&self) -> Vec<String>
+    {
+	// About four seconds of a regular stream
+	let samples = if self.rate > 0.0 { ((self.rate * 4.0) as usize).max(100).min(4000) } else { 500 };
+	let mut lines = vec![format!("`SCOPE {} SIZE 600 {} SAMPLES {}", self.scope, 100 + 60 * self.labels.len(), samples)];
+	// Stacked like the traces of an EEG montage
+	for (index, label) in self.labels.iter().enumerate() {
+	    lines.push(format!("`{} '{}' {} {} 60 {}", self.scope, label, self.min, self.max, 60 * index));
+	}
+	lines
+    }
+
+    pub fn sample(&self, values: &[f32]) -> String
+    {
+	let values: Vec<String> = values.iter().take(self.labels.len()).map(|value| { value.to_string() }).collect();
+	format!("`{} {}", self.scope, values.join(" "))
+    }
+}
+
+#[cfg(feature = "lsl")]
+pub use connector::LslConnector;
+
+#[cfg(feature = "lsl")]
+mod connector {
+    use std::thread;
+    use crossbeam::channel::{Receiver, unbounded};
+    use log::warn;
+    use lsl::{Pullable, StreamInlet};
+    use crate::serial::Received;
+    use crate::shutdown;
+    use super::LslLayout;
+
+    // Seconds to wait for the stream to show up on the network
+    const RESOLVE_TIMEOUT: f64 = 5.0;
+
+    // Pulls samples from the first LSL stream of the given name
+    pub struct LslConnector
+    {
+	pub receiver: Receiver<Received>,
+    }
+
+    fn labels(info: &lsl::StreamInfo) -> Vec<String>
+    {
+	let mut labels = vec![];
+	let mut channel = info.desc().child("channels").child("channel");
+	while channel.is_valid() {
+	    labels.push(channel.child_value_named("label"));
+	    channel = channel.next_sibling();
+	}
+	labels
+    }
+
+    impl LslConnector
+    {
+	pub fn new(name: &str, min: f32, max: f32) -> Result<LslConnector, String>
+	{
+	    let streams = lsl::resolve_byprop("name", name, 1, RESOLVE_TIMEOUT).map_err(|error| { error.to_string() })?;
+	    let stream = streams.first().ok_or(format!("no LSL stream named {}", name))?;
+	    let inlet = StreamInlet::new(stream, 360, 0, true).map_err(|error| { error.to_string() })?;
+	    // Only the full info has the channel description
+	    let info = inlet.info(RESOLVE_TIMEOUT).map_err(|error| { error.to_string() })?;
+	    let layout = LslLayout::new(name, &labels(&info), info.channel_count() as usize, info.nominal_srate(), min, max);
+	    let (s, r) = unbounded();
+	    for line in layout.declarations() {
+		s.send(Received::now(line)).ok();
+	    }
+	    thread::spawn(move || {
+		while !shutdown::requested() {
+		    let pulled: Result<(Vec<f32>, f64), _> = inlet.pull_sample(0.1);
+		    match pulled {
+			// A timestamp of 0 means the timeout passed
+			Ok((_, timestamp)) if timestamp == 0.0 => {}
+			Ok((values, _)) => {
+			    if s.send(Received::now(layout.sample(&values))).is_err() {
+				return;
+			    }
+			}
+			Err(error) => {
+			    warn!("LSL error: {}", error);
+			    thread::sleep(std::time::Duration::from_secs(1));
+			}
+		    }
+		}
+	    });
+	    Ok(LslConnector{ receiver: r })
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn channels_become_signals() {
+	let layout = LslLayout::new("EEG-Board 1", &["Fp1".to_string(), "".to_string()], 3, 250.0, -100.0, 100.0);
+	assert_eq!(layout.declarations(), vec![
+	    "`SCOPE EEG_Board_1 SIZE 600 280 SAMPLES 1000",
+	    "`EEG_Board_1 'Fp1' -100 100 60 0",
+	    "`EEG_Board_1 'channel 2' -100 100 60 60",
+	    "`EEG_Board_1 'channel 3' -100 100 60 120",
+	]);
+	assert_eq!(layout.sample(&[1.5, -2.0, 0.0, 9.0]), "`EEG_Board_1 1.5 -2 0");
+    }
+}
@@ -0,0 +1,142 @@
+// Number formatting following the conventions of a locale,
+// e.g. a decimal comma for German reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat
+{
+    pub decimal: char,
+    pub thousands: Option<char>,
+}
+
+impl Default for NumberFormat
+{
+    fn default() -> Self
+    {
+	NumberFormat{ decimal: '.', thousands: None }
+    }
+}
+
+impl NumberFormat
+{
+    // Accepts POSIX locale names like de_DE.UTF-8
+    pub fn from_locale(name: &str) -> NumberFormat
+    {
+	let language = name.split(|c| { c == '_' || c == '.' || c == '-' }).next().unwrap_or("");
+	match language {
+	    "de" | "nl" | "it" | "es" | "pt" | "da" | "tr" | "id" => NumberFormat{ decimal: ',', thousands: Some('.') },
+	    "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "uk" => NumberFormat{ decimal: ',', thousands: Some(' ') },
+	    "en" => NumberFormat{ decimal: '.', thousands: Some(',') },
+	    _ => NumberFormat::default(),
+	}
+    }
+
+    // PEANUT_LOCALE overrides the usual locale environment variables
+    pub fn from_env() -> NumberFormat
+    {
+	for variable in &["PEANUT_LOCALE", "LC_ALL", "LC_NUMERIC", "LANG"] {
+	    if let Ok(value) = std::env::var(variable) {
+		if !value.is_empty() {
+		    return NumberFormat::from_locale(&value);
+		}
+	    }
+	}
+	NumberFormat::default()
+    }
+
+    // Generic so samples are printed as the f32 they are,
+    // widened to f64 0.1 would come out as 0.10000000149011612
+    fn render<T: std::fmt::Display>(&self, value: T, precision: Option<usize>, thousands: Option<char>) -> String
+    {
+	let plain = match precision {
+	    Some(precision) => format!("{:.*}", precision, value),
+	    None => value.to_string(),
+	};
+	let (sign, plain) = if plain.starts_with('-') { ("-", &plain[1..]) } else { ("", &plain[..]) };
+	let (integer, fraction) = match plain.find('.') {
+	    Some(position) => (&plain[..position], Some(&plain[position + 1..])),
+	    None => (plain, None),
+	};
+	let mut result = String::from(sign);
+	for (index, digit) in integer.chars().enumerate() {
+	    if index > 0 && (integer.len() - index) % 3 == 0 {
+		if let Some(separator) = thousands {
+		    result.push(separator);
+		}
+	    }
+	    result.push(digit);
+	}
+	if let Some(fraction) = fraction {
+	    result.push(self.decimal);
+	    result.push_str(fraction);
+	}
+	result
+    }
+
+    // For on-screen labels
+    pub fn format<T: std::fmt::Display>(&self, value: T, precision: Option<usize>) -> String
+    {
+	self.render(value, precision, self.thousands)
+    }
+
+    // Spreadsheets don't import grouped numbers, so CSV
+    // values only get the decimal separator.
+    pub fn format_csv<T: std::fmt::Display>(&self, value: T) -> String
+    {
+	self.render(value, None, None)
+    }
+
+    // Names may contain the separator, so fields holding it, a
+    // quote or a line break are quoted as in RFC 4180
+    pub fn csv_field<'a>(&self, text: &'a str) -> std::borrow::Cow<'a, str>
+    {
+	if text.contains(|c| { c == self.csv_separator() || c == '"' || c == '\r' || c == '\n' }) {
+	    format!("\"{}\"", text.replace('"', "\"\"")).into()
+	} else {
+	    text.into()
+	}
+    }
+
+    // A decimal comma clashes with comma separated values
+    pub fn csv_separator(&self) -> char
+    {
+	if self.decimal == ',' { ';' } else { ',' }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn format_numbers() {
+	let german = NumberFormat::from_locale("de_DE.UTF-8");
+	assert_eq!(german.format(1234567.5, None), "1.234.567,5");
+	assert_eq!(german.format(-1234.0, Some(2)), "-1.234,00");
+	assert_eq!(german.format_csv(1234.25), "1234,25");
+	assert_eq!(german.csv_separator(), ';');
+	let english = NumberFormat::from_locale("en_US");
+	assert_eq!(english.format(123456.0, None), "123,456");
+	assert_eq!(english.csv_separator(), ',');
+	assert_eq!(NumberFormat::from_locale("C").format(1000.5, None), "1000.5");
+    }
+
+    #[test]
+    fn samples_keep_their_precision() {
+	let sample: f32 = 0.1;
+	assert_eq!(NumberFormat::default().format_csv(sample), "0.1");
+	assert_eq!(NumberFormat::from_locale("de_DE").format_csv(sample), "0,1");
+	assert_eq!(NumberFormat::default().format(sample, None), "0.1");
+	assert_eq!(NumberFormat::default().format(-2.5f32, Some(2)), "-2.50");
+    }
+
+    #[test]
+    fn quote_csv_fields() {
+	let format = NumberFormat::default();
+	assert_eq!(format.csv_field("Volts"), "Volts");
+	assert_eq!(format.csv_field("Power Rail"), "Power Rail");
+	assert_eq!(format.csv_field("I, V"), "\"I, V\"");
+	assert_eq!(format.csv_field("5\" disk"), "\"5\"\" disk\"");
+	assert_eq!(NumberFormat::from_locale("de_DE").csv_field("I, V"), "I, V");
+	assert_eq!(NumberFormat::from_locale("de_DE").csv_field("I; V"), "\"I; V\"");
+    }
+}
@@ -0,0 +1,106 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+// Bytes of one test block, the block number makes stale
+// echoes of a previous block show up as errors.
+fn pattern(block: usize, len: usize) -> Vec<u8>
+{
+    (0..len).map(|index| { (index * 7 + block * 13) as u8 ^ 0x55 }).collect()
+}
+
+fn byte_errors(expected: &[u8], received: &[u8]) -> usize
+{
+    let differing = expected.iter().zip(received).filter(|(a, b)| { a != b }).count();
+    differing + expected.len().saturating_sub(received.len())
+}
+
+// Outcome of writing blocks out and reading them back through
+// a loopback adapter, e.g. TX bridged to RX.
+#[derive(Debug, Default, PartialEq)]
+pub struct LoopbackReport
+{
+    pub blocks: usize,
+    pub bytes: usize,
+    pub byte_errors: usize,
+    // Blocks that didn't come back completely in time
+    pub incomplete: usize,
+    // From writing a block until its last byte arrived
+    pub latencies: Vec<Duration>,
+}
+
+impl LoopbackReport
+{
+    fn record(&mut self, expected: &[u8], received: &[u8], latency: Option<Duration>)
+    {
+	self.blocks += 1;
+	self.bytes += expected.len();
+	self.byte_errors += byte_errors(expected, received);
+	match latency {
+	    Some(latency) => { self.latencies.push(latency); }
+	    None => { self.incomplete += 1; }
+	}
+    }
+
+    pub fn summary(&self) -> String
+    {
+	let mut summary = format!("{} blocks, {} bytes, {} byte errors, {} incomplete",
+				  self.blocks, self.bytes, self.byte_errors, self.incomplete);
+	if let (Some(min), Some(max)) = (self.latencies.iter().min(), self.latencies.iter().max()) {
+	    let average = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+	    summary.push_str(&format!(", latency min {:.2}ms avg {:.2}ms max {:.2}ms",
+				      min.as_secs_f64() * 1000.0, average.as_secs_f64() * 1000.0, max.as_secs_f64() * 1000.0));
+	}
+	summary
+    }
+}
+
+pub fn run(path: &str, baud: u32, blocks: usize, block_size: usize) -> Result<LoopbackReport, serialport::Error>
+{
+    let mut port = serialport::new(path, baud).timeout(Duration::from_millis(100)).open()?;
+    // Twice the time the block needs on the wire, plus slack for the adapter
+    let deadline = Duration::from_secs_f64(block_size as f64 * 10.0 / baud as f64 * 2.0) + Duration::from_millis(500);
+    port.clear(serialport::ClearBuffer::All)?;
+    let mut report = LoopbackReport::default();
+    for block in 0..blocks {
+	let expected = pattern(block, block_size);
+	let start = Instant::now();
+	port.write_all(&expected)?;
+	let mut received = vec![];
+	let mut buffer = [0u8; 1024];
+	while received.len() < expected.len() && start.elapsed() < deadline {
+	    match port.read(&mut buffer) {
+		Ok(bytes_read) => { received.extend_from_slice(&buffer[0..bytes_read]); }
+		Err(error) if error.kind() == std::io::ErrorKind::TimedOut => {}
+		Err(error) => { return Err(error.into()); }
+	    }
+	}
+	let latency = if received.len() >= expected.len() { Some(start.elapsed()) } else { None };
+	received.truncate(expected.len());
+	report.record(&expected, &received, latency);
+	// Drop late bytes so they don't spill into the next block
+	if latency.is_none() {
+	    port.clear(serialport::ClearBuffer::Input)?;
+	}
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn report_counts_errors_and_latencies() {
+	assert_ne!(pattern(0, 16), pattern(1, 16));
+	let mut report = LoopbackReport::default();
+	let expected = pattern(0, 4);
+	let mut corrupted = expected.clone();
+	corrupted[1] ^= 0xff;
+	report.record(&expected, &expected, Some(Duration::from_millis(2)));
+	report.record(&expected, &corrupted, Some(Duration::from_millis(4)));
+	report.record(&expected, &expected[0..1], None);
+	assert_eq!(report.byte_errors, 4);
+	assert_eq!(report.summary(), "3 blocks, 12 bytes, 4 byte errors, 1 incomplete, latency min 2.00ms avg 3.00ms max 4.00ms");
+    }
+}
@@ -2,31 +2,913 @@
 use nannou::prelude::*;
 
 mod serial;
+mod cobs;
+mod base64;
+mod msgpack;
+mod cbor;
+mod checksum;
+mod stdin;
+mod replay;
+mod sink;
+mod proxy;
 mod debugobjects;
 mod parser;
+mod alarms;
+mod control;
+mod locale;
+mod raster;
+mod svg;
+mod export;
+mod report;
+mod instrument;
+mod diagnostics;
+mod rules;
+mod history;
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod journal;
+mod throughput;
+mod picker;
+mod config;
+mod swo;
+mod loopback;
+mod demo;
+mod shutdown;
+mod session;
+mod fatal;
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+mod fifo;
+mod push;
+mod osc;
+mod devices;
+mod source;
+mod search;
+mod summary;
+mod diff;
+mod dialect;
+mod mavlink;
+mod modbus;
+// The mapping is configured either way, only the socket needs the feature
+#[cfg_attr(not(feature = "can"), allow(dead_code))]
+mod can;
+#[cfg_attr(not(all(feature = "ble", target_os = "linux")), allow(dead_code))]
+mod ble;
+#[cfg_attr(not(all(feature = "bridge", target_os = "linux")), allow(dead_code))]
+mod bridge;
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+mod audio;
+#[cfg_attr(not(feature = "lsl"), allow(dead_code))]
+mod labstreaming;
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+mod metrics;
+#[cfg_attr(not(all(feature = "gpio", target_os = "linux")), allow(dead_code))]
+mod gpio;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "zmq")]
+mod zeromq;
 
-use serial::SerialConnector;
+use std::sync::atomic::Ordering;
+use serde_json::{json, Value};
+use serial::{SerialConnector, ConnectionState, IngestOptions, ResetLine};
+use source::DataSource;
+use devices::{Device, HealthPanel};
+use sink::CsvSink;
+use proxy::Proxy;
 use debugobjects::{DebugObjects};
+use alarms::AlarmPanel;
+use diagnostics::DiagnosticsPanel;
+use throughput::ThroughputPanel;
+use picker::PortPicker;
+use search::SearchPalette;
+use config::{Config, StartupStep};
+use session::Session;
+use fatal::FatalError;
+use control::{ControlServer, str_param, f64_param};
+use locale::NumberFormat;
+use replay::Transport;
+use history::{Change, History};
 
-const BAUD:u32 = 230_400;
-const PORT:&str = "/dev/serial/by-id/usb-FTDI_FT232R_USB_UART_00000000-if00-port0";
+
+// Destructive actions waiting for the user to press y
+enum Confirmation {
+    Clear(String),
+    Delete(String),
+}
 
 struct Model {
     views: DebugObjects,
-    serial: SerialConnector,
+    // Groups toggled with the number keys
+    hidden_groups: Vec<String>,
+    // Changes made with keyboard and mouse, for Ctrl+Z
+    history: History,
+    // Protocol lines from the transport chosen by the configuration
+    source: Option<Box<dyn DataSource>>,
+    dialect: Box<dyn dialect::Dialect>,
+    connection: ConnectionState,
+    alarm_panel: AlarmPanel,
+    diagnostics: DiagnosticsPanel,
+    throughput: ThroughputPanel,
+    // Missing while the control socket couldn't be opened
+    control: Option<ControlServer>,
+    // While paused incoming lines are discarded
+    paused: bool,
+    confirmation: Option<Confirmation>,
+    // Shown until a serial port was chosen
+    picker: Option<PortPicker>,
+    // Ctrl+P signal finder
+    search: Option<SearchPalette>,
+    // The signal shown alone, if any
+    solo: Option<(String, String)>,
+    // Compares the live input against a capture
+    diff: Option<diff::LiveDiff>,
+    baud: u32,
+    ingest: IngestOptions,
+    // Additional serial inputs, each with its own health
+    devices: Vec<Device>,
+    health: HealthPanel,
+    journal: Option<Box<dyn DataSource>>,
+    // Where the session is saved on exit
+    session: Option<String>,
+    // Shown instead of the scopes until retried
+    error: Option<FatalError>,
+    config: Config,
+}
+
+fn model(app: &App) -> Model {
+    app.new_window()
+	.view(view)
+	.key_pressed(key_pressed)
+	.mouse_pressed(mouse_pressed)
+	.received_character(received_character)
+	.build()
+	.unwrap();
+    app.set_exit_on_escape(false);
+    // main already reported invalid configurations
+    let config = Config::from_matches(&cli().get_matches()).unwrap_or_default();
+    let mut views = DebugObjects::new();
+    views.set_number_format(match &config.locale {
+	Some(locale) => NumberFormat::from_locale(locale),
+	None => NumberFormat::from_env(),
+    });
+    if let Some(path) = &config.font {
+	match nannou::text::font::from_file(path) {
+	    Ok(font) => { views.set_fallback_font(Some(font)); }
+	    Err(error) => { println!("no fallback font loaded from {}: {}", path, error); }
+	}
+    }
+    views.set_memory_budget(config.memory_budget.map(|megabytes| { megabytes.saturating_mul(1024 * 1024) }));
+    views.set_capture_dir(&config.capture_dir);
+    let session = config.session.as_ref()
+	.and_then(|path| { Session::load(path).map_err(|error| { println!("no session restored from {}: {}", path, error) }).ok() });
+    let restored = session.as_ref().map(|session| { (session.locked, session.reconnect_markers) }).unwrap_or((false, false));
+    views.set_locked(config.locked || restored.0);
+    views.set_reconnect_markers(config.reconnect_markers || restored.1);
+    views.set_colors(config.colors.resolve().unwrap_or_default());
+    let ingest = IngestOptions{ realtime: config.realtime, core: config.ingest_core, queue: config.queue, drop: config.drop_policy, ending: config.line_ending, framing: config.framing, checksum: config.checksum };
+    let mut model = Model { views , hidden_groups: vec![], history: History::new(), source: None, dialect: Box::new(dialect::Native), connection: ConnectionState::Connected, alarm_panel: AlarmPanel::new(), diagnostics: DiagnosticsPanel::new(), throughput: ThroughputPanel::new(), control: None, paused: false, confirmation: None, picker: None, search: None, solo: None, diff: None, baud: config.baud, ingest, devices: vec![], health: HealthPanel::new(), journal: None, session: config.session.clone(), error: None, config };
+    if let Some(session) = session {
+	model.alarm_panel.visible = session.alarm_panel;
+	model.diagnostics.visible = session.diagnostics;
+	model.health.visible = session.health;
+	model.throughput.visible = session.throughput;
+    }
+    run_startup(app, &mut model);
+    if let Err(message) = connect(&mut model) {
+	model.error = Some(FatalError::new(&message));
+    }
+    model
+}
+
+// The startup script, a failing step doesn't stop the others
+fn run_startup(app: &App, model: &mut Model)
+{
+    for step in model.config.startup.clone() {
+	match step {
+	    StartupStep::Line(line) => { model.views.feed(&line); }
+	    StartupStep::Request{ method, params } => {
+		if let Err(error) = execute(app, model, &method, &params) {
+		    println!("startup {} failed: {}", method, error);
+		}
+	    }
+	}
+    }
+}
+
+// Everything opened from the configuration, replaced as a whole
+// when the user retries after an error
+struct Inputs
+{
+    source: Option<Box<dyn DataSource>>,
+    dialect: Box<dyn dialect::Dialect>,
+    devices: Vec<Device>,
+    // Host events shown next to the input
+    journal: Option<Box<dyn DataSource>>,
+    // Nothing configured, the user picks a serial port
+    pick: bool,
+}
+
+fn open_inputs(config: &Config, ingest: IngestOptions) -> Result<Inputs, String>
+{
+    let dialect = dialect::create(&config.dialect, config)?;
+    let source = source::open(config, ingest)?;
+    let pick = source.is_none() && config.devices.is_empty();
+    let devices = config.devices.iter().enumerate()
+	.map(|(index, device)| {
+	    let dialect = dialect::create(device.dialect.as_ref().unwrap_or(&config.dialect), config)?;
+	    let mut opened = Device::open(&device.name, index, &device.port, device.baud.unwrap_or(config.baud), config.line_settings(), ingest, dialect)
+		.map_err(|error| { format!("opening device {} at {} failed: {}", device.name, device.port, error) })?;
+	    opened.namespace = device.namespace.clone().or_else(|| { if config.namespace_devices { Some(device.name.clone()) } else { None } });
+	    Ok(opened)
+	})
+	.collect::<Result<Vec<Device>, String>>()?;
+    let journal = source::open_journal(config)?;
+    Ok(Inputs{ source, dialect, devices, journal, pick })
+}
+
+// Renders the comparison of two captures, or prepares comparing
+// the live input against one
+fn start_diff(config: &Config, views: &mut DebugObjects) -> Result<Option<diff::LiveDiff>, String>
+{
+    if config.diff.is_empty() {
+	return Ok(None);
+    }
+    if config.diff.len() > 2 {
+	return Err("--diff compares at most two captures".to_string());
+    }
+    let target = config.diff_signal.as_ref().ok_or("--diff needs --diff-signal OBJECT.SIGNAL")?;
+    let position = target.rfind('.').ok_or(format!("{} is not OBJECT.SIGNAL", target))?;
+    let (object, signal) = (&target[..position], &target[position + 1..]);
+    let align: diff::Align = config.diff_align.parse()?;
+    let captures = config.diff.iter()
+	.map(|path| { diff::Capture::load(path).map_err(|error| { format!("loading capture {} failed: {}", path, error) }) })
+	.collect::<Result<Vec<diff::Capture>, String>>()?;
+    if let [a, b] = &captures[..] {
+	let missing = || { format!("no samples of {} in both captures", target) };
+	let pairs = diff::align(a.series(object, signal).ok_or_else(missing)?, b.series(object, signal).ok_or_else(missing)?, align);
+	let mut renderer = diff::DiffRenderer::new(config.diff_tolerance);
+	for line in renderer.render(&pairs) {
+	    views.feed(&line);
+	}
+	println!("{} of {} samples differ by more than {}", renderer.violations, pairs.len(), config.diff_tolerance);
+	return Ok(None);
+    }
+    let (live, declarations) = diff::LiveDiff::new(&captures[0], object, signal, align, config.diff_tolerance)?;
+    for line in declarations {
+	views.feed(&line);
+    }
+    Ok(Some(live))
+}
+
+// Opens what the configuration asks for and isn't open yet.
+// Failures end up on the error screen, from where they are retried.
+fn connect(model: &mut Model) -> Result<(), String>
+{
+    let config = &model.config;
+    if let (None, Some(address)) = (&model.control, &config.control) {
+	let control = ControlServer::new(address).map_err(|error| { format!("control socket {} failed: {}", address, error) })?;
+	model.control = Some(control);
+    }
+    if let Some(directory) = &config.record {
+	if !model.views.recording() {
+	    let sink = CsvSink::new(directory, std::time::Duration::from_secs(config.rotate * 60), *model.views.number_format())
+		.map_err(|error| { format!("recording to {} failed: {}", directory, error) })?;
+	    model.views.set_sink(Some(sink));
+	}
+    }
+    if let Some(target) = &config.proxy {
+	if !model.views.proxying() {
+	    let reducer = proxy::Reducer::new(config.proxy_rate, config.proxy_aggregate);
+	    let proxy = target.parse::<proxy::Target>()
+		.and_then(|parsed| {
+		    Proxy::new(&parsed, config.proxy_baud.unwrap_or(config.baud), config.line_settings(), reducer).map_err(|error| { error.to_string() })
+		})
+		.map_err(|error| { format!("proxy to {} failed: {}", target, error) })?;
+	    model.views.set_proxy(Some(proxy));
+	}
+    }
+    for rule in &config.rules {
+	model.views.add_rule(rules::Rule::new(rule.clone()).map_err(|error| { format!("rule {}: {}", rule.name, error) })?);
+    }
+    model.diff = start_diff(config, &mut model.views)?;
+    let inputs = open_inputs(config, model.ingest)?;
+    model.source = inputs.source;
+    model.dialect = inputs.dialect;
+    model.devices = inputs.devices;
+    model.journal = inputs.journal;
+    model.picker = if inputs.pick { Some(PortPicker::new()) } else { None };
+    model.connection = ConnectionState::Connected;
+    model.error = None;
+    Ok(())
+}
+
+fn session_summary(model: &Model) -> Value
+{
+    summary::summary(&model.views, model.source.as_ref().and_then(|source| { source.stats() }).map(|stats| { &**stats }), &model.devices)
+}
+
+// Window closed or SIGINT: stop the readers and get everything on disk
+fn exit(_app: &App, mut model: Model)
+{
+    shutdown::request();
+    if let Err(error) = model.views.close_sink() {
+	eprintln!("flushing the recording failed: {}", error);
+    }
+    if let Some(path) = &model.config.summary {
+	let summary = serde_json::to_string_pretty(&session_summary(&model)).unwrap_or_default();
+	if let Err(error) = std::fs::write(path, summary) {
+	    eprintln!("writing the summary to {} failed: {}", path, error);
+	}
+    }
+    if let Some(path) = &model.session {
+	let session = Session{
+	    locked: model.views.locked(),
+	    reconnect_markers: model.views.reconnect_markers(),
+	    alarm_panel: model.alarm_panel.visible,
+	    diagnostics: model.diagnostics.visible,
+	    health: model.health.visible,
+	    throughput: model.throughput.visible,
+	};
+	if let Err(error) = session.save(path) {
+	    eprintln!("saving the session to {} failed: {}", path, error);
+	}
+    }
+}
+
+// Handles the search palette, returns false while it is closed
+fn search_signal(model: &mut Model, key: Key) -> bool
+{
+    let search = match &mut model.search {
+	Some(search) => search,
+	None => { return false; }
+    };
+    match key {
+	Key::Up => { search.up(); }
+	Key::Down => { search.down(); }
+	Key::Back => { search.pop(); }
+	Key::Escape => { model.search = None; }
+	Key::Return | Key::Tab => {
+	    if let Some((object, signal)) = search.current() {
+		if key == Key::Tab {
+		    model.views.solo(Some((&object, &signal)));
+		    model.solo = Some((object.clone(), signal));
+		}
+		model.views.focus(&object).ok();
+	    }
+	    model.search = None;
+	}
+	_ => {}
+    }
+    true
+}
+
+fn apply_change(model: &mut Model, change: &Change)
+{
+    match change {
+	Change::GroupVisible{ group, visible } => {
+	    model.views.set_group_visible(group, *visible).ok();
+	    model.hidden_groups.retain(|name| { name != group });
+	    if !visible {
+		model.hidden_groups.push(group.clone());
+	    }
+	}
+	Change::ZOrder{ after, .. } => { model.views.set_z_order(after); }
+	Change::Locked(locked) => { model.views.set_locked(*locked); }
+	Change::ReconnectMarkers(enabled) => { model.views.set_reconnect_markers(*enabled); }
+    }
+}
+
+// Applies a change made by the user, so it can be undone
+fn perform(model: &mut Model, change: Change)
+{
+    apply_change(model, &change);
+    model.history.record(change);
+}
+
+// Records raising or lowering an object
+fn restack(model: &mut Model, name: &str, raise: bool)
+{
+    let before = model.views.z_order().to_vec();
+    let result = if raise { model.views.raise(name) } else { model.views.lower(name) };
+    if result.is_ok() && model.views.z_order() != &before[..] {
+	let after = model.views.z_order().to_vec();
+	model.history.record(Change::ZOrder{ before, after });
+    }
+}
+
+// Shows all signals again, except the hidden groups
+fn end_solo(model: &mut Model)
+{
+    model.views.solo(None);
+    for group in &model.hidden_groups {
+	model.views.set_group_visible(group, false).ok();
+    }
+    model.solo = None;
+}
+
+// Handles the port picker, returns false once it is gone
+fn pick_port(model: &mut Model, key: Key) -> bool
+{
+    let picker = match &mut model.picker {
+	Some(picker) => picker,
+	None => { return false; }
+    };
+    match key {
+	Key::Up => { picker.up(); }
+	Key::Down => { picker.down(); }
+	Key::R => { picker.rescan(); }
+	Key::Return => {
+	    if let Some(port) = picker.current() {
+		match SerialConnector::new(&port.name, model.baud, model.config.line_settings(), model.ingest) {
+		    Ok(serial) => {
+			model.source = Some(Box::new(serial));
+			model.picker = None;
+		    }
+		    Err(error) => {
+			model.error = Some(FatalError::new(&format!("opening {} failed: {}", port.name, error)));
+			model.picker = None;
+		    }
+		}
+	    }
+	}
+	_ => {}
+    }
+    true
+}
+
+fn execute(app: &App, model: &mut Model, method: &str, params: &Value) -> Result<Value, String>
+{
+    match method {
+	// Reboots the target, "dtr" for Arduinos, "rts" for ESP32 boards
+	"reset-target" => {
+	    let line = params.get("line").and_then(|line| { line.as_str() }).unwrap_or("dtr").parse::<ResetLine>()?;
+	    model.source.as_ref().ok_or("no input".to_string())?.reset_target(line)?;
+	    Ok(Value::Null)
+	}
+	"pause" => {
+	    model.paused = params.get("paused").and_then(|paused| { paused.as_bool() }).unwrap_or(!model.paused);
+	    Ok(json!(model.paused))
+	}
+	// Replays only: "pause", "resume", "step", "seek" to {time}
+	// seconds or "speed" {speed}
+	"transport" => {
+	    let command = match str_param(params, "action")? {
+		"pause" => Transport::Pause,
+		"resume" => Transport::Resume,
+		"step" => Transport::Step,
+		"seek" => Transport::Seek(f64_param(params, "time")?),
+		"speed" => Transport::Speed(f64_param(params, "speed")?),
+		action => { return Err(format!("unknown transport action {}", action)); }
+	    };
+	    model.source.as_ref().ok_or("no input".to_string())?.transport(command)?;
+	    Ok(Value::Null)
+	}
+	"set-trigger" => {
+	    let offset = params.get("offset").and_then(|offset| { offset.as_u64() }).map(|offset| { offset as usize });
+	    model.views.set_trigger(
+		str_param(params, "scope")?,
+		f64_param(params, "channel")? as usize,
+		f64_param(params, "arm_level")? as f32,
+		f64_param(params, "trigger_level")? as f32,
+		offset).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	// Redraws the samples of an object at most rate times a
+	// second, without rate every frame
+	"set-refresh" => {
+	    let rate = params.get("rate").and_then(|rate| { rate.as_f64() });
+	    model.views.set_refresh(str_param(params, "object")?, rate).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"raise" => {
+	    model.views.raise(str_param(params, "object")?).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"lower" => {
+	    model.views.lower(str_param(params, "object")?).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"export-csv" => {
+	    model.views.export_csv(str_param(params, "scope")?, str_param(params, "path")?)
+		.map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"export-svg" => {
+	    model.views.export_svg(str_param(params, "scope")?, str_param(params, "path")?)
+		.map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"export-alarms" => {
+	    let filter = params.get("filter").and_then(|filter| { filter.as_str() }).unwrap_or("");
+	    model.views.alarms().export_csv(str_param(params, "path")?, filter, model.views.number_format())
+		.map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"lock" => {
+	    let locked = params.get("locked").and_then(|locked| { locked.as_bool() }).unwrap_or(!model.views.locked());
+	    model.views.set_locked(locked);
+	    Ok(json!(locked))
+	}
+	// Destructive requests need "confirm": true
+	"clear" | "delete" => {
+	    if params.get("confirm").and_then(|confirm| { confirm.as_bool() }) != Some(true) {
+		return Err(format!("{} needs confirmation", method));
+	    }
+	    let object = params.get("object").and_then(|object| { object.as_str() });
+	    let result = match (method, object) {
+		("clear", object) => model.views.clear(object),
+		(_, Some(object)) => model.views.delete(object),
+		_ => { return Err("missing string parameter object".to_string()); }
+	    };
+	    result.map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"set-reconnect-markers" => {
+	    let markers = params.get("enabled").and_then(|enabled| { enabled.as_bool() }).unwrap_or(!model.views.reconnect_markers());
+	    model.views.set_reconnect_markers(markers);
+	    Ok(json!(markers))
+	}
+	"reconnected" => {
+	    model.views.reconnected();
+	    Ok(Value::Null)
+	}
+	"define-group" => {
+	    let members = params.get("members").and_then(|members| { members.as_array() })
+		.ok_or("missing array parameter members".to_string())?
+		.iter().filter_map(|member| { member.as_str().map(|member| { member.to_string() }) })
+		.collect();
+	    model.views.define_group(str_param(params, "group")?, members);
+	    Ok(Value::Null)
+	}
+	"group-visible" => {
+	    let visible = params.get("visible").and_then(|visible| { visible.as_bool() }).unwrap_or(true);
+	    model.views.set_group_visible(str_param(params, "group")?, visible).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"group-palette" => {
+	    model.views.color_group(str_param(params, "group")?).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"export-group" => {
+	    model.views.export_group_csv(str_param(params, "group")?, str_param(params, "path")?)
+		.map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	// Overlays an instrument capture, see instrument::ReferenceSpec
+	"load-reference" => {
+	    let spec = instrument::ReferenceSpec::from_file(str_param(params, "spec")?).map_err(|error| { error.to_string() })?;
+	    instrument::load(&mut model.views, &spec).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"diagnostics" => {
+	    let widgets: Vec<Value> = model.views.widget_stats().iter().map(|widget| {
+		json!({
+		    "name": widget.name,
+		    "primitives": widget.cost.primitives,
+		    "vertices": widget.cost.vertices,
+		    "texture_bytes": widget.cost.texture_bytes,
+		    "draw_time_us": widget.draw_time.as_micros() as u64,
+		})
+	    }).collect();
+	    Ok(json!({ "frame_time_ms": app.duration.since_prev_update.as_secs_f64() * 1000.0, "widgets": widgets }))
+	}
+	"signal-quality" => {
+	    Ok(json!(model.views.signal_quality(str_param(params, "object")?).map_err(|error| { error.to_string() })?))
+	}
+	"memory" => {
+	    Ok(json!({ "used": model.views.memory_used(), "error": model.views.budget_error() }))
+	}
+	"devices" => {
+	    let now = std::time::Instant::now();
+	    Ok(Value::Array(model.devices.iter().map(|device| { json!(device.health.summary(now)) }).collect()))
+	}
+	"ingest-stats" => {
+	    let stats = model.source.as_ref().and_then(|source| { source.stats().cloned() }).unwrap_or_default();
+	    Ok(summary::link_stats(&stats))
+	}
+	"summary" => {
+	    Ok(session_summary(model))
+	}
+	"capabilities" => {
+	    Ok(json!({
+		"viewer": debugobjects::CAPABILITIES,
+		"input": model.views.peer_capabilities(),
+		"unknown_keywords": model.views.unknown_keywords(),
+	    }))
+	}
+	// Global default colors, objects keep their own
+	"set-colors" => {
+	    let color = |key: &str, current| {
+		match params.get(key).and_then(|name| { name.as_str() }) {
+		    Some(name) => parser::color(name).map_err(|_| { format!("unknown color {}", name) }),
+		    None => Ok(current),
+		}
+	    };
+	    let current = *model.views.colors();
+	    // CYCLE brings back the colorblind cycle
+	    let signal = params.get("signal").and_then(|name| { name.as_str() });
+	    let colors = debugobjects::Colors{
+		background: color("background", current.background)?,
+		grid: color("grid", current.grid)?,
+		signal: if signal == Some("CYCLE") { current.signal } else { color("signal", current.signal)? },
+		cycle: signal.map_or(current.cycle, |name| { name == "CYCLE" }),
+	    };
+	    model.views.set_colors(colors);
+	    Ok(Value::Null)
+	}
+	"set-locale" => {
+	    model.views.set_number_format(NumberFormat::from_locale(str_param(params, "locale")?));
+	    Ok(Value::Null)
+	}
+	"report" => {
+	    let title = params.get("title").and_then(|title| { title.as_str() }).unwrap_or("rusty-peanut session");
+	    let lines = session_summary(model)["link"]["lines"].as_u64().map(|lines| { lines.to_string() }).unwrap_or_default();
+	    let metadata = vec![
+		("Lines".to_string(), lines),
+		("Objects".to_string(), model.views.names().len().to_string()),
+		("Gaps".to_string(), model.views.gaps().len().to_string()),
+		("Devices".to_string(), model.devices.iter().map(|device| { device.health.name.clone() }).collect::<Vec<String>>().join(", ")),
+	    ];
+	    let html = report::render(&model.views, title, &metadata).map_err(|error| { error.to_string() })?;
+	    std::fs::write(str_param(params, "path")?, html).map_err(|error| { error.to_string() })?;
+	    Ok(Value::Null)
+	}
+	"screenshot" => {
+	    app.main_window().capture_frame(str_param(params, "path")?);
+	    Ok(Value::Null)
+	}
+	// Feeds a previously captured file of protocol lines
+	"load-replay" => {
+	    let content = std::fs::read_to_string(str_param(params, "path")?).map_err(|error| { error.to_string() })?;
+	    Ok(json!(model.views.feed_text(&content)))
+	}
+	_ => Err(format!("unknown method {}", method)),
+    }
+}
+
+fn update(app: &App, model: &mut Model, _update: Update)
+{
+    if shutdown::requested() {
+	app.quit();
+	return;
+    }
+    let requests: Vec<control::Request> = match &model.control {
+	Some(control) => control.receiver.try_iter().collect(),
+	None => vec![],
+    };
+    for request in requests {
+	let result = execute(app, model, &request.method, &request.params);
+	request.reply.send(result).ok();
+    }
+    if let Some(source) = &model.source {
+	for state in source.status().into_iter().flat_map(|status| { status.try_iter() }) {
+	    if state == ConnectionState::Connected && model.connection == ConnectionState::Reconnecting {
+		model.views.reconnected();
+	    }
+	    model.connection = state;
+	}
+	for received in source.receiver().try_iter() {
+	    if model.paused {
+		continue;
+	    }
+	    for line in model.dialect.translate(&received.line) {
+		let line = match &model.config.namespace {
+		    Some(namespace) => debugobjects::namespace_line(namespace, &line),
+		    None => line,
+		};
+		model.views.feed_received(&line, received.at);
+		if let Some(sample) = model.diff.as_mut().and_then(|diff| { diff.feed(&line) }) {
+		    model.views.feed_received(&sample, received.at);
+		}
+	    }
+	}
+    }
+    let now = std::time::Instant::now();
+    for device in model.devices.iter_mut() {
+	for state in device.source.status().into_iter().flat_map(|status| { status.try_iter() }) {
+	    if state == ConnectionState::Connected && device.health.state == ConnectionState::Reconnecting {
+		model.views.reconnected();
+	    }
+	    device.health.state = state;
+	}
+	for received in device.source.receiver().try_iter() {
+	    device.health.line(received.at);
+	    if model.paused {
+		continue;
+	    }
+	    for line in device.dialect.translate(&received.line) {
+		let line = match &device.namespace {
+		    Some(namespace) => debugobjects::namespace_line(namespace, &line),
+		    None => line,
+		};
+		model.views.feed_from(&device.health.name, device.health.color, &line, received.at);
+	    }
+	}
+	device.health.tick(now);
+    }
+    if let Some(journal) = &model.journal {
+	for received in journal.receiver().try_iter() {
+	    if !model.paused {
+		model.views.feed_received(&received.line, received.at);
+	    }
+	}
+    }
+    for command in model.views.drain_commands() {
+	match &model.source {
+	    Some(source) => {
+		if let Err(error) = source.send(&command) {
+		    println!("sending {} failed: {}", command, error);
+		}
+	    }
+	    None => { println!("no input to send {} to", command); }
+	}
+    }
+    if let Some(stats) = model.source.as_ref().and_then(|source| { source.stats() }) {
+	model.throughput.tick(stats, now);
+    }
+}
+
+fn mouse_pressed(app: &App, model: &mut Model, _button: MouseButton)
+{
+    // Click to front
+    if let Some(name) = model.views.object_at(app.mouse.position()) {
+	restack(model, &name, true);
+    }
+}
+
+// Handles the error screen, returns false if there is none
+fn resolve_error(app: &App, model: &mut Model, key: Key) -> bool
+{
+    if model.error.is_none() {
+	return false;
+    }
+    match key {
+	Key::R => {
+	    if let Err(message) = connect(model) {
+		model.error = Some(FatalError::new(&message));
+	    }
+	}
+	Key::P => {
+	    model.error = None;
+	    model.picker = Some(PortPicker::new());
+	}
+	Key::Escape => { app.quit(); }
+	_ => {}
+    }
+    true
 }
 
-fn model(_app: &App) -> Model {
-    let views = DebugObjects::new();
-    let serial = SerialConnector::new(PORT, BAUD).expect("serial port failed");
-    Model { views , serial }
+fn key_pressed(app: &App, model: &mut Model, key: Key)
+{
+    if resolve_error(app, model, key) {
+	return;
+    }
+    if pick_port(model, key) {
+	return;
+    }
+    if search_signal(model, key) {
+	return;
+    }
+    match key {
+	// Ctrl+R resets through DTR, with Shift through RTS
+	Key::R if app.keys.mods.ctrl() => {
+	    let line = if app.keys.mods.shift() { ResetLine::Rts } else { ResetLine::Dtr };
+	    match model.source.as_ref().map(|source| { source.reset_target(line) }) {
+		Some(Ok(())) => { println!("resetting the target through {:?}", line); }
+		Some(Err(error)) => { println!("reset failed: {}", error); }
+		None => {}
+	    }
+	}
+	// Replay transport: Space pauses, Right steps a line, Home
+	// rewinds, [ and ] halve and double the speed
+	Key::Space | Key::Right | Key::Home | Key::LBracket | Key::RBracket if !model.alarm_panel.visible => {
+	    let command = match key {
+		Key::Space => Transport::Toggle,
+		Key::Right => Transport::Step,
+		Key::Home => Transport::Seek(0.0),
+		Key::LBracket => Transport::Scale(0.5),
+		_ => Transport::Scale(2.0),
+	    };
+	    if let Some(Err(error)) = model.source.as_ref().map(|source| { source.transport(command) }) {
+		println!("{}", error);
+	    }
+	}
+	Key::P if app.keys.mods.ctrl() => {
+	    model.search = Some(SearchPalette::new(model.views.signals()));
+	}
+	Key::Escape if model.solo.is_some() && model.confirmation.is_none() => {
+	    end_solo(model);
+	}
+	Key::PageUp | Key::PageDown => {
+	    if let Some(name) = model.views.object_at(app.mouse.position()) {
+		restack(model, &name, key == Key::PageUp);
+	    }
+	}
+	Key::F2 => {
+	    model.alarm_panel.visible = !model.alarm_panel.visible;
+	}
+	Key::F3 => {
+	    let path = "alarms.csv";
+	    match model.views.alarms().export_csv(path, &model.alarm_panel.filter, model.views.number_format()) {
+		Ok(_) => { println!("exported alarms to {}", path); }
+		Err(error) => { println!("alarm export failed: {:?}", error); }
+	    }
+	}
+	// Paste protocol lines, handy for prototyping widgets without a device
+	Key::V if app.keys.mods.ctrl() => {
+	    match arboard::Clipboard::new().and_then(|mut clipboard| { clipboard.get_text() }) {
+		Ok(text) => {
+		    let count = model.views.feed_text(&text);
+		    println!("fed {} pasted lines", count);
+		}
+		Err(error) => { println!("clipboard paste failed: {:?}", error); }
+	    }
+	}
+	// 1-9 toggle the visibility of the signal groups in order of definition
+	Key::Key1 | Key::Key2 | Key::Key3 | Key::Key4 | Key::Key5 | Key::Key6 | Key::Key7 | Key::Key8 | Key::Key9
+	    if !model.alarm_panel.visible && model.confirmation.is_none() => {
+	    let index = key as usize - Key::Key1 as usize;
+	    if let Some(group) = model.views.groups().get(index).map(|group| { group.name.clone() }) {
+		let visible = model.hidden_groups.contains(&group);
+		perform(model, Change::GroupVisible{ group, visible });
+	    }
+	}
+	Key::F4 => {
+	    let locked = !model.views.locked();
+	    perform(model, Change::Locked(locked));
+	}
+	Key::F5 => {
+	    let enabled = !model.views.reconnect_markers();
+	    perform(model, Change::ReconnectMarkers(enabled));
+	}
+	// Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes
+	Key::Z | Key::Y if app.keys.mods.ctrl() => {
+	    let undo = key == Key::Z && !app.keys.mods.shift();
+	    match if undo { model.history.undo() } else { model.history.redo() } {
+		Some(change) => { apply_change(model, &change); }
+		None => { println!("nothing to {}", if undo { "undo" } else { "redo" }); }
+	    }
+	}
+	Key::F6 => {
+	    model.throughput.visible = !model.throughput.visible;
+	}
+	Key::F7 => {
+	    model.diagnostics.visible = !model.diagnostics.visible;
+	}
+	Key::F8 => {
+	    model.health.visible = !model.health.visible;
+	}
+	Key::Delete => {
+	    if let Some(name) = model.views.object_at(app.mouse.position()) {
+		if model.views.locked() {
+		    println!("locked, not touching {}", name);
+		} else if app.keys.mods.shift() {
+		    model.confirmation = Some(Confirmation::Delete(name));
+		} else {
+		    model.confirmation = Some(Confirmation::Clear(name));
+		}
+	    }
+	}
+	Key::Escape if model.confirmation.is_some() => {
+	    model.confirmation = None;
+	}
+	Key::Back if model.alarm_panel.visible => {
+	    model.alarm_panel.filter.pop();
+	}
+	// Quitting is left to us so Escape can close the palette first
+	Key::Escape => {
+	    app.quit();
+	}
+	_ => {}
+    }
 }
 
-fn update(_app: &App, model: &mut Model, _update: Update)
+fn received_character(_app: &App, model: &mut Model, character: char)
 {
-    for line in model.serial.receiver.try_iter() {
-	//println!("{}", line);
-	model.views.feed(&line);
+    if model.error.is_some() || model.picker.is_some() {
+	return;
+    }
+    if let Some(search) = &mut model.search {
+	if !character.is_control() {
+	    search.push(character);
+	}
+	return;
+    }
+    if let Some(confirmation) = model.confirmation.take() {
+	if character == 'y' {
+	    let result = match &confirmation {
+		Confirmation::Clear(name) => model.views.clear(Some(name)),
+		Confirmation::Delete(name) => model.views.delete(name),
+	    };
+	    if let Err(error) = result {
+		println!("{}", error);
+	    }
+	}
+	return;
+    }
+    if model.alarm_panel.visible && !character.is_control() {
+	model.alarm_panel.filter.push(character);
     }
 }
 
@@ -34,15 +916,563 @@ fn view(app: &App, model: &Model, frame: Frame) {
     // Begin drawing
     let draw = app.draw();
     draw.background().color(BLACK);
+    let window = app.window_rect();
+    if let Some(error) = &model.error {
+	error.draw(&draw, window.pad(20.0));
+	draw.to_frame(app, &frame).unwrap();
+	return;
+    }
+    if let Some(picker) = &model.picker {
+	picker.draw(&draw, window.pad(20.0));
+	draw.to_frame(app, &frame).unwrap();
+	return;
+    }
+    model.views.hover(Some(app.mouse.position()));
     model.views.draw(&draw);
+    let panel = Rect::from_w_h(window.w() / 3.0, window.h()).align_right_of(window);
+    model.alarm_panel.draw(&draw, panel, model.views.alarms(), model.views.number_format());
+    let diagnostics = Rect::from_w_h(window.w() / 2.0, window.h() / 3.0).align_left_of(window).align_bottom_of(window);
+    model.diagnostics.draw(&draw, diagnostics, &model.views.widget_stats(), app.duration.since_prev_update);
+    // Below the LOCKED marker
+    let health = Rect::from_w_h(window.w() / 3.0, window.h() - 24.0).align_left_of(window).align_bottom_of(window);
+    model.health.draw(&draw, health, &model.devices);
+    if let Some(source) = &model.source {
+	let stats = source.stats().cloned().unwrap_or_default();
+	let throughput = Rect::from_w_h(window.w() / 3.0, window.h() - 24.0).align_right_of(window).align_bottom_of(window);
+	model.throughput.draw(&draw, throughput, &stats, source.receiver().len(), model.connection);
+    }
+    if let Some(error) = model.views.budget_error() {
+	draw.text(error)
+	    .xy(window.mid_top() + vec2(0.0, -10.0))
+	    .w(window.w())
+	    .font_size(14)
+	    .color(RED);
+    }
+    let stats = model.source.as_ref().and_then(|source| { source.stats() });
+    let overruns = stats.map_or(0, |stats| { stats.overruns() });
+    if overruns > 0 {
+	let dropped = stats.map_or(0, |stats| { stats.dropped.load(Ordering::Relaxed) });
+	draw.text(&format!("overruns: {} (dropped: {})", overruns, dropped))
+	    .xy(window.bottom_right() + vec2(-100.0, 10.0))
+	    .w(200.0)
+	    .font_size(12)
+	    .color(RED);
+    }
+    if model.connection == ConnectionState::Reconnecting {
+	draw.text("reconnecting...")
+	    .xy(window.top_right() + vec2(-70.0, -10.0))
+	    .font_size(14)
+	    .color(ORANGE);
+    }
+    if model.views.locked() {
+	draw.text("LOCKED")
+	    .xy(window.top_left() + vec2(40.0, -10.0))
+	    .font_size(14)
+	    .color(RED);
+    }
+    if let Some(search) = &model.search {
+	search.draw(&draw, window.pad(40.0));
+    }
+    if let Some(diff) = model.diff.as_ref().filter(|diff| { diff.violations() > 0 }) {
+	draw.text(&format!("{} samples out of tolerance", diff.violations()))
+	    .xy(window.bottom_left() + vec2(100.0, 10.0))
+	    .w(200.0)
+	    .font_size(12)
+	    .color(RED);
+    }
+    if model.solo.is_some() {
+	draw.text("SOLO (Esc)")
+	    .xy(window.top_left() + vec2(120.0, -10.0))
+	    .font_size(14)
+	    .color(YELLOW);
+    }
+    if let Some(confirmation) = &model.confirmation {
+	let question = match confirmation {
+	    Confirmation::Clear(name) => format!("Clear {}? (y/n)", name),
+	    Confirmation::Delete(name) => format!("Delete {}? (y/n)", name),
+	};
+	let rect = Rect::from_w_h(300.0, 40.0);
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.9)).stroke(YELLOW).stroke_weight(1.0);
+	draw.text(&question).xy(rect.xy()).w_h(rect.w(), rect.h()).font_size(14).color(YELLOW);
+    }
     // Write the result of our drawing to the window's frame.
     draw.to_frame(app, &frame).unwrap();
 }
 
+fn cli() -> clap::App<'static, 'static>
+{
+    clap::App::new("rusty-peanut")
+	.about("Visualizes the debug output of Propeller 2 and other microcontrollers")
+	.arg(clap::Arg::with_name("port")
+	     .long("port")
+	     .value_name("PATH")
+	     .help("Serial port to open, otherwise a picker is shown")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("baud")
+	     .long("baud")
+	     .value_name("RATE")
+	     .help("Serial baud rate [default: 230400]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("config")
+	     .long("config")
+	     .value_name("FILE")
+	     .help("TOML file with settings, command line options take precedence")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("realtime")
+	     .long("realtime")
+	     .help("Raises the priority of the serial reader thread where the OS allows"))
+	.arg(clap::Arg::with_name("ingest-core")
+	     .long("ingest-core")
+	     .value_name("CORE")
+	     .help("Pins the serial reader thread to CORE")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("queue")
+	     .long("queue")
+	     .value_name("LINES")
+	     .help("Lines buffered between the serial reader and the display [default: 65536]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("drop-policy")
+	     .long("drop-policy")
+	     .value_name("POLICY")
+	     .possible_values(&["drop-oldest", "drop-newest"])
+	     .help("Lines lost once the buffer is full [default: drop-oldest]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("line-ending")
+	     .long("line-ending")
+	     .value_name("ENDING")
+	     .possible_values(&["auto", "lf", "cr", "crlf"])
+	     .help("What ends a line from the serial port [default: auto]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("format")
+	     .long("format")
+	     .value_name("FORMAT")
+	     .help("Data bits, parity and stop bits of the serial line, e.g. 7E1 [default: 8N1]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("flow-control")
+	     .long("flow-control")
+	     .value_name("FLOW")
+	     .possible_values(&["none", "hardware", "software"])
+	     .help("RTS/CTS or XON/XOFF flow control of the serial line [default: none]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("framing")
+	     .long("framing")
+	     .value_name("FRAMING")
+	     .possible_values(&["text", "cobs", "mavlink"])
+	     .help("Plain protocol lines, COBS frames or MAVLink (with --dialect mavlink) from the serial port [default: text]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("diff")
+	     .long("diff")
+	     .value_name("CAPTURE")
+	     .multiple(true)
+	     .number_of_values(1)
+	     .help("Compares the input, or a second --diff capture, against CAPTURE")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("diff-signal")
+	     .long("diff-signal")
+	     .value_name("OBJECT.SIGNAL")
+	     .help("Signal compared by --diff")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("diff-align")
+	     .long("diff-align")
+	     .value_name("ALIGN")
+	     .help("start, time or trigger:LEVEL [default: start]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("diff-tolerance")
+	     .long("diff-tolerance")
+	     .value_name("VALUE")
+	     .help("Differences within VALUE are fine [default: 0]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("checksum")
+	     .long("checksum")
+	     .value_name("MODE")
+	     .possible_values(&["off", "optional", "required"])
+	     .help("Verifies NMEA style *XX checksums at the end of serial lines [default: off]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("dialect")
+	     .long("dialect")
+	     .value_name("DIALECT")
+	     .help("Line format of the input, for firmware written for other tools [default: native]")
+	     .possible_values(&["native", "teleplot", "arduino", "jsonl", "nmea", "mavlink"])
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("summary")
+	     .long("summary")
+	     .value_name("FILE")
+	     .help("Writes signal, alarm and link statistics as JSON on exit")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("capture-dir")
+	     .long("capture-dir")
+	     .value_name("DIRECTORY")
+	     .help("Where fired rules write the objects they capture [default: captures]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("memory-budget")
+	     .long("memory-budget")
+	     .value_name("MEGABYTES")
+	     .help("Refuses declarations whose sample buffers exceed MEGABYTES [default: 256]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("mqtt")
+	     .long("mqtt")
+	     .value_name("BROKER")
+	     .help("Subscribes to --topic on the MQTT broker at mqtt://host:port")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("topic")
+	     .long("topic")
+	     .value_name("TOPIC")
+	     .help("MQTT topic filter carrying protocol lines, can be repeated")
+	     .takes_value(true)
+	     .multiple(true)
+	     .number_of_values(1))
+	.arg(clap::Arg::with_name("zmq")
+	     .long("zmq")
+	     .value_name("ENDPOINT")
+	     .help("Subscribes to the ZeroMQ PUB socket at ENDPOINT, e.g. tcp://host:5556")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("zmq-topic")
+	     .long("zmq-topic")
+	     .value_name("PREFIX")
+	     .help("ZeroMQ topic prefix to subscribe to, can be repeated")
+	     .takes_value(true)
+	     .multiple(true)
+	     .number_of_values(1))
+	.arg(clap::Arg::with_name("lsl")
+	     .long("lsl")
+	     .value_name("NAME")
+	     .help("Shows the Lab Streaming Layer stream NAME, one signal per channel, needs the lsl feature")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("lsl-min")
+	     .long("lsl-min")
+	     .value_name("VALUE")
+	     .help("Lower end of the LSL channels [default: -100]")
+	     .allow_hyphen_values(true)
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("lsl-max")
+	     .long("lsl-max")
+	     .value_name("VALUE")
+	     .help("Upper end of the LSL channels [default: 100]")
+	     .allow_hyphen_values(true)
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("device")
+	     .long("device")
+	     .value_name("NAME=PATH")
+	     .help("Additional serial device, can be repeated")
+	     .takes_value(true)
+	     .multiple(true)
+	     .number_of_values(1))
+	.arg(clap::Arg::with_name("namespace")
+	     .long("namespace")
+	     .value_name("NAME")
+	     .help("Prefixes the object names of the main input with NAME")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("namespace-devices")
+	     .long("namespace-devices")
+	     .help("Prefixes the object names of each device with its name"))
+	.arg(clap::Arg::with_name("can")
+	     .long("can")
+	     .value_name("INTERFACE")
+	     .help("Receives CAN frames mapped by can_signals in --config, e.g. can0")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("audio")
+	     .long("audio")
+	     .value_name("DEVICE")
+	     .help("Captures an audio input into the Audio scope, \"default\" for the system's default, needs the audio feature")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("audio-rate")
+	     .long("audio-rate")
+	     .value_name("HZ")
+	     .help("Lines per second the audio is decimated to [default: 1000]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("audio-channel")
+	     .long("audio-channel")
+	     .value_name("N")
+	     .help("Audio channel to show, from 1, can be given twice [default: all]")
+	     .takes_value(true)
+	     .multiple(true)
+	     .number_of_values(1))
+	.arg(clap::Arg::with_name("audio-mean")
+	     .long("audio-mean")
+	     .help("Decimates audio to block means instead of peaks, for analog levels like filtered PWM"))
+	.arg(clap::Arg::with_name("gpio")
+	     .long("gpio")
+	     .value_name("PINS")
+	     .help("Samples Raspberry Pi GPIO pins into the Logic scope, e.g. 17,27,22, needs the gpio feature")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("gpio-rate")
+	     .long("gpio-rate")
+	     .value_name("HZ")
+	     .help("Samples per second of the GPIO pins [default: 1000]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("journal")
+	     .long("journal")
+	     .help("Shows new systemd journal entries as events next to the input"))
+	.arg(clap::Arg::with_name("journal-unit")
+	     .long("journal-unit")
+	     .value_name("UNIT")
+	     .help("Only follows the journal of this unit, can be repeated")
+	     .takes_value(true)
+	     .multiple(true)
+	     .number_of_values(1))
+	.arg(clap::Arg::with_name("journal-match")
+	     .long("journal-match")
+	     .value_name("TEXT")
+	     .help("Only shows journal entries containing TEXT")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("metrics")
+	     .long("metrics")
+	     .help("Plots CPU, memory and network of this machine, needs the metrics feature"))
+	.arg(clap::Arg::with_name("metrics-interval")
+	     .long("metrics-interval")
+	     .value_name("MS")
+	     .help("Milliseconds between host metrics samples [default: 1000]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("bridge")
+	     .long("bridge")
+	     .help("Polls bridge_registers in --config from I2C/SPI buses, needs the bridge feature on Linux"))
+	.arg(clap::Arg::with_name("bridge-interval")
+	     .long("bridge-interval")
+	     .value_name("MS")
+	     .help("Milliseconds between polling the I2C/SPI registers [default: 100]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("modbus")
+	     .long("modbus")
+	     .value_name("PORT")
+	     .help("Polls modbus_registers in --config from Modbus RTU slaves on this serial port")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("modbus-interval")
+	     .long("modbus-interval")
+	     .value_name("MS")
+	     .help("Milliseconds between Modbus polling rounds [default: 100]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("ble")
+	     .long("ble")
+	     .value_name("DEVICE")
+	     .help("Receives the Nordic UART Service of the BLE peripheral with this address or name")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("unix")
+	     .long("unix")
+	     .value_name("PATH")
+	     .help("Listens for protocol lines on a unix domain socket")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("push")
+	     .long("push")
+	     .value_name("ADDRESS")
+	     .help("Accepts length-prefixed JSON messages from test rigs on a TCP address")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("osc")
+	     .long("osc")
+	     .value_name("ADDRESS")
+	     .help("Receives OSC packets on a UDP address, mapped by osc_signals in --config")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("fifo")
+	     .long("fifo")
+	     .value_name("PATH")
+	     .help("Reads protocol lines from a named pipe, surviving writer restarts")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("swo")
+	     .long("swo")
+	     .value_name("FILE")
+	     .help("Decodes ITM from a raw SWO capture file or FIFO, e.g. written by OpenOCD")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("itm-port")
+	     .long("itm-port")
+	     .value_name("PORT")
+	     .help("ITM stimulus port carrying protocol lines [default: 0]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("control")
+	     .long("control")
+	     .value_name("ADDRESS")
+	     .help("Accepts JSON-RPC requests on unix:PATH or a TCP address [default: unix socket in the runtime directory]")
+	     .takes_value(true)
+	     .min_values(0))
+	.arg(clap::Arg::with_name("session")
+	     .long("session")
+	     .value_name("FILE")
+	     .help("Restores panels, lock and markers from FILE and saves them on exit")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("demo")
+	     .long("demo")
+	     .help("Shows generated sine, sawtooth and noise signals, no device needed"))
+	.arg(clap::Arg::with_name("demo-rate")
+	     .long("demo-rate")
+	     .value_name("LINES")
+	     .help("Lines per second of --demo [default: 50]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("stdin")
+	     .long("stdin")
+	     .help("Reads protocol lines from standard input instead of the serial port"))
+	.arg(clap::Arg::with_name("replay")
+	     .long("replay")
+	     .value_name("FILE")
+	     .help("Replays a captured log at its original pace")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("record")
+	     .long("record")
+	     .value_name("DIRECTORY")
+	     .help("Streams all samples into CSV files below DIRECTORY")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("rotate")
+	     .long("rotate")
+	     .value_name("MINUTES")
+	     .help("Starts a new recording file every MINUTES [default: 10]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("proxy")
+	     .long("proxy")
+	     .value_name("TARGET")
+	     .help("Republishes the input at a reduced rate to tcp:ADDRESS, file:PATH or serial:PORT")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("proxy-rate")
+	     .long("proxy-rate")
+	     .value_name("LINES")
+	     .help("Data lines per second and object passed on by --proxy [default: 10]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("proxy-aggregate")
+	     .long("proxy-aggregate")
+	     .value_name("AGGREGATE")
+	     .help("How --proxy combines the rows in between [default: mean]")
+	     .possible_values(&["last", "mean", "min", "max"])
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("proxy-baud")
+	     .long("proxy-baud")
+	     .value_name("BAUD")
+	     .help("Baud rate of a serial --proxy port [default: --baud]")
+	     .takes_value(true))
+	.arg(clap::Arg::with_name("speed")
+	     .long("speed")
+	     .value_name("FACTOR")
+	     .help("Replay speed multiplier [default: 1]")
+	     .takes_value(true))
+	.subcommand(clap::SubCommand::with_name("export")
+		    .about("Replays a capture headlessly and writes the artifacts described in a spec")
+		    .arg(clap::Arg::with_name("input")
+			 .long("input")
+			 .value_name("FILE")
+			 .help("Captured protocol lines")
+			 .takes_value(true)
+			 .required(true))
+		    .arg(clap::Arg::with_name("spec")
+			 .long("spec")
+			 .value_name("FILE")
+			 .help("TOML file listing the artifacts to write")
+			 .takes_value(true)
+			 .required(true)))
+	.subcommand(clap::SubCommand::with_name("report")
+		    .about("Replays a capture headlessly and writes a self-contained HTML report")
+		    .arg(clap::Arg::with_name("input")
+			 .long("input")
+			 .value_name("FILE")
+			 .help("Captured protocol lines")
+			 .takes_value(true)
+			 .required(true))
+		    .arg(clap::Arg::with_name("output")
+			 .long("output")
+			 .value_name("FILE")
+			 .default_value("report.html"))
+		    .arg(clap::Arg::with_name("title")
+			 .long("title")
+			 .value_name("TEXT")
+			 .default_value("rusty-peanut report")))
+	.subcommand(clap::SubCommand::with_name("selftest")
+		    .about("Writes test blocks to a port with TX bridged to RX and verifies they come back")
+		    .arg(clap::Arg::with_name("port")
+			 .long("port")
+			 .value_name("PATH")
+			 .takes_value(true)
+			 .required(true))
+		    .arg(clap::Arg::with_name("baud")
+			 .long("baud")
+			 .value_name("RATE")
+			 .default_value("230400"))
+		    .arg(clap::Arg::with_name("blocks")
+			 .long("blocks")
+			 .value_name("COUNT")
+			 .default_value("100"))
+		    .arg(clap::Arg::with_name("size")
+			 .long("size")
+			 .value_name("BYTES")
+			 .help("Bytes per block")
+			 .default_value("256")))
+}
+
 fn main() {
     //env_logger::init();
+    let matches = cli().get_matches();
+    if let Some(matches) = matches.subcommand_matches("export") {
+	let input = matches.value_of("input").unwrap();
+	let spec = matches.value_of("spec").unwrap();
+	if let Err(error) = export::run(input, spec) {
+	    eprintln!("export failed: {}", error);
+	    std::process::exit(1);
+	}
+	return;
+    }
+    if let Some(matches) = matches.subcommand_matches("report") {
+	if let Err(error) = report::run(matches.value_of("input").unwrap(), matches.value_of("output").unwrap(), matches.value_of("title").unwrap()) {
+	    eprintln!("report failed: {}", error);
+	    std::process::exit(1);
+	}
+	return;
+    }
+    if let Some(matches) = matches.subcommand_matches("selftest") {
+	let number = |name: &str| {
+	    matches.value_of(name).unwrap().parse::<usize>().unwrap_or_else(|_| {
+		eprintln!("invalid {}", name);
+		std::process::exit(1);
+	    })
+	};
+	match loopback::run(matches.value_of("port").unwrap(), number("baud") as u32, number("blocks"), number("size")) {
+	    Ok(report) => {
+		println!("{}", report.summary());
+		if report.byte_errors > 0 || report.incomplete > 0 {
+		    std::process::exit(2);
+		}
+	    }
+	    Err(error) => {
+		eprintln!("selftest failed: {}", error);
+		std::process::exit(1);
+	    }
+	}
+	return;
+    }
+    match Config::from_matches(&matches) {
+	Err(error) => {
+	    eprintln!("{}", error);
+	    std::process::exit(1);
+	}
+	Ok(config) if config.mqtt.is_some() && !cfg!(feature = "mqtt") => {
+	    eprintln!("built without MQTT support, enable the mqtt feature");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.zmq.is_some() && !cfg!(feature = "zmq") => {
+	    eprintln!("built without ZeroMQ support, enable the zmq feature");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.lsl.is_some() && !cfg!(feature = "lsl") => {
+	    eprintln!("built without LSL support, enable the lsl feature");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.ble.is_some() && !cfg!(all(feature = "ble", target_os = "linux")) => {
+	    eprintln!("built without BLE support, enable the ble feature on Linux");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.can.is_some() && !cfg!(feature = "can") => {
+	    eprintln!("built without CAN support, enable the can feature");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.audio.is_some() && !cfg!(feature = "audio") => {
+	    eprintln!("built without audio support, enable the audio feature");
+	    std::process::exit(1);
+	}
+	Ok(config) if !config.gpio.is_empty() && !cfg!(all(feature = "gpio", target_os = "linux")) => {
+	    eprintln!("built without GPIO support, enable the gpio feature on Linux");
+	    std::process::exit(1);
+	}
+	Ok(config) if config.metrics && !cfg!(feature = "metrics") => {
+	    eprintln!("built without host metrics support, enable the metrics feature");
+	    std::process::exit(1);
+	}
+	_ => {}
+    }
+    shutdown::install_signal_handler();
     nannou::app(model)
-        .update(update)
-        .simple_window(view)
-        .run();
+	.update(update)
+	.exit(exit)
+	.run();
 }
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+use crate::dialect::Dialect;
+
+// MAVLink v1 and v2 frames from the serial port. Known messages are
+// turned into lines like
+//
+// MAVLINK ATTITUDE time_boot_ms=1000 roll=0.5 pitch=-0.25 ...
+//
+// which the mavlink dialect maps to scopes.
+
+const MAGIC_V1: u8 = 0xfe;
+const MAGIC_V2: u8 = 0xfd;
+// Set in the incompatibility flags of signed v2 frames
+const SIGNED: u8 = 0x01;
+const SIGNATURE_LENGTH: usize = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field
+{
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    F32,
+}
+
+impl Field
+{
+    fn size(&self) -> usize
+    {
+	match self {
+	    Field::U8 | Field::I8 => 1,
+	    Field::U16 | Field::I16 => 2,
+	    Field::U32 | Field::F32 => 4,
+	}
+    }
+
+    fn read(&self, bytes: &[u8]) -> f32
+    {
+	match self {
+	    Field::U8 => bytes[0] as f32,
+	    Field::I8 => bytes[0] as i8 as f32,
+	    Field::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+	    Field::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+	    Field::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32,
+	    Field::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+	}
+    }
+}
+
+struct Message
+{
+    id: u32,
+    name: &'static str,
+    // Seeds the checksum, derived from the message definition
+    crc_extra: u8,
+    // In wire order, which sorts them by size
+    fields: &'static [(&'static str, Field)],
+}
+
+const MESSAGES: &[Message] = &[
+    Message{ id: 1, name: "SYS_STATUS", crc_extra: 124, fields: &[
+	("onboard_control_sensors_present", Field::U32),
+	("onboard_control_sensors_enabled", Field::U32),
+	("onboard_control_sensors_health", Field::U32),
+	("load", Field::U16),
+	("voltage_battery", Field::U16),
+	("current_battery", Field::I16),
+	("drop_rate_comm", Field::U16),
+	("errors_comm", Field::U16),
+	("errors_count1", Field::U16),
+	("errors_count2", Field::U16),
+	("errors_count3", Field::U16),
+	("errors_count4", Field::U16),
+	("battery_remaining", Field::I8),
+    ] },
+    Message{ id: 30, name: "ATTITUDE", crc_extra: 39, fields: &[
+	("time_boot_ms", Field::U32),
+	("roll", Field::F32),
+	("pitch", Field::F32),
+	("yaw", Field::F32),
+	("rollspeed", Field::F32),
+	("pitchspeed", Field::F32),
+	("yawspeed", Field::F32),
+    ] },
+    Message{ id: 65, name: "RC_CHANNELS", crc_extra: 118, fields: &[
+	("time_boot_ms", Field::U32),
+	("chan1_raw", Field::U16), ("chan2_raw", Field::U16), ("chan3_raw", Field::U16),
+	("chan4_raw", Field::U16), ("chan5_raw", Field::U16), ("chan6_raw", Field::U16),
+	("chan7_raw", Field::U16), ("chan8_raw", Field::U16), ("chan9_raw", Field::U16),
+	("chan10_raw", Field::U16), ("chan11_raw", Field::U16), ("chan12_raw", Field::U16),
+	("chan13_raw", Field::U16), ("chan14_raw", Field::U16), ("chan15_raw", Field::U16),
+	("chan16_raw", Field::U16), ("chan17_raw", Field::U16), ("chan18_raw", Field::U16),
+	("chancount", Field::U8),
+	("rssi", Field::U8),
+    ] },
+];
+
+// CRC-16/MCRF4XX as MAVLink calls it X.25
+pub fn crc(bytes: &[u8], crc_extra: u8) -> u16
+{
+    bytes.iter().chain(Some(&crc_extra)).fold(0xffff, |crc: u16, byte| {
+	let mut tmp = byte ^ (crc & 0xff) as u8;
+	tmp ^= tmp << 4;
+	let tmp = tmp as u16;
+	(crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+    })
+}
+
+fn message_line(message: &Message, payload: &[u8]) -> String
+{
+    // MAVLink 2 strips trailing zeros from the payload
+    let mut payload = payload.to_vec();
+    let length: usize = message.fields.iter().map(|(_, field)| { field.size() }).sum();
+    payload.resize(length.max(payload.len()), 0);
+    let mut line = format!("MAVLINK {}", message.name);
+    let mut offset = 0;
+    for (name, field) in message.fields {
+	line.push_str(&format!(" {}={}", name, field.read(&payload[offset..])));
+	offset += field.size();
+    }
+    line
+}
+
+pub(crate) struct MavlinkProtocol
+{
+    bytes: Vec<u8>,
+    // Frames of known messages with a wrong checksum
+    pub(crate) invalid: u64,
+}
+
+impl MavlinkProtocol
+{
+    pub(crate) fn new() -> MavlinkProtocol
+    {
+	MavlinkProtocol{ bytes: vec![], invalid: 0 }
+    }
+
+    pub(crate) fn reset(&mut self)
+    {
+	self.bytes.clear();
+    }
+
+    pub(crate) fn feed<F>(&mut self, buffer: &[u8], mut func: F) where F: FnMut(&str)
+    {
+	self.bytes.extend_from_slice(buffer);
+	loop {
+	    // Anything up to the next start marker is noise
+	    match self.bytes.iter().position(|byte| { *byte == MAGIC_V1 || *byte == MAGIC_V2 }) {
+		Some(start) => { self.bytes.drain(..start); }
+		None => {
+		    self.bytes.clear();
+		    return;
+		}
+	    }
+	    if self.bytes.len() < 3 {
+		return;
+	    }
+	    let v1 = self.bytes[0] == MAGIC_V1;
+	    let header = if v1 { 6 } else { 10 };
+	    let signature = if !v1 && self.bytes[2] & SIGNED != 0 { SIGNATURE_LENGTH } else { 0 };
+	    let end = header + self.bytes[1] as usize;
+	    let total = end + 2 + signature;
+	    if self.bytes.len() < total {
+		return;
+	    }
+	    let id = if v1 {
+		self.bytes[5] as u32
+	    } else {
+		u32::from_le_bytes([self.bytes[7], self.bytes[8], self.bytes[9], 0])
+	    };
+	    match MESSAGES.iter().find(|message| { message.id == id }) {
+		Some(message) => {
+		    let expected = u16::from_le_bytes([self.bytes[end], self.bytes[end + 1]]);
+		    if crc(&self.bytes[1..end], message.crc_extra) == expected {
+			func(&message_line(message, &self.bytes[header..end]));
+			self.bytes.drain(..total);
+		    } else {
+			// Resynchronize right after the bogus start marker
+			self.invalid += 1;
+			self.bytes.drain(..1);
+		    }
+		}
+		// Without its CRC extra it can't be checked, only skipped
+		None => { self.bytes.drain(..total); }
+	    }
+	}
+    }
+}
+
+fn one() -> f32
+{
+    1.0
+}
+
+// A field of a MAVLink message mapped to a scope signal, e.g.
+//
+// [[mavlink_signals]]
+// message = "SYS_STATUS"
+// field = "voltage_battery"
+// scope = "Battery"
+// name = "volts"
+// scale = 0.001
+// min = 0.0
+// max = 25.2
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct MavlinkSignal
+{
+    pub message: String,
+    pub field: String,
+    pub scope: String,
+    // Defaults to the field
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "one")]
+    pub scale: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl MavlinkSignal
+{
+    fn new(message: &str, field: &str, scope: &str, min: f32, max: f32) -> MavlinkSignal
+    {
+	MavlinkSignal{ message: message.to_string(), field: field.to_string(), scope: scope.to_string(), name: None, scale: 1.0, min, max }
+    }
+}
+
+// Used when the configuration maps nothing
+fn default_signals() -> Vec<MavlinkSignal>
+{
+    let mut signals = vec![
+	MavlinkSignal::new("ATTITUDE", "roll", "Attitude", -3.2, 3.2),
+	MavlinkSignal::new("ATTITUDE", "pitch", "Attitude", -3.2, 3.2),
+	MavlinkSignal::new("ATTITUDE", "yaw", "Attitude", -3.2, 3.2),
+	MavlinkSignal{ name: Some("volts".to_string()), scale: 0.001, ..MavlinkSignal::new("SYS_STATUS", "voltage_battery", "Battery", 0.0, 25.2) },
+	MavlinkSignal{ name: Some("amps".to_string()), scale: 0.01, ..MavlinkSignal::new("SYS_STATUS", "current_battery", "Battery", 0.0, 100.0) },
+    ];
+    signals.extend((1..=4).map(|channel| { MavlinkSignal::new("RC_CHANNELS", &format!("chan{}_raw", channel), "RC", 1000.0, 2000.0) }));
+    signals
+}
+
+// Like CanMapping: a scope is fed whenever one of its signals
+// arrives, the others keep their last value.
+pub struct MavlinkMapping
+{
+    signals: Vec<MavlinkSignal>,
+    // Scopes in order of their first signal
+    scopes: Vec<String>,
+    latest: HashMap<String, Vec<f32>>,
+    declared: bool,
+}
+
+impl MavlinkMapping
+{
+    pub fn new(signals: &[MavlinkSignal]) -> MavlinkMapping
+    {
+	let signals = if signals.is_empty() { default_signals() } else { signals.to_vec() };
+	let mut scopes: Vec<String> = vec![];
+	for signal in &signals {
+	    if !scopes.contains(&signal.scope) {
+		scopes.push(signal.scope.clone());
+	    }
+	}
+	let latest = scopes.iter().map(|scope| {
+	    (scope.clone(), vec![0.0; signals.iter().filter(|signal| { &signal.scope == scope }).count()])
+	}).collect();
+	MavlinkMapping{ signals, scopes, latest, declared: false }
+    }
+
+    fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for scope in &self.scopes {
+	    lines.push(format!("`SCOPE {}", scope));
+	    for signal in self.signals.iter().filter(|signal| { &signal.scope == scope }) {
+		lines.push(format!("`{} '{}' {} {} 256 0", scope, signal.name.as_ref().unwrap_or(&signal.field), signal.min, signal.max));
+	    }
+	}
+	lines
+    }
+}
+
+impl Dialect for MavlinkMapping
+{
+    fn translate(&mut self, line: &str) -> Vec<String>
+    {
+	let mut lines = vec![];
+	if !self.declared {
+	    self.declared = true;
+	    lines.extend(self.declarations());
+	}
+	let mut tokens = match line.strip_prefix("MAVLINK ") {
+	    Some(rest) => rest.split_whitespace(),
+	    None => {
+		lines.push(line.to_string());
+		return lines;
+	    }
+	};
+	let message = tokens.next().unwrap_or("");
+	let fields: HashMap<&str, f32> = tokens
+	    .filter_map(|token| {
+		let mut parts = token.splitn(2, '=');
+		Some((parts.next()?, parts.next()?.parse::<f32>().ok()?))
+	    })
+	    .collect();
+	let mut touched: Vec<String> = vec![];
+	for scope in &self.scopes {
+	    let values = self.latest.get_mut(scope).unwrap();
+	    let signals = self.signals.iter().filter(|signal| { &signal.scope == scope });
+	    for (index, signal) in signals.enumerate() {
+		if signal.message != message {
+		    continue;
+		}
+		if let Some(value) = fields.get(signal.field.as_str()) {
+		    values[index] = value * signal.scale;
+		    if !touched.contains(scope) {
+			touched.push(scope.clone());
+		    }
+		}
+	    }
+	}
+	lines.extend(touched.iter().map(|scope| {
+	    let values: Vec<String> = self.latest[scope].iter().map(|value| { value.to_string() }).collect();
+	    format!("`{} {}", scope, values.join(" "))
+	}));
+	lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    // A v1 frame as an autopilot sends it
+    fn frame(sequence: u8, id: u8, crc_extra: u8, payload: &[u8]) -> Vec<u8>
+    {
+	let mut frame = vec![MAGIC_V1, payload.len() as u8, sequence, 1, 1, id];
+	frame.extend_from_slice(payload);
+	let crc = crc(&frame[1..], crc_extra);
+	frame.extend_from_slice(&crc.to_le_bytes());
+	frame
+    }
+
+    #[test]
+    fn attitude_frames_to_scopes() {
+	let mut payload = 1000u32.to_le_bytes().to_vec();
+	for value in &[0.5f32, -0.25, 1.0, 0.0, 0.0, 0.0] {
+	    payload.extend_from_slice(&value.to_le_bytes());
+	}
+	let mut wire = vec![0x42, 0x00];
+	wire.extend(frame(0, 30, 39, &payload));
+	let mut corrupted = frame(1, 30, 39, &payload);
+	corrupted[10] ^= 0xff;
+	wire.extend(corrupted);
+	let mut lp = MavlinkProtocol::new();
+	let mut lines = vec![];
+	let (first, second) = wire.split_at(20);
+	lp.feed(first, |line: &str| { lines.push(line.to_string()) });
+	lp.feed(second, |line: &str| { lines.push(line.to_string()) });
+	assert_eq!(lines, vec!["MAVLINK ATTITUDE time_boot_ms=1000 roll=0.5 pitch=-0.25 yaw=1 rollspeed=0 pitchspeed=0 yawspeed=0"]);
+	assert_eq!(lp.invalid, 1);
+	let mut mapping = MavlinkMapping::new(&[]);
+	let lines = mapping.translate(&lines[0]);
+	assert_eq!(lines[0], "`SCOPE Attitude");
+	assert_eq!(lines[4], "`SCOPE Battery");
+	assert_eq!(lines.last().unwrap(), "`Attitude 0.5 -0.25 1");
+	assert_eq!(mapping.translate("MAVLINK SYS_STATUS voltage_battery=12600 current_battery=150"), vec!["`Battery 12.6 1.5"]);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::dialect::AutoScope;
+
+// One reading of the host, rates over the polling interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostSample
+{
+    // Percent
+    pub cpu: f32,
+    pub memory: f32,
+    // kB/s summed over all interfaces
+    pub received: f32,
+    pub transmitted: f32,
+}
+
+// Plots the host next to the device, CPU and memory on a fixed
+// scale, the network on a growing one
+pub struct HostMetrics
+{
+    declared: bool,
+    network: AutoScope,
+}
+
+impl HostMetrics
+{
+    pub fn new() -> HostMetrics
+    {
+	let mut network = AutoScope::new("Network", "POS 210 0 SIZE 400 200 SAMPLES 300".to_string(), 200.0);
+	network.rename(vec!["received".to_string(), "transmitted".to_string()]);
+	HostMetrics{ declared: false, network }
+    }
+
+    pub fn lines(&mut self, sample: &HostSample) -> Vec<String>
+    {
+	let mut lines = vec![];
+	if !self.declared {
+	    self.declared = true;
+	    lines.push("`SCOPE Host POS -210 0 SIZE 400 200 SAMPLES 300".to_string());
+	    lines.push("`Host 'cpu' 0 100 200 0 YELLOW".to_string());
+	    lines.push("`Host 'memory' 0 100 200 0 CYAN".to_string());
+	}
+	lines.push(format!("`Host {} {}", sample.cpu, sample.memory));
+	lines.extend(self.network.feed("", &[sample.received, sample.transmitted]));
+	lines
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use connector::MetricsConnector;
+
+#[cfg(feature = "metrics")]
+mod connector {
+    use std::thread;
+    use std::time::Duration;
+    use crossbeam::channel::{Receiver, unbounded};
+    use sysinfo::{NetworkExt, ProcessorExt, System, SystemExt};
+    use crate::serial::Received;
+    use crate::shutdown;
+    use super::{HostMetrics, HostSample};
+
+    // Samples CPU, memory and network of this machine
+    pub struct MetricsConnector
+    {
+	pub receiver: Receiver<Received>,
+    }
+
+    fn sample(system: &mut System, interval: Duration) -> HostSample
+    {
+	system.refresh_cpu();
+	system.refresh_memory();
+	system.refresh_networks();
+	let (received, transmitted) = system.get_networks().iter().fold((0, 0), |(received, transmitted), (_, data)| {
+	    (received + data.get_received(), transmitted + data.get_transmitted())
+	});
+	let seconds = interval.as_secs_f32();
+	HostSample{
+	    cpu: system.get_global_processor_info().get_cpu_usage(),
+	    memory: 100.0 * system.get_used_memory() as f32 / system.get_total_memory().max(1) as f32,
+	    received: received as f32 / 1000.0 / seconds,
+	    transmitted: transmitted as f32 / 1000.0 / seconds,
+	}
+    }
+
+    impl MetricsConnector
+    {
+	pub fn new(interval: Duration) -> MetricsConnector
+	{
+	    let (s, r) = unbounded();
+	    thread::spawn(move || {
+		let mut system = System::new();
+		system.refresh_networks_list();
+		// The first CPU reading needs a previous one
+		system.refresh_cpu();
+		let mut metrics = HostMetrics::new();
+		while !shutdown::requested() {
+		    thread::sleep(interval);
+		    for line in metrics.lines(&sample(&mut system, interval)) {
+			if s.send(Received::now(line)).is_err() {
+			    return;
+			}
+		    }
+		}
+	    });
+	    MetricsConnector{ receiver: r }
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn declares_once() {
+	let mut metrics = HostMetrics::new();
+	let lines = metrics.lines(&HostSample{ cpu: 12.5, memory: 40.0, received: 10.0, transmitted: 2.0 });
+	assert_eq!(lines[0], "`SCOPE Host POS -210 0 SIZE 400 200 SAMPLES 300");
+	assert_eq!(lines[3], "`Host 12.5 40");
+	assert_eq!(lines[4], "`SCOPE Network POS 210 0 SIZE 400 200 SAMPLES 300");
+	assert_eq!(lines.last().unwrap(), "`Network 10 2");
+	let lines = metrics.lines(&HostSample{ cpu: 15.0, memory: 41.0, received: 8.0, transmitted: 3.0 });
+	assert_eq!(lines, vec!["`Host 15 41", "`Network 8 3"]);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use crossbeam::channel::{Receiver, unbounded};
+use log::warn;
+use serde::Deserialize;
+use serialport::SerialPort;
+
+use crate::serial::{ConnectionState, LineSettings, Received, backoff, open_port};
+use crate::shutdown;
+
+// How long a slave gets to answer a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(200);
+
+fn holding() -> u8
+{
+    3
+}
+
+fn one_word() -> usize
+{
+    1
+}
+
+fn one() -> f32
+{
+    1.0
+}
+
+// A register polled from a Modbus RTU slave, e.g.
+//
+// [[modbus_registers]]
+// slave = 1
+// address = 0x2103
+// scope = "VFD"
+// name = "frequency"
+// scale = 0.01
+// min = 0.0
+// max = 60.0
+//
+// Values of two words are sent high word first.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ModbusRegister
+{
+    pub slave: u8,
+    pub address: u16,
+    // 3 reads holding registers, 4 input registers
+    #[serde(default = "holding")]
+    pub function: u8,
+    #[serde(default = "one_word")]
+    pub words: usize,
+    #[serde(default)]
+    pub signed: bool,
+    pub scope: String,
+    pub name: String,
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ModbusRegister
+{
+    fn value(&self, words: &[u16]) -> f32
+    {
+	let raw = words.iter().fold(0u64, |raw, word| { raw << 16 | *word as u64 });
+	let bits = 16 * words.len() as u32;
+	let value = if self.signed && bits < 64 && raw >> (bits - 1) & 1 == 1 {
+	    (raw as i64 - (1i64 << bits)) as f64
+	} else if self.signed {
+	    raw as i64 as f64
+	} else {
+	    raw as f64
+	};
+	value as f32 * self.scale + self.offset
+    }
+}
+
+// CRC-16/MODBUS, sent low byte first
+pub fn crc(bytes: &[u8]) -> u16
+{
+    bytes.iter().fold(0xffff, |crc: u16, byte| {
+	(0..8).fold(crc ^ *byte as u16, |crc, _| {
+	    if crc & 1 == 1 { crc >> 1 ^ 0xa001 } else { crc >> 1 }
+	})
+    })
+}
+
+pub fn request(slave: u8, function: u8, address: u16, count: u16) -> Vec<u8>
+{
+    let mut frame = vec![slave, function];
+    frame.extend_from_slice(&address.to_be_bytes());
+    frame.extend_from_slice(&count.to_be_bytes());
+    frame.extend_from_slice(&crc(&frame).to_le_bytes());
+    frame
+}
+
+// Bytes of a complete response, as far as they tell
+fn response_length(bytes: &[u8]) -> Option<usize>
+{
+    match bytes.get(1)? {
+	function if function & 0x80 != 0 => Some(5),
+	_ => Some(5 + *bytes.get(2)? as usize),
+    }
+}
+
+// The register words of a read response
+pub fn parse_response(slave: u8, function: u8, count: usize, bytes: &[u8]) -> Result<Vec<u16>, String>
+{
+    let length = response_length(bytes).ok_or("response too short")?;
+    if bytes.len() < length {
+	return Err("response too short".to_string());
+    }
+    let (frame, checksum) = bytes[..length].split_at(length - 2);
+    if crc(frame) != u16::from_le_bytes([checksum[0], checksum[1]]) {
+	return Err("bad CRC".to_string());
+    }
+    if frame[0] != slave {
+	return Err(format!("response from slave {}", frame[0]));
+    }
+    if frame[1] == function | 0x80 {
+	return Err(format!("exception {}", frame[2]));
+    }
+    if frame[1] != function || frame[2] as usize != count * 2 {
+	return Err("unexpected response".to_string());
+    }
+    Ok(frame[3..].chunks(2).map(|word| { u16::from_be_bytes([word[0], word[1]]) }).collect())
+}
+
+// Like CanMapping, with one line per scope and polling round
+pub struct ModbusMapping
+{
+    registers: Vec<ModbusRegister>,
+    // Scopes in order of their first register
+    scopes: Vec<String>,
+    latest: HashMap<String, Vec<f32>>,
+}
+
+impl ModbusMapping
+{
+    pub fn new(registers: &[ModbusRegister]) -> ModbusMapping
+    {
+	let mut scopes: Vec<String> = vec![];
+	for register in registers {
+	    if !scopes.contains(&register.scope) {
+		scopes.push(register.scope.clone());
+	    }
+	}
+	let latest = scopes.iter().map(|scope| {
+	    (scope.clone(), vec![0.0; registers.iter().filter(|register| { &register.scope == scope }).count()])
+	}).collect();
+	ModbusMapping{ registers: registers.to_vec(), scopes, latest }
+    }
+
+    // Declares the scopes, all signals share the full height
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for scope in &self.scopes {
+	    lines.push(format!("`SCOPE {}", scope));
+	    for register in self.registers.iter().filter(|register| { &register.scope == scope }) {
+		lines.push(format!("`{} '{}' {} {} 256 0", scope, register.name, register.min, register.max));
+	    }
+	}
+	lines
+    }
+
+    // Registers that didn't answer keep their last value
+    pub fn update(&mut self, index: usize, words: &[u16])
+    {
+	let register = &self.registers[index];
+	let position = self.registers[..index].iter().filter(|other| { other.scope == register.scope }).count();
+	let value = register.value(words);
+	self.latest.get_mut(&register.scope).unwrap()[position] = value;
+    }
+
+    pub fn lines(&self) -> Vec<String>
+    {
+	self.scopes.iter().map(|scope| {
+	    let values: Vec<String> = self.latest[scope].iter().map(|value| { value.to_string() }).collect();
+	    format!("`{} {}", scope, values.join(" "))
+	}).collect()
+    }
+}
+
+// Polls the registers of Modbus RTU slaves on a serial port
+pub struct ModbusConnector
+{
+    pub receiver: Receiver<Received>,
+    pub status: Receiver<ConnectionState>,
+}
+
+fn open(path: &str, baud: u32, line: LineSettings) -> Result<Box<dyn SerialPort>, serialport::Error>
+{
+    open_port(path, baud, line, RESPONSE_TIMEOUT)
+}
+
+// Sends a request and collects its response, an error only if the port failed
+fn transact(port: &mut Box<dyn SerialPort>, register: &ModbusRegister) -> std::io::Result<Result<Vec<u16>, String>>
+{
+    port.clear(serialport::ClearBuffer::Input).ok();
+    port.write_all(&request(register.slave, register.function, register.address, register.words as u16))?;
+    let mut bytes = vec![];
+    let deadline = Instant::now() + RESPONSE_TIMEOUT;
+    while response_length(&bytes).map(|length| { bytes.len() < length }).unwrap_or(true) && Instant::now() < deadline {
+	let mut buffer = [0; 256];
+	match port.read(&mut buffer) {
+	    Ok(count) => { bytes.extend_from_slice(&buffer[..count]); }
+	    Err(error) if error.kind() == std::io::ErrorKind::TimedOut => { break; }
+	    Err(error) => { return Err(error); }
+	}
+    }
+    Ok(parse_response(register.slave, register.function, register.words, &bytes))
+}
+
+impl ModbusConnector
+{
+    pub fn new(path: &str, baud: u32, line: LineSettings, interval: Duration, registers: &[ModbusRegister]) -> Result<ModbusConnector, serialport::Error>
+    {
+	let mut port = open(path, baud, line)?;
+	let path = path.to_string();
+	let mut mapping = ModbusMapping::new(registers);
+	let (s, r) = unbounded();
+	let (status_s, status_r) = unbounded();
+	for line in mapping.declarations() {
+	    s.send(Received::now(line)).ok();
+	}
+	thread::spawn(move || {
+	    while !shutdown::requested() {
+		let start = Instant::now();
+		let mut failed = None;
+		for index in 0..mapping.registers.len() {
+		    match transact(&mut port, &mapping.registers[index]) {
+			Ok(Ok(words)) => { mapping.update(index, &words); }
+			Ok(Err(error)) => {
+			    let register = &mapping.registers[index];
+			    warn!("Modbus slave {} register {}: {}", register.slave, register.address, error);
+			}
+			Err(error) => {
+			    failed = Some(error);
+			    break;
+			}
+		    }
+		}
+		if let Some(error) = failed {
+		    warn!("Modbus error: {:?}, reconnecting", error);
+		    status_s.send(ConnectionState::Reconnecting).ok();
+		    let mut attempt = 0;
+		    port = loop {
+			thread::sleep(backoff(attempt));
+			if shutdown::requested() {
+			    return;
+			}
+			match open(&path, baud, line) {
+			    Ok(port) => { break port; }
+			    Err(_) => { attempt += 1; }
+			}
+		    };
+		    status_s.send(ConnectionState::Connected).ok();
+		    continue;
+		}
+		for line in mapping.lines() {
+		    if s.send(Received::now(line)).is_err() {
+			return;
+		    }
+		}
+		thread::sleep(interval.checked_sub(start.elapsed()).unwrap_or_default());
+	    }
+	});
+	Ok(ModbusConnector{ receiver: r, status: status_r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    fn register(name: &str, words: usize, signed: bool) -> ModbusRegister
+    {
+	ModbusRegister{ slave: 1, address: 0, function: 3, words, signed, scope: "VFD".to_string(), name: name.to_string(), scale: 1.0, offset: 0.0, min: 0.0, max: 100.0 }
+    }
+
+    #[test]
+    fn requests_and_responses() {
+	assert_eq!(request(1, 3, 0, 10), vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x0a, 0xc5, 0xcd]);
+	let mut response = vec![0x01, 0x03, 0x04, 0x00, 0x2a, 0xff, 0xfe];
+	response.extend_from_slice(&crc(&response).to_le_bytes());
+	assert_eq!(parse_response(1, 3, 2, &response), Ok(vec![42, 0xfffe]));
+	assert_eq!(parse_response(2, 3, 2, &response), Err("response from slave 1".to_string()));
+	response[3] = 1;
+	assert_eq!(parse_response(1, 3, 2, &response), Err("bad CRC".to_string()));
+	let mut exception = vec![0x01, 0x83, 0x02];
+	exception.extend_from_slice(&crc(&exception).to_le_bytes());
+	assert_eq!(parse_response(1, 3, 1, &exception), Err("exception 2".to_string()));
+	let mut mapping = ModbusMapping::new(&[register("speed", 1, false), register("torque", 1, true), register("count", 2, false)]);
+	assert_eq!(mapping.declarations()[0], "`SCOPE VFD");
+	mapping.update(1, &[0xfffe]);
+	mapping.update(2, &[1, 2]);
+	assert_eq!(mapping.lines(), vec!["`VFD 0 -2 65538"]);
+    }
+}
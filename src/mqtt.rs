@@ -0,0 +1,85 @@
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+use log::{info, warn};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use crate::serial::Received;
+
+// Subscribes to topics on a broker, every payload carries one
+// or more protocol lines.
+pub struct MqttConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+// mqtt://host:port, scheme and port are optional
+fn parse_broker(url: &str) -> Result<(String, u16), String>
+{
+    let address = url.strip_prefix("mqtt://").unwrap_or(url);
+    match address.rfind(':') {
+	Some(position) => {
+	    let port = address[position + 1..].parse::<u16>().map_err(|_| { format!("invalid broker port in {}", url) })?;
+	    Ok((address[..position].to_string(), port))
+	}
+	None => Ok((address.to_string(), 1883)),
+    }
+}
+
+fn payload_lines(payload: &[u8]) -> Vec<String>
+{
+    String::from_utf8_lossy(payload).lines()
+	.map(|line| { line.trim_end_matches('\r') })
+	.filter(|line| { !line.is_empty() })
+	.map(|line| { line.to_string() })
+	.collect()
+}
+
+impl MqttConnector
+{
+    pub fn new(broker: &str, topics: &[String]) -> Result<MqttConnector, String>
+    {
+	let (host, port) = parse_broker(broker)?;
+	let options = MqttOptions::new(format!("rusty-peanut-{}", std::process::id()), host, port);
+	let (mut client, mut connection) = Client::new(options, 16);
+	for topic in topics {
+	    client.subscribe(topic.as_str(), QoS::AtMostOnce).map_err(|error| { error.to_string() })?;
+	}
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    // The client has to stay alive for the subscriptions
+	    let _client = client;
+	    for notification in connection.iter() {
+		match notification {
+		    Ok(Event::Incoming(Packet::Publish(publish))) => {
+			for line in payload_lines(&publish.payload) {
+			    if s.send(Received::now(line)).is_err() {
+				return;
+			    }
+			}
+		    }
+		    Ok(Event::Incoming(Packet::ConnAck(_))) => { info!("connected to the MQTT broker"); }
+		    Ok(_) => {}
+		    // The event loop reconnects on the next iteration
+		    Err(error) => {
+			warn!("MQTT error: {:?}", error);
+			thread::sleep(std::time::Duration::from_secs(1));
+		    }
+		}
+	    }
+	});
+	Ok(MqttConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn brokers_and_payloads() {
+	assert_eq!(parse_broker("mqtt://broker.local:1884"), Ok(("broker.local".to_string(), 1884)));
+	assert_eq!(parse_broker("broker.local"), Ok(("broker.local".to_string(), 1883)));
+	assert!(parse_broker("broker:x").is_err());
+	assert_eq!(payload_lines(b"`MyScope 1\r\n`MyScope 2\n\n"), vec!["`MyScope 1", "`MyScope 2"]);
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::{json, Map, Value};
+
+fn take(rest: &[u8], count: usize) -> Option<(&[u8], &[u8])>
+{
+    if rest.len() < count { None } else { Some(rest.split_at(count)) }
+}
+
+// Big endian, as all of MessagePack
+fn uint(rest: &[u8], count: usize) -> Option<(u64, &[u8])>
+{
+    let (bytes, rest) = take(rest, count)?;
+    Some((bytes.iter().fold(0u64, |value, byte| { value << 8 | *byte as u64 }), rest))
+}
+
+fn string(rest: &[u8], length: usize) -> Option<(Value, &[u8])>
+{
+    let (bytes, rest) = take(rest, length)?;
+    Some((Value::String(std::str::from_utf8(bytes).ok()?.to_string()), rest))
+}
+
+// A MessagePack value and the bytes after it. Extension types aren't
+// supported, binary data becomes an array of bytes.
+fn value(bytes: &[u8]) -> Option<(Value, &[u8])>
+{
+    let (marker, rest) = bytes.split_first()?;
+    match *marker {
+	0x00..=0x7f => Some((json!(*marker), rest)),
+	0xe0..=0xff => Some((json!(*marker as i8), rest)),
+	0xc0 => Some((Value::Null, rest)),
+	0xc2 => Some((json!(false), rest)),
+	0xc3 => Some((json!(true), rest)),
+	0xcc..=0xcf => {
+	    let (value, rest) = uint(rest, 1 << (marker - 0xcc))?;
+	    Some((json!(value), rest))
+	}
+	0xd0..=0xd3 => {
+	    let size = 1 << (marker - 0xd0);
+	    let (value, rest) = uint(rest, size)?;
+	    // Sign extend from the size of the integer
+	    let shift = 64 - 8 * size as u32;
+	    Some((json!((value << shift) as i64 >> shift), rest))
+	}
+	0xca => {
+	    let (value, rest) = uint(rest, 4)?;
+	    Some((json!(f32::from_bits(value as u32)), rest))
+	}
+	0xcb => {
+	    let (value, rest) = uint(rest, 8)?;
+	    Some((json!(f64::from_bits(value)), rest))
+	}
+	0xa0..=0xbf => string(rest, (marker & 0x1f) as usize),
+	0xd9..=0xdb => {
+	    let (length, rest) = uint(rest, 1 << (marker - 0xd9))?;
+	    string(rest, length as usize)
+	}
+	0xc4..=0xc6 => {
+	    let (length, rest) = uint(rest, 1 << (marker - 0xc4))?;
+	    let (bytes, rest) = take(rest, length as usize)?;
+	    Some((json!(bytes), rest))
+	}
+	0x90..=0x9f => array(rest, (marker & 0x0f) as usize),
+	0xdc | 0xdd => {
+	    let (length, rest) = uint(rest, if *marker == 0xdc { 2 } else { 4 })?;
+	    array(rest, length as usize)
+	}
+	0x80..=0x8f => map(rest, (marker & 0x0f) as usize),
+	0xde | 0xdf => {
+	    let (length, rest) = uint(rest, if *marker == 0xde { 2 } else { 4 })?;
+	    map(rest, length as usize)
+	}
+	_ => None,
+    }
+}
+
+fn array(mut rest: &[u8], length: usize) -> Option<(Value, &[u8])>
+{
+    let mut values = vec![];
+    for _ in 0..length {
+	let (element, remaining) = value(rest)?;
+	values.push(element);
+	rest = remaining;
+    }
+    Some((Value::Array(values), rest))
+}
+
+// Keys have to be strings, as in JSON
+fn map(mut rest: &[u8], length: usize) -> Option<(Value, &[u8])>
+{
+    let mut entries = Map::new();
+    for _ in 0..length {
+	let (key, remaining) = value(rest)?;
+	let (element, remaining) = value(remaining)?;
+	entries.insert(key.as_str()?.to_string(), element);
+	rest = remaining;
+    }
+    Some((Value::Object(entries), rest))
+}
+
+// None unless the bytes hold exactly one value
+pub fn decode(bytes: &[u8]) -> Option<Value>
+{
+    match value(bytes)? {
+	(value, rest) if rest.is_empty() => Some(value),
+	_ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn decodes_values() {
+	// ["MyScope", 1, -2, 2.5, {"a": nil}]
+	let bytes = [0x95, 0xa7, b'M', b'y', b'S', b'c', b'o', b'p', b'e', 0x01, 0xfe, 0xcb, 0x40, 0x04, 0, 0, 0, 0, 0, 0, 0x81, 0xa1, b'a', 0xc0];
+	assert_eq!(decode(&bytes), Some(json!(["MyScope", 1, -2, 2.5, {"a": null}])));
+	assert_eq!(decode(&[0xd1, 0xff, 0x38]), Some(json!(-200)));
+	assert_eq!(decode(&[0xcd, 0x01, 0x00]), Some(json!(256)));
+	assert_eq!(decode(&[0x92, 0x01]), None);
+	assert_eq!(decode(&[0x01, 0x02]), None);
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::UdpSocket;
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+use log::warn;
+use serde::Deserialize;
+
+use crate::serial::Received;
+
+fn one() -> f32
+{
+    1.0
+}
+
+// Maps an argument of OSC messages to a scope signal, e.g.
+//
+// [[osc_signals]]
+// address = "/synth/*/cutoff"
+// scope = "Synth"
+// name = "cutoff"
+// min = 0.0
+// max = 20000.0
+//
+// In addresses * matches any characters but / and ? a single one.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OscSignal
+{
+    pub address: String,
+    // Index among the message arguments
+    #[serde(default)]
+    pub argument: usize,
+    pub scope: String,
+    pub name: String,
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage
+{
+    pub address: String,
+    // None for arguments without a numeric value, like strings
+    pub arguments: Vec<Option<f32>>,
+}
+
+pub fn matches(pattern: &str, address: &str) -> bool
+{
+    match (pattern.chars().next(), address.chars().next()) {
+	(None, None) => true,
+	(Some('*'), _) => {
+	    matches(&pattern[1..], address)
+		|| address.chars().next().map(|c| { c != '/' && matches(pattern, &address[c.len_utf8()..]) }).unwrap_or(false)
+	}
+	(Some('?'), Some(c)) if c != '/' => matches(&pattern[1..], &address[c.len_utf8()..]),
+	(Some(p), Some(c)) if p == c => matches(&pattern[p.len_utf8()..], &address[c.len_utf8()..]),
+	_ => false,
+    }
+}
+
+// Strings are NUL terminated and padded to four bytes
+fn read_string(bytes: &[u8], position: &mut usize) -> Result<String, String>
+{
+    let rest = bytes.get(*position..).ok_or("truncated string")?;
+    let end = rest.iter().position(|byte| { *byte == 0 }).ok_or("unterminated string")?;
+    let text = String::from_utf8_lossy(&rest[..end]).to_string();
+    *position += (end + 4) / 4 * 4;
+    Ok(text)
+}
+
+fn read_bytes<'a>(bytes: &'a [u8], position: &mut usize, count: usize) -> Result<&'a [u8], String>
+{
+    let slice = bytes.get(*position..*position + count).ok_or("truncated argument")?;
+    *position += count;
+    Ok(slice)
+}
+
+fn read_message(bytes: &[u8]) -> Result<OscMessage, String>
+{
+    let mut position = 0;
+    let address = read_string(bytes, &mut position)?;
+    // Old implementations leave out the type tags
+    if position >= bytes.len() {
+	return Ok(OscMessage{ address, arguments: vec![] });
+    }
+    let tags = read_string(bytes, &mut position)?;
+    let mut arguments = vec![];
+    for tag in tags.chars().skip_while(|c| { *c == ',' }) {
+	let value = match tag {
+	    'i' => Some(i32::from_be_bytes(read_bytes(bytes, &mut position, 4)?.try_into().unwrap()) as f32),
+	    'f' => Some(f32::from_be_bytes(read_bytes(bytes, &mut position, 4)?.try_into().unwrap())),
+	    'h' => Some(i64::from_be_bytes(read_bytes(bytes, &mut position, 8)?.try_into().unwrap()) as f32),
+	    'd' => Some(f64::from_be_bytes(read_bytes(bytes, &mut position, 8)?.try_into().unwrap()) as f32),
+	    'T' => Some(1.0),
+	    'F' => Some(0.0),
+	    'N' | 'I' => None,
+	    's' | 'S' => {
+		read_string(bytes, &mut position)?;
+		None
+	    }
+	    'b' => {
+		let length = i32::from_be_bytes(read_bytes(bytes, &mut position, 4)?.try_into().unwrap()).max(0) as usize;
+		read_bytes(bytes, &mut position, (length + 3) / 4 * 4)?;
+		None
+	    }
+	    't' | 'c' | 'r' | 'm' => {
+		read_bytes(bytes, &mut position, if tag == 't' { 8 } else { 4 })?;
+		None
+	    }
+	    _ => { return Err(format!("unknown type tag {}", tag)); }
+	};
+	arguments.push(value);
+    }
+    Ok(OscMessage{ address, arguments })
+}
+
+// The messages of a packet, bundles are flattened. Time tags
+// are ignored, everything is shown when it arrives.
+pub fn decode(bytes: &[u8]) -> Result<Vec<OscMessage>, String>
+{
+    if !bytes.starts_with(b"#bundle\0") {
+	return Ok(vec![read_message(bytes)?]);
+    }
+    let mut messages = vec![];
+    let mut position = 16;
+    while position < bytes.len() {
+	let size = i32::from_be_bytes(read_bytes(bytes, &mut position, 4)?.try_into().unwrap()).max(0) as usize;
+	messages.extend(decode(read_bytes(bytes, &mut position, size)?)?);
+    }
+    Ok(messages)
+}
+
+// Like ModbusMapping, a scope gets a line whenever one of its
+// signals changed, the others repeat their last value
+pub struct OscMapping
+{
+    signals: Vec<OscSignal>,
+    // Scopes in order of their first signal
+    scopes: Vec<String>,
+    latest: HashMap<String, Vec<f32>>,
+}
+
+impl OscMapping
+{
+    pub fn new(signals: &[OscSignal]) -> OscMapping
+    {
+	let mut scopes: Vec<String> = vec![];
+	for signal in signals {
+	    if !scopes.contains(&signal.scope) {
+		scopes.push(signal.scope.clone());
+	    }
+	}
+	let latest = scopes.iter().map(|scope| {
+	    (scope.clone(), vec![0.0; signals.iter().filter(|signal| { &signal.scope == scope }).count()])
+	}).collect();
+	OscMapping{ signals: signals.to_vec(), scopes, latest }
+    }
+
+    pub fn declarations(&self) -> Vec<String>
+    {
+	let mut lines = vec![];
+	for scope in &self.scopes {
+	    lines.push(format!("`SCOPE {}", scope));
+	    for signal in self.signals.iter().filter(|signal| { &signal.scope == scope }) {
+		lines.push(format!("`{} '{}' {} {} 256 0", scope, signal.name, signal.min, signal.max));
+	    }
+	}
+	lines
+    }
+
+    // Lines of the scopes the messages changed
+    pub fn update(&mut self, messages: &[OscMessage]) -> Vec<String>
+    {
+	let mut changed: Vec<String> = vec![];
+	for message in messages {
+	    for (index, signal) in self.signals.iter().enumerate() {
+		let value = match message.arguments.get(signal.argument) {
+		    Some(Some(value)) if matches(&signal.address, &message.address) => *value,
+		    _ => { continue; }
+		};
+		let position = self.signals[..index].iter().filter(|other| { other.scope == signal.scope }).count();
+		self.latest.get_mut(&signal.scope).unwrap()[position] = value * signal.scale + signal.offset;
+		if !changed.contains(&signal.scope) {
+		    changed.push(signal.scope.clone());
+		}
+	    }
+	}
+	changed.iter().map(|scope| {
+	    let values: Vec<String> = self.latest[scope].iter().map(|value| { value.to_string() }).collect();
+	    format!("`{} {}", scope, values.join(" "))
+	}).collect()
+    }
+}
+
+// Receives OSC packets on a UDP address like 0.0.0.0:9000
+pub struct OscConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+impl OscConnector
+{
+    pub fn new(address: &str, signals: &[OscSignal]) -> std::io::Result<OscConnector>
+    {
+	let socket = UdpSocket::bind(address)?;
+	let mut mapping = OscMapping::new(signals);
+	let (s, r) = unbounded();
+	for line in mapping.declarations() {
+	    s.send(Received::now(line)).ok();
+	}
+	thread::spawn(move || {
+	    let mut buffer = vec![0; 65536];
+	    loop {
+		let size = match socket.recv(&mut buffer) {
+		    Ok(size) => size,
+		    Err(error) => {
+			warn!("OSC receive failed: {:?}", error);
+			continue;
+		    }
+		};
+		match decode(&buffer[..size]) {
+		    Ok(messages) => {
+			for line in mapping.update(&messages) {
+			    if s.send(Received::now(line)).is_err() {
+				return;
+			    }
+			}
+		    }
+		    Err(error) => { warn!("invalid OSC packet: {}", error); }
+		}
+	    }
+	});
+	Ok(OscConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    fn padded(text: &str) -> Vec<u8>
+    {
+	let mut bytes = text.as_bytes().to_vec();
+	bytes.resize((text.len() + 4) / 4 * 4, 0);
+	bytes
+    }
+
+    #[test]
+    fn bundles_map_to_scope_lines() {
+	assert!(matches("/synth/*/cutoff", "/synth/voice1/cutoff"));
+	assert!(!matches("/synth/*/cutoff", "/synth/a/b/cutoff"));
+	assert!(matches("/gain?", "/gain2"));
+	let mut message = padded("/synth/voice1/cutoff");
+	message.extend(padded(",fs"));
+	message.extend(&440.0f32.to_be_bytes());
+	message.extend(padded("hz"));
+	let mut bundle = padded("#bundle");
+	bundle.extend(&[0, 0, 0, 0, 0, 0, 0, 1]);
+	bundle.extend(&(message.len() as i32).to_be_bytes());
+	bundle.extend(&message);
+	let messages = decode(&bundle).unwrap();
+	assert_eq!(messages, vec![OscMessage{ address: "/synth/voice1/cutoff".to_string(), arguments: vec![Some(440.0), None] }]);
+	assert!(decode(&bundle[..bundle.len() - 2]).is_err());
+	let signal = |address: &str, name: &str| {
+	    OscSignal{ address: address.to_string(), argument: 0, scope: "Synth".to_string(), name: name.to_string(), scale: 1.0, offset: 0.0, min: 0.0, max: 1000.0 }
+	};
+	let mut mapping = OscMapping::new(&[signal("/synth/*/gain", "gain"), signal("/synth/*/cutoff", "cutoff")]);
+	assert_eq!(mapping.declarations(), vec!["`SCOPE Synth", "`Synth 'gain' 0 1000 256 0", "`Synth 'cutoff' 0 1000 256 0"]);
+	assert_eq!(mapping.update(&messages), vec!["`Synth 0 440"]);
+    }
+}
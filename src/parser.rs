@@ -6,35 +6,38 @@ use nom::branch::alt;
 use nom::sequence::{tuple, preceded, terminated, delimited};
 use nom::character::complete::{
     one_of,
-    alphanumeric1,
     char,
-    multispace1
+    hex_digit1,
+    multispace1,
+    space0
 };
 use nom::multi::{
-    many_m_n,
     many0,
-    many1
+    many1,
+    separated_list1
 };
-use nom::combinator::recognize;
+use nom::combinator::{all_consuming, recognize, map, map_opt};
 use nom::{
     named,
-    alt,
     tag,
     preceded,
-    bytes::complete::{tag},
+    bytes::complete::{tag, is_not, escaped_transform, take_while, take_while1, take_while_m_n},
     sequence::{separated_pair},
     IResult,
 };
-use phf::phf_map;
 use nannou::prelude::*;
+use thiserror::Error;
+
+use crate::debugobjects::color_by_name;
 
 type Color = Rgb<u8>;
 
-mod ast {
+pub mod ast {
     use super::*;
 
 
-    #[derive(Debug, PartialEq)]
+    // Which of the min/max labels and lines to draw
+    #[derive(Debug, Clone, Copy, PartialEq)]
     pub struct Legend
     {
 	pub max: bool,
@@ -43,11 +46,18 @@ mod ast {
 	pub min_line: bool
     }
 
+    // ABS, RMS <window> or DB after legend and color
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Math
+    {
+	Abs,
+	Rms(i64),
+	Db,
+    }
+
     #[derive(Debug, PartialEq)]
     pub enum DebugInstructionAtom
     {
-	// Keywords
-	SCOPE,
 	// Name
 	Identifier{value: String},
 	// `Name
@@ -56,74 +66,125 @@ mod ast {
 	String{value: String},
 	// SCOPE Parameters
 	Title(String),
-	Pos(i64, i64),
-	Size(i64, i64),
+	Pos(f64, f64),
+	Size(f64, f64),
 	Samples(i64),
 	Rate(i64),
 	DotSize(i64),
 	LineSize(i64),
 	TextSize(i64),
-	Color{ background: Color, grid: Option<Color> },
-	// TODO: packed data
+	// BACKGROUND, GRID and COLOR override the default colors
+	Background(Color),
+	Grid(Color),
+	Color(Color),
+	Trigger{ channel: i64, arm_level: f64, trigger_level: f64, offset: Option<i64> },
+	Overlay(i64),
+	// TIMEBASE HOST or TIMEBASE DEVICE <ticks per second>
+	Timebase{ ticks_per_second: Option<f64> },
+	TextRate(f64),
+	// REFRESH <per second>, for SCOPE and EYE
+	Refresh(f64),
+	// RESAMPLE HOLD or RESAMPLE LINEAR
+	Resample{ linear: bool },
+	// SKIP <samples>
+	Skip(i64),
+	// EYE Parameters
+	Period(i64),
+	Persist(i64),
+	// PACKED <bits per sample> [SIGNED], samples follow as #hex or =base64
+	Packed{ bits: i64, signed: bool },
+    }
+
+    // What follows the sequence number and timestamp of a data line
+    #[derive(Debug, PartialEq)]
+    pub enum Payload
+    {
+	// 1, 2, 3 or 1 2 3
+	Values(Vec<f64>),
+	// #hex or =base64 packed samples
+	Blob(Vec<u8>),
+	// 'Name' value, for resampling scopes
+	Update{ name: String, value: f64 },
     }
 
     #[derive(Debug, PartialEq)]
     pub enum DebugInstruction
     {
 	SCOPE{ name: String, configurations: Vec<DebugInstructionAtom> },
+	EYE{ name: String, configurations: Vec<DebugInstructionAtom> },
 	SignalDefinition{
 	    name: String,
-	    min: Option<i64>,
-	    max: Option<i64>,
-	    y_size: Option<i64>,
-	    y_base: Option<i64>,
+	    min: Option<f64>,
+	    max: Option<f64>,
+	    y_size: Option<f64>,
+	    y_base: Option<f64>,
 	    legend: Option<Legend>,
-	    color: Option<Color>
-	}
+	    color: Option<Color>,
+	    math: Option<Math>,
+	    // Drawn as sample-and-hold steps with update markers
+	    hold: bool,
+	    // Followed by its standard deviation in data lines
+	    sigma: bool,
+	},
+	// %sequence @ticks payload
+	Data{ sequence: Option<u64>, ticks: Option<f64>, payload: Payload },
+	// READOUT 'Name' precision [deadband]
+	Readout{ name: String, precision: i64, deadband: Option<f64> },
+	// BAND 'First' 'Second' [color]
+	Band{ first: String, second: String, color: Option<Color> },
+    }
+
+    // What follows the name in `GROUP name ...
+    #[derive(Debug, PartialEq)]
+    pub enum GroupAction
+    {
+	Hide,
+	Show,
+	Palette,
+	// Members as OBJECT.SIGNAL or SIGNAL
+	Define(Vec<String>),
     }
 }
 
-static COLOR_MAP: phf::Map<&[u8], Color> = phf_map! {
-    b"BLACK" => BLACK,
-    b"WHITE" => WHITE,
-    b"ORANGE" => ORANGE,
-    b"BLUE" => BLUE,
-    b"GREEN" => GREEN,
-    b"CYAN" => CYAN,
-    b"RED" => RED,
-    b"MAGENTA" => MAGENTA,
-    b"YELLOW" => YELLOW,
-};
+#[derive(Error, Debug, PartialEq)]
+#[error("cannot parse {0:?}")]
+pub struct ParseError(pub String);
 
-fn named_color_parser(input: &[u8]) -> IResult<&[u8], Color> {
-    named!( color_name, alt!(
-    tag!("BLACK") |
-    tag!("WHITE") |
-    tag!("ORANGE") |
-    tag!("BLUE") |
-    tag!("GREEN") |
-    tag!("CYAN") |
-    tag!("RED") |
-    tag!("MAGENTA") |
-    tag!("YELLOW")
-    ));
-    let (rest, color) = color_name(input)?;
-    Ok((rest, *COLOR_MAP.get(color).unwrap()))
+fn named_color_parser(input: &str) -> IResult<&str, Color> {
+    map_opt(take_while1(|c: char| { c.is_ascii_uppercase() }), color_by_name)(input)
 }
 
 // Symbols
-named!(scope_symbol, preceded!(tag!("`"), tag!("SCOPE")));
+named!(scope_symbol<&str, &str>, preceded!(tag!("`"), tag!("SCOPE")));
+named!(eye_symbol<&str, &str>, preceded!(tag!("`"), tag!("EYE")));
 
 // Keywords
-named!(title_keyword, tag!("TITLE"));
-named!(pos_keyword, tag!("POS"));
-named!(size_keyword, tag!("SIZE"));
-named!(samples_keyword, tag!("SAMPLES"));
-named!(rate_keyword, tag!("RATE"));
-named!(dotsize_keyword, tag!("DOTSIZE"));
-named!(linesize_keyword, tag!("LINESIZE"));
-named!(textsize_keyword, tag!("TEXTSIZE"));
-named!(color_keyword, tag!("COLOR"));
+named!(title_keyword<&str, &str>, tag!("TITLE"));
+named!(pos_keyword<&str, &str>, tag!("POS"));
+named!(size_keyword<&str, &str>, tag!("SIZE"));
+named!(samples_keyword<&str, &str>, tag!("SAMPLES"));
+named!(rate_keyword<&str, &str>, tag!("RATE"));
+named!(dotsize_keyword<&str, &str>, tag!("DOTSIZE"));
+named!(linesize_keyword<&str, &str>, tag!("LINESIZE"));
+named!(textsize_keyword<&str, &str>, tag!("TEXTSIZE"));
+named!(background_keyword<&str, &str>, tag!("BACKGROUND"));
+named!(grid_keyword<&str, &str>, tag!("GRID"));
+named!(color_keyword<&str, &str>, tag!("COLOR"));
+named!(trigger_keyword<&str, &str>, tag!("TRIGGER"));
+named!(overlay_keyword<&str, &str>, tag!("OVERLAY"));
+named!(timebase_keyword<&str, &str>, tag!("TIMEBASE"));
+named!(textrate_keyword<&str, &str>, tag!("TEXTRATE"));
+named!(refresh_keyword<&str, &str>, tag!("REFRESH"));
+named!(resample_keyword<&str, &str>, tag!("RESAMPLE"));
+named!(packed_keyword<&str, &str>, tag!("PACKED"));
+named!(skip_keyword<&str, &str>, tag!("SKIP"));
+named!(period_keyword<&str, &str>, tag!("PERIOD"));
+named!(persist_keyword<&str, &str>, tag!("PERSIST"));
+named!(readout_keyword<&str, &str>, tag!("READOUT"));
+named!(band_keyword<&str, &str>, tag!("BAND"));
+named!(hide_keyword<&str, &str>, tag!("HIDE"));
+named!(show_keyword<&str, &str>, tag!("SHOW"));
+named!(palette_keyword<&str, &str>, tag!("PALETTE"));
 
 fn string_from_atom(identifier: &ast::DebugInstructionAtom) -> String
 {
@@ -134,18 +195,14 @@ fn string_from_atom(identifier: &ast::DebugInstructionAtom) -> String
     };
 }
 
-fn identifier_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
-    let (rest, value) =
-	recognize(many1(
-	    alt((
-		alphanumeric1,
-		tag("_")))
-	))(input)?;
-    let value = std::str::from_utf8(value).expect("parser error").to_string();
-    Ok((rest, ast::DebugInstructionAtom::Identifier{ value }))
+// Anything up to the next whitespace, so namespaced names like
+// left.MyScope stay one identifier
+fn identifier_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, value) = take_while1(|c: char| { !c.is_whitespace() && c != '\'' && c != ',' })(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Identifier{ value: value.to_string() }))
 }
 
-fn symbol_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn symbol_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, value) =
 	preceded(
 	    tag("`"),
@@ -154,72 +211,135 @@ fn symbol_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::Symbol{ value: string_from_atom(&value) }))
 }
 
-fn string_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+// 'Current [A]' or 'It\'s 50%', a backslash escapes a quote or itself
+fn string_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let escape = alt((map(tag("'"), |_| { "'" }), map(tag("\\"), |_| { "\\" })));
     let (rest, value) =
 	delimited(
 	    tag("'"),
-	    recognize(many0(
-		alt((
-		    alphanumeric1,
-		    tag(" "),
-		    tag("_")))
-	    )),
+	    opt(escaped_transform(is_not("\\'\r\n"), '\\', escape)),
 	    tag("'")
 	)(input)?;
-    let value = std::str::from_utf8(value).expect("parser error").to_string();
-    Ok((rest, ast::DebugInstructionAtom::String{ value }))
+    Ok((rest, ast::DebugInstructionAtom::String{ value: value.unwrap_or_default() }))
+}
+
+// Object names may be quoted to contain spaces
+fn name_parser(input: &str) -> IResult<&str, String> {
+    map(alt((string_parser, identifier_parser)), |name| { string_from_atom(&name) })(input)
 }
 
-fn decimal(input: &[u8]) -> IResult<&[u8], i64> {
-    let (rest, number_literal) = recognize(
+// Digits out of alphabet, optionally grouped by _ like 1_000
+fn grouped<'a>(alphabet: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    recognize(
 	many1(
-	    terminated(one_of("0123456789"), many0(char('_')))
+	    terminated(one_of(alphabet), many0(char('_')))
 	)
-    )(input)?;
-    let number = std::str::from_utf8(number_literal).expect("parser error").parse::<i64>().expect("parser error");
-    Ok((rest, number))
+    )
+}
+
+fn digits(input: &str) -> IResult<&str, &str> {
+    grouped("0123456789")(input)
+}
+
+// 0x1F or 0b1010, firmware dumps registers and bitfields like that
+fn radix_literal(input: &str) -> IResult<&str, i64> {
+    let parse = |radix: u32| { move |literal: &str| { i64::from_str_radix(&literal.replace('_', ""), radix).ok() } };
+    alt((
+	map_opt(preceded(alt((tag("0x"), tag("0X"))), grouped("0123456789abcdefABCDEF")), parse(16)),
+	map_opt(preceded(alt((tag("0b"), tag("0B"))), grouped("01")), parse(2)),
+    ))(input)
 }
 
-fn gray_color_parser(input: &[u8]) -> IResult<&[u8], Color> {
+// Counts and sizes, a sign makes no sense for them
+fn decimal(input: &str) -> IResult<&str, i64> {
+    alt((
+	radix_literal,
+	map_opt(digits, |literal: &str| { literal.replace('_', "").parse::<i64>().ok() }),
+    ))(input)
+}
+
+// 3, 3.3, .5, 3. or 1.5e-3, read like the f32 parsing
+// of the firmware side. No inf or nan.
+fn float_literal(input: &str) -> IResult<&str, f64> {
+    let mantissa = alt((
+	recognize(pair(digits, opt(pair(char('.'), opt(digits))))),
+	recognize(pair(char('.'), digits)),
+    ));
+    let exponent = tuple((one_of("eE"), opt(one_of("+-")), digits));
+    map_opt(
+	recognize(pair(mantissa, opt(exponent))),
+	|literal: &str| { literal.replace('_', "").parse::<f64>().ok() }
+    )(input)
+}
+
+// An optional + or -, directly in front of the digits
+fn sign(input: &str) -> IResult<&str, f64> {
+    map(opt(one_of("+-")), |sign| { if sign == Some('-') { -1.0 } else { 1.0 } })(input)
+}
+
+// Ranges, positions and samples, e.g. -5, +2.5, 1e3 or 0x1F
+fn number(input: &str) -> IResult<&str, f64> {
+    let magnitude = alt((map(radix_literal, |value| { value as f64 }), float_literal));
+    map(pair(sign, magnitude), |(sign, magnitude)| { sign * magnitude })(input)
+}
+
+fn gray_color_parser(input: &str) -> IResult<&str, Color> {
     let (rest, (_name, level)) = separated_pair(
 	alt((tag("GRAY"), tag("GREY"))),
 	multispace1,
 	decimal)(input)?;
-    let level = (5 + level * 25) as u8;
+    let level = (5 + level.min(10) * 25) as u8;
     Ok((rest, Color::new(level, level, level)))
 }
 
-fn color_value_parser(input: &[u8]) -> IResult<&[u8], Color> {
-    alt((gray_color_parser, named_color_parser))(input)
+// #FF8800
+fn hex_color_parser(input: &str) -> IResult<&str, Color> {
+    map_opt(
+	preceded(char('#'), take_while_m_n(6, 6, |c: char| { c.is_ascii_hexdigit() })),
+	|hex: &str| { u32::from_str_radix(hex, 16).ok().map(|rgb| { Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8) }) }
+    )(input)
 }
 
-fn size_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+// RGB 255 136 0
+fn rgb_color_parser(input: &str) -> IResult<&str, Color> {
+    let channel = || { map_opt(preceded(multispace1, decimal), |value| { if value <= 255 { Some(value as u8) } else { None } }) };
+    map(
+	preceded(tag("RGB"), tuple((channel(), channel(), channel()))),
+	|(red, green, blue)| { Color::new(red, green, blue) }
+    )(input)
+}
+
+fn color_value_parser(input: &str) -> IResult<&str, Color> {
+    alt((hex_color_parser, rgb_color_parser, gray_color_parser, named_color_parser))(input)
+}
+
+fn size_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, (x, y))) = separated_pair(
 	size_keyword,
 	multispace1,
 	separated_pair(
-	    decimal,
+	    number,
 	    multispace1,
-	    decimal
+	    number
 	)
     )(input)?;
     Ok((rest, ast::DebugInstructionAtom::Size(x, y)))
 }
 
-fn pos_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn pos_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, (x, y))) = separated_pair(
 	pos_keyword,
 	multispace1,
 	separated_pair(
-	    decimal,
+	    number,
 	    multispace1,
-	    decimal
+	    number
 	)
     )(input)?;
     Ok((rest, ast::DebugInstructionAtom::Pos(x, y)))
 }
 
-fn samples_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn samples_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, samples)) = separated_pair(
 	samples_keyword,
 	multispace1,
@@ -228,7 +348,7 @@ fn samples_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::Samples(samples)))
 }
 
-fn rate_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn rate_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, rate)) = separated_pair(
 	rate_keyword,
 	multispace1,
@@ -237,20 +357,35 @@ fn rate_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::Rate(rate)))
 }
 
-fn color_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
-    let (rest, colors) = preceded(
+fn background_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, color)) = separated_pair(
+	background_keyword,
+	multispace1,
+	color_value_parser,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Background(color)))
+}
+
+fn grid_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, color)) = separated_pair(
+	grid_keyword,
+	multispace1,
+	color_value_parser,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Grid(color)))
+}
+
+// The color of signals without one of their own
+fn color_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, color)) = separated_pair(
 	color_keyword,
-	many_m_n(1, 2, preceded(multispace1, color_value_parser)),
+	multispace1,
+	color_value_parser,
     )(input)?;
-    let background = colors[0];
-    let mut grid = None;
-    if colors.len() == 2 {
-	grid = Some(colors[1]);
-    }
-    Ok((rest, ast::DebugInstructionAtom::Color{ background, grid}))
+    Ok((rest, ast::DebugInstructionAtom::Color(color)))
 }
 
-fn title_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn title_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, title)) = separated_pair(
 	title_keyword,
 	multispace1,
@@ -260,7 +395,7 @@ fn title_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::Title(string_from_atom(&title))))
 }
 
-fn dotsize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn dotsize_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, dotsize)) = separated_pair(
 	dotsize_keyword,
 	multispace1,
@@ -269,7 +404,7 @@ fn dotsize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::DotSize(dotsize)))
 }
 
-fn linesize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn linesize_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, linesize)) = separated_pair(
 	linesize_keyword,
 	multispace1,
@@ -278,7 +413,7 @@ fn linesize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::LineSize(linesize)))
 }
 
-fn textsize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
+fn textsize_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
     let (rest, (_, textsize)) = separated_pair(
 	textsize_keyword,
 	multispace1,
@@ -287,7 +422,105 @@ fn textsize_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstructionAtom> {
     Ok((rest, ast::DebugInstructionAtom::TextSize(textsize)))
 }
 
-fn legend_parser(input: &[u8]) -> IResult<&[u8], ast::Legend> {
+fn trigger_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, channel, arm_level, trigger_level, offset)) = tuple((
+	trigger_keyword,
+	preceded(multispace1, decimal),
+	preceded(multispace1, number),
+	preceded(multispace1, number),
+	opt(preceded(multispace1, decimal)),
+    ))(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Trigger{ channel, arm_level, trigger_level, offset }))
+}
+
+fn overlay_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, overlay)) = separated_pair(
+	overlay_keyword,
+	multispace1,
+	decimal,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Overlay(overlay)))
+}
+
+fn timebase_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let host = map(tag("HOST"), |_| { None });
+    let device = map(preceded(pair(tag("DEVICE"), multispace1), number), Some);
+    let (rest, (_, ticks_per_second)) = separated_pair(
+	timebase_keyword,
+	multispace1,
+	alt((host, device)),
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Timebase{ ticks_per_second }))
+}
+
+fn textrate_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, rate)) = separated_pair(
+	textrate_keyword,
+	multispace1,
+	number,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::TextRate(rate)))
+}
+
+// REFRESH <per second>, how often the samples are redrawn
+fn refresh_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, rate)) = separated_pair(
+	refresh_keyword,
+	multispace1,
+	number,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Refresh(rate)))
+}
+
+fn resample_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, linear)) = separated_pair(
+	resample_keyword,
+	multispace1,
+	alt((map(tag("HOLD"), |_| { false }), map(tag("LINEAR"), |_| { true }))),
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Resample{ linear }))
+}
+
+fn packed_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, ((_, bits), signed)) = pair(
+	separated_pair(
+	    packed_keyword,
+	    multispace1,
+	    decimal),
+	opt(preceded(multispace1, tag("SIGNED"))),
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Packed{ bits, signed: signed.is_some() }))
+}
+
+fn skip_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, skip)) = separated_pair(
+	skip_keyword,
+	multispace1,
+	decimal,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Skip(skip)))
+}
+
+fn period_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, period)) = separated_pair(
+	period_keyword,
+	multispace1,
+	decimal,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Period(period)))
+}
+
+fn persist_parser(input: &str) -> IResult<&str, ast::DebugInstructionAtom> {
+    let (rest, (_, persist)) = separated_pair(
+	persist_keyword,
+	multispace1,
+	decimal,
+    )(input)?;
+    Ok((rest, ast::DebugInstructionAtom::Persist(persist)))
+}
+
+// %abcd with a = max label, b = min label, c = max line, d = min line
+fn legend_parser(input: &str) -> IResult<&str, ast::Legend> {
     let (rest, (max, min, max_line, min_line)) = preceded(
 	tag("%"),
 	tuple((
@@ -311,69 +544,182 @@ fn legend_parser(input: &[u8]) -> IResult<&[u8], ast::Legend> {
     }))
 }
 
-fn scope_definition_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstruction> {
-    let preamble = preceded(
-	scope_symbol,
-	preceded(
-	    multispace1,
-	    identifier_parser));
+fn math_parser(input: &str) -> IResult<&str, ast::Math> {
+    alt((
+	map(tag("ABS"), |_| { ast::Math::Abs }),
+	map(tag("DB"), |_| { ast::Math::Db }),
+	map(preceded(pair(tag("RMS"), multispace1), decimal), ast::Math::Rms),
+    ))(input)
+}
+
+// Parsers following the SCOPE or EYE keyword
+fn scope_configuration_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
     let configurations = many0(
 	preceded(multispace1,
 		 alt((
 		     title_parser, pos_parser, size_parser,
 		     samples_parser, rate_parser, dotsize_parser,
-		     linesize_parser, textsize_parser, color_parser))));
-    let (rest, (name, configurations)) = separated_pair(
-	preamble,
-	multispace1,
+		     linesize_parser, textsize_parser, background_parser,
+		     grid_parser, color_parser, trigger_parser,
+		     overlay_parser, timebase_parser, textrate_parser,
+		     resample_parser, packed_parser, skip_parser,
+		     refresh_parser))));
+    let (rest, (name, configurations)) = pair(
+	name_parser,
+	configurations)(input)?;
+    Ok((rest, ast::DebugInstruction::SCOPE{ name, configurations }))
+}
+
+fn eye_configuration_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    let configurations = many0(
+	preceded(multispace1,
+		 alt((
+		     pos_parser, size_parser, period_parser,
+		     persist_parser, background_parser, grid_parser,
+		     color_parser, refresh_parser))));
+    let (rest, (name, configurations)) = pair(
+	name_parser,
 	configurations)(input)?;
-    Ok((rest, ast::DebugInstruction::SCOPE{ name: string_from_atom(&name), configurations }))
+    Ok((rest, ast::DebugInstruction::EYE{ name, configurations }))
+}
+
+fn scope_definition_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    preceded(
+	pair(scope_symbol, multispace1),
+	scope_configuration_parser)(input)
+}
+
+fn eye_definition_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    preceded(
+	pair(eye_symbol, multispace1),
+	eye_configuration_parser)(input)
 }
 
 // The following parsers all imply a keyword with the
 // SCOPE name in the beginning. E.g.
 // `MyScope 1, 2, 3, 4
-fn scope_signal_data_parser(input: &[u8]) -> IResult<&[u8], Vec<i64>> {
-    let (rest, (first, mut tail)) = pair(
-	decimal,
-	many0(
-	    preceded(
-		preceded(tag(","), multispace0),
-		decimal
-	    )))(input)?;
-    tail.insert(0, first);
-    Ok((rest, tail))
+fn value_separator(input: &str) -> IResult<&str, &str> {
+    alt((recognize(tuple((space0, char(','), space0))), multispace1))(input)
+}
+
+// A trailing comma is tolerated
+fn scope_signal_data_parser(input: &str) -> IResult<&str, Vec<f64>> {
+    terminated(
+	separated_list1(value_separator, number),
+	opt(pair(space0, char(','))))(input)
+}
+
+// #ff7f as hex or =/38= as base64
+fn blob_parser(input: &str) -> IResult<&str, Vec<u8>> {
+    let hex = map_opt(preceded(char('#'), hex_digit1), |hex: &str| {
+	if hex.len() % 2 != 0 {
+	    return None;
+	}
+	(0..hex.len()).step_by(2)
+	    .map(|index| { u8::from_str_radix(&hex[index..index + 2], 16).ok() })
+	    .collect()
+    });
+    let base64 = map_opt(
+	preceded(char('='), take_while(|c: char| { c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=' })),
+	crate::base64::decode);
+    alt((hex, base64))(input)
+}
+
+// `MyScope 'B' 1.5 updates a single signal
+fn update_parser(input: &str) -> IResult<&str, ast::Payload> {
+    let (rest, (name, value)) = separated_pair(
+	string_parser,
+	multispace1,
+	number)(input)?;
+    Ok((rest, ast::Payload::Update{ name: string_from_atom(&name), value }))
 }
 
-// `MyScope 'Sawtooth' 0 63 64 10 %1111
-fn legend_and_color_parser(input: &[u8]) -> IResult<&[u8], (Option<ast::Legend>, Color)>
+// `MyScope %17 @123456 1, 2, 3 with an optional sequence number,
+// counting up by one per line, and device timestamp
+fn data_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    let (rest, (sequence, ticks, payload)) = tuple((
+	opt(terminated(preceded(char('%'), decimal), value_separator)),
+	opt(terminated(preceded(char('@'), number), value_separator)),
+	alt((
+	    map(scope_signal_data_parser, ast::Payload::Values),
+	    map(blob_parser, ast::Payload::Blob),
+	    update_parser)),
+    ))(input)?;
+    Ok((rest, ast::DebugInstruction::Data{ sequence: sequence.map(|sequence| { sequence as u64 }), ticks, payload }))
+}
+
+// `MyScope READOUT 'A' precision [deadband]
+fn readout_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    let (rest, (_, name, precision, deadband)) = tuple((
+	readout_keyword,
+	preceded(multispace1, string_parser),
+	preceded(multispace1, decimal),
+	opt(preceded(multispace1, number)),
+    ))(input)?;
+    Ok((rest, ast::DebugInstruction::Readout{ name: string_from_atom(&name), precision, deadband }))
+}
+
+// `MyScope BAND 'Commanded' 'Measured' [color]
+fn band_parser(input: &str) -> IResult<&str, ast::DebugInstruction> {
+    let (rest, (_, first, second, color)) = tuple((
+	band_keyword,
+	preceded(multispace1, string_parser),
+	preceded(multispace1, string_parser),
+	opt(preceded(multispace1, color_value_parser)),
+    ))(input)?;
+    Ok((rest, ast::DebugInstruction::Band{ first: string_from_atom(&first), second: string_from_atom(&second), color }))
+}
+
+// Motor.IA, 'Power Rail'.'Volts' or 'Supply.Rail 1', quoted and
+// plain parts join up to the next whitespace
+fn group_member_parser(input: &str) -> IResult<&str, String> {
+    map(many1(name_parser), |parts| { parts.concat() })(input)
+}
+
+// `GROUP 'Power Rail' HIDE or `GROUP 'Power Rail' A.B C
+fn group_parser(input: &str) -> IResult<&str, (String, ast::GroupAction)> {
+    pair(
+	name_parser,
+	alt((
+	    map(whole(preceded(multispace1, hide_keyword)), |_| { ast::GroupAction::Hide }),
+	    map(whole(preceded(multispace1, show_keyword)), |_| { ast::GroupAction::Show }),
+	    map(whole(preceded(multispace1, palette_keyword)), |_| { ast::GroupAction::Palette }),
+	    map(whole(many1(preceded(multispace1, group_member_parser))), ast::GroupAction::Define),
+	))
+    )(input)
+}
+
+// `MyScope 'Sawtooth' 0 63 64 10 %1111 YELLOW
+fn legend_and_color_parser(input: &str) -> IResult<&str, (Option<ast::Legend>, Option<Color>)>
 {
-    let (rest, (legend, color)) = pair(
-	opt(terminated(legend_parser, multispace1)),
-	color_value_parser
-    )(input)?;
-    Ok((rest, (legend, color)))
+    alt((
+	pair(map(legend_parser, Some), opt(preceded(multispace1, color_value_parser))),
+	map(color_value_parser, |color| { (None, Some(color)) }),
+    ))(input)
 }
 
-fn scope_signal_declaration_parser(input: &[u8]) -> IResult<&[u8], ast::DebugInstruction>
+fn scope_signal_declaration_parser(input: &str) -> IResult<&str, ast::DebugInstruction>
 {
     let (rest, (name, arguments)) = pair(
 	string_parser,
 	opt(
 	    pair(
-		preceded(multispace1, decimal), // min
+		preceded(multispace1, number), // min
 		opt(
 		    pair(
-			preceded(multispace1, decimal), // max
+			preceded(multispace1, number), // max
 			opt(
 			    pair(
-				preceded(multispace1, decimal), // y_size
+				preceded(multispace1, number), // y_size
 				opt(
 				    pair(
-					preceded(multispace1, decimal), // y_base
-					opt(
-					    preceded(multispace1, legend_and_color_parser),
-					)
+					preceded(multispace1, number), // y_base
+					tuple((
+					    opt(preceded(multispace1, legend_and_color_parser)),
+					    opt(preceded(multispace1, math_parser)),
+					    opt(preceded(multispace1, tag("HOLD"))),
+					    opt(preceded(multispace1, tag("SIGMA"))),
+					))
 				    )
 				)
 			    )
@@ -391,6 +737,9 @@ fn scope_signal_declaration_parser(input: &[u8]) -> IResult<&[u8], ast::DebugIns
     let mut y_base = None;
     let mut color = None;
     let mut legend = None;
+    let mut math = None;
+    let mut hold = false;
+    let mut sigma = false;
 
     if let Some((min_value, arguments)) = arguments {
 	min = Some(min_value);
@@ -398,12 +747,15 @@ fn scope_signal_declaration_parser(input: &[u8]) -> IResult<&[u8], ast::DebugIns
 	    max = Some(max_value);
 	    if let Some((y_size_value, arguments)) = arguments {
 		y_size = Some(y_size_value);
-		if let Some((y_base_value, arguments)) = arguments {
+		if let Some((y_base_value, (legend_and_color, math_value, hold_value, sigma_value))) = arguments {
 		    y_base = Some(y_base_value);
-		    if let Some((legend_value, color_value)) = arguments {
-			color = Some(color_value);
+		    if let Some((legend_value, color_value)) = legend_and_color {
+			color = color_value;
 			legend = legend_value;
 		    }
+		    math = math_value;
+		    hold = hold_value.is_some();
+		    sigma = sigma_value.is_some();
 		}
 	    }
 	}
@@ -417,53 +769,192 @@ fn scope_signal_declaration_parser(input: &[u8]) -> IResult<&[u8], ast::DebugIns
 	y_base: y_base,
 	legend: legend,
 	color: color,
+	math: math,
+	hold,
+	sigma,
     };
     Ok((rest, result))
 }
 
+// `MyEye 'Name' min max [color]
+fn eye_signal_declaration_parser(input: &str) -> IResult<&str, ast::DebugInstruction>
+{
+    let (rest, (name, min, max, color)) = tuple((
+	string_parser,
+	preceded(multispace1, number),
+	preceded(multispace1, number),
+	opt(preceded(multispace1, color_value_parser)),
+    ))(input)?;
+    Ok((rest, ast::DebugInstruction::SignalDefinition{
+	name: string_from_atom(&name),
+	min: Some(min),
+	max: Some(max),
+	y_size: None,
+	y_base: None,
+	legend: None,
+	color,
+	math: None,
+	hold: false,
+	sigma: false,
+    }))
+}
+
+// Lines have to be consumed completely, alternatives can't
+// leave a rest for the next one to pick up
+fn whole<'a, O, F>(parser: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>
+{
+    all_consuming(terminated(parser, multispace0))
+}
+
+fn finish<'a, O>(input: &'a str, result: IResult<&'a str, O>) -> Result<O, ParseError>
+{
+    result.map(|(_, output)| { output }).map_err(|_| { ParseError(input.to_string()) })
+}
+
+// The keyword of a line like `MyScope 1 2 and what follows it
+pub fn symbol(line: &str) -> Result<(String, &str), ParseError>
+{
+    let (rest, keyword) = symbol_parser(line.trim_start()).map_err(|_| { ParseError(line.to_string()) })?;
+    Ok((string_from_atom(&keyword), rest.trim()))
+}
+
+// Lines starting with ; or # are notes or disabled commands
+pub fn comment(line: &str) -> bool
+{
+    line.trim_start().starts_with(|c| { c == ';' || c == '#' })
+}
+
+// A color on its own, as in settings and control requests
+pub fn color(text: &str) -> Result<Color, ParseError>
+{
+    finish(text, whole(color_value_parser)(text.trim_start()))
+}
+
+// A complete `SCOPE or `EYE declaration
+pub fn declaration(line: &str) -> Result<ast::DebugInstruction, ParseError>
+{
+    finish(line, alt((whole(scope_definition_parser), whole(eye_definition_parser)))(line.trim_start()))
+}
+
+// What follows the name of a scope: data, a READOUT, a BAND or a signal
+pub fn scope_line(body: &str) -> Result<ast::DebugInstruction, ParseError>
+{
+    finish(body, alt((
+	whole(data_parser),
+	whole(readout_parser),
+	whole(band_parser),
+	whole(scope_signal_declaration_parser)))(body.trim_start()))
+}
+
+// What follows `GROUP: the group name and what to do with it
+pub fn group(body: &str) -> Result<(String, ast::GroupAction), ParseError>
+{
+    finish(body, whole(group_parser)(body.trim_start()))
+}
+
+// What follows the name of an eye: data or its signal
+pub fn eye_line(body: &str) -> Result<ast::DebugInstruction, ParseError>
+{
+    finish(body, alt((
+	whole(data_parser),
+	whole(eye_signal_declaration_parser)))(body.trim_start()))
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn recognize_comments() {
+	assert!(comment("; motor off"));
+	assert!(comment("  #`MyScope 1 2"));
+	assert!(!comment("`MyScope 1 2 ; 3"));
+	assert!(!comment(""));
+    }
+
     #[test]
     fn parse_color_value() {
-	let (_rest, result) = color_value_parser(b"YELLOW").unwrap();
+	let (_rest, result) = color_value_parser("YELLOW").unwrap();
 	assert_eq!(result, YELLOW);
-	let (_rest, result) = color_value_parser(b"GRAY 1").unwrap();
+	let (_rest, result) = color_value_parser("GRAY 1").unwrap();
 	assert_eq!(result, Color::new(30, 30, 30));
-	let (_rest, result) = color_value_parser(b"GRAY 10").unwrap();
+	let (_rest, result) = color_value_parser("GRAY 10").unwrap();
 	assert_eq!(result, Color::new(255, 255, 255));
+	assert_eq!(color_value_parser("DARKGREEN").unwrap().1, DARKGREEN);
+	assert!(color_value_parser("BLURPLE").is_err());
+	assert_eq!(color_value_parser("#FF8800").unwrap().1, Color::new(255, 136, 0));
+	assert_eq!(color_value_parser("#0a0B0c").unwrap().1, Color::new(10, 11, 12));
+	assert!(color_value_parser("#FF88").is_err());
+	assert_eq!(color_value_parser("RGB 255 136 0").unwrap().1, Color::new(255, 136, 0));
+	assert_eq!(color_value_parser("RGB 0x10 0 0b1").unwrap().1, Color::new(16, 0, 1));
+	assert!(color_value_parser("RGB 256 0 0").is_err());
+	assert!(color_value_parser("RGB 1 2").is_err());
+	assert_eq!(color("#ff8800"), Ok(Color::new(255, 136, 0)));
+	assert_eq!(color("BLUE"), Ok(BLUE));
+	assert!(color("#FF88001").is_err());
+	assert!(matches!(scope_line("'A' 0 10 64 0 %1111 #00FF80"), Ok(ast::DebugInstruction::SignalDefinition{ color: Some(color), .. }) if color == Color::new(0, 255, 128)));
+	assert!(matches!(declaration("`SCOPE MyScope BACKGROUND RGB 1 2 3 COLOR #102030"), Ok(ast::DebugInstruction::SCOPE{ configurations, .. }) if configurations == vec![
+	    ast::DebugInstructionAtom::Background(Color::new(1, 2, 3)),
+	    ast::DebugInstructionAtom::Color(Color::new(16, 32, 48)),
+	]));
     }
 
     #[test]
     fn parse_scope_configurations() {
-	let (_rest, result) = title_parser(b"TITLE  'FooBarBaz'").unwrap();
+	let (_rest, result) = title_parser("TITLE  'FooBarBaz'").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::Title("FooBarBaz".to_string()));
-	let (_rest, result) = pos_parser(b"POS   100   200").unwrap();
-	assert_eq!(result, ast::DebugInstructionAtom::Pos(100, 200));
-	let (_rest, result) = size_parser(b"SIZE   100   200").unwrap();
-	assert_eq!(result, ast::DebugInstructionAtom::Size(100, 200));
-	let (_rest, result) = samples_parser(b"SAMPLES  128").unwrap();
+	let (_rest, result) = pos_parser("POS   100   200").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Pos(100.0, 200.0));
+	let (_rest, result) = size_parser("SIZE   100   200").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Size(100.0, 200.0));
+	let (_rest, result) = samples_parser("SAMPLES  128").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::Samples(128));
-	let (_rest, result) = rate_parser(b"RATE  128").unwrap();
+	let (_rest, result) = rate_parser("RATE  128").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::Rate(128));
-	let (_rest, result) = dotsize_parser(b"DOTSIZE  12").unwrap();
+	let (_rest, result) = dotsize_parser("DOTSIZE  12").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::DotSize(12));
-	let (_rest, result) = linesize_parser(b"LINESIZE  12").unwrap();
+	let (_rest, result) = linesize_parser("LINESIZE  12").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::LineSize(12));
-	let (_rest, result) = textsize_parser(b"TEXTSIZE  12").unwrap();
+	let (_rest, result) = textsize_parser("TEXTSIZE  12").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::TextSize(12));
 
-	let (_rest, result) = color_parser(b"COLOR  YELLOW").unwrap();
-	assert_eq!(result, ast::DebugInstructionAtom::Color{ background: YELLOW, grid: None });
-	let (_rest, result) = color_parser(b"COLOR  YELLOW   GREEN").unwrap();
-	assert_eq!(result, ast::DebugInstructionAtom::Color{ background: YELLOW, grid: Some(GREEN) });
+	let (_rest, result) = background_parser("BACKGROUND  BLUE").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Background(BLUE));
+	let (_rest, result) = grid_parser("GRID GRAY 2").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Grid(Color::new(55, 55, 55)));
+	let (_rest, result) = color_parser("COLOR  YELLOW").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Color(YELLOW));
+
+	let (_rest, result) = trigger_parser("TRIGGER 0 10 20").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Trigger{ channel: 0, arm_level: 10.0, trigger_level: 20.0, offset: None });
+	let (_rest, result) = trigger_parser("TRIGGER 1 20 10.5 64").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Trigger{ channel: 1, arm_level: 20.0, trigger_level: 10.5, offset: Some(64) });
+	let (_rest, result) = overlay_parser("OVERLAY 8").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Overlay(8));
+	let (_rest, result) = timebase_parser("TIMEBASE HOST").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Timebase{ ticks_per_second: None });
+	let (_rest, result) = timebase_parser("TIMEBASE DEVICE 1000000").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Timebase{ ticks_per_second: Some(1000000.0) });
+	let (_rest, result) = textrate_parser("TEXTRATE 4").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::TextRate(4.0));
+	let (_rest, result) = refresh_parser("REFRESH 2.5").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Refresh(2.5));
+	assert!(matches!(declaration("`EYE MyEye PERIOD 8 REFRESH 5"), Ok(ast::DebugInstruction::EYE{ configurations, .. }) if configurations.contains(&ast::DebugInstructionAtom::Refresh(5.0))));
+	let (_rest, result) = resample_parser("RESAMPLE LINEAR").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Resample{ linear: true });
+	let (_rest, result) = packed_parser("PACKED 16 SIGNED").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Packed{ bits: 16, signed: true });
+	let (_rest, result) = packed_parser("PACKED 8").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Packed{ bits: 8, signed: false });
+	let (_rest, result) = skip_parser("SKIP 1_000").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::Skip(1000));
     }
 
     #[test]
     fn parse_signal_legend() {
-	let (_rest, result) = legend_parser(b"%1010").unwrap();
+	let (_rest, result) = legend_parser("%1010").unwrap();
 	assert_eq!(result, ast::Legend{
 	    max: true, min: false, max_line: true, min_line: false}
 	);
@@ -471,74 +962,178 @@ mod tests {
 
     #[test]
     fn parse_symbols() {
-	let (_rest, result) = symbol_parser(b"`SpaceSignal").unwrap();
+	let (_rest, result) = symbol_parser("`SpaceSignal").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::Symbol{ value: "SpaceSignal".to_string() });
-	let (rest, _result) = scope_symbol(b"`SCOPE").unwrap();
-	assert_eq!(rest, b"");
+	let (rest, _result) = scope_symbol("`SCOPE").unwrap();
+	assert_eq!(rest, "");
+	assert_eq!(symbol("`left.MyScope  1 2 "), Ok(("left.MyScope".to_string(), "1 2")));
+	assert!(symbol("MyScope 1 2").is_err());
     }
 
     #[test]
     fn parse_string() {
-	let (_rest, result) = string_parser(b"'String'").unwrap();
+	let (_rest, result) = string_parser("'String'").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::String{ value: "String".to_string() });
-	let (_rest, result) = string_parser(b"'String with Space'").unwrap();
+	let (_rest, result) = string_parser("'String with Space'").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::String{ value: "String with Space".to_string() });
-	let (_rest, result) = string_parser(b"'String_with_underscores'").unwrap();
+	let (_rest, result) = string_parser("'String_with_underscores'").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::String{ value: "String_with_underscores".to_string() });
+	let (_rest, result) = string_parser("'Saw<tooth> 温度'").unwrap();
+	assert_eq!(result, ast::DebugInstructionAtom::String{ value: "Saw<tooth> 温度".to_string() });
+	let string = |input: &str| { string_parser(input).map(|(rest, atom)| { (rest, string_from_atom(&atom)) }) };
+	assert_eq!(string("'Current [A]'"), Ok(("", "Current [A]".to_string())));
+	assert_eq!(string("'50% duty, (avg.)' 1"), Ok((" 1", "50% duty, (avg.)".to_string())));
+	assert_eq!(string("'It\\'s'"), Ok(("", "It's".to_string())));
+	assert_eq!(string("'C:\\\\temp\\\\'"), Ok(("", "C:\\temp\\".to_string())));
+	assert_eq!(string("''"), Ok(("", "".to_string())));
+	assert!(string("'\\x'").is_err());
+	assert!(string("'open\\'").is_err());
+	assert!(string("'line\nbreak'").is_err());
+	assert!(matches!(scope_line("'Duty [%]' 0 100 64 0"), Ok(ast::DebugInstruction::SignalDefinition{ name, .. }) if name == "Duty [%]"));
+	assert!(matches!(declaration("`SCOPE MyScope TITLE 'Motor \\'A\\''"), Ok(ast::DebugInstruction::SCOPE{ configurations, .. }) if configurations == vec![ast::DebugInstructionAtom::Title("Motor 'A'".to_string())]));
     }
 
     #[test]
     fn parse_identifier() {
-	let (_rest, result) = identifier_parser(b"Identifier").unwrap();
+	let (_rest, result) = identifier_parser("Identifier").unwrap();
 	assert_eq!(result, ast::DebugInstructionAtom::Identifier{ value: "Identifier".to_string() });
     }
 
     #[test]
     fn parse_scope_declaration() {
-	let (_rest, result) = scope_definition_parser(b"`SCOPE MyScope SIZE 254 84 SAMPLES 128").unwrap();
+	let (_rest, result) = scope_definition_parser("`SCOPE MyScope SIZE 254 84 SAMPLES 128").unwrap();
 	match result {
-	    ast::DebugInstruction::SCOPE{ name, configurations } => {
+	    ast::DebugInstruction::SCOPE{ name, configurations: _ } => {
 		assert!(name == "MyScope".to_string());
 	    },
 	    _ => { assert!(false); }
 	}
+	assert_eq!(declaration("`SCOPE 'left.My Scope' COLOR CYAN"), Ok(ast::DebugInstruction::SCOPE{
+	    name: "left.My Scope".to_string(),
+	    configurations: vec![ast::DebugInstructionAtom::Color(CYAN)],
+	}));
+	assert!(declaration("`SCOPE MyScope RESAMPLE CUBIC").is_err());
+	assert!(declaration("`SCOPE MyScope BACKGROUND BLURPLE").is_err());
+    }
+
+    #[test]
+    fn parse_eye_declaration() {
+	let (_rest, result) = eye_definition_parser("`EYE MyEye SIZE 128 128 PERIOD 32 PERSIST 8").unwrap();
+	assert_eq!(result, ast::DebugInstruction::EYE{
+	    name: "MyEye".to_string(),
+	    configurations: vec![
+		ast::DebugInstructionAtom::Size(128.0, 128.0),
+		ast::DebugInstructionAtom::Period(32),
+		ast::DebugInstructionAtom::Persist(8),
+	    ]
+	});
+    }
+
+    #[test]
+    fn parse_float_literals() {
+	assert_eq!(float_literal("3.3"), Ok(("", 3.3)));
+	assert_eq!(float_literal(".5"), Ok(("", 0.5)));
+	assert_eq!(float_literal("3."), Ok(("", 3.0)));
+	assert_eq!(float_literal("1e3"), Ok(("", 1000.0)));
+	assert_eq!(float_literal("2.5E-3 1"), Ok((" 1", 0.0025)));
+	assert_eq!(float_literal("1_000.25"), Ok(("", 1000.25)));
+	assert_eq!(float_literal("1e"), Ok(("e", 1.0)));
+	assert!(float_literal(".").is_err());
+	assert!(float_literal("e3").is_err());
+	assert!(float_literal("inf").is_err());
+	assert!(float_literal("nan").is_err());
+	assert!(matches!(scope_line("'Voltage' 0 3.3 64 0.5"), Ok(ast::DebugInstruction::SignalDefinition{ max: Some(max), y_base: Some(y_base), .. }) if max == 3.3 && y_base == 0.5));
+	assert_eq!(scope_line("1.25, .5 3e-1"), Ok(ast::DebugInstruction::Data{
+	    sequence: None, ticks: None, payload: ast::Payload::Values(vec![1.25, 0.5, 0.3]),
+	}));
+	assert!(scope_line("1.2.3").is_err());
+    }
+
+    #[test]
+    fn parse_radix_literals() {
+	assert_eq!(decimal("0x1F"), Ok(("", 31)));
+	assert_eq!(decimal("0Xff_ff"), Ok(("", 65535)));
+	assert_eq!(decimal("0b1010"), Ok(("", 10)));
+	assert_eq!(decimal("0b1111_0000 1"), Ok((" 1", 240)));
+	assert_eq!(decimal("0x"), Ok(("x", 0)));
+	assert_eq!(number("-0x10"), Ok(("", -16.0)));
+	assert_eq!(number("0b102"), Ok(("2", 2.0)));
+	assert_eq!(samples_parser("SAMPLES 0x100").unwrap().1, ast::DebugInstructionAtom::Samples(256));
+	assert_eq!(scope_line("0x1F, 0b1010 0xDEAD"), Ok(ast::DebugInstruction::Data{
+	    sequence: None, ticks: None, payload: ast::Payload::Values(vec![31.0, 10.0, 57005.0]),
+	}));
+	assert!(matches!(scope_line("'Flags' 0 0xFF 64 0"), Ok(ast::DebugInstruction::SignalDefinition{ max: Some(max), .. }) if max == 255.0));
+	assert!(scope_line("0x1G").is_err());
+    }
+
+    #[test]
+    fn parse_signed_numbers() {
+	assert_eq!(number("-5"), Ok(("", -5.0)));
+	assert_eq!(number("+2.5"), Ok(("", 2.5)));
+	assert_eq!(number("-0.25 1"), Ok((" 1", -0.25)));
+	assert!(number("--5").is_err());
+	assert!(number("- 5").is_err());
+	assert!(number("-").is_err());
+	assert!(decimal("-5").is_err());
+	assert_eq!(pos_parser("POS -210 -40").unwrap().1, ast::DebugInstructionAtom::Pos(-210.0, -40.0));
+	assert_eq!(trigger_parser("TRIGGER 0 -10 -20").unwrap().1, ast::DebugInstructionAtom::Trigger{ channel: 0, arm_level: -10.0, trigger_level: -20.0, offset: None });
+	assert!(matches!(scope_line("'Delta' -100 +100 64 -10"), Ok(ast::DebugInstruction::SignalDefinition{ min: Some(min), max: Some(max), y_base: Some(y_base), .. }) if min == -100.0 && max == 100.0 && y_base == -10.0));
+	assert_eq!(scope_line("@-3 -12, +3,-1"), Ok(ast::DebugInstruction::Data{
+	    sequence: None, ticks: Some(-3.0), payload: ast::Payload::Values(vec![-12.0, 3.0, -1.0]),
+	}));
+	assert!(scope_line("-12 - 3").is_err());
+	assert!(scope_line("READOUT 'A' -1").is_err());
     }
 
     #[test]
     fn parse_scope_signal_data() {
-	let (_rest, result) = scope_signal_data_parser(b"1, 2,  3, 4,5").unwrap();
-	assert_eq!(result, vec![1, 2, 3, 4, 5]);
+	let (_rest, result) = scope_signal_data_parser("1, 2,  3, 4,5").unwrap();
+	assert_eq!(result, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+	let (_rest, result) = scope_signal_data_parser("-3 2.5, 1e3,").unwrap();
+	assert_eq!(result, vec![-3.0, 2.5, 1000.0]);
+	assert_eq!(scope_line("%17 @1500, 1 2"), Ok(ast::DebugInstruction::Data{
+	    sequence: Some(17), ticks: Some(1500.0), payload: ast::Payload::Values(vec![1.0, 2.0]),
+	}));
+	assert_eq!(scope_line("#ff7f"), Ok(ast::DebugInstruction::Data{
+	    sequence: None, ticks: None, payload: ast::Payload::Blob(vec![0xff, 0x7f]),
+	}));
+	assert_eq!(scope_line("'B' 1.5"), Ok(ast::DebugInstruction::Data{
+	    sequence: None, ticks: None, payload: ast::Payload::Update{ name: "B".to_string(), value: 1.5 },
+	}));
+	assert_eq!(scope_line("READOUT 'A' 1 0.5"), Ok(ast::DebugInstruction::Readout{
+	    name: "A".to_string(), precision: 1, deadband: Some(0.5),
+	}));
+	assert_eq!(scope_line("BAND 'Set' 'Actual' GRAY 3"), Ok(ast::DebugInstruction::Band{
+	    first: "Set".to_string(), second: "Actual".to_string(), color: Some(Color::new(80, 80, 80)),
+	}));
+	assert!(matches!(scope_line("BAND 'Set' 'Actual'"), Ok(ast::DebugInstruction::Band{ color: None, .. })));
+	assert!(scope_line("BAND 'Set'").is_err());
+	assert!(scope_line("#0g").is_err());
+	assert!(scope_line("#fff").is_err());
+	assert!(scope_line("1 2 x").is_err());
     }
 
     #[test]
     fn parse_scope_signal_definition() {
-	let (_rest, (legend, color)) = legend_and_color_parser(b"%1111 YELLOW").unwrap();
-	assert_eq!(color, YELLOW);
+	let (_rest, (legend, color)) = legend_and_color_parser("%1111 YELLOW").unwrap();
+	assert_eq!(color, Some(YELLOW));
 	assert_eq!(legend, Some(ast::Legend{
 	    max: true, min: true, max_line: true, min_line: true}
 	));
 
-	let (_rest, (legend, color)) = legend_and_color_parser(b"YELLOW").unwrap();
-	assert_eq!(color, YELLOW);
+	let (_rest, (legend, color)) = legend_and_color_parser("YELLOW").unwrap();
+	assert_eq!(color, Some(YELLOW));
 	assert_eq!(legend, None);
 
-	let (_rest, result) = scope_signal_declaration_parser(b"'Sawtooth'").unwrap();
+	let (_rest, result) = scope_signal_declaration_parser("'Sawtooth'").unwrap();
 	match result {
-	    ast::DebugInstruction::SignalDefinition{
-		name,
-		min: _,
-		max: _,
-		y_base: _,
-		y_size: _,
-		legend: _,
-		color: _
-	    } => {
+	    ast::DebugInstruction::SignalDefinition{ name, .. } => {
 		assert_eq!(name, "Sawtooth".to_string());
 	    },
 	    _ => { assert!(false); }
 	}
 
-	let (_rest, result) = scope_signal_declaration_parser(b"'Sawtooth' 10 20 30 40 YELLOW").unwrap();
+	let (_rest, result) = scope_signal_declaration_parser("'Sawtooth' 10 20 30 40 YELLOW").unwrap();
 	match result {
 	    ast::DebugInstruction::SignalDefinition{
 		name,
@@ -547,19 +1142,22 @@ mod tests {
 		y_size,
 		y_base,
 		legend: _,
-		color
+		color,
+		math: _,
+		hold: _,
+		sigma: _,
 	    } => {
 		assert_eq!(name, "Sawtooth".to_string());
-		assert_eq!(min, Some(10));
-		assert_eq!(max, Some(20));
-		assert_eq!(y_size, Some(30));
-		assert_eq!(y_base, Some(40));
+		assert_eq!(min, Some(10.0));
+		assert_eq!(max, Some(20.0));
+		assert_eq!(y_size, Some(30.0));
+		assert_eq!(y_base, Some(40.0));
 		assert_eq!(color, Some(YELLOW));
 	    },
 	    _ => { assert!(false); }
 	}
 
-	let (_rest, result) = scope_signal_declaration_parser(b"'Sawtooth' 10 20 30 40 %1111 YELLOW").unwrap();
+	let (_rest, result) = scope_signal_declaration_parser("'Sawtooth' 10 20 30 40 %1111 YELLOW").unwrap();
 	match result {
 	    ast::DebugInstruction::SignalDefinition{
 		name,
@@ -568,20 +1166,57 @@ mod tests {
 		y_size,
 		y_base,
 		legend,
-		color
+		color,
+		math,
+		hold,
+		sigma,
 	    } => {
 		assert_eq!(name, "Sawtooth".to_string());
-		assert_eq!(min, Some(10));
-		assert_eq!(max, Some(20));
-		assert_eq!(y_size, Some(30));
-		assert_eq!(y_base, Some(40));
+		assert_eq!(min, Some(10.0));
+		assert_eq!(max, Some(20.0));
+		assert_eq!(y_size, Some(30.0));
+		assert_eq!(y_base, Some(40.0));
 		assert_eq!(color, Some(YELLOW));
 		assert_eq!(legend, Some(ast::Legend{
 		    max: true, min: true, max_line: true, min_line: true}
 		));
+		assert_eq!(math, None);
+		assert!(!hold);
+		assert!(!sigma);
 	    },
 	    _ => { assert!(false); }
 	}
+
+	let result = scope_line("'C' -100 100 64 0 BLUE DB").unwrap();
+	match result {
+	    ast::DebugInstruction::SignalDefinition{ min, color, math, .. } => {
+		assert_eq!(min, Some(-100.0));
+		assert_eq!(color, Some(BLUE));
+		assert_eq!(math, Some(ast::Math::Db));
+	    },
+	    _ => { assert!(false); }
+	}
+	assert!(matches!(scope_line("'B' 0 10 64 0 %1001 RMS 2"), Ok(ast::DebugInstruction::SignalDefinition{ math: Some(ast::Math::Rms(2)), .. })));
+	assert!(matches!(scope_line("'T' 0 50 64 0 ORANGE HOLD"), Ok(ast::DebugInstruction::SignalDefinition{ color: Some(ORANGE), hold: true, .. })));
+	assert!(matches!(scope_line("'T' 0 50 64 0 ABS HOLD"), Ok(ast::DebugInstruction::SignalDefinition{ math: Some(ast::Math::Abs), hold: true, .. })));
+	assert!(matches!(scope_line("'X' -1 1 64 0 CYAN SIGMA"), Ok(ast::DebugInstruction::SignalDefinition{ hold: false, sigma: true, .. })));
+	assert!(matches!(scope_line("'X' -1 1 64 0 HOLD SIGMA"), Ok(ast::DebugInstruction::SignalDefinition{ hold: true, sigma: true, .. })));
+	assert!(scope_line("'X' -1 1 64 0 SIGMA HOLD").is_err());
+	assert!(matches!(eye_line("'PWM' 0 1 CYAN"), Ok(ast::DebugInstruction::SignalDefinition{ color: Some(CYAN), .. })));
+    }
+
+    #[test]
+    fn parse_group() {
+	assert_eq!(group("Phases Motor.IA IB"), Ok(("Phases".to_string(), ast::GroupAction::Define(vec!["Motor.IA".to_string(), "IB".to_string()]))));
+	assert_eq!(group("'Power Rail' HIDE"), Ok(("Power Rail".to_string(), ast::GroupAction::Hide)));
+	assert_eq!(group("'Power Rail' PALETTE "), Ok(("Power Rail".to_string(), ast::GroupAction::Palette)));
+	assert_eq!(group("'Power Rail' 'My Scope'.'Rail 1' Supply.'It\\'s'"), Ok(("Power Rail".to_string(), ast::GroupAction::Define(vec![
+	    "My Scope.Rail 1".to_string(),
+	    "Supply.It's".to_string(),
+	]))));
+	assert_eq!(group("Phases HIDE IA"), Ok(("Phases".to_string(), ast::GroupAction::Define(vec!["HIDE".to_string(), "IA".to_string()]))));
+	assert!(group("Phases").is_err());
+	assert!(group("'Unterminated HIDE").is_err());
     }
 
 }
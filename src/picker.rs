@@ -0,0 +1,129 @@
+use nannou::prelude::*;
+use serialport::{SerialPortInfo, SerialPortType};
+
+type Rect = nannou::geom::rect::Rect;
+
+pub struct PortEntry
+{
+    pub name: String,
+    pub description: String,
+}
+
+impl PortEntry
+{
+    pub fn from_info(info: &SerialPortInfo) -> PortEntry
+    {
+	let description = match &info.port_type {
+	    SerialPortType::UsbPort(usb) => {
+		let mut description = format!("USB {:04x}:{:04x}", usb.vid, usb.pid);
+		for detail in &[&usb.manufacturer, &usb.product, &usb.serial_number] {
+		    if let Some(detail) = detail {
+			description.push(' ');
+			description.push_str(detail);
+		    }
+		}
+		description
+	    }
+	    SerialPortType::PciPort => "PCI".to_string(),
+	    SerialPortType::BluetoothPort => "Bluetooth".to_string(),
+	    SerialPortType::Unknown => "unknown".to_string(),
+	};
+	PortEntry{ name: info.port_name.clone(), description }
+    }
+}
+
+// Lists the serial ports at startup, the user picks one with
+// the cursor keys and Return.
+pub struct PortPicker
+{
+    pub ports: Vec<PortEntry>,
+    pub selected: usize,
+}
+
+impl PortPicker
+{
+    pub fn new() -> PortPicker
+    {
+	let mut picker = PortPicker{ ports: vec![], selected: 0 };
+	picker.rescan();
+	picker
+    }
+
+    pub fn rescan(&mut self)
+    {
+	self.ports = serialport::available_ports()
+	    .unwrap_or_default()
+	    .iter()
+	    .map(PortEntry::from_info)
+	    .collect();
+	self.selected = self.selected.min(self.ports.len().saturating_sub(1));
+    }
+
+    pub fn up(&mut self)
+    {
+	self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self)
+    {
+	if self.selected + 1 < self.ports.len() {
+	    self.selected += 1;
+	}
+    }
+
+    pub fn current(&self) -> Option<&PortEntry>
+    {
+	self.ports.get(self.selected)
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect)
+    {
+	let line_height = 18.0;
+	let mut lines = vec![("Select the serial port (Up/Down, Return, R to rescan)".to_string(), YELLOW)];
+	if self.ports.is_empty() {
+	    lines.push(("No serial ports found".to_string(), RED));
+	}
+	for (index, port) in self.ports.iter().enumerate() {
+	    let marker = if index == self.selected { ">" } else { " " };
+	    lines.push((format!("{} {} - {}", marker, port.name, port.description), if index == self.selected { WHITE } else { GRAY }));
+	}
+	for (index, (line, color)) in lines.iter().enumerate() {
+	    draw.text(line)
+		.x_y(rect.x(), rect.top() - line_height * (index as f32 + 0.5))
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(14)
+		.left_justify()
+		.color(*color);
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+    use serialport::UsbPortInfo;
+
+    #[test]
+    fn describe_usb_ports() {
+	let info = SerialPortInfo{
+	    port_name: "/dev/ttyUSB0".to_string(),
+	    port_type: SerialPortType::UsbPort(UsbPortInfo{
+		vid: 0x0403,
+		pid: 0x6001,
+		serial_number: Some("A50285BI".to_string()),
+		manufacturer: Some("FTDI".to_string()),
+		product: None,
+	    }),
+	};
+	let entry = PortEntry::from_info(&info);
+	assert_eq!(entry.name, "/dev/ttyUSB0");
+	assert_eq!(entry.description, "USB 0403:6001 FTDI A50285BI");
+	let mut picker = PortPicker{ ports: vec![entry, PortEntry::from_info(&info)], selected: 0 };
+	picker.up();
+	assert_eq!(picker.selected, 0);
+	picker.down();
+	picker.down();
+	assert_eq!(picker.selected, 1);
+    }
+}
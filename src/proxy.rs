@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crossbeam::channel::{Receiver, Sender, unbounded};
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::debugobjects::DebugLine;
+use crate::parser::{self, ast};
+use crate::serial::{self, LineSettings};
+
+// A consumer not taking a line within this is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How the rows of one interval become the row passed on
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregate
+{
+    Last,
+    Mean,
+    Min,
+    Max,
+}
+
+impl std::str::FromStr for Aggregate
+{
+    type Err = String;
+
+    fn from_str(aggregate: &str) -> Result<Aggregate, String>
+    {
+	match aggregate {
+	    "last" => Ok(Aggregate::Last),
+	    "mean" => Ok(Aggregate::Mean),
+	    "min" => Ok(Aggregate::Min),
+	    "max" => Ok(Aggregate::Max),
+	    _ => Err(format!("unknown aggregate {}", aggregate)),
+	}
+    }
+}
+
+// Where the reduced stream goes: tcp:0.0.0.0:7880 serves any number
+// of consumers, file:slow.log appends, serial:/dev/ttyUSB1 writes to
+// a port.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target
+{
+    Tcp(String),
+    File(String),
+    Serial(String),
+}
+
+impl std::str::FromStr for Target
+{
+    type Err = String;
+
+    fn from_str(target: &str) -> Result<Target, String>
+    {
+	if let Some(address) = target.strip_prefix("tcp:") {
+	    return Ok(Target::Tcp(address.to_string()));
+	}
+	if let Some(path) = target.strip_prefix("file:") {
+	    return Ok(Target::File(path.to_string()));
+	}
+	match target.strip_prefix("serial:") {
+	    Some(port) => Ok(Target::Serial(port.to_string())),
+	    None => Err(format!("unknown proxy target {}, expected tcp:, file: or serial:", target)),
+	}
+    }
+}
+
+// Rows of an object, or of one signal of a resampling scope, since
+// the last line passed on
+struct Pending
+{
+    // Seconds after start
+    emitted: f64,
+    rows: usize,
+    values: Vec<f64>,
+    // Device timestamp of the newest row
+    ticks: Option<f64>,
+}
+
+impl Pending
+{
+    fn new() -> Pending
+    {
+	Pending{ emitted: f64::NEG_INFINITY, rows: 0, values: vec![], ticks: None }
+    }
+
+    fn add(&mut self, values: Vec<f64>, ticks: Option<f64>, aggregate: Aggregate)
+    {
+	// A redeclared scope may have a different number of signals
+	if self.rows == 0 || self.values.len() != values.len() {
+	    self.values = values;
+	    self.rows = 0;
+	} else {
+	    for (pending, value) in self.values.iter_mut().zip(values) {
+		*pending = match aggregate {
+		    Aggregate::Last => value,
+		    Aggregate::Mean => *pending + value,
+		    Aggregate::Min => pending.min(value),
+		    Aggregate::Max => pending.max(value),
+		};
+	    }
+	}
+	self.rows += 1;
+	self.ticks = ticks;
+    }
+
+    fn due(&self, timestamp: f64, interval: f64) -> bool
+    {
+	timestamp - self.emitted >= interval
+    }
+
+    // The aggregate of the interval, starting the next one
+    fn take(&mut self, timestamp: f64, aggregate: Aggregate) -> (Vec<f64>, Option<f64>)
+    {
+	let rows = self.rows as f64;
+	let values = std::mem::take(&mut self.values).into_iter()
+	    .map(|value| { if aggregate == Aggregate::Mean { value / rows } else { value } })
+	    .collect();
+	self.emitted = timestamp;
+	self.rows = 0;
+	(values, self.ticks.take())
+    }
+}
+
+fn quote(name: &str) -> String
+{
+    format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+// Limits the data lines of each object to rate per second, the rows
+// in between are aggregated. Packed rows can't be aggregated, of those
+// the first of each interval is passed on. Declarations, commands and
+// anything unparsed pass unchanged.
+pub struct Reducer
+{
+    // Seconds between lines of an object
+    interval: f64,
+    aggregate: Aggregate,
+    pending: HashMap<String, Pending>,
+}
+
+impl Reducer
+{
+    pub fn new(rate: f64, aggregate: Aggregate) -> Reducer
+    {
+	Reducer{ interval: 1.0 / rate.max(0.001), aggregate, pending: HashMap::new() }
+    }
+
+    // The line to pass on for line, received timestamp seconds after start
+    pub fn reduce(&mut self, line: &str, timestamp: f64) -> Option<String>
+    {
+	let parsed = match DebugLine::from_str(line, timestamp) {
+	    Ok(parsed) => parsed,
+	    Err(_) => { return Some(line.to_string()); }
+	};
+	let (ticks, payload) = match parser::scope_line(&parsed.body) {
+	    Ok(ast::DebugInstruction::Data{ ticks, payload, .. }) => (ticks, payload),
+	    _ => { return Some(line.to_string()); }
+	};
+	let (key, prefix, values) = match payload {
+	    ast::Payload::Values(values) => (parsed.keyword.clone(), String::new(), values),
+	    ast::Payload::Update{ name, value } => (format!("{}\n{}", parsed.keyword, name), format!("{} ", quote(&name)), vec![value]),
+	    ast::Payload::Blob(_) => {
+		let pending = self.pending.entry(parsed.keyword).or_insert_with(Pending::new);
+		if !pending.due(timestamp, self.interval) {
+		    return None;
+		}
+		pending.emitted = timestamp;
+		return Some(line.to_string());
+	    }
+	};
+	let pending = self.pending.entry(key).or_insert_with(Pending::new);
+	pending.add(values, ticks, self.aggregate);
+	if !pending.due(timestamp, self.interval) {
+	    return None;
+	}
+	let (values, ticks) = pending.take(timestamp, self.aggregate);
+	let values: Vec<String> = values.iter().map(|value| { value.to_string() }).collect();
+	let ticks = ticks.map(|ticks| { format!("@{} ", ticks) }).unwrap_or_default();
+	Some(format!("`{} {}{}{}", parsed.keyword, ticks, prefix, values.join(" ")))
+    }
+}
+
+// The object a line sets up, to be replayed to consumers connecting
+// later: `SCOPE and `EYE declarations, true for those, and the
+// signals, readouts and bands following them
+fn declared_object(line: &str) -> Option<(String, bool)>
+{
+    let parsed = DebugLine::from_str(line, 0.0).ok()?;
+    if parsed.keyword == "SCOPE" || parsed.keyword == "EYE" {
+	return parsed.tokens.first().map(|name| { (name.trim_matches('\'').to_string(), true) });
+    }
+    match parser::scope_line(&parsed.body).or_else(|_| { parser::eye_line(&parsed.body) }) {
+	Ok(ast::DebugInstruction::Data{ .. }) | Err(_) => None,
+	Ok(_) => Some((parsed.keyword, false)),
+    }
+}
+
+// Connected consumers and what they need to make sense of the data
+#[derive(Default)]
+struct Consumers
+{
+    streams: Vec<TcpStream>,
+    // Declarations in order, with the object they belong to
+    setup: Vec<(String, String)>,
+}
+
+impl Consumers
+{
+    fn send(&mut self, line: &str)
+    {
+	if let Some((object, declaration)) = declared_object(line) {
+	    // Redeclaring replaces all of the object
+	    if declaration {
+		self.setup.retain(|(owner, _)| { owner != &object });
+	    }
+	    self.setup.push((object, line.to_string()));
+	}
+	self.streams.retain(|stream| {
+	    let mut stream = stream;
+	    match writeln!(stream, "{}", line) {
+		Ok(()) => true,
+		Err(error) => {
+		    info!("proxy consumer {:?} dropped: {}", stream.peer_addr(), error);
+		    false
+		}
+	    }
+	});
+    }
+
+    fn join(&mut self, stream: TcpStream)
+    {
+	stream.set_write_timeout(Some(CLIENT_TIMEOUT)).ok();
+	let mut writer = &stream;
+	for (_, line) in &self.setup {
+	    if writeln!(writer, "{}", line).is_err() {
+		return;
+	    }
+	}
+	self.streams.push(stream);
+    }
+}
+
+fn write_lines<W: Write>(mut writer: W, receiver: Receiver<String>)
+{
+    for line in receiver {
+	if let Err(error) = writeln!(writer, "{}", line) {
+	    warn!("proxy output failed: {}", error);
+	    return;
+	}
+    }
+}
+
+// Republishes the input at a reduced rate for slower consumers, while
+// everything is still shown at full rate. Writing happens on a thread
+// of its own, so a stalled consumer never holds up the display.
+pub struct Proxy
+{
+    reducer: Reducer,
+    sender: Sender<String>,
+}
+
+impl Proxy
+{
+    pub fn new(target: &Target, baud: u32, line: LineSettings, reducer: Reducer) -> std::io::Result<Proxy>
+    {
+	let (s, r) = unbounded::<String>();
+	match target {
+	    Target::Tcp(address) => {
+		let listener = TcpListener::bind(address)?;
+		let consumers = Arc::new(Mutex::new(Consumers::default()));
+		let joining = consumers.clone();
+		thread::spawn(move || {
+		    for stream in listener.incoming() {
+			match stream {
+			    Ok(stream) => {
+				info!("proxy consumer {:?} connected", stream.peer_addr());
+				joining.lock().unwrap().join(stream);
+			    }
+			    Err(error) => { warn!("proxy accept failed: {:?}", error); }
+			}
+		    }
+		});
+		thread::spawn(move || {
+		    for line in r {
+			consumers.lock().unwrap().send(&line);
+		    }
+		});
+	    }
+	    Target::File(path) => {
+		let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+		thread::spawn(move || { write_lines(LineWriter::new(file), r); });
+	    }
+	    Target::Serial(port) => {
+		let port = serial::open_port(port, baud, line, CLIENT_TIMEOUT)?;
+		thread::spawn(move || { write_lines(port, r); });
+	    }
+	}
+	Ok(Proxy{ reducer, sender: s })
+    }
+
+    pub fn feed(&mut self, line: &str, timestamp: f64)
+    {
+	if let Some(line) = self.reducer.reduce(line, timestamp) {
+	    self.sender.send(line).ok();
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use test_env_log::test;
+
+    #[test]
+    fn reduce_data_lines() {
+	let mut reducer = Reducer::new(10.0, Aggregate::Mean);
+	assert_eq!(reducer.reduce("`SCOPE MyScope SAMPLES 8", 0.0), Some("`SCOPE MyScope SAMPLES 8".to_string()));
+	assert_eq!(reducer.reduce("`MyScope 1 10", 0.0), Some("`MyScope 1 10".to_string()));
+	assert_eq!(reducer.reduce("`MyScope %2 2 20", 0.04), None);
+	assert_eq!(reducer.reduce("; note", 0.05), Some("; note".to_string()));
+	assert_eq!(reducer.reduce("`MyScope %3 @1500 3 30", 0.08), None);
+	assert_eq!(reducer.reduce("`MyScope %4 @1600 4 40", 0.12), Some("`MyScope @1600 3 30".to_string()));
+	assert_eq!(reducer.reduce("`Other 5", 0.13), Some("`Other 5".to_string()));
+	let mut reducer = Reducer::new(1.0, Aggregate::Max);
+	assert_eq!(reducer.reduce("`MyScope 'It\\'s' 2", 0.0), Some("`MyScope 'It\\'s' 2".to_string()));
+	assert_eq!(reducer.reduce("`MyScope 'B' 1", 0.0), Some("`MyScope 'B' 1".to_string()));
+	assert_eq!(reducer.reduce("`MyScope 'It\\'s' 7", 0.5), None);
+	assert_eq!(reducer.reduce("`MyScope 'It\\'s' 3", 1.0), Some("`MyScope 'It\\'s' 7".to_string()));
+	assert_eq!(reducer.reduce("`MyScope #0102", 0.0), Some("`MyScope #0102".to_string()));
+	assert_eq!(reducer.reduce("`MyScope #0304", 0.5), None);
+    }
+
+    #[test]
+    fn parse_targets() {
+	assert_eq!("tcp:0.0.0.0:7880".parse(), Ok(Target::Tcp("0.0.0.0:7880".to_string())));
+	assert_eq!("file:slow.log".parse(), Ok(Target::File("slow.log".to_string())));
+	assert_eq!("serial:/dev/ttyUSB1".parse(), Ok(Target::Serial("/dev/ttyUSB1".to_string())));
+	assert!("/dev/ttyUSB1".parse::<Target>().is_err());
+	assert_eq!("min".parse(), Ok(Aggregate::Min));
+	assert!("median".parse::<Aggregate>().is_err());
+    }
+
+    #[test]
+    fn late_consumers_get_declarations() {
+	let mut consumers = Consumers::default();
+	for line in &["`SCOPE MyScope SAMPLES 8", "`MyScope 'A' 0 10 64 0", "`MyScope 1", "`SCOPE Other", "`SCOPE MyScope SAMPLES 16"] {
+	    consumers.send(line);
+	}
+	assert_eq!(consumers.setup.iter().map(|(_, line)| { line.as_str() }).collect::<Vec<&str>>(), vec!["`SCOPE Other", "`SCOPE MyScope SAMPLES 16"]);
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+	consumers.join(listener.accept().unwrap().0);
+	consumers.send("`MyScope 2");
+	let lines: Vec<String> = BufReader::new(client).lines().take(3).map(|line| { line.unwrap() }).collect();
+	assert_eq!(lines, vec!["`SCOPE Other", "`SCOPE MyScope SAMPLES 16", "`MyScope 2"]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use crossbeam::channel::{Receiver, Sender, unbounded};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use crate::serial::Received;
+
+// Larger frames are taken as a broken client
+const MAX_FRAME: usize = 1 << 20;
+
+// A message pushed by a test rig. Each frame is a big endian u32
+// length followed by one JSON message, e.g.
+//
+// {"type": "scope", "name": "Motor", "samples": 500}
+// {"type": "signal", "object": "Motor", "name": "Current", "min": 0, "max": 5}
+// {"type": "data", "object": "Motor", "values": [1.5]}
+//
+// Invalid messages are answered with an error frame
+// {"error": "..."} and end the connection.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Message
+{
+    // A protocol line as is
+    Line{ line: String },
+    Scope{ name: String, samples: Option<usize>, size: Option<[f32; 2]>, pos: Option<[f32; 2]> },
+    Signal{ object: String, name: String, min: f32, max: f32, height: Option<f32>, base: Option<f32> },
+    Data{ object: String, values: Vec<f64> },
+    Event{ origin: String, text: String },
+    // Clears one object or everything
+    Clear{ object: Option<String> },
+}
+
+impl Message
+{
+    // The equivalent protocol line
+    pub fn line(&self) -> String
+    {
+	match self {
+	    Message::Line{ line } => line.clone(),
+	    Message::Scope{ name, samples, size, pos } => {
+		let mut line = format!("`SCOPE {}", name);
+		if let Some([width, height]) = size {
+		    line.push_str(&format!(" SIZE {} {}", width, height));
+		}
+		if let Some([x, y]) = pos {
+		    line.push_str(&format!(" POS {} {}", x, y));
+		}
+		if let Some(samples) = samples {
+		    line.push_str(&format!(" SAMPLES {}", samples));
+		}
+		line
+	    }
+	    Message::Signal{ object, name, min, max, height, base } => {
+		format!("`{} '{}' {} {} {} {}", object, name, min, max, height.unwrap_or(100.0), base.unwrap_or(0.0))
+	    }
+	    Message::Data{ object, values } => {
+		let values: Vec<String> = values.iter().map(|value| { value.to_string() }).collect();
+		format!("`{} {}", object, values.join(" "))
+	    }
+	    Message::Event{ origin, text } => format!("`EVENT {} {}", origin, text),
+	    Message::Clear{ object: Some(object) } => format!("`{} CLEAR", object),
+	    Message::Clear{ object: None } => "`CLEAR".to_string(),
+	}
+    }
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>>
+{
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length) {
+	Ok(()) => {}
+	Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => { return Ok(None); }
+	Err(error) => { return Err(error); }
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME {
+	return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("frame of {} bytes", length)));
+    }
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> std::io::Result<()>
+{
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+fn handle_client(mut stream: TcpStream, sender: Sender<Received>)
+{
+    loop {
+	let error = match read_frame(&mut stream) {
+	    Ok(None) => { return; }
+	    Ok(Some(frame)) => {
+		match serde_json::from_slice::<Message>(&frame) {
+		    Ok(message) => {
+			if sender.send(Received::now(message.line())).is_err() {
+			    return;
+			}
+			continue;
+		    }
+		    Err(error) => error.to_string(),
+		}
+	    }
+	    Err(error) => error.to_string(),
+	};
+	warn!("push client failed: {}", error);
+	write_frame(&mut stream, json!({ "error": error }).to_string().as_bytes()).ok();
+	return;
+    }
+}
+
+// Accepts pushed messages on a TCP address like 0.0.0.0:7879,
+// from any number of clients at once
+pub struct PushConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+impl PushConnector
+{
+    pub fn new(address: &str) -> std::io::Result<PushConnector>
+    {
+	let listener = TcpListener::bind(address)?;
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    for stream in listener.incoming() {
+		match stream {
+		    Ok(stream) => {
+			info!("push client {:?} connected", stream.peer_addr());
+			let sender = s.clone();
+			thread::spawn(move || { handle_client(stream, sender); });
+		    }
+		    Err(error) => { warn!("push accept failed: {:?}", error); }
+		}
+	    }
+	});
+	Ok(PushConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn messages_become_protocol_lines() {
+	assert_eq!(serde_json::from_str::<Message>("{\"type\": \"data\", \"object\": \"Motor\", \"values\": [1.5, 2]}").unwrap().line(), "`Motor 1.5 2");
+	assert_eq!(Message::Scope{ name: "Motor".to_string(), samples: Some(500), size: None, pos: None }.line(), "`SCOPE Motor SAMPLES 500");
+	assert_eq!(Message::Clear{ object: None }.line(), "`CLEAR");
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	let (s, r) = unbounded();
+	thread::spawn(move || { handle_client(listener.accept().unwrap().0, s); });
+	let mut client = TcpStream::connect(address).unwrap();
+	write_frame(&mut client, b"{\"type\": \"signal\", \"object\": \"Motor\", \"name\": \"Current\", \"min\": 0, \"max\": 5}").unwrap();
+	write_frame(&mut client, b"{\"type\": \"bogus\"}").unwrap();
+	assert_eq!(r.recv().unwrap().line, "`Motor 'Current' 0 5 100 0");
+	let answer = String::from_utf8(read_frame(&mut client).unwrap().unwrap()).unwrap();
+	assert!(answer.starts_with("{\"error\":"));
+    }
+}
@@ -0,0 +1,107 @@
+use nannou::image::{RgbImage, Rgb as Pixel};
+
+type Color = nannou::color::Rgb<u8>;
+
+// A minimal software renderer, so charts can be produced
+// without a window or GPU.
+pub struct Canvas
+{
+    pub image: RgbImage,
+}
+
+impl Canvas
+{
+    pub fn new(width: u32, height: u32, background: Color) -> Canvas
+    {
+	Canvas{ image: RgbImage::from_pixel(width.max(1), height.max(1), Pixel([background.red, background.green, background.blue])) }
+    }
+
+    pub fn width(&self) -> f32
+    {
+	self.image.width() as f32
+    }
+
+    pub fn height(&self) -> f32
+    {
+	self.image.height() as f32
+    }
+
+    fn plot(&mut self, x: i64, y: i64, color: Color)
+    {
+	if x >= 0 && y >= 0 && (x as u32) < self.image.width() && (y as u32) < self.image.height() {
+	    self.image.put_pixel(x as u32, y as u32, Pixel([color.red, color.green, color.blue]));
+	}
+    }
+
+    // Bresenham, coordinates have their origin in the top left corner
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), color: Color)
+    {
+	let (mut x0, mut y0) = (from.0.round() as i64, from.1.round() as i64);
+	let (x1, y1) = (to.0.round() as i64, to.1.round() as i64);
+	let dx = (x1 - x0).abs();
+	let dy = -(y1 - y0).abs();
+	let sx = if x0 < x1 { 1 } else { -1 };
+	let sy = if y0 < y1 { 1 } else { -1 };
+	let mut error = dx + dy;
+	loop {
+	    self.plot(x0, y0, color);
+	    if x0 == x1 && y0 == y1 {
+		break;
+	    }
+	    let e2 = 2 * error;
+	    if e2 >= dy {
+		error += dy;
+		x0 += sx;
+	    }
+	    if e2 <= dx {
+		error += dx;
+		y0 += sy;
+	    }
+	}
+    }
+
+    pub fn polyline(&mut self, points: &[(f32, f32)], color: Color)
+    {
+	for pair in points.windows(2) {
+	    self.line(pair[0], pair[1], color);
+	}
+    }
+
+    pub fn frame(&mut self, color: Color)
+    {
+	let (w, h) = (self.width() - 1.0, self.height() - 1.0);
+	self.polyline(&[(0.0, 0.0), (w, 0.0), (w, h), (0.0, h), (0.0, 0.0)], color);
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()>
+    {
+	self.image.save(path).map_err(|error| { std::io::Error::new(std::io::ErrorKind::Other, error.to_string()) })
+    }
+
+    // PNG encoded, e.g. to embed into a report
+    pub fn png(&self) -> std::io::Result<Vec<u8>>
+    {
+	let mut bytes = vec![];
+	nannou::image::DynamicImage::ImageRgb8(self.image.clone()).write_to(&mut bytes, nannou::image::ImageOutputFormat::Png)
+	    .map_err(|error| { std::io::Error::new(std::io::ErrorKind::Other, error.to_string()) })?;
+	Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::prelude::{BLACK, WHITE};
+
+    #[test]
+    fn draw_lines() {
+	let mut canvas = Canvas::new(10, 10, BLACK);
+	canvas.line((0.0, 0.0), (9.0, 9.0), WHITE);
+	canvas.line((0.0, 9.0), (20.0, 9.0), WHITE);
+	for i in 0..10 {
+	    assert_eq!(canvas.image.get_pixel(i, i), &Pixel([255, 255, 255]));
+	    assert_eq!(canvas.image.get_pixel(i, 9), &Pixel([255, 255, 255]));
+	}
+	assert_eq!(canvas.image.get_pixel(9, 0), &Pixel([0, 0, 0]));
+    }
+}
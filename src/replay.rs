@@ -0,0 +1,246 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+use log::info;
+use crate::serial::Received;
+use crate::shutdown;
+
+// Replays a captured log at its original pace. Lines may be
+// prefixed with their capture time in seconds, e.g.
+//
+// 12.5 `MyScope 1 2 3
+//
+// speed scales the pace, lines without timestamp are fed at once.
+// The replay stays open after the last line so it can be rewound.
+pub struct ReplayConnector
+{
+    pub receiver: Receiver<Received>,
+    pub transport: Sender<Transport>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport
+{
+    Pause,
+    Resume,
+    Toggle,
+    // Pauses and feeds the next line
+    Step,
+    // Seconds after the first line of the capture
+    Seek(f64),
+    Speed(f64),
+    // Multiplies the speed
+    Scale(f64),
+}
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 10.0;
+
+// Decides which captured lines are due. Times are capture
+// seconds relative to the first timestamp.
+pub struct Playback
+{
+    lines: Vec<(f64, String)>,
+    position: usize,
+    time: f64,
+    speed: f64,
+    paused: bool,
+}
+
+impl Playback
+{
+    // Lines without timestamp share the time of their predecessor
+    pub fn new(content: &str, speed: f64) -> Playback
+    {
+	let mut first = None;
+	let mut time = 0.0;
+	let lines = content.lines()
+	    .map(|line| {
+		let (timestamp, content) = parse_timestamped(line);
+		if let Some(timestamp) = timestamp {
+		    time = (timestamp - *first.get_or_insert(timestamp)).max(time);
+		}
+		(time, content.to_string())
+	    })
+	    .collect();
+	Playback{ lines, position: 0, time: 0.0, speed: Playback::clamp(speed), paused: false }
+    }
+
+    fn clamp(speed: f64) -> f64
+    {
+	if speed > 0.0 { speed.max(MIN_SPEED).min(MAX_SPEED) } else { 1.0 }
+    }
+
+    pub fn paused(&self) -> bool
+    {
+	self.paused
+    }
+
+    pub fn speed(&self) -> f64
+    {
+	self.speed
+    }
+
+    pub fn time(&self) -> f64
+    {
+	self.time
+    }
+
+    pub fn duration(&self) -> f64
+    {
+	self.lines.last().map(|(time, _)| { *time }).unwrap_or(0.0)
+    }
+
+    fn due(&mut self) -> Vec<String>
+    {
+	let start = self.position;
+	while self.position < self.lines.len() && self.lines[self.position].0 <= self.time {
+	    self.position += 1;
+	}
+	self.lines[start..self.position].iter().map(|(_, line)| { line.clone() }).collect()
+    }
+
+    // Lines due after seconds of wall time
+    pub fn advance(&mut self, seconds: f64) -> Vec<String>
+    {
+	if self.paused {
+	    return vec![];
+	}
+	self.time = (self.time + seconds * self.speed).min(self.duration());
+	self.due()
+    }
+
+    // Wall seconds until the next line, None while nothing is due
+    pub fn until_next(&self) -> Option<f64>
+    {
+	if self.paused {
+	    return None;
+	}
+	self.lines.get(self.position).map(|(time, _)| { ((time - self.time) / self.speed).max(0.0) })
+    }
+
+    // Lines to feed right away. Seeking backwards starts over with
+    // a `CLEAR.
+    pub fn control(&mut self, transport: Transport) -> Vec<String>
+    {
+	match transport {
+	    Transport::Pause => { self.paused = true; }
+	    Transport::Resume => { self.paused = false; }
+	    Transport::Toggle => { self.paused = !self.paused; }
+	    Transport::Speed(speed) => { self.speed = Playback::clamp(speed); }
+	    Transport::Scale(factor) => { self.speed = Playback::clamp(self.speed * factor); }
+	    Transport::Step => {
+		self.paused = true;
+		if let Some((time, line)) = self.lines.get(self.position) {
+		    self.time = *time;
+		    self.position += 1;
+		    return vec![line.clone()];
+		}
+	    }
+	    Transport::Seek(time) => {
+		let time = time.max(0.0).min(self.duration());
+		let mut result = vec![];
+		if time < self.time || (self.position > 0 && time < self.lines[self.position - 1].0) {
+		    self.position = 0;
+		    result.push("`CLEAR".to_string());
+		}
+		self.time = time;
+		result.extend(self.due());
+		return result;
+	    }
+	}
+	vec![]
+    }
+}
+
+// Splits off a leading timestamp
+pub fn parse_timestamped(line: &str) -> (Option<f64>, &str)
+{
+    let line = line.trim_end_matches('\r');
+    if let Some(position) = line.find(char::is_whitespace) {
+	if let Ok(timestamp) = line[..position].parse::<f64>() {
+	    return (Some(timestamp), line[position..].trim_start());
+	}
+    }
+    (None, line)
+}
+
+impl ReplayConnector
+{
+    pub fn new(path: &str, speed: f64) -> std::io::Result<ReplayConnector>
+    {
+	let mut playback = Playback::new(&std::fs::read_to_string(path)?, speed);
+	let (s, r) = unbounded();
+	let (transport, commands) = unbounded();
+	thread::spawn(move || {
+	    let mut last = Instant::now();
+	    let mut lines = playback.advance(0.0);
+	    loop {
+		for line in lines.drain(..) {
+		    if s.send(Received::now(line)).is_err() {
+			return;
+		    }
+		}
+		// Wake up now and then to notice a shutdown
+		let timeout = playback.until_next().map(Duration::from_secs_f64).unwrap_or(Duration::from_millis(100));
+		match commands.recv_timeout(timeout.min(Duration::from_millis(100))) {
+		    Ok(command) => {
+			lines = playback.control(command);
+			info!("replay at {:.3}s of {:.3}s, {}x{}", playback.time(), playback.duration(), playback.speed(),
+			      if playback.paused() { ", paused" } else { "" });
+		    }
+		    Err(RecvTimeoutError::Timeout) => {}
+		    Err(RecvTimeoutError::Disconnected) => { return; }
+		}
+		if shutdown::requested() {
+		    return;
+		}
+		let now = Instant::now();
+		lines.extend(playback.advance((now - last).as_secs_f64()));
+		last = now;
+	    }
+	});
+	Ok(ReplayConnector{ receiver: r, transport })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn split_timestamps() {
+	assert_eq!(parse_timestamped("12.5 `MyScope 1 2"), (Some(12.5), "`MyScope 1 2"));
+	assert_eq!(parse_timestamped("`MyScope 1 2\r"), (None, "`MyScope 1 2"));
+    }
+
+    #[test]
+    fn replay_keeps_pace() {
+	let path = std::env::temp_dir().join(format!("rusty-peanut-replay-{}.log", std::process::id()));
+	std::fs::write(&path, "`SCOPE MyScope\n10.0 `MyScope 1\n10.2 `MyScope 2\n").unwrap();
+	let start = Instant::now();
+	let connector = ReplayConnector::new(&path.to_string_lossy(), 2.0).unwrap();
+	let lines: Vec<String> = connector.receiver.iter().take(3).map(|received| { received.line }).collect();
+	assert!(start.elapsed() >= Duration::from_millis(100));
+	assert_eq!(lines, vec!["`SCOPE MyScope", "`MyScope 1", "`MyScope 2"]);
+	std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn transport_steps_and_seeks() {
+	let mut playback = Playback::new("`SCOPE MyScope\n10.0 `MyScope 1\n`MyScope 2\n11.0 `MyScope 3\n", 100.0);
+	assert_eq!(playback.speed(), MAX_SPEED);
+	assert_eq!(playback.advance(0.0), vec!["`SCOPE MyScope", "`MyScope 1", "`MyScope 2"]);
+	assert_eq!(playback.until_next(), Some(0.1));
+	assert_eq!(playback.control(Transport::Step), vec!["`MyScope 3"]);
+	assert!(playback.paused());
+	assert_eq!(playback.advance(1.0), Vec::<String>::new());
+	assert_eq!(playback.control(Transport::Seek(0.5)), vec!["`CLEAR", "`SCOPE MyScope", "`MyScope 1", "`MyScope 2"]);
+	playback.control(Transport::Speed(0.01));
+	assert_eq!(playback.speed(), MIN_SPEED);
+	playback.control(Transport::Resume);
+	assert_eq!(playback.advance(4.0), Vec::<String>::new());
+	assert_eq!(playback.advance(1.0), vec!["`MyScope 3"]);
+    }
+}
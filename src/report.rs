@@ -0,0 +1,113 @@
+use log::info;
+
+use crate::base64;
+use crate::debugobjects::{DebugObjects, DebugObjectError};
+use crate::export::{replay, ExportError};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }\n\
+table { border-collapse: collapse; margin: 1em 0; }\n\
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n\
+td.number { text-align: right; }\n\
+img { display: block; background: black; }\n";
+
+fn escape(text: &str) -> String
+{
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn row(cells: &[String], number_from: usize) -> String
+{
+    let cells: Vec<String> = cells.iter().enumerate()
+	.map(|(index, cell)| {
+	    if index >= number_from {
+		format!("<td class=\"number\">{}</td>", escape(cell))
+	    } else {
+		format!("<td>{}</td>", escape(cell))
+	    }
+	})
+	.collect();
+    format!("<tr>{}</tr>\n", cells.concat())
+}
+
+fn header(names: &[&str]) -> String
+{
+    let cells: Vec<String> = names.iter().map(|name| { format!("<th>{}</th>", name) }).collect();
+    format!("<tr>{}</tr>\n", cells.concat())
+}
+
+// A single HTML file with the charts embedded, to be mailed around
+pub fn render(views: &DebugObjects, title: &str, metadata: &[(String, String)]) -> Result<String, DebugObjectError>
+{
+    let format = views.number_format();
+    let optional = |value: Option<f32>| { value.map(|value| { format.format(value, None) }).unwrap_or_default() };
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+			   escape(title), STYLE, escape(title));
+    html.push_str("<table>\n");
+    for (key, value) in metadata {
+	html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", escape(key), escape(value)));
+    }
+    html.push_str("</table>\n");
+    for name in views.names() {
+	html.push_str(&format!("<h2>{}</h2>\n", escape(&name)));
+	// Objects without a software renderer only get their table
+	match views.png(&name) {
+	    Ok(png) => { html.push_str(&format!("<img alt=\"{}\" src=\"data:image/png;base64,{}\">\n", escape(&name), base64::encode(&png))); }
+	    Err(DebugObjectError::Unsupported(_)) => {}
+	    Err(error) => { return Err(error); }
+	}
+	html.push_str("<table>\n");
+	html.push_str(&header(&["Signal", "Samples", "Min", "Max", "Mean"]));
+	for stats in views.signal_stats(&name)? {
+	    html.push_str(&row(&[stats.name.clone(), stats.count.to_string(), optional(stats.min), optional(stats.max), optional(stats.mean)], 1));
+	}
+	html.push_str("</table>\n");
+    }
+    html.push_str("<h2>Alarms</h2>\n");
+    let events = views.alarms().events();
+    if events.is_empty() {
+	html.push_str("<p>None</p>\n");
+    } else {
+	html.push_str("<table>\n");
+	html.push_str(&header(&["Time (UTC)", "Kind", "Object", "Signal", "Value"]));
+	for event in events {
+	    html.push_str(&row(&[event.time_of_day(), event.kind.to_string(), event.scope.clone(), event.signal.clone(), format.format(event.value, None)], 4));
+	}
+	html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+// Entry point of `rusty-peanut report --input capture.log --output report.html`
+pub fn run(input: &str, output: &str, title: &str) -> Result<(), ExportError>
+{
+    let mut views = DebugObjects::new();
+    let count = replay(&mut views, input)?;
+    info!("replayed {} lines from {}", count, input);
+    let metadata = vec![
+	("Input".to_string(), input.to_string()),
+	("Lines".to_string(), count.to_string()),
+	("Objects".to_string(), views.names().len().to_string()),
+	("Gaps".to_string(), views.gaps().len().to_string()),
+    ];
+    std::fs::write(output, render(&views, title, &metadata)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn report_embeds_charts_and_tables() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SIZE 64 32 SAMPLES 16\n`MyScope 'Saw<tooth>' 0 63 32 0\n`MyScope 10\n`MyScope 20\n");
+	let html = render(&views, "Bench & test", &[("Input".to_string(), "capture.log".to_string())]).unwrap();
+	assert!(html.contains("<title>Bench &amp; test</title>"));
+	assert!(html.contains("<th>Input</th><td>capture.log</td>"));
+	assert!(html.contains("<img alt=\"MyScope\" src=\"data:image/png;base64,iVBORw0KGgo"));
+	assert!(html.contains("<td>Saw&lt;tooth&gt;</td><td class=\"number\">2</td>"));
+	assert!(html.contains("<p>None</p>"));
+    }
+}
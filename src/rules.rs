@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+fn yes() -> bool
+{
+    true
+}
+
+// A rule of the configuration, e.g.
+//
+// [[rules]]
+// name = "stall"
+// when = "Motor.Current > 2.0 && Velocity < 10 for 100ms"
+// capture = ["Motor"]
+// send = "STOP"
+//
+// Signals are named OBJECT.SIGNAL or just SIGNAL. A firing rule
+// raises an alarm, writes the captured objects as CSV into the
+// capture directory and sends a line to the input.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct RuleConfig
+{
+    pub name: String,
+    pub when: String,
+    #[serde(default = "yes")]
+    pub alarm: bool,
+    #[serde(default)]
+    pub capture: Vec<String>,
+    pub send: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison
+{
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison
+{
+    fn apply(self, a: f32, b: f32) -> bool
+    {
+	match self {
+	    Comparison::Less => a < b,
+	    Comparison::LessEqual => a <= b,
+	    Comparison::Greater => a > b,
+	    Comparison::GreaterEqual => a >= b,
+	    Comparison::Equal => a == b,
+	    Comparison::NotEqual => a != b,
+	}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand
+{
+    Number(f32),
+    Signal(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition
+{
+    Compare(Operand, Comparison, Operand),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token
+{
+    Number(f32),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 11] = ["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "(", ")"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String>
+{
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+	if let Some(symbol) = SYMBOLS.iter().find(|symbol| { rest.starts_with(*symbol) }) {
+	    tokens.push(Token::Symbol(symbol));
+	    rest = &rest[symbol.len()..];
+	} else {
+	    let end = rest.find(|c: char| { c.is_whitespace() || "&|<>=!()".contains(c) }).unwrap_or(rest.len());
+	    if end == 0 {
+		return Err(format!("unexpected {}", rest));
+	    }
+	    let word = &rest[..end];
+	    let numeric = word.starts_with(|c: char| { c.is_ascii_digit() || c == '-' || c == '+' || c == '.' });
+	    tokens.push(match word.parse::<f32>() {
+		Ok(number) if numeric => Token::Number(number),
+		_ if numeric => { return Err(format!("invalid number {}", word)); }
+		_ => Token::Name(word.to_string()),
+	    });
+	    rest = &rest[end..];
+	}
+	rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Recursive descent, && binds tighter than ||
+struct Parser
+{
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser
+{
+    fn peek(&self) -> Option<&Token>
+    {
+	self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token>
+    {
+	let token = self.tokens.get(self.position).cloned();
+	self.position += 1;
+	token
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool
+    {
+	match self.peek() {
+	    Some(Token::Symbol(found)) if *found == symbol => {
+		self.position += 1;
+		true
+	    }
+	    _ => false,
+	}
+    }
+
+    fn or(&mut self) -> Result<Condition, String>
+    {
+	let mut condition = self.and()?;
+	while self.accept("||") {
+	    condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+	}
+	Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String>
+    {
+	let mut condition = self.unary()?;
+	while self.accept("&&") {
+	    condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+	}
+	Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String>
+    {
+	if self.accept("!") {
+	    return Ok(Condition::Not(Box::new(self.unary()?)));
+	}
+	if self.accept("(") {
+	    let condition = self.or()?;
+	    if !self.accept(")") {
+		return Err("missing )".to_string());
+	    }
+	    return Ok(condition);
+	}
+	let left = self.operand()?;
+	let comparison = match self.next() {
+	    Some(Token::Symbol("<")) => Comparison::Less,
+	    Some(Token::Symbol("<=")) => Comparison::LessEqual,
+	    Some(Token::Symbol(">")) => Comparison::Greater,
+	    Some(Token::Symbol(">=")) => Comparison::GreaterEqual,
+	    Some(Token::Symbol("==")) => Comparison::Equal,
+	    Some(Token::Symbol("!=")) => Comparison::NotEqual,
+	    other => { return Err(format!("expected a comparison, got {:?}", other)); }
+	};
+	Ok(Condition::Compare(left, comparison, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String>
+    {
+	match self.next() {
+	    Some(Token::Number(number)) => Ok(Operand::Number(number)),
+	    Some(Token::Name(name)) => Ok(Operand::Signal(name)),
+	    other => Err(format!("expected a signal or number, got {:?}", other)),
+	}
+    }
+}
+
+impl Condition
+{
+    // None while a signal hasn't got a value yet
+    fn evaluate(&self, values: &HashMap<String, f32>) -> Option<bool>
+    {
+	let value = |operand: &Operand| {
+	    match operand {
+		Operand::Number(number) => Some(*number),
+		Operand::Signal(name) => values.get(name).cloned(),
+	    }
+	};
+	match self {
+	    Condition::Compare(a, comparison, b) => Some(comparison.apply(value(a)?, value(b)?)),
+	    Condition::Not(condition) => condition.evaluate(values).map(|holds| { !holds }),
+	    Condition::And(a, b) => Some(a.evaluate(values)? && b.evaluate(values)?),
+	    Condition::Or(a, b) => Some(a.evaluate(values)? || b.evaluate(values)?),
+	}
+    }
+}
+
+// Splits off a trailing "for 100ms" or "for 2s", in seconds
+fn split_duration(text: &str) -> Result<(&str, f64), String>
+{
+    let position = match text.rfind(" for ") {
+	Some(position) => position,
+	None => { return Ok((text, 0.0)); }
+    };
+    let duration = text[position + 5..].trim();
+    let seconds = if let Some(milliseconds) = duration.strip_suffix("ms") {
+	milliseconds.parse::<f64>().map(|milliseconds| { milliseconds / 1000.0 })
+    } else if let Some(seconds) = duration.strip_suffix('s') {
+	seconds.parse::<f64>()
+    } else {
+	return Err(format!("invalid duration {}", duration));
+    };
+    Ok((&text[..position], seconds.map_err(|_| { format!("invalid duration {}", duration) })?))
+}
+
+// What a fired rule asks for beyond the alarm
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fired
+{
+    pub rule: String,
+    pub capture: Vec<String>,
+    pub send: Option<String>,
+}
+
+pub struct Rule
+{
+    pub config: RuleConfig,
+    condition: Condition,
+    // Seconds the condition has to hold
+    hold: f64,
+    // Since when the condition holds
+    since: Option<f64>,
+    // Fired and waiting for the condition to end
+    fired: bool,
+}
+
+impl Rule
+{
+    pub fn new(config: RuleConfig) -> Result<Rule, String>
+    {
+	let (text, hold) = split_duration(&config.when)?;
+	let mut parser = Parser{ tokens: tokenize(text)?, position: 0 };
+	let condition = parser.or()?;
+	if let Some(token) = parser.peek() {
+	    return Err(format!("unexpected {:?} in rule {}", token, config.name));
+	}
+	Ok(Rule{ config, condition, hold, since: None, fired: false })
+    }
+
+    // From `RULE name condition, raising alarms only
+    pub fn from_tokens(tokens: &[String]) -> Result<Rule, String>
+    {
+	let (name, when) = tokens.split_first().ok_or("RULE needs a name")?;
+	Rule::new(RuleConfig{ name: name.to_string(), when: when.join(" "), alarm: true, capture: vec![], send: None })
+    }
+
+    // Fires once per period the condition holds long enough
+    pub fn update(&mut self, values: &HashMap<String, f32>, time: f64) -> bool
+    {
+	if self.condition.evaluate(values) != Some(true) {
+	    self.since = None;
+	    self.fired = false;
+	    return false;
+	}
+	let since = *self.since.get_or_insert(time);
+	if !self.fired && time - since >= self.hold {
+	    self.fired = true;
+	    return true;
+	}
+	false
+    }
+
+    pub fn fired(&self) -> Fired
+    {
+	Fired{ rule: self.config.name.clone(), capture: self.config.capture.clone(), send: self.config.send.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    fn values(pairs: &[(&str, f32)]) -> HashMap<String, f32>
+    {
+	pairs.iter().map(|(name, value)| { (name.to_string(), *value) }).collect()
+    }
+
+    #[test]
+    fn conditions_held_long_enough_fire_once() {
+	let mut rule = Rule::new(RuleConfig{ name: "stall".to_string(), when: "Motor.Current > 2.0 && Velocity < 10 for 100ms".to_string(), alarm: true, capture: vec![], send: None }).unwrap();
+	assert!(!rule.update(&values(&[("Motor.Current", 3.0)]), 0.0));
+	let stalled = values(&[("Motor.Current", 3.0), ("Velocity", 5.0)]);
+	assert!(!rule.update(&stalled, 1.0));
+	assert!(!rule.update(&stalled, 1.05));
+	assert!(rule.update(&stalled, 1.1));
+	assert!(!rule.update(&stalled, 1.2));
+	assert!(!rule.update(&values(&[("Motor.Current", 1.0), ("Velocity", 5.0)]), 1.3));
+	assert!(!rule.update(&stalled, 1.4));
+	let mut rule = Rule::from_tokens(&["hot".to_string(), "!(T".to_string(), "<=".to_string(), "-5)".to_string(), "||".to_string(), "Fault".to_string(), "==".to_string(), "1".to_string()]).unwrap();
+	assert!(rule.update(&values(&[("T", 0.0), ("Fault", 0.0)]), 0.0));
+	assert!(Rule::from_tokens(&["broken".to_string(), "A".to_string(), ">".to_string()]).is_err());
+	assert_eq!(tokenize("T > +5"), tokenize("T > 5"));
+	assert!(tokenize("T > -+5").is_err());
+	assert_eq!(split_duration("A > 1 for 2s"), Ok(("A > 1", 2.0)));
+	assert!(split_duration("A > 1 for ever").is_err());
+    }
+}
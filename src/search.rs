@@ -0,0 +1,141 @@
+use nannou::prelude::*;
+
+type Rect = nannou::geom::rect::Rect;
+
+// Entries listed at most, the query narrows them down
+const VISIBLE_MATCHES: usize = 12;
+
+// Higher is better, None if the query isn't a subsequence.
+// Consecutive characters and word starts score extra.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32>
+{
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| { !c.is_whitespace() }) {
+	let found = (position..candidate.len())
+	    .find(|index| { candidate[*index].to_lowercase().eq(wanted.to_lowercase()) })?;
+	score += 1;
+	if previous.map(|previous| { previous + 1 == found }).unwrap_or(false) {
+	    score += 2;
+	}
+	if found == 0 || !candidate[found - 1].is_alphanumeric() {
+	    score += 3;
+	}
+	previous = Some(found);
+	position = found + 1;
+    }
+    Some(score)
+}
+
+// Ctrl+P finder over all Object.Signal pairs
+pub struct SearchPalette
+{
+    pub query: String,
+    pub selected: usize,
+    // Object and signal, snapshot taken when opened
+    entries: Vec<(String, String)>,
+}
+
+impl SearchPalette
+{
+    pub fn new(entries: Vec<(String, String)>) -> SearchPalette
+    {
+	SearchPalette{ query: String::new(), selected: 0, entries }
+    }
+
+    pub fn matches(&self) -> Vec<&(String, String)>
+    {
+	let mut scored: Vec<(i32, &(String, String))> = self.entries.iter()
+	    .filter_map(|entry| { fuzzy_score(&self.query, &format!("{}.{}", entry.0, entry.1)).map(|score| { (score, entry) }) })
+	    .collect();
+	// Stable, so equal scores keep the order of declaration
+	scored.sort_by(|a, b| { b.0.cmp(&a.0) });
+	scored.into_iter().map(|(_, entry)| { entry }).collect()
+    }
+
+    pub fn push(&mut self, character: char)
+    {
+	self.query.push(character);
+	self.selected = 0;
+    }
+
+    pub fn pop(&mut self)
+    {
+	self.query.pop();
+	self.selected = 0;
+    }
+
+    pub fn up(&mut self)
+    {
+	self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self)
+    {
+	if self.selected + 1 < self.matches().len().min(VISIBLE_MATCHES) {
+	    self.selected += 1;
+	}
+    }
+
+    pub fn current(&self) -> Option<(String, String)>
+    {
+	self.matches().get(self.selected).map(|entry| { (*entry).clone() })
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect)
+    {
+	let line_height = 18.0;
+	let matches = self.matches();
+	let shown = matches.len().min(VISIBLE_MATCHES);
+	let rect = Rect::from_w_h(rect.w().min(400.0), line_height * (shown as f32 + 2.0) + 4.0).align_top_of(rect);
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.9)).stroke(YELLOW).stroke_weight(1.0);
+	let mut lines = vec![(format!("> {}", self.query), YELLOW)];
+	if matches.is_empty() {
+	    lines.push(("no matching signal".to_string(), RED));
+	}
+	for (index, (object, signal)) in matches.iter().take(shown).enumerate() {
+	    lines.push((format!("{}.{}", object, signal), if index == self.selected { WHITE } else { GRAY }));
+	}
+	lines.push(("Return: focus   Tab: solo   Esc: close".to_string(), GRAY));
+	for (index, (line, color)) in lines.iter().enumerate() {
+	    draw.text(line)
+		.x_y(rect.x(), rect.top() - 2.0 - line_height * (index as f32 + 0.5))
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(14)
+		.left_justify()
+		.color(*color);
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn finds_signals_fuzzily() {
+	assert_eq!(fuzzy_score("tmp", "Board.Temp"), Some(8));
+	assert_eq!(fuzzy_score("x", "Board.Temp"), None);
+	let entries = vec![
+	    ("Power".to_string(), "Temperature".to_string()),
+	    ("Board".to_string(), "Temp".to_string()),
+	    ("Board".to_string(), "Voltage".to_string()),
+	];
+	let mut palette = SearchPalette::new(entries);
+	assert_eq!(palette.matches().len(), 3);
+	for c in "b.temp".chars() {
+	    palette.push(c);
+	}
+	assert_eq!(palette.current(), Some(("Board".to_string(), "Temp".to_string())));
+	palette.down();
+	assert_eq!(palette.selected, 0);
+	for _ in 0..5 {
+	    palette.pop();
+	}
+	assert_eq!(palette.query, "b");
+	assert_eq!(palette.matches().len(), 2);
+    }
+}
@@ -1,69 +1,736 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
-use std::time::Duration;
-use crossbeam::channel::{Receiver, unbounded};
+use std::time::{Duration, Instant};
+use crossbeam::channel::{Receiver, Sender, TrySendError, bounded, unbounded};
+use log::warn;
+use serde::Deserialize;
+use crate::shutdown;
+use crate::cobs::CobsProtocol;
+use crate::mavlink::MavlinkProtocol;
+use crate::checksum::{self, ChecksumMode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState
+{
+    Connected,
+    // The port vanished, e.g. the adapter was unplugged
+    Reconnecting,
+}
+
+// The modem line wired to the MCU's reset, DTR on Arduinos,
+// RTS to EN on ESP32 boards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetLine
+{
+    Dtr,
+    Rts,
+}
+
+impl std::str::FromStr for ResetLine
+{
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<ResetLine, String>
+    {
+	match line {
+	    "dtr" => Ok(ResetLine::Dtr),
+	    "rts" => Ok(ResetLine::Rts),
+	    _ => Err(format!("unknown reset line {}", line)),
+	}
+    }
+}
+
+// Requests to the reader thread, which owns the port
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortCommand
+{
+    Reset(ResetLine),
+    // Bytes for the target, e.g. a command line
+    Write(Vec<u8>),
+}
+
+// How long the reset line is asserted
+const RESET_PULSE: Duration = Duration::from_millis(100);
+
+fn pulse(port: &mut Box<dyn serialport::SerialPort>, line: ResetLine) -> Result<(), serialport::Error>
+{
+    let mut set = |level| {
+	match line {
+	    ResetLine::Dtr => port.write_data_terminal_ready(level),
+	    ResetLine::Rts => port.write_request_to_send(level),
+	}
+    };
+    set(true)?;
+    thread::sleep(RESET_PULSE);
+    set(false)
+}
+
+// What happens to a line when the UI doesn't keep up and the
+// queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DropPolicy
+{
+    // The display catches up with the device, losing history
+    DropOldest,
+    DropNewest,
+}
+
+impl std::str::FromStr for DropPolicy
+{
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<DropPolicy, String>
+    {
+	match policy {
+	    "drop-oldest" => Ok(DropPolicy::DropOldest),
+	    "drop-newest" => Ok(DropPolicy::DropNewest),
+	    _ => Err(format!("unknown drop policy {}", policy)),
+	}
+    }
+}
+
+// What ends a protocol line
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEnding
+{
+    // Either of CR, LF or CRLF, for firmware printing just \n
+    Auto,
+    Lf,
+    Cr,
+    CrLf,
+}
+
+impl std::str::FromStr for LineEnding
+{
+    type Err = String;
+
+    fn from_str(ending: &str) -> Result<LineEnding, String>
+    {
+	match ending {
+	    "auto" => Ok(LineEnding::Auto),
+	    "lf" => Ok(LineEnding::Lf),
+	    "cr" => Ok(LineEnding::Cr),
+	    "crlf" => Ok(LineEnding::CrLf),
+	    _ => Err(format!("unknown line ending {}", ending)),
+	}
+    }
+}
+
+// How protocol lines arrive over the wire
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Framing
+{
+    Text,
+    // COBS frames carrying lines or packed samples, see cobs.rs
+    Cobs,
+    // MAVLink telemetry, for the mavlink dialect
+    Mavlink,
+}
+
+impl std::str::FromStr for Framing
+{
+    type Err = String;
+
+    fn from_str(framing: &str) -> Result<Framing, String>
+    {
+	match framing {
+	    "text" => Ok(Framing::Text),
+	    "cobs" => Ok(Framing::Cobs),
+	    "mavlink" => Ok(Framing::Mavlink),
+	    _ => Err(format!("unknown framing {}", framing)),
+	}
+    }
+}
+
+// Lines buffered between the reader and the UI, at 230400 baud
+// a few seconds worth of short lines
+pub const DEFAULT_QUEUE: usize = 65_536;
+
+// Data bits, parity and stop bits of the serial line, written
+// like 8N1 or 7E1
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CharacterFormat
+{
+    pub data_bits: serialport::DataBits,
+    pub parity: serialport::Parity,
+    pub stop_bits: serialport::StopBits,
+}
+
+impl Default for CharacterFormat
+{
+    fn default() -> Self
+    {
+	CharacterFormat{ data_bits: serialport::DataBits::Eight, parity: serialport::Parity::None, stop_bits: serialport::StopBits::One }
+    }
+}
+
+impl std::str::FromStr for CharacterFormat
+{
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<CharacterFormat, String>
+    {
+	let invalid = || { format!("unknown character format {}, expected e.g. 8N1 or 7E1", format) };
+	let chars: Vec<char> = format.to_uppercase().chars().collect();
+	if chars.len() != 3 {
+	    return Err(invalid());
+	}
+	let data_bits = match chars[0] {
+	    '5' => serialport::DataBits::Five,
+	    '6' => serialport::DataBits::Six,
+	    '7' => serialport::DataBits::Seven,
+	    '8' => serialport::DataBits::Eight,
+	    _ => { return Err(invalid()); }
+	};
+	let parity = match chars[1] {
+	    'N' => serialport::Parity::None,
+	    'E' => serialport::Parity::Even,
+	    'O' => serialport::Parity::Odd,
+	    _ => { return Err(invalid()); }
+	};
+	let stop_bits = match chars[2] {
+	    '1' => serialport::StopBits::One,
+	    '2' => serialport::StopBits::Two,
+	    _ => { return Err(invalid()); }
+	};
+	Ok(CharacterFormat{ data_bits, parity, stop_bits })
+    }
+}
+
+impl std::convert::TryFrom<String> for CharacterFormat
+{
+    type Error = String;
+
+    fn try_from(format: String) -> Result<CharacterFormat, String>
+    {
+	format.parse()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlowControl
+{
+    None,
+    // RTS/CTS
+    Hardware,
+    // XON/XOFF
+    Software,
+}
+
+impl std::str::FromStr for FlowControl
+{
+    type Err = String;
+
+    fn from_str(flow: &str) -> Result<FlowControl, String>
+    {
+	match flow {
+	    "none" => Ok(FlowControl::None),
+	    "hardware" => Ok(FlowControl::Hardware),
+	    "software" => Ok(FlowControl::Software),
+	    _ => Err(format!("unknown flow control {}", flow)),
+	}
+    }
+}
+
+// Everything about the serial line besides the baud rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineSettings
+{
+    pub format: CharacterFormat,
+    pub flow: FlowControl,
+}
+
+impl Default for LineSettings
+{
+    fn default() -> Self
+    {
+	LineSettings{ format: CharacterFormat::default(), flow: FlowControl::None }
+    }
+}
+
+// A port with the line settings applied
+pub(crate) fn open_port(path: &str, baud: u32, line: LineSettings, timeout: Duration) -> Result<Box<dyn serialport::SerialPort>, serialport::Error>
+{
+    let flow = match line.flow {
+	FlowControl::None => serialport::FlowControl::None,
+	FlowControl::Hardware => serialport::FlowControl::Hardware,
+	FlowControl::Software => serialport::FlowControl::Software,
+    };
+    serialport::new(path, baud)
+	.data_bits(line.format.data_bits)
+	.parity(line.format.parity)
+	.stop_bits(line.format.stop_bits)
+	.flow_control(flow)
+	.timeout(timeout)
+	.open()
+}
+
+// How the reader thread is scheduled and buffers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IngestOptions
+{
+    // Raise the thread priority as far as the OS allows
+    pub realtime: bool,
+    // Pin the thread to this core
+    pub core: Option<usize>,
+    // Lines the queue to the UI holds
+    pub queue: usize,
+    pub drop: DropPolicy,
+    pub ending: LineEnding,
+    pub framing: Framing,
+    pub checksum: ChecksumMode,
+}
+
+impl Default for IngestOptions
+{
+    fn default() -> Self
+    {
+	IngestOptions{ realtime: false, core: None, queue: DEFAULT_QUEUE, drop: DropPolicy::DropOldest, ending: LineEnding::Auto, framing: Framing::Text, checksum: ChecksumMode::Off }
+    }
+}
+
+// Counters of the reader thread. A read filling the whole buffer
+// means data was waiting, so the reader is falling behind.
+#[derive(Debug, Default)]
+pub struct IngestStats
+{
+    pub bytes: AtomicU64,
+    pub lines: AtomicU64,
+    pub full_reads: AtomicU64,
+    pub invalid_lines: AtomicU64,
+    // ITM overflow packets of an SWO input
+    pub overflows: AtomicU64,
+    // Lines discarded because the queue to the UI was full
+    pub dropped: AtomicU64,
+    // Lines discarded for a wrong or missing checksum
+    pub bad_checksums: AtomicU64,
+}
+
+impl IngestStats
+{
+    // Signs that data got lost or is about to
+    pub fn overruns(&self) -> u64
+    {
+	self.full_reads.load(Ordering::Relaxed) + self.invalid_lines.load(Ordering::Relaxed) + self.overflows.load(Ordering::Relaxed)
+	    + self.dropped.load(Ordering::Relaxed) + self.bad_checksums.load(Ordering::Relaxed)
+    }
+}
+
+// Sending side of the bounded queue to the UI. Dropping the oldest
+// line needs the receiving end too.
+pub(crate) struct LineQueue
+{
+    sender: Sender<Received>,
+    // Dropping the oldest line needs a receiver of our own, so the
+    // channel never disconnects and the UI side reports its drop
+    receiver: Receiver<Received>,
+    closed: Arc<AtomicBool>,
+    drop: DropPolicy,
+}
+
+// The UI side of a LineQueue
+pub struct QueueReceiver
+{
+    receiver: Receiver<Received>,
+    closed: Arc<AtomicBool>,
+}
+
+impl std::ops::Deref for QueueReceiver
+{
+    type Target = Receiver<Received>;
+
+    fn deref(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+impl Drop for QueueReceiver
+{
+    fn drop(&mut self)
+    {
+	self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl LineQueue
+{
+    pub(crate) fn new(options: &IngestOptions) -> (LineQueue, QueueReceiver)
+    {
+	let (s, r) = bounded(options.queue.max(1));
+	let closed = Arc::new(AtomicBool::new(false));
+	(LineQueue{ sender: s, receiver: r.clone(), closed: closed.clone(), drop: options.drop }, QueueReceiver{ receiver: r, closed })
+    }
+
+    // Errs once the UI side is gone
+    pub(crate) fn send(&self, received: Received, stats: &IngestStats) -> Result<(), ()>
+    {
+	if self.closed.load(Ordering::Relaxed) {
+	    return Err(());
+	}
+	let mut received = received;
+	loop {
+	    match self.sender.try_send(received) {
+		Ok(()) => { return Ok(()); }
+		Err(TrySendError::Full(rejected)) => {
+		    stats.dropped.fetch_add(1, Ordering::Relaxed);
+		    match self.drop {
+			DropPolicy::DropNewest => { return Ok(()); }
+			DropPolicy::DropOldest => {
+			    self.receiver.try_recv().ok();
+			    received = rejected;
+			}
+		    }
+		}
+		Err(TrySendError::Disconnected(_)) => { return Err(()); }
+	    }
+	}
+    }
+}
+
+// A line stamped by the reader thread, before any queueing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Received
+{
+    pub line: String,
+    pub at: Instant,
+}
+
+impl Received
+{
+    pub fn now(line: String) -> Received
+    {
+	Received{ line, at: Instant::now() }
+    }
+}
 
 pub struct SerialConnector
 {
-    pub receiver: Receiver<String>
+    pub receiver: QueueReceiver,
+    // Changes of the connection state
+    pub status: Receiver<ConnectionState>,
+    pub stats: Arc<IngestStats>,
+    // Resets and writes through the reader thread
+    pub commands: Sender<PortCommand>,
+}
+
+fn tune_thread(options: &IngestOptions)
+{
+    if let Some(core) = options.core {
+	match core_affinity::get_core_ids().and_then(|ids| { ids.into_iter().find(|id| { id.id == core }) }) {
+	    Some(id) => { core_affinity::set_for_current(id); }
+	    None => { warn!("can't pin the serial reader to core {}", core); }
+	}
+    }
+    if options.realtime {
+	if let Err(error) = thread_priority::set_current_thread_priority(thread_priority::ThreadPriority::Max) {
+	    warn!("can't raise the serial reader priority: {:?}", error);
+	}
+    }
+}
+
+// Delay before the given retry to reopen the port
+pub(crate) fn backoff(attempt: u32) -> Duration
+{
+    Duration::from_millis((250u64 << attempt.min(5)).min(5000))
+}
+
+// How often a missing device node is looked for
+const PLUG_POLL: Duration = Duration::from_millis(250);
+
+// Whether a device node exists. Ports without one, like COM ports,
+// count as present and are found by trying to open them.
+fn present(path: &str) -> bool
+{
+    !cfg!(unix) || std::path::Path::new(path).exists()
 }
 
-struct LineProtocol
+// The port isn't there, as opposed to being there but failing
+fn missing(error: &serialport::Error) -> bool
 {
-    bytes: Vec<u8>
+    match error.kind() {
+	serialport::ErrorKind::NoDevice => true,
+	serialport::ErrorKind::Io(kind) => kind == std::io::ErrorKind::NotFound,
+	_ => false,
+    }
+}
+
+pub(crate) struct LineProtocol
+{
+    bytes: Vec<u8>,
+    ending: LineEnding,
+    // A CR just ended a line, in auto mode a LF following it belongs to it
+    after_cr: bool,
+    // Lines dropped for not being UTF-8, usually garbled by an overrun
+    pub(crate) invalid: u64,
 }
 
 impl LineProtocol
 {
-    fn new() -> LineProtocol
+    pub(crate) fn new(ending: LineEnding) -> LineProtocol
     {
-	LineProtocol{ bytes: vec![] }
+	LineProtocol{ bytes: vec![], ending, after_cr: false, invalid: 0 }
     }
 
-    fn feed<F>(&mut self, buffer: &[u8], mut func: F) where F: FnMut(&str)
+    // Drops a partial line, it won't be continued after a reconnect
+    fn reset(&mut self)
+    {
+	self.bytes.clear();
+	self.after_cr = false;
+    }
+
+    pub(crate) fn feed<F>(&mut self, buffer: &[u8], mut func: F) where F: FnMut(&str)
     {
 	for c in buffer {
-	    self.bytes.push(*c);
-	    let l = self.bytes.len();
-	    let ends_with_crlf = unsafe {
-		l >= 2 && *self.bytes.get_unchecked(l - 2) == 13 as u8 && *self.bytes.get_unchecked(l - 1) == 10 as u8
+	    let after_cr = std::mem::replace(&mut self.after_cr, *c == b'\r');
+	    let ends_line = match (self.ending, *c) {
+		(LineEnding::Auto, b'\r') | (LineEnding::Cr, b'\r') | (LineEnding::Lf, b'\n') => true,
+		(LineEnding::Auto, b'\n') => !after_cr,
+		(LineEnding::CrLf, b'\n') => self.bytes.last() == Some(&b'\r'),
+		_ => false,
 	    };
-	    if ends_with_crlf {
-		if let Ok(s) = std::str::from_utf8(&self.bytes[0..self.bytes.len() - 2])
-		{
-		    func(s);
+	    if !ends_line {
+		// The LF of a CRLF in auto mode
+		if !(self.ending == LineEnding::Auto && *c == b'\n') {
+		    self.bytes.push(*c);
 		}
-		self.bytes.clear();
+		continue;
+	    }
+	    if self.bytes.last() == Some(&b'\r') {
+		self.bytes.pop();
+	    }
+	    match std::str::from_utf8(&self.bytes)
+	    {
+		Ok(s) => { func(s); }
+		Err(_) => { self.invalid += 1; }
+	    }
+	    self.bytes.clear();
+	}
+    }
+
+    // The last line of a stream that ends without a terminator
+    fn finish<F>(&mut self, mut func: F) where F: FnMut(&str)
+    {
+	if self.bytes.last() == Some(&b'\r') {
+	    self.bytes.pop();
+	}
+	if !self.bytes.is_empty() {
+	    match std::str::from_utf8(&self.bytes) {
+		Ok(s) => { func(s); }
+		Err(_) => { self.invalid += 1; }
 	    }
 	}
+	self.reset();
     }
 }
 
+// Splits a byte stream into lines like the serial reader, lines
+// that aren't UTF-8 are skipped and counted instead of ending the
+// input. Returns false once func refused a line, true at the end
+// of the stream.
+pub(crate) fn forward_lines<R, F>(mut reader: R, ending: LineEnding, origin: &str, mut func: F) -> std::io::Result<bool>
+    where R: std::io::Read, F: FnMut(&str) -> bool
+{
+    let mut lp = LineProtocol::new(ending);
+    let mut buffer = [0u8; 4096];
+    let mut open = true;
+    while open {
+	let count = match reader.read(&mut buffer) {
+	    Ok(0) => {
+		lp.finish(|line| { open = open && func(line); });
+		break;
+	    }
+	    Ok(count) => count,
+	    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => { continue; }
+	    Err(error) => { return Err(error); }
+	};
+	let invalid = lp.invalid;
+	lp.feed(&buffer[..count], |line| { open = open && func(line); });
+	if lp.invalid > invalid {
+	    warn!("{}: skipped a line that isn't UTF-8, {} so far", origin, lp.invalid);
+	}
+    }
+    Ok(open)
+}
+
+// Turns the bytes read into lines according to the framing
+enum Decoder
+{
+    Text(LineProtocol),
+    Cobs(CobsProtocol),
+    Mavlink(MavlinkProtocol),
+}
+
+impl Decoder
+{
+    fn new(options: &IngestOptions) -> Decoder
+    {
+	match options.framing {
+	    Framing::Text => Decoder::Text(LineProtocol::new(options.ending)),
+	    Framing::Cobs => Decoder::Cobs(CobsProtocol::new()),
+	    Framing::Mavlink => Decoder::Mavlink(MavlinkProtocol::new()),
+	}
+    }
+
+    fn reset(&mut self)
+    {
+	match self {
+	    Decoder::Text(lp) => lp.reset(),
+	    Decoder::Cobs(cobs) => cobs.reset(),
+	    Decoder::Mavlink(mavlink) => mavlink.reset(),
+	}
+    }
+
+    fn feed<F>(&mut self, buffer: &[u8], func: F) where F: FnMut(&str)
+    {
+	match self {
+	    Decoder::Text(lp) => lp.feed(buffer, func),
+	    Decoder::Cobs(cobs) => cobs.feed(buffer, func),
+	    Decoder::Mavlink(mavlink) => mavlink.feed(buffer, func),
+	}
+    }
+
+    fn invalid(&self) -> u64
+    {
+	match self {
+	    Decoder::Text(lp) => lp.invalid,
+	    Decoder::Cobs(cobs) => cobs.invalid,
+	    Decoder::Mavlink(mavlink) => mavlink.invalid,
+	}
+    }
+}
 
 impl SerialConnector
 {
-    pub fn new(port: &str, baud: u32) -> Result<SerialConnector, serialport::Error>
+    fn open(path: &str, baud: u32, line: LineSettings) -> Result<Box<dyn serialport::SerialPort>, serialport::Error>
+    {
+	// Short enough for resets not to wait on a silent port
+	open_port(path, baud, line, Duration::from_millis(100))
+    }
+
+    // Waits for the device to be plugged (back) in and opens it.
+    // Opening right after the node appeared can fail until udev
+    // applied its permissions, hence the backoff. None on shutdown.
+    fn wait_for(path: &str, baud: u32, line: LineSettings) -> Option<Box<dyn serialport::SerialPort>>
     {
-	let mut port = serialport::new(port, baud).open()?;
-	port.set_timeout(Duration::from_millis(1000))?;
-	let mut lp = LineProtocol::new();
-	let (s, r) = unbounded();
+	let mut attempt = 0;
+	while !shutdown::requested() {
+	    if !present(path) {
+		thread::sleep(PLUG_POLL);
+		continue;
+	    }
+	    match SerialConnector::open(path, baud, line) {
+		Ok(port) => { return Some(port); }
+		Err(_) => {
+		    thread::sleep(backoff(attempt));
+		    attempt += 1;
+		}
+	    }
+	}
+	None
+    }
+
+    // A port that isn't plugged in yet is waited for, the status
+    // reports it as reconnecting until then
+    pub fn new(path: &str, baud: u32, line: LineSettings, options: IngestOptions) -> Result<SerialConnector, serialport::Error>
+    {
+	let initial = match SerialConnector::open(path, baud, line) {
+	    Ok(port) => Some(port),
+	    Err(error) if missing(&error) => {
+		warn!("{} is not there yet, waiting for it", path);
+		None
+	    }
+	    Err(error) => { return Err(error); }
+	};
+	let path = path.to_string();
+	let mut decoder = Decoder::new(&options);
+	let (queue, r) = LineQueue::new(&options);
+	let (status_s, status_r) = unbounded();
+	let (commands_s, commands_r) = unbounded();
+	let stats = Arc::new(IngestStats::default());
+	let thread_stats = stats.clone();
 	thread::spawn(move || {
-	    loop {
+	    tune_thread(&options);
+	    let mut port = match initial {
+		Some(port) => port,
+		None => {
+		    status_s.send(ConnectionState::Reconnecting).ok();
+		    match SerialConnector::wait_for(&path, baud, line) {
+			Some(port) => port,
+			None => { return; }
+		    }
+		}
+	    };
+	    status_s.send(ConnectionState::Connected).ok();
+	    while !shutdown::requested() {
+		for command in commands_r.try_iter() {
+		    let reset = match command {
+			PortCommand::Reset(reset) => reset,
+			PortCommand::Write(bytes) => {
+			    if let Err(error) = std::io::Write::write_all(&mut port, &bytes) {
+				warn!("writing to {} failed: {}", path, error);
+			    }
+			    continue;
+			}
+		    };
+		    if let Err(error) = pulse(&mut port, reset) {
+			warn!("resetting through {:?} failed: {}", reset, error);
+			continue;
+		    }
+		    // Whatever was buffered belongs to the old run, the
+		    // status marks the restart in the scopes
+		    port.clear(serialport::ClearBuffer::Input).ok();
+		    decoder.reset();
+		    status_s.send(ConnectionState::Reconnecting).ok();
+		    status_s.send(ConnectionState::Connected).ok();
+		}
 		let mut buffer: [u8; 1024] = [0; 1024];
 		match port.read(&mut buffer)
 		{
 		    Ok(bytes_read) => {
-			lp.feed(&buffer[0..bytes_read], |line: &str| {
-			    s.send(line.to_string()).expect("serial crossbeam channel failed");
+			// All lines completed by a read share its time
+			let at = Instant::now();
+			thread_stats.bytes.fetch_add(bytes_read as u64, Ordering::Relaxed);
+			if bytes_read == buffer.len() {
+			    thread_stats.full_reads.fetch_add(1, Ordering::Relaxed);
+			}
+			let mut lines = 0;
+			let mut closed = false;
+			decoder.feed(&buffer[0..bytes_read], |line: &str| {
+			    lines += 1;
+			    match checksum::verify(line, options.checksum) {
+				Some(line) => { closed |= queue.send(Received{ line: line.to_string(), at }, &thread_stats).is_err(); }
+				None => { thread_stats.bad_checksums.fetch_add(1, Ordering::Relaxed); }
+			    }
 			});
+			// The receiving side is gone, e.g. replaced after a retry
+			if closed {
+			    break;
+			}
+			thread_stats.lines.fetch_add(lines, Ordering::Relaxed);
+			thread_stats.invalid_lines.store(decoder.invalid(), Ordering::Relaxed);
 		    }
+		    Err(error) if error.kind() == std::io::ErrorKind::TimedOut => {}
 		    Err(error) => {
-			println!("error: {:?}", error);
+			warn!("reading {} failed: {:?}, reconnecting", path, error);
+			status_s.send(ConnectionState::Reconnecting).ok();
+			decoder.reset();
+			port = match SerialConnector::wait_for(&path, baud, line) {
+			    Some(port) => port,
+			    None => { return; }
+			};
+			status_s.send(ConnectionState::Connected).ok();
 		    }
 		}
 	}
 	});
-	Ok(SerialConnector{receiver: r})
+	Ok(SerialConnector{receiver: r, status: status_r, stats, commands: commands_s})
     }
 }
 
@@ -74,15 +741,103 @@ mod tests {
 
     #[test]
     fn feed_bytes_but_no_crlf() {
-	let mut lp = LineProtocol::new();
+	let mut lp = LineProtocol::new(LineEnding::CrLf);
 	let mut called = false;
 	lp.feed(b"Hallo", |_x: &str| { called = true; });
 	assert!(called == false);
     }
 
+    #[test]
+    fn count_invalid_lines() {
+	let mut lp = LineProtocol::new(LineEnding::CrLf);
+	let mut lines = vec![];
+	lp.feed(b"ok\r\n\xff\xfe\r\nfine\r\n", |x: &str| { lines.push(x.to_string()) });
+	assert_eq!(lines, vec!["ok", "fine"]);
+	assert_eq!(lp.invalid, 1);
+    }
+
+    #[test]
+    fn line_endings() {
+	let mut lines = vec![];
+	let mut lp = LineProtocol::new(LineEnding::Auto);
+	lp.feed(b"a\r\nb\nc\rd\r", |x: &str| { lines.push(x.to_string()) });
+	lp.feed(b"\ne\n\n", |x: &str| { lines.push(x.to_string()) });
+	assert_eq!(lines, vec!["a", "b", "c", "d", "e", ""]);
+	lines.clear();
+	let mut lp = LineProtocol::new(LineEnding::Lf);
+	lp.feed(b"a\r\nb\rc\n", |x: &str| { lines.push(x.to_string()) });
+	assert_eq!(lines, vec!["a", "b\rc"]);
+	lines.clear();
+	let mut lp = LineProtocol::new(LineEnding::CrLf);
+	lp.feed(b"a\nb\r\n", |x: &str| { lines.push(x.to_string()) });
+	assert_eq!(lines, vec!["a\nb"]);
+	assert_eq!("cr".parse::<LineEnding>(), Ok(LineEnding::Cr));
+    }
+
+    #[test]
+    fn full_queue_drops_by_policy() {
+	let stats = IngestStats::default();
+	let options = IngestOptions{ queue: 2, ..IngestOptions::default() };
+	let (queue, r) = LineQueue::new(&options);
+	for line in &["a", "b", "c"] {
+	    queue.send(Received::now(line.to_string()), &stats).unwrap();
+	}
+	assert_eq!(r.try_iter().map(|received| { received.line }).collect::<Vec<String>>(), vec!["b", "c"]);
+	assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+	let (queue, r) = LineQueue::new(&IngestOptions{ drop: DropPolicy::DropNewest, ..options });
+	for line in &["a", "b", "c"] {
+	    queue.send(Received::now(line.to_string()), &stats).unwrap();
+	}
+	assert_eq!(r.try_iter().map(|received| { received.line }).collect::<Vec<String>>(), vec!["a", "b"]);
+	assert_eq!(stats.overruns(), 2);
+	assert_eq!("drop-newest".parse::<DropPolicy>(), Ok(DropPolicy::DropNewest));
+    }
+
+    #[test]
+    fn dropped_receiver_closes_queue() {
+	let stats = IngestStats::default();
+	let (queue, r) = LineQueue::new(&IngestOptions::default());
+	assert!(queue.send(Received::now("a".to_string()), &stats).is_ok());
+	drop(r);
+	assert!(queue.send(Received::now("b".to_string()), &stats).is_err());
+    }
+
+    #[test]
+    fn character_formats() {
+	let format: CharacterFormat = "7e1".parse().unwrap();
+	assert_eq!(format.data_bits, serialport::DataBits::Seven);
+	assert_eq!(format.parity, serialport::Parity::Even);
+	assert_eq!(format.stop_bits, serialport::StopBits::One);
+	assert_eq!("8N1".parse::<CharacterFormat>(), Ok(CharacterFormat::default()));
+	assert!("9N1".parse::<CharacterFormat>().is_err());
+	assert!("8N".parse::<CharacterFormat>().is_err());
+    }
+
+    #[test]
+    fn reset_lines() {
+	assert_eq!("dtr".parse::<ResetLine>(), Ok(ResetLine::Dtr));
+	assert_eq!("rts".parse::<ResetLine>(), Ok(ResetLine::Rts));
+	assert!("cts".parse::<ResetLine>().is_err());
+    }
+
+    #[test]
+    fn missing_ports() {
+	assert!(missing(&serialport::Error::new(serialport::ErrorKind::NoDevice, "gone")));
+	assert!(missing(&serialport::Error::new(serialport::ErrorKind::Io(std::io::ErrorKind::NotFound), "gone")));
+	assert!(!missing(&serialport::Error::new(serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied), "busy")));
+	assert_eq!(present("/dev/does-not-exist"), !cfg!(unix));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+	assert_eq!(backoff(0), Duration::from_millis(250));
+	assert_eq!(backoff(2), Duration::from_millis(1000));
+	assert_eq!(backoff(10), Duration::from_millis(5000));
+    }
+
     #[test]
     fn feed_bytes_with_crlf() {
-	let mut lp = LineProtocol::new();
+	let mut lp = LineProtocol::new(LineEnding::CrLf);
 	let mut line:String = "".to_string();
 	lp.feed(b"Hallo\r\n", |x: &str| { line = x.to_string() });
 	assert!(line == "Hallo");
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+// What is restored on the next start, saved on exit
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Session
+{
+    pub locked: bool,
+    pub reconnect_markers: bool,
+    pub alarm_panel: bool,
+    pub diagnostics: bool,
+    pub health: bool,
+    pub throughput: bool,
+}
+
+impl Session
+{
+    pub fn load(path: &str) -> std::io::Result<Session>
+    {
+	let content = std::fs::read_to_string(path)?;
+	serde_json::from_str(&content).map_err(|error| { std::io::Error::new(std::io::ErrorKind::InvalidData, error) })
+    }
+
+    // Written aside and renamed, so exiting mid-write can't corrupt it
+    pub fn save(&self, path: &str) -> std::io::Result<()>
+    {
+	let temporary = format!("{}.tmp", path);
+	std::fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+	std::fs::rename(&temporary, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn sessions_round_trip() {
+	let path = std::env::temp_dir().join(format!("rusty-peanut-session-{}.json", std::process::id()));
+	let path = path.to_str().unwrap();
+	let session = Session{ locked: true, diagnostics: true, ..Session::default() };
+	session.save(path).unwrap();
+	assert_eq!(Session::load(path).unwrap(), session);
+	std::fs::remove_file(path).unwrap();
+	assert!(Session::load(path).is_err());
+    }
+}
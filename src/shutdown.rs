@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::warn;
+
+// Set once the application is about to exit. Reader threads
+// poll it, so they stop instead of being torn down mid-read.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn request()
+{
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool
+{
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+// SIGINT takes the same path as closing the window
+pub fn install_signal_handler()
+{
+    if let Err(error) = ctrlc::set_handler(request) {
+	warn!("can't handle SIGINT: {:?}", error);
+    }
+}
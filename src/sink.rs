@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::info;
+
+use crate::locale::NumberFormat;
+
+// Streams every sample into CSV files below directory, starting
+// a new file each period. Lines are flushed as they are written,
+// so a crash loses at most the current line.
+pub struct CsvSink
+{
+    directory: PathBuf,
+    // Seconds per file
+    period: f64,
+    format: NumberFormat,
+    sequence: usize,
+    // The open file and the time it was started at
+    current: Option<(LineWriter<File>, f64)>,
+}
+
+impl CsvSink
+{
+    pub fn new(directory: &str, period: Duration, format: NumberFormat) -> std::io::Result<CsvSink>
+    {
+	std::fs::create_dir_all(directory)?;
+	Ok(CsvSink{
+	    directory: PathBuf::from(directory),
+	    period: period.as_secs_f64().max(1.0),
+	    format,
+	    sequence: 0,
+	    current: None,
+	})
+    }
+
+    fn open(&mut self, timestamp: f64) -> std::io::Result<()>
+    {
+	let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| { duration.as_secs() }).unwrap_or(0);
+	self.sequence += 1;
+	let path = self.directory.join(format!("capture-{:04}-{}.csv", self.sequence, epoch));
+	info!("streaming samples to {:?}", path);
+	let mut writer = LineWriter::new(File::create(path)?);
+	let s = self.format.csv_separator();
+	writeln!(writer, "time{}object{}signal{}value", s, s, s)?;
+	self.current = Some((writer, timestamp));
+	Ok(())
+    }
+
+    // timestamp is in seconds since the start of the session
+    pub fn write(&mut self, timestamp: f64, object: &str, signals: &[String], values: &[f32]) -> std::io::Result<()>
+    {
+	let rotate = match &self.current {
+	    Some((_, started)) => timestamp - started >= self.period,
+	    None => true,
+	};
+	if rotate {
+	    self.open(timestamp)?;
+	}
+	let format = self.format;
+	let s = format.csv_separator();
+	if let Some((writer, _)) = &mut self.current {
+	    for (signal, value) in signals.iter().zip(values) {
+		writeln!(writer, "{}{}{}{}{}{}{}", format.format_csv(timestamp), s, format.csv_field(object), s, format.csv_field(signal), s, format.format_csv(*value))?;
+	    }
+	}
+	Ok(())
+    }
+
+    // Makes sure the current file is on disk, e.g. before exiting
+    pub fn close(&mut self) -> std::io::Result<()>
+    {
+	if let Some((mut writer, _)) = self.current.take() {
+	    writer.flush()?;
+	    writer.get_ref().sync_all()?;
+	}
+	Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn files_rotate_after_period() {
+	let directory = std::env::temp_dir().join(format!("rusty-peanut-sink-{}", std::process::id()));
+	let mut sink = CsvSink::new(&directory.to_string_lossy(), Duration::from_secs(60), NumberFormat::default()).unwrap();
+	let signals = vec!["A".to_string(), "B".to_string()];
+	for timestamp in &[0.0, 30.0, 61.0] {
+	    sink.write(*timestamp, "MyScope", &signals, &[1.0, 2.5]).unwrap();
+	}
+	let mut files: Vec<PathBuf> = std::fs::read_dir(&directory).unwrap().map(|entry| { entry.unwrap().path() }).collect();
+	files.sort();
+	assert_eq!(files.len(), 2);
+	assert_eq!(std::fs::read_to_string(&files[0]).unwrap(), "time,object,signal,value\n0,MyScope,A,1\n0,MyScope,B,2.5\n30,MyScope,A,1\n30,MyScope,B,2.5\n");
+	std::fs::remove_dir_all(directory).unwrap();
+    }
+}
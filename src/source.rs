@@ -0,0 +1,630 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+
+use crate::config::Config;
+use crate::demo::SimulatedSource;
+use crate::push::PushConnector;
+use crate::osc::OscConnector;
+use crate::replay::{ReplayConnector, Transport};
+use crate::serial::{ConnectionState, IngestOptions, IngestStats, PortCommand, Received, ResetLine, SerialConnector};
+use crate::stdin::StdinConnector;
+use crate::swo::SwoConnector;
+
+// A transport delivering protocol lines. Connectors spawn their
+// reader thread when created and reconnect on their own, the
+// status reports it.
+pub trait DataSource
+{
+    fn receiver(&self) -> &Receiver<Received>;
+    // Only sources that can get disconnected report their state
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	None
+    }
+    fn stats(&self) -> Option<&Arc<IngestStats>>
+    {
+	None
+    }
+    // Pulses a modem line to reboot the target
+    fn reset_target(&self, _line: ResetLine) -> Result<(), String>
+    {
+	Err("the input has no reset line".to_string())
+    }
+    // Sends a line to the target
+    fn send(&self, _line: &str) -> Result<(), String>
+    {
+	Err("the input can't send".to_string())
+    }
+    // Pauses, steps or seeks a replay
+    fn transport(&self, _command: Transport) -> Result<(), String>
+    {
+	Err("the input isn't a replay".to_string())
+    }
+}
+
+impl DataSource for SerialConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	Some(&self.status)
+    }
+
+    fn stats(&self) -> Option<&Arc<IngestStats>>
+    {
+	Some(&self.stats)
+    }
+
+    fn reset_target(&self, line: ResetLine) -> Result<(), String>
+    {
+	self.commands.send(PortCommand::Reset(line)).map_err(|_| { "the serial reader is gone".to_string() })
+    }
+
+    fn send(&self, line: &str) -> Result<(), String>
+    {
+	self.commands.send(PortCommand::Write(format!("{}\n", line).into_bytes())).map_err(|_| { "the serial reader is gone".to_string() })
+    }
+}
+
+impl DataSource for SwoConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn stats(&self) -> Option<&Arc<IngestStats>>
+    {
+	Some(&self.stats)
+    }
+}
+
+impl DataSource for StdinConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+impl DataSource for ReplayConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn transport(&self, command: Transport) -> Result<(), String>
+    {
+	self.transport.send(command).map_err(|_| { "the replay is gone".to_string() })
+    }
+}
+
+impl DataSource for PushConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+impl DataSource for OscConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+impl DataSource for SimulatedSource
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(unix)]
+impl DataSource for crate::unix::UnixConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(unix)]
+impl DataSource for crate::fifo::FifoConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl DataSource for crate::mqtt::MqttConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "zmq")]
+impl DataSource for crate::zeromq::ZmqConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "lsl")]
+impl DataSource for crate::labstreaming::LslConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "can")]
+impl DataSource for crate::can::CanConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	Some(&self.status)
+    }
+}
+
+#[cfg(all(feature = "ble", target_os = "linux"))]
+impl DataSource for crate::ble::BleConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	Some(&self.status)
+    }
+}
+
+impl DataSource for crate::modbus::ModbusConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	Some(&self.status)
+    }
+}
+
+#[cfg(all(feature = "bridge", target_os = "linux"))]
+impl DataSource for crate::bridge::BridgeConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "audio")]
+impl DataSource for crate::audio::AudioConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl DataSource for crate::metrics::MetricsConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl DataSource for crate::gpio::GpioConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DataSource for crate::journal::JournalConnector
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+}
+
+// Counts what sources without counters of their own deliver, on a
+// thread relaying their lines
+struct Metered
+{
+    inner: Box<dyn DataSource>,
+    receiver: Receiver<Received>,
+    stats: Arc<IngestStats>,
+}
+
+impl Metered
+{
+    fn new(inner: Box<dyn DataSource>) -> Metered
+    {
+	let lines = inner.receiver().clone();
+	let (s, r) = unbounded();
+	let stats = Arc::new(IngestStats::default());
+	let thread_stats = stats.clone();
+	thread::spawn(move || {
+	    for received in lines.iter() {
+		thread_stats.bytes.fetch_add(received.line.len() as u64 + 1, Ordering::Relaxed);
+		thread_stats.lines.fetch_add(1, Ordering::Relaxed);
+		if s.send(received).is_err() {
+		    break;
+		}
+	    }
+	});
+	Metered{ inner, receiver: r, stats }
+    }
+}
+
+impl DataSource for Metered
+{
+    fn receiver(&self) -> &Receiver<Received>
+    {
+	&self.receiver
+    }
+
+    fn status(&self) -> Option<&Receiver<ConnectionState>>
+    {
+	self.inner.status()
+    }
+
+    fn stats(&self) -> Option<&Arc<IngestStats>>
+    {
+	Some(&self.stats)
+    }
+
+    fn reset_target(&self, line: ResetLine) -> Result<(), String>
+    {
+	self.inner.reset_target(line)
+    }
+
+    fn send(&self, line: &str) -> Result<(), String>
+    {
+	self.inner.send(line)
+    }
+
+    fn transport(&self, command: Transport) -> Result<(), String>
+    {
+	self.inner.transport(command)
+    }
+}
+
+type Opened = Result<Box<dyn DataSource>, String>;
+
+// Opens the source if the configuration selects it
+type Factory = fn(&Config, IngestOptions) -> Option<Opened>;
+
+fn boxed<S: DataSource + 'static, E: std::fmt::Display>(result: Result<S, E>, what: &str) -> Opened
+{
+    result.map(|source| { Box::new(source) as Box<dyn DataSource> }).map_err(|error| { format!("{} failed: {}", what, error) })
+}
+
+fn demo(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.demo {
+	Some(Ok(Box::new(SimulatedSource::new(config.demo_rate))))
+    } else {
+	None
+    }
+}
+
+fn replay(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.replay.as_ref().map(|path| { boxed(ReplayConnector::new(path, config.speed), &format!("replaying {}", path)) })
+}
+
+#[cfg(feature = "mqtt")]
+fn mqtt(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.mqtt.as_ref().map(|broker| { boxed(crate::mqtt::MqttConnector::new(broker, &config.topics), &format!("MQTT broker {}", broker)) })
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn mqtt(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.mqtt.as_ref().map(|_| { Err("built without MQTT support, enable the mqtt feature".to_string()) })
+}
+
+#[cfg(feature = "zmq")]
+fn zmq(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.zmq.as_ref().map(|endpoint| { boxed(crate::zeromq::ZmqConnector::new(endpoint, &config.zmq_topics), &format!("ZeroMQ endpoint {}", endpoint)) })
+}
+
+#[cfg(not(feature = "zmq"))]
+fn zmq(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.zmq.as_ref().map(|_| { Err("built without ZeroMQ support, enable the zmq feature".to_string()) })
+}
+
+#[cfg(feature = "lsl")]
+fn lsl(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.lsl.as_ref().map(|name| { boxed(crate::labstreaming::LslConnector::new(name, config.lsl_min, config.lsl_max), &format!("LSL stream {}", name)) })
+}
+
+#[cfg(not(feature = "lsl"))]
+fn lsl(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.lsl.as_ref().map(|_| { Err("built without LSL support, enable the lsl feature".to_string()) })
+}
+
+#[cfg(feature = "can")]
+fn can(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.can.as_ref().map(|interface| { boxed(crate::can::CanConnector::new(interface, &config.can_signals), &format!("CAN interface {}", interface)) })
+}
+
+#[cfg(not(feature = "can"))]
+fn can(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.can.as_ref().map(|_| { Err("built without CAN support, enable the can feature".to_string()) })
+}
+
+#[cfg(all(feature = "ble", target_os = "linux"))]
+fn ble(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.ble.as_ref().map(|target| { Ok(Box::new(crate::ble::BleConnector::new(target)) as Box<dyn DataSource>) })
+}
+
+#[cfg(not(all(feature = "ble", target_os = "linux")))]
+fn ble(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.ble.as_ref().map(|_| { Err("built without BLE support, enable the ble feature on Linux".to_string()) })
+}
+
+#[cfg(unix)]
+fn unix(config: &Config, ingest: IngestOptions) -> Option<Opened>
+{
+    config.unix.as_ref().map(|path| { boxed(crate::unix::UnixConnector::new(path, ingest.ending), &format!("unix socket {}", path)) })
+}
+
+#[cfg(not(unix))]
+fn unix(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.unix.as_ref().map(|_| { Err("unix domain sockets are not available on this platform".to_string()) })
+}
+
+#[cfg(unix)]
+fn fifo(config: &Config, ingest: IngestOptions) -> Option<Opened>
+{
+    config.fifo.as_ref().map(|path| { boxed(crate::fifo::FifoConnector::new(path, ingest.ending), &format!("FIFO {}", path)) })
+}
+
+#[cfg(not(unix))]
+fn fifo(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.fifo.as_ref().map(|_| { Err("named pipes are not available on this platform".to_string()) })
+}
+
+fn swo(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.swo.as_ref().map(|path| { boxed(SwoConnector::new(path, config.itm_port), &format!("opening SWO capture {}", path)) })
+}
+
+fn stdin(config: &Config, ingest: IngestOptions) -> Option<Opened>
+{
+    if config.stdin {
+	Some(Ok(Box::new(StdinConnector::new(ingest.ending))))
+    } else {
+	None
+    }
+}
+
+#[cfg(feature = "audio")]
+fn audio(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.audio.as_ref().map(|device| {
+	let channels: Vec<usize> = config.audio_channels.iter().map(|channel| { channel - 1 }).collect();
+	boxed(crate::audio::AudioConnector::new(device, &channels, config.audio_rate, config.audio_mean), &format!("audio input {}", device))
+    })
+}
+
+#[cfg(not(feature = "audio"))]
+fn audio(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.audio.as_ref().map(|_| { Err("built without audio support, enable the audio feature".to_string()) })
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+fn gpio(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.gpio.is_empty() {
+	return None;
+    }
+    Some(boxed(crate::gpio::GpioConnector::new(&config.gpio, config.gpio_rate), "sampling the GPIO pins"))
+}
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+fn gpio(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.gpio.is_empty() {
+	return None;
+    }
+    Some(Err("built without GPIO support, enable the gpio feature on Linux".to_string()))
+}
+
+#[cfg(feature = "metrics")]
+fn metrics(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.metrics {
+	let interval = std::time::Duration::from_millis(config.metrics_interval.max(1));
+	Some(Ok(Box::new(crate::metrics::MetricsConnector::new(interval)) as Box<dyn DataSource>))
+    } else {
+	None
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+fn metrics(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.metrics {
+	Some(Err("built without host metrics support, enable the metrics feature".to_string()))
+    } else {
+	None
+    }
+}
+
+#[cfg(all(feature = "bridge", target_os = "linux"))]
+fn bridge(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.bridge {
+	let interval = std::time::Duration::from_millis(config.bridge_interval);
+	Some(boxed(crate::bridge::BridgeConnector::new(&config.bridge_registers, interval), "opening the I2C/SPI buses"))
+    } else {
+	None
+    }
+}
+
+#[cfg(not(all(feature = "bridge", target_os = "linux")))]
+fn bridge(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    if config.bridge {
+	Some(Err("built without I2C/SPI bridge support, enable the bridge feature on Linux".to_string()))
+    } else {
+	None
+    }
+}
+
+fn push(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.push.as_ref().map(|address| { boxed(PushConnector::new(address), &format!("listening on {}", address)) })
+}
+
+fn osc(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.osc.as_ref().map(|address| { boxed(OscConnector::new(address, &config.osc_signals), &format!("OSC on {}", address)) })
+}
+
+fn modbus(config: &Config, _ingest: IngestOptions) -> Option<Opened>
+{
+    config.modbus.as_ref().map(|port| {
+	let interval = std::time::Duration::from_millis(config.modbus_interval);
+	boxed(crate::modbus::ModbusConnector::new(port, config.baud, config.line_settings(), interval, &config.modbus_registers), &format!("Modbus on {}", port))
+    })
+}
+
+fn serial(config: &Config, ingest: IngestOptions) -> Option<Opened>
+{
+    config.port.as_ref().map(|port| { boxed(SerialConnector::new(port, config.baud, config.line_settings(), ingest), &format!("opening {}", port)) })
+}
+
+// All transports in order of precedence, the first one the
+// configuration selects is opened. New transports go here.
+pub const REGISTRY: &[(&str, Factory)] = &[
+    ("demo", demo),
+    ("replay", replay),
+    ("mqtt", mqtt),
+    ("zmq", zmq),
+    ("lsl", lsl),
+    ("can", can),
+    ("ble", ble),
+    ("unix", unix),
+    ("fifo", fifo),
+    ("push", push),
+    ("osc", osc),
+    ("swo", swo),
+    ("stdin", stdin),
+    ("modbus", modbus),
+    ("bridge", bridge),
+    ("audio", audio),
+    ("metrics", metrics),
+    ("gpio", gpio),
+    ("serial", serial),
+];
+
+// None if no transport is configured. All sources come with
+// counters, for the throughput panel.
+pub fn open(config: &Config, ingest: IngestOptions) -> Result<Option<Box<dyn DataSource>>, String>
+{
+    let source = REGISTRY.iter()
+	.find_map(|(_, factory)| { factory(config, ingest) })
+	.transpose()?;
+    Ok(source.map(|source| {
+	match source.stats() {
+	    Some(_) => source,
+	    None => Box::new(Metered::new(source)) as Box<dyn DataSource>,
+	}
+    }))
+}
+
+// The journal runs next to the input rather than instead of it
+#[cfg(target_os = "linux")]
+pub fn open_journal(config: &Config) -> Result<Option<Box<dyn DataSource>>, String>
+{
+    if !config.journal {
+	return Ok(None);
+    }
+    boxed(crate::journal::JournalConnector::new(&config.journal_units, config.journal_match.as_deref()), "following the journal").map(Some)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_journal(config: &Config) -> Result<Option<Box<dyn DataSource>>, String>
+{
+    if config.journal {
+	Err("the systemd journal is only available on Linux".to_string())
+    } else {
+	Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn registry_picks_by_precedence() {
+	let mut config = Config::default();
+	assert!(open(&config, IngestOptions::default()).unwrap().is_none());
+	// The replay takes precedence over the port
+	config.port = Some("/dev/does-not-exist".to_string());
+	config.replay = Some("/does/not/exist.log".to_string());
+	match open(&config, IngestOptions::default()) {
+	    Err(message) => assert!(message.starts_with("replaying /does/not/exist.log failed")),
+	    Ok(_) => panic!("opened a missing file"),
+	}
+	config.replay = None;
+	config.demo = true;
+	let source = open(&config, IngestOptions::default()).unwrap().unwrap();
+	assert!(source.receiver().recv().unwrap().line.starts_with("`SCOPE Demo"));
+	assert!(source.status().is_none());
+	// Counted on the way, the demo has no counters of its own
+	assert!(source.stats().is_some());
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Read;
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+use log::warn;
+use crate::serial::{LineEnding, Received, forward_lines};
+
+// Reads protocol lines from standard input, so any producer
+// can be piped in, e.g. `cat /dev/ttyUSB0 | rusty-peanut --stdin`
+pub struct StdinConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+impl StdinConnector
+{
+    pub fn new(ending: LineEnding) -> StdinConnector
+    {
+	StdinConnector::from_reader(std::io::stdin(), ending)
+    }
+
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, ending: LineEnding) -> StdinConnector
+    {
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    let result = forward_lines(reader, ending, "standard input", |line| {
+		s.send(Received::now(line.to_string())).is_ok()
+	    });
+	    if let Err(error) = result {
+		warn!("reading standard input failed: {:?}", error);
+	    }
+	});
+	StdinConnector{receiver: r}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn lines_are_forwarded() {
+	let connector = StdinConnector::from_reader(std::io::Cursor::new(b"`SCOPE MyScope\r\n`MyScope 1\n".to_vec()), LineEnding::Auto);
+	let lines: Vec<String> = connector.receiver.iter().map(|received| { received.line }).collect();
+	assert_eq!(lines, vec!["`SCOPE MyScope", "`MyScope 1"]);
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+	let connector = StdinConnector::from_reader(std::io::Cursor::new(b"`MyScope 1\r`MyScope \xff\xfe\r`MyScope 3".to_vec()), LineEnding::Cr);
+	let lines: Vec<String> = connector.receiver.iter().map(|received| { received.line }).collect();
+	assert_eq!(lines, vec!["`MyScope 1", "`MyScope 3"]);
+    }
+}
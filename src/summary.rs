@@ -0,0 +1,80 @@
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use serde_json::{json, Value};
+
+use crate::alarms::AlarmKind;
+use crate::debugobjects::DebugObjects;
+use crate::devices::Device;
+use crate::serial::IngestStats;
+
+// Counters of a reader thread, as reported by ingest-stats
+pub fn link_stats(stats: &IngestStats) -> Value
+{
+    json!({
+	"bytes": stats.bytes.load(Ordering::Relaxed),
+	"lines": stats.lines.load(Ordering::Relaxed),
+	"full_reads": stats.full_reads.load(Ordering::Relaxed),
+	"invalid_lines": stats.invalid_lines.load(Ordering::Relaxed),
+	"overflows": stats.overflows.load(Ordering::Relaxed),
+	"dropped": stats.dropped.load(Ordering::Relaxed),
+	"bad_checksums": stats.bad_checksums.load(Ordering::Relaxed),
+    })
+}
+
+// Everything a test script wants to assert on after a run. The
+// signal statistics cover the samples still held by each object.
+pub fn summary(views: &DebugObjects, stats: Option<&IngestStats>, devices: &[Device]) -> Value
+{
+    let signals: serde_json::Map<String, Value> = views.names().iter()
+	.filter_map(|name| { views.signal_stats(name).ok().map(|stats| { (name.clone(), json!(stats)) }) })
+	.collect();
+    let events = views.alarms().events();
+    let count = |kind: AlarmKind| { events.iter().filter(|event| { event.kind == kind }).count() };
+    let triggers: Vec<Value> = events.iter()
+	.filter(|event| { event.kind == AlarmKind::Trigger })
+	.map(|event| { json!({ "timestamp": event.timestamp, "scope": event.scope, "signal": event.signal, "value": event.value }) })
+	.collect();
+    let now = Instant::now();
+    let devices: serde_json::Map<String, Value> = devices.iter()
+	.map(|device| {
+	    let stats = device.source.stats().map(|stats| { link_stats(stats) }).unwrap_or(Value::Null);
+	    (device.health.name.clone(), json!({ "health": device.health.summary(now), "link": stats }))
+	})
+	.collect();
+    json!({
+	"signals": signals,
+	"alarms": {
+	    "trigger": count(AlarmKind::Trigger),
+	    "blocked": count(AlarmKind::Blocked),
+	    "reconnect": count(AlarmKind::Reconnect),
+	    "event": count(AlarmKind::Event),
+	    "rule": count(AlarmKind::Rule),
+	},
+	"triggers": triggers,
+	"gaps": views.gaps(),
+	"dropped": views.dropped().into_iter().map(|(name, dropped)| { (name, json!(dropped)) }).collect::<serde_json::Map<String, Value>>(),
+	"link": stats.map(link_stats).unwrap_or(Value::Null),
+	"devices": devices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn summary_of_a_scope() {
+	let mut views = DebugObjects::new();
+	views.feed_text("`SCOPE MyScope SAMPLES 16\n`MyScope 'A' 0 10 64 0\n`MyScope 1\n`MyScope 3\n");
+	views.reconnected();
+	let stats = IngestStats::default();
+	stats.lines.store(4, Ordering::Relaxed);
+	let summary = summary(&views, Some(&stats), &[]);
+	assert_eq!(summary["signals"]["MyScope"][0]["max"], json!(3.0));
+	assert_eq!(summary["signals"]["MyScope"][0]["mean"], json!(2.0));
+	assert_eq!(summary["alarms"]["trigger"], json!(0));
+	assert_eq!(summary["gaps"].as_array().unwrap().len(), 1);
+	assert_eq!(summary["link"]["lines"], json!(4));
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+
+type Color = nannou::color::Rgb<u8>;
+
+// The vector counterpart of the raster Canvas, for figures that
+// have to scale, e.g. in papers. Coordinates have their origin in
+// the top left corner.
+pub struct Svg
+{
+    width: f32,
+    height: f32,
+    elements: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor
+{
+    Start,
+    End,
+}
+
+fn hex(color: Color) -> String
+{
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+fn escape(text: &str) -> String
+{
+    text.chars().map(|c| {
+	match c {
+	    '&' => "&amp;".to_string(),
+	    '<' => "&lt;".to_string(),
+	    '>' => "&gt;".to_string(),
+	    '"' => "&quot;".to_string(),
+	    c => c.to_string(),
+	}
+    }).collect()
+}
+
+impl Svg
+{
+    pub fn new(width: f32, height: f32) -> Svg
+    {
+	Svg{ width: width.max(1.0), height: height.max(1.0), elements: vec![] }
+    }
+
+    pub fn width(&self) -> f32
+    {
+	self.width
+    }
+
+    pub fn height(&self) -> f32
+    {
+	self.height
+    }
+
+    // Paints over everything drawn so far
+    pub fn fill(&mut self, color: Color)
+    {
+	self.elements.push(format!("<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>", hex(color)));
+    }
+
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), color: Color)
+    {
+	self.elements.push(format!(
+	    "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-width=\"1\"/>",
+	    from.0, from.1, to.0, to.1, hex(color)));
+    }
+
+    // One path, each segment starts a new subpath so gaps stay open
+    pub fn path(&mut self, segments: &[Vec<(f32, f32)>], color: Color)
+    {
+	let data: Vec<String> = segments.iter()
+	    .flat_map(|segment| {
+		segment.iter().enumerate().map(|(i, (x, y))| { format!("{}{:.2},{:.2}", if i == 0 { 'M' } else { 'L' }, x, y) })
+	    })
+	    .collect();
+	if data.is_empty() {
+	    return;
+	}
+	self.elements.push(format!(
+	    "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\" stroke-linejoin=\"round\"/>",
+	    data.join(" "), hex(color)));
+    }
+
+    // Filled, without an outline
+    pub fn polygon(&mut self, points: &[(f32, f32)], color: Color)
+    {
+	if points.len() < 3 {
+	    return;
+	}
+	let points: Vec<String> = points.iter().map(|(x, y)| { format!("{:.2},{:.2}", x, y) }).collect();
+	self.elements.push(format!("<polygon points=\"{}\" fill=\"{}\"/>", points.join(" "), hex(color)));
+    }
+
+    pub fn circle(&mut self, center: (f32, f32), radius: f32, color: Color)
+    {
+	self.elements.push(format!("<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"/>", center.0, center.1, radius, hex(color)));
+    }
+
+    // position is the start or end of the baseline
+    pub fn text(&mut self, position: (f32, f32), text: &str, size: u32, anchor: Anchor, color: Color)
+    {
+	let anchor = match anchor {
+	    Anchor::Start => "start",
+	    Anchor::End => "end",
+	};
+	self.elements.push(format!(
+	    "<text x=\"{:.2}\" y=\"{:.2}\" font-family=\"sans-serif\" font-size=\"{}\" text-anchor=\"{}\" fill=\"{}\">{}</text>",
+	    position.0, position.1, size, anchor, hex(color), escape(text)));
+    }
+
+    pub fn frame(&mut self, color: Color)
+    {
+	self.elements.push(format!(
+	    "<rect x=\"0.5\" y=\"0.5\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\"/>",
+	    self.width - 1.0, self.height - 1.0, hex(color)));
+    }
+}
+
+impl fmt::Display for Svg
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+	writeln!(f, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">", w = self.width, h = self.height)?;
+	for element in &self.elements {
+	    writeln!(f, "  {}", element)?;
+	}
+	writeln!(f, "</svg>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nannou::prelude::{BLACK, WHITE};
+
+    #[test]
+    fn write_elements() {
+	let mut svg = Svg::new(10.0, 20.0);
+	svg.fill(BLACK);
+	svg.path(&[vec![(0.0, 0.0), (1.0, 1.5)], vec![(3.0, 2.0), (4.0, 2.0)]], WHITE);
+	svg.path(&[], WHITE);
+	svg.polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)], WHITE);
+	svg.polygon(&[(0.0, 0.0), (1.0, 0.0)], WHITE);
+	svg.text((2.0, 10.0), "<A & B>", 10, Anchor::End, WHITE);
+	let svg = svg.to_string();
+	assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"10\" height=\"20\" viewBox=\"0 0 10 20\">\n"));
+	assert!(svg.contains("fill=\"#000000\""));
+	assert!(svg.contains("d=\"M0.00,0.00 L1.00,1.50 M3.00,2.00 L4.00,2.00\" fill=\"none\" stroke=\"#ffffff\""));
+	assert_eq!(svg.matches("<path").count(), 1);
+	assert_eq!(svg.matches("<polygon points=\"0.00,0.00 1.00,0.00 1.00,1.00\" fill=\"#ffffff\"/>").count(), 1);
+	assert!(svg.contains("text-anchor=\"end\" fill=\"#ffffff\">&lt;A &amp; B&gt;</text>"));
+	assert!(svg.ends_with("</svg>\n"));
+    }
+}
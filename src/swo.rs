@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use crossbeam::channel::{Receiver, unbounded};
+use log::warn;
+use crate::serial::{IngestStats, LineEnding, LineProtocol, Received};
+use crate::shutdown;
+
+// Where the decoder is within an ITM packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ItmState
+{
+    Header,
+    // Payload bytes of a source packet still to come
+    Payload{ keep: bool, remaining: usize },
+    // Timestamp and extension packets continue while bit 7 is set
+    Continuation,
+}
+
+// Extracts the bytes written to one stimulus port from an
+// ITM stream, as captured from SWO by OpenOCD or a probe.
+pub struct ItmDecoder
+{
+    port: u8,
+    state: ItmState,
+    // Overflow packets, the trace FIFO dropped data
+    pub overflows: u64,
+}
+
+impl ItmDecoder
+{
+    pub fn new(port: u8) -> ItmDecoder
+    {
+	ItmDecoder{ port, state: ItmState::Header, overflows: 0 }
+    }
+
+    pub fn feed(&mut self, buffer: &[u8], output: &mut Vec<u8>)
+    {
+	for byte in buffer {
+	    self.state = match self.state {
+		ItmState::Header => self.header(*byte),
+		ItmState::Payload{ keep, remaining } => {
+		    if keep {
+			output.push(*byte);
+		    }
+		    if remaining > 1 {
+			ItmState::Payload{ keep, remaining: remaining - 1 }
+		    } else {
+			ItmState::Header
+		    }
+		}
+		ItmState::Continuation if byte & 0x80 != 0 => ItmState::Continuation,
+		ItmState::Continuation => ItmState::Header,
+	    };
+	}
+    }
+
+    fn header(&mut self, header: u8) -> ItmState
+    {
+	match header & 0x03 {
+	    // Sync packets are runs of zeros ended by 0x80
+	    0 if header == 0x00 || header == 0x80 => ItmState::Header,
+	    0 if header == 0x70 => {
+		self.overflows += 1;
+		ItmState::Header
+	    }
+	    0 if header & 0x80 != 0 => ItmState::Continuation,
+	    0 => ItmState::Header,
+	    size => {
+		// Bit 2 distinguishes hardware sources from stimulus ports
+		let keep = header & 0x04 == 0 && header >> 3 == self.port;
+		ItmState::Payload{ keep, remaining: if size == 3 { 4 } else { size as usize } }
+	    }
+	}
+    }
+}
+
+// Reads raw SWO output from a file or FIFO, e.g. the one given to
+// OpenOCD's `tpiu config internal`, and keeps following it.
+pub struct SwoConnector
+{
+    pub receiver: Receiver<Received>,
+    pub stats: Arc<IngestStats>,
+}
+
+impl SwoConnector
+{
+    pub fn new(path: &str, port: u8) -> std::io::Result<SwoConnector>
+    {
+	let mut file = File::open(path)?;
+	let (s, r) = unbounded();
+	let stats = Arc::new(IngestStats::default());
+	let thread_stats = stats.clone();
+	thread::spawn(move || {
+	    let mut decoder = ItmDecoder::new(port);
+	    let mut lp = LineProtocol::new(LineEnding::Auto);
+	    let mut text = vec![];
+	    while !shutdown::requested() {
+		let mut buffer: [u8; 1024] = [0; 1024];
+		match file.read(&mut buffer) {
+		    // Wait for the writer to append more
+		    Ok(0) => { thread::sleep(Duration::from_millis(10)); }
+		    Ok(bytes_read) => {
+			thread_stats.bytes.fetch_add(bytes_read as u64, Ordering::Relaxed);
+			text.clear();
+			decoder.feed(&buffer[0..bytes_read], &mut text);
+			let mut lines = 0;
+			let mut closed = false;
+			lp.feed(&text, |line: &str| {
+			    closed |= s.send(Received::now(line.to_string())).is_err();
+			    lines += 1;
+			});
+			if closed {
+			    break;
+			}
+			thread_stats.lines.fetch_add(lines, Ordering::Relaxed);
+			thread_stats.invalid_lines.store(lp.invalid, Ordering::Relaxed);
+			thread_stats.overflows.store(decoder.overflows, Ordering::Relaxed);
+		    }
+		    Err(error) => {
+			warn!("reading the SWO capture failed: {:?}", error);
+			break;
+		    }
+		}
+	    }
+	});
+	Ok(SwoConnector{ receiver: r, stats })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn stimulus_port_text_is_extracted() {
+	let mut decoder = ItmDecoder::new(0);
+	let mut text = vec![];
+	let stream = [
+	    // sync
+	    0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+	    // port 0, one byte each, then four bytes
+	    0x01, b'`', 0x01, b'S', 0x03, b'c', b'o', b'p', b'e',
+	    // port 1 is ignored
+	    0x09, b'x',
+	    // local timestamp with continuation
+	    0xc0, 0x81, 0x01,
+	    // hardware source with two bytes
+	    0x06, 0x01, 0x02,
+	    // overflow
+	    0x70,
+	    0x02, b'\r', b'\n',
+	];
+	// Packets may straddle reads
+	decoder.feed(&stream[0..8], &mut text);
+	decoder.feed(&stream[8..], &mut text);
+	assert_eq!(text, b"`Scope\r\n".to_vec());
+	assert_eq!(decoder.overflows, 1);
+    }
+}
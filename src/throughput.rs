@@ -0,0 +1,111 @@
+use nannou::prelude::*;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use crate::serial::{ConnectionState, IngestStats};
+
+type Rect = nannou::geom::rect::Rect;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Throughput and error counters of the main input. A full queue
+// with a modest byte rate points at the UI, full reads at the link.
+pub struct ThroughputPanel
+{
+    pub visible: bool,
+    window_start: Instant,
+    // Counters at the start of the window
+    bytes: u64,
+    lines: u64,
+    bytes_per_second: f64,
+    lines_per_second: f64,
+}
+
+impl ThroughputPanel
+{
+    pub fn new() -> ThroughputPanel
+    {
+	ThroughputPanel{ visible: false, window_start: Instant::now(), bytes: 0, lines: 0, bytes_per_second: 0.0, lines_per_second: 0.0 }
+    }
+
+    pub fn tick(&mut self, stats: &IngestStats, now: Instant)
+    {
+	let elapsed = now.duration_since(self.window_start);
+	if elapsed >= RATE_WINDOW {
+	    let bytes = stats.bytes.load(Ordering::Relaxed);
+	    let lines = stats.lines.load(Ordering::Relaxed);
+	    // Counters start over with a new source
+	    self.bytes_per_second = bytes.saturating_sub(self.bytes) as f64 / elapsed.as_secs_f64();
+	    self.lines_per_second = lines.saturating_sub(self.lines) as f64 / elapsed.as_secs_f64();
+	    self.bytes = bytes;
+	    self.lines = lines;
+	    self.window_start = now;
+	}
+    }
+
+    pub fn lines(&self, stats: &IngestStats, queued: usize, connection: ConnectionState) -> Vec<String>
+    {
+	let state = match connection {
+	    ConnectionState::Connected => "connected",
+	    ConnectionState::Reconnecting => "reconnecting",
+	};
+	vec![
+	    format!("Input {}: {:.0} bytes/s, {:.0} lines/s", state, self.bytes_per_second, self.lines_per_second),
+	    format!("total: {} bytes, {} lines, {} queued", stats.bytes.load(Ordering::Relaxed), stats.lines.load(Ordering::Relaxed), queued),
+	    format!("invalid lines: {}", stats.invalid_lines.load(Ordering::Relaxed)),
+	    format!("bad checksums: {}", stats.bad_checksums.load(Ordering::Relaxed)),
+	    format!("full reads: {}", stats.full_reads.load(Ordering::Relaxed)),
+	    format!("dropped: {}", stats.dropped.load(Ordering::Relaxed)),
+	    format!("overflows: {}", stats.overflows.load(Ordering::Relaxed)),
+	]
+    }
+
+    pub fn draw(&self, draw: &nannou::draw::Draw, rect: Rect, stats: &IngestStats, queued: usize, connection: ConnectionState)
+    {
+	if !self.visible {
+	    return;
+	}
+	let line_height = 16.0;
+	let lines = self.lines(stats, queued, connection);
+	let rect = Rect::from_w_h(rect.w(), line_height * lines.len() as f32 + 4.0).align_top_of(rect).align_right_of(rect);
+	draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgba(0.0, 0.0, 0.0, 0.85));
+	for (index, line) in lines.iter().enumerate() {
+	    let y = rect.top() - 2.0 - line_height * (index as f32 + 0.5);
+	    let color = if index == 0 {
+		if connection == ConnectionState::Connected { YELLOW } else { RED }
+	    } else if index > 1 && !line.ends_with(": 0") {
+		RED
+	    } else {
+		WHITE
+	    };
+	    draw.text(line)
+		.x_y(rect.x(), y)
+		.w_h(rect.w() - 8.0, line_height)
+		.font_size(12)
+		.left_justify()
+		.color(color);
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn rates_per_window() {
+	let stats = IngestStats::default();
+	let mut panel = ThroughputPanel::new();
+	let start = panel.window_start;
+	stats.bytes.store(2000, Ordering::Relaxed);
+	stats.lines.store(100, Ordering::Relaxed);
+	stats.bad_checksums.store(3, Ordering::Relaxed);
+	panel.tick(&stats, start + Duration::from_millis(500));
+	assert_eq!(panel.lines_per_second, 0.0);
+	panel.tick(&stats, start + Duration::from_secs(2));
+	let lines = panel.lines(&stats, 5, ConnectionState::Connected);
+	assert_eq!(lines[0], "Input connected: 1000 bytes/s, 50 lines/s");
+	assert_eq!(lines[1], "total: 2000 bytes, 100 lines, 5 queued");
+	assert_eq!(lines[3], "bad checksums: 3");
+    }
+}
@@ -0,0 +1,63 @@
+use std::os::unix::net::UnixListener;
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+use log::{info, warn};
+use crate::serial::{LineEnding, Received, forward_lines};
+
+// Listens on a unix domain socket, e.g. for simulators. Clients
+// are served one after another, so a restarted client reconnects.
+pub struct UnixConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+impl UnixConnector
+{
+    pub fn new(path: &str, ending: LineEnding) -> std::io::Result<UnixConnector>
+    {
+	// A previous run leaves the socket file behind
+	if std::fs::metadata(path).is_ok() {
+	    std::fs::remove_file(path)?;
+	}
+	let listener = UnixListener::bind(path)?;
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    for stream in listener.incoming() {
+		let stream = match stream {
+		    Ok(stream) => stream,
+		    Err(error) => {
+			warn!("unix socket accept failed: {:?}", error);
+			continue;
+		    }
+		};
+		info!("unix socket client connected");
+		match forward_lines(stream, ending, "unix socket", |line| { s.send(Received::now(line.to_string())).is_ok() }) {
+		    Ok(true) => {}
+		    Ok(false) => { return; }
+		    Err(error) => { warn!("unix socket read failed: {:?}", error); }
+		}
+	    }
+	});
+	Ok(UnixConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use test_env_log::test;
+
+    #[test]
+    fn clients_are_served_in_turn() {
+	let path = std::env::temp_dir().join(format!("rusty-peanut-{}.sock", std::process::id()));
+	let path = path.to_str().unwrap();
+	let connector = UnixConnector::new(path, LineEnding::Auto).unwrap();
+	UnixStream::connect(path).unwrap().write_all(b"`SCOPE MyScope\r\n").unwrap();
+	UnixStream::connect(path).unwrap().write_all(b"`MyScope \xff\n`MyScope 1\n").unwrap();
+	let lines: Vec<String> = connector.receiver.iter().take(2).map(|received| { received.line }).collect();
+	assert_eq!(lines, vec!["`SCOPE MyScope", "`MyScope 1"]);
+	std::fs::remove_file(path).unwrap();
+    }
+}
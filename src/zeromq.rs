@@ -0,0 +1,85 @@
+use std::thread;
+use crossbeam::channel::{Receiver, unbounded};
+use log::warn;
+use crate::serial::Received;
+
+// Subscribes to a ZeroMQ PUB socket. Messages carry one or more
+// protocol lines, either after a topic frame or prefixed by the
+// topic in a single frame.
+pub struct ZmqConnector
+{
+    pub receiver: Receiver<Received>
+}
+
+fn message_lines(frames: &[Vec<u8>], topics: &[String]) -> Vec<String>
+{
+    let payloads: Vec<String> = match frames {
+	[] => vec![],
+	[single] => {
+	    let text = String::from_utf8_lossy(single).to_string();
+	    let topic = topics.iter().filter(|topic| { text.starts_with(topic.as_str()) }).map(|topic| { topic.len() }).max().unwrap_or(0);
+	    vec![text[topic..].to_string()]
+	}
+	[_topic, rest @ ..] => rest.iter().map(|frame| { String::from_utf8_lossy(frame).to_string() }).collect(),
+    };
+    payloads.iter()
+	.flat_map(|payload| {
+	    payload.lines()
+		.map(|line| { line.trim().to_string() })
+		.filter(|line| { !line.is_empty() })
+		.collect::<Vec<String>>()
+	})
+	.collect()
+}
+
+impl ZmqConnector
+{
+    // An empty topic list subscribes to everything
+    pub fn new(endpoint: &str, topics: &[String]) -> Result<ZmqConnector, String>
+    {
+	let context = zmq::Context::new();
+	let socket = context.socket(zmq::SUB).map_err(|error| { error.to_string() })?;
+	socket.connect(endpoint).map_err(|error| { error.to_string() })?;
+	if topics.is_empty() {
+	    socket.set_subscribe(b"").map_err(|error| { error.to_string() })?;
+	}
+	for topic in topics {
+	    socket.set_subscribe(topic.as_bytes()).map_err(|error| { error.to_string() })?;
+	}
+	let topics = topics.to_vec();
+	let (s, r) = unbounded();
+	thread::spawn(move || {
+	    // The socket reconnects on its own
+	    loop {
+		match socket.recv_multipart(0) {
+		    Ok(frames) => {
+			for line in message_lines(&frames, &topics) {
+			    if s.send(Received::now(line)).is_err() {
+				return;
+			    }
+			}
+		    }
+		    Err(error) => {
+			warn!("ZeroMQ error: {:?}", error);
+			thread::sleep(std::time::Duration::from_secs(1));
+		    }
+		}
+	    }
+	});
+	Ok(ZmqConnector{ receiver: r })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_env_log::test;
+
+    #[test]
+    fn topics_and_payloads() {
+	let topics = vec!["scope".to_string()];
+	assert_eq!(message_lines(&[b"scope".to_vec(), b"`MyScope 1\r\n`MyScope 2\n".to_vec()], &topics), vec!["`MyScope 1", "`MyScope 2"]);
+	assert_eq!(message_lines(&[b"scope `MyScope 3".to_vec()], &topics), vec!["`MyScope 3"]);
+	assert_eq!(message_lines(&[b"`MyScope 4".to_vec()], &[]), vec!["`MyScope 4"]);
+    }
+}